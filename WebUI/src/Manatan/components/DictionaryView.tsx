@@ -52,6 +52,30 @@ const tagStyle: React.CSSProperties = {
     color: '#fff', verticalAlign: 'middle', lineHeight: '1.2'
 };
 
+// Image paths are rewritten on import to `media/{dictionaryId}/...`; anything else was not
+// found in the archive and can't be shown.
+const ImageNode: React.FC<{ node: any }> = ({ node }) => {
+    if (typeof node.path !== 'string' || !node.path.startsWith('media/')) return null;
+    const units = node.sizeUnits === 'em' ? 'em' : 'px';
+    const size = (value: unknown) => (typeof value === 'number' ? `${value}${units}` : undefined);
+    const label = node.description || node.alt || node.title;
+    return (
+        <img
+            src={`/api/yomitan/${node.path.split('/').map(encodeURIComponent).join('/')}`}
+            alt={typeof label === 'string' ? label : ''}
+            title={typeof node.title === 'string' ? node.title : undefined}
+            loading="lazy"
+            style={{
+                width: size(node.width),
+                height: size(node.height),
+                maxWidth: '100%',
+                verticalAlign: node.verticalAlign || 'middle',
+                imageRendering: node.pixelated || node.imageRendering === 'pixelated' ? 'pixelated' : undefined,
+            }}
+        />
+    );
+};
+
 const ContentNode: React.FC<{ node: any; onLinkClick?: (href: string, text: string) => void }> = ({ node, onLinkClick }) => {
     if (node === null || node === undefined) return null;
     if (typeof node === 'string' || typeof node === 'number') return <>{node}</>;
@@ -59,6 +83,7 @@ const ContentNode: React.FC<{ node: any; onLinkClick?: (href: string, text: stri
     if (node.type === 'structured-content') return <ContentNode node={node.content} onLinkClick={onLinkClick} />;
 
     if (node?.data?.content === 'attribution') return null;
+    if (node.type === 'image' || node.tag === 'img') return <ImageNode node={node} />;
 
     const { tag, content, style, href, data, title } = node;
     const s = style || {};
@@ -645,6 +670,14 @@ export const DictionaryView: React.FC<DictionaryViewProps> = ({
                                     </ruby>
                                 )}
                             </div>
+                            {entry.matchInfo?.chain && entry.matchInfo.chain.length > 0 && (
+                                <div
+                                    style={{ fontSize: '0.8em', color: '#aaa', marginRight: '8px' }}
+                                    title={`Deinflected from ${entry.matchInfo.surface}`}
+                                >
+                                    {entry.matchInfo.chain.map((step) => `« ${step.label}`).join(' ')}
+                                </div>
+                            )}
                             {entry.termTags && entry.termTags.length > 0 && (
                                 <div style={{ display: 'flex', gap: '4px' }}>
                                     {entry.termTags.flatMap((tag: any) => {
@@ -8,7 +8,7 @@ import { bindTrigger, usePopupState } from 'material-ui-popup-state/hooks';
 import { useOCR } from '@/Manatan/context/OCRContext';
 import { AppStorage } from '@/lib/storage/AppStorage.ts';
 import { COLOR_THEMES, DEFAULT_SETTINGS } from '@/Manatan/types';
-import { apiRequest, getAppVersion, checkForUpdates, triggerAppUpdate, installAppUpdate, getFrequencyDictionaries, getDictionaries } from '@/Manatan/utils/api';
+import { apiRequest, getAppVersion, checkForUpdates, triggerAppUpdate, installAppUpdate, getFrequencyDictionaries, getDictionaries, getApiAuthHeaders, waitForImportJob } from '@/Manatan/utils/api';
 import { DictionaryManager } from './DictionaryManager';
 import { getAnkiVersion, getDeckNames, getModelNames, getModelFields } from '@/Manatan/utils/anki';
 import { ResetButton } from '@/base/components/buttons/ResetButton.tsx';
@@ -556,13 +556,21 @@ export const SettingsModal: React.FC<{ onClose: () => void }> = ({ onClose }) =>
             formData.append('file', file);
             try {
                 showProgress(`Importing ${i + 1}/${files.length}...`);
-                const res = await fetch('/api/yomitan/import', { method: 'POST', body: formData });
+                const res = await fetch('/api/yomitan/import', {
+                    method: 'POST',
+                    body: formData,
+                    headers: getApiAuthHeaders(),
+                });
                 const json = await res.json();
-                if (json.status === 'ok') {
+                const job = json.status === 'ok' && json.job_id != null
+                    ? await waitForImportJob(json.job_id)
+                    : null;
+                if (job?.status === 'done') {
                     successCount += 1;
                 } else {
                     failCount += 1;
-                    const message = json.message ? String(json.message) : 'Unknown error.';
+                    const detail = job ? job.message : json.message;
+                    const message = detail ? String(detail) : 'Unknown error.';
                     failureMessages.push(`${file.name}: ${message}`);
                 }
             } catch (err) {
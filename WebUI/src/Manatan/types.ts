@@ -160,8 +160,22 @@ export interface DictionaryResult {
     forms?: { headword: string; reading: string }[];
     source?: number;
     matchLen?: number;
+    // Matched region of the looked-up text ([start, end) in characters / UTF-8 bytes)
+    spanChars?: { start: number; end: number };
+    spanBytes?: { start: number; end: number };
+    // Why this entry matched; fuzzy kinds (deinflected, segmenter, prefix) can be de-emphasized
+    matchInfo?: {
+        kind: 'exact' | 'reading' | 'deinflected' | 'segmenter' | 'counter' | 'gloss';
+        surface: string;
+        candidate: string;
+        rule?: string | null;
+        chain?: Array<{ id: string; label: string }>;
+        prefix: boolean;
+        via_reading: boolean;
+    };
     termTags?: Array<string | { name?: string; label?: string; tag?: string; value?: string }>;
     frequencies?: any[];
+    pitches?: Array<{ dictionaryName: string; position: number }>;
 
 }
 
@@ -13,6 +13,7 @@ export interface DictionaryMeta {
     name: string;
     priority: number;
     enabled: boolean;
+    termCount?: number;
 }
 
 export interface AppVersionInfo {
@@ -87,6 +88,15 @@ export const fetchChapterPagesGraphQL = async (chapterId: number) => {
     return json.data?.fetchChapterPages?.pages as string[] | undefined;
 };
 
+// --- API TOKEN ---
+// Set when the server runs with MANATAN_API_TOKEN; sent on every Manatan API request.
+const API_TOKEN_KEY = 'manatan_api_token';
+
+export const getApiAuthHeaders = (): Record<string, string> => {
+    const token = localStorage.getItem(API_TOKEN_KEY);
+    return token ? { Authorization: `Bearer ${token}` } : {};
+};
+
 // --- SAFE API REQUEST WRAPPER ---
 export const apiRequest = async <T>(
     url: string,
@@ -96,7 +106,7 @@ export const apiRequest = async <T>(
     
     const response = await fetch(fullUrl, {
         method: options.method || 'GET',
-        headers: { 'Content-Type': 'application/json', ...options.headers },
+        headers: { 'Content-Type': 'application/json', ...getApiAuthHeaders(), ...options.headers },
         body: options.body ? JSON.stringify(options.body) : undefined,
     });
 
@@ -142,12 +152,13 @@ export const getDictionaries = async (): Promise<DictionaryMeta[] | null> => {
         if (res.status && res.status !== 'ready') {
             return null;
         }
-        // Backend returns "dictionaries" array with {id: [number], name, priority, enabled}
+        // Backend returns "dictionaries" array with {id: [number], name, priority, enabled, term_count}
         return res.dictionaries.map(d => ({
             id: d.id, // Rust DictionaryId is a tuple struct or plain integer based on serialization
             name: d.name,
             priority: d.priority,
-            enabled: d.enabled
+            enabled: d.enabled,
+            termCount: d.term_count
         }));
     } catch (e) {
         console.error("Failed to fetch dictionaries", e);
@@ -175,6 +186,28 @@ export const manageDictionary = async (action: 'Toggle' | 'Delete' | 'Reorder',
     });
 };
 
+export interface ImportJob {
+    id: number;
+    status: 'queued' | 'running' | 'done' | 'failed';
+    size: number;
+    message: string | null;
+    error: string | null;
+}
+
+// Imports run in the background; poll the job until it finishes.
+export const waitForImportJob = async (jobId: number, intervalMs = 1000): Promise<ImportJob> => {
+    for (;;) {
+        const job = await apiRequest<ImportJob>(`/api/yomitan/import/jobs/${jobId}`);
+        if (job.status === 'done' || job.status === 'failed') {
+            return job;
+        }
+        if (job.status !== 'queued' && job.status !== 'running') {
+            throw new Error(job.message || `Import job ${jobId} not found`);
+        }
+        await new Promise((resolve) => setTimeout(resolve, intervalMs));
+    }
+};
+
 // --- OCR / CHAPTER API ---
 
 export const checkChapterStatus = async (
@@ -3,7 +3,7 @@ mod io;
 use std::{
     env,
     fs::{self},
-    net::Ipv4Addr,
+    net::{Ipv4Addr, SocketAddr},
     path::{Path, PathBuf},
     process::Stdio,
     sync::{
@@ -23,6 +23,7 @@ use axum::{
         ws::{Message, WebSocket, WebSocketUpgrade},
     },
     http::{HeaderMap, StatusCode, Uri},
+    middleware,
     response::{IntoResponse, Response},
     routing::any,
 };
@@ -33,6 +34,7 @@ use eframe::{
     icon_data,
 };
 use futures::{SinkExt, StreamExt, TryStreamExt};
+use manatan_api_auth::{ApiAuth, require_token};
 use reqwest::{
     Client, Method,
     header::{
@@ -54,6 +56,7 @@ use tokio_tungstenite::{
 use tower_http::cors::{AllowOrigin, CorsLayer};
 use tracing::{error, info, warn};
 use tracing_subscriber::EnvFilter;
+use utoipa::OpenApi;
 
 #[cfg(feature = "embed-jre")]
 use crate::io::extract_zip;
@@ -112,6 +115,14 @@ struct Cli {
     /// Sets the Port to bind the server to
     #[arg(long, default_value_t = 4568, env = "MANATAN_PORT")]
     port: u16,
+
+    /// Requires this bearer token on mutating /api/ocr, /api/yomitan and /api/audio requests
+    #[arg(long, env = "MANATAN_API_TOKEN", hide_env_values = true)]
+    api_token: Option<String>,
+
+    /// Also requires the API token for read-only (GET) requests
+    #[arg(long, env = "MANATAN_AUTH_READS", requires = "api_token")]
+    protect_reads: bool,
 }
 
 fn resolve_data_dir() -> PathBuf {
@@ -250,6 +261,7 @@ fn main() -> eframe::Result<()> {
 
     let host = args.host;
     let port = args.port;
+    let auth = ApiAuth::new(args.api_token.clone(), args.protect_reads);
 
     if args.headless {
         info!("👻 Starting in Headless Mode (No GUI)...");
@@ -276,7 +288,7 @@ fn main() -> eframe::Result<()> {
                 }
             });
 
-            if let Err(err) = run_server(shutdown_rx, &server_data_dir, host, port).await {
+            if let Err(err) = run_server(shutdown_rx, &server_data_dir, host, port, auth).await {
                 error!("Server crashed: {err}");
             }
         });
@@ -297,7 +309,9 @@ fn main() -> eframe::Result<()> {
                 tx: server_stopped_tx,
             };
 
-            if let Err(err) = run_server(shutdown_rx, &server_data_dir, thread_host, port).await {
+            if let Err(err) =
+                run_server(shutdown_rx, &server_data_dir, thread_host, port, auth).await
+            {
                 error!("Server crashed: {err}");
             }
         });
@@ -612,6 +626,7 @@ async fn run_server(
     data_dir: &PathBuf,
     host: Ipv4Addr,
     port: u16,
+    auth: ApiAuth,
 ) -> Result<(), Box<anyhow::Error>> {
     info!("🚀 Initializing Manatan Launcher...");
     info!("📂 Data Directory: {}", data_dir.display());
@@ -688,6 +703,7 @@ async fn run_server(
     let yomitan_router = manatan_yomitan_server::create_router(data_dir.clone());
     let audio_router = manatan_audio_server::create_router(data_dir.clone());
     let system_router = Router::new().route("/version", any(current_version_handler));
+    let openapi_router = openapi_router();
 
     let client = Client::new();
     let cors = CorsLayer::new()
@@ -714,11 +730,19 @@ async fn run_server(
         .route("/api/{*path}", any(proxy_suwayomi_handler))
         .with_state(client);
 
-    let app = Router::new()
+    if auth.is_enabled() {
+        info!("🔒 API token authentication enabled.");
+    }
+    let api_router = Router::new()
         .nest("/api/ocr", ocr_router)
         .nest("/api/yomitan", yomitan_router)
         .nest("/api/audio", audio_router)
+        .layer(middleware::from_fn_with_state(auth, require_token));
+
+    let app = Router::new()
+        .merge(api_router)
         .nest("/api/system", system_router)
+        .merge(openapi_router)
         .merge(proxy_router)
         .fallback(serve_react_app)
         .layer(cors);
@@ -728,6 +752,8 @@ async fn run_server(
         .await
         .map_err(|err| anyhow!("Failed to create main server socket: {err:?}"))?;
 
+    // Connection info lets per-client middleware (rate limiting) key on the peer address.
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
     let server_future = axum::serve(listener, app).with_graceful_shutdown(async move {
         let _ = shutdown_signal.recv().await;
         info!("🛑 Shutdown signal received.");
@@ -751,6 +777,38 @@ async fn run_server(
     Ok(())
 }
 
+/// Combined OpenAPI document for the OCR, Yomitan and audio routers at `/api/openapi.json`,
+/// plus Swagger UI at `/api/docs` when built with the `swagger-ui` feature.
+fn openapi_router() -> Router {
+    let doc = utoipa::openapi::OpenApiBuilder::new()
+        .info(
+            utoipa::openapi::InfoBuilder::new()
+                .title("Manatan API")
+                .version(env!("CARGO_PKG_VERSION"))
+                .build(),
+        )
+        .build()
+        .nest("/api/ocr", manatan_ocr_server::ApiDoc::openapi())
+        .nest("/api/yomitan", manatan_yomitan_server::openapi::ApiDoc::openapi())
+        .nest("/api/audio", manatan_audio_server::ApiDoc::openapi());
+
+    openapi_routes(doc)
+}
+
+#[cfg(feature = "swagger-ui")]
+fn openapi_routes(doc: utoipa::openapi::OpenApi) -> Router {
+    Router::new()
+        .merge(utoipa_swagger_ui::SwaggerUi::new("/api/docs").url("/api/openapi.json", doc))
+}
+
+#[cfg(not(feature = "swagger-ui"))]
+fn openapi_routes(doc: utoipa::openapi::OpenApi) -> Router {
+    Router::new().route(
+        "/api/openapi.json",
+        axum::routing::get(move || async move { axum::Json(doc) }),
+    )
+}
+
 async fn proxy_suwayomi_handler(State(client): State<Client>, req: Request) -> Response {
     let (mut parts, body) = req.into_parts();
 
@@ -22,6 +22,7 @@ use axum::{
         ws::{Message, WebSocket, WebSocketUpgrade},
     },
     http::{HeaderMap, Method, StatusCode, Uri},
+    middleware,
     response::{IntoResponse, Response},
     routing::any,
 };
@@ -35,6 +36,7 @@ use jni::{
     sys::{JNI_VERSION_1_6, jint, jobject},
 };
 use lazy_static::lazy_static;
+use manatan_api_auth::{ApiAuth, require_token};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -742,6 +744,17 @@ async fn start_web_server(data_dir: PathBuf) -> Result<(), Box<dyn std::error::E
         ])
         .allow_credentials(true);
 
+    // The server listens on every interface, so other devices on the network can reach it.
+    let auth = ApiAuth::from_env();
+    if auth.is_enabled() {
+        info!("🔒 API token authentication enabled.");
+    }
+    let api_router = Router::new()
+        .nest_service("/api/ocr", ocr_router)
+        .nest_service("/api/yomitan", yomitan_router)
+        .nest_service("/api/audio", audio_router)
+        .layer(middleware::from_fn_with_state(auth, require_token));
+
     let app = Router::new()
         .route("/api/v1/webview", any(webview_shim_handler))
         .route("/api/system/version", any(current_version_handler))
@@ -750,9 +763,7 @@ async fn start_web_server(data_dir: PathBuf) -> Result<(), Box<dyn std::error::E
             axum::routing::post(download_update_handler),
         )
         .route("/api/system/install-update", any(install_update_handler))
-        .nest_service("/api/ocr", ocr_router)
-        .nest_service("/api/yomitan", yomitan_router)
-        .nest_service("/api/audio", audio_router)
+        .merge(api_router)
         .route("/api/{*path}", any(proxy_suwayomi_handler))
         .fallback(serve_react_app)
         .layer(cors)
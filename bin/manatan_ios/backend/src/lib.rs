@@ -18,10 +18,12 @@ use axum::{
         ws::{Message, WebSocket, WebSocketUpgrade},
     },
     http::{HeaderMap, Method, StatusCode, Uri},
+    middleware,
     response::{IntoResponse, Response},
     routing::any,
 };
 use futures::{SinkExt, StreamExt};
+use manatan_api_auth::{ApiAuth, require_token};
 use reqwest::Client;
 use serde::Serialize;
 use socket2::{Domain, Protocol, Socket, Type};
@@ -171,10 +173,18 @@ async fn start_web_server(
     let proxy_router: Router<AppState> =
         Router::new().route("/api/{*path}", any(proxy_suwayomi_handler));
 
-    let app: Router<AppState> = Router::new()
+    let auth = ApiAuth::from_env();
+    if auth.is_enabled() {
+        info!("🔒 API token authentication enabled.");
+    }
+    let api_router: Router<AppState> = Router::new()
         .nest_service("/api/ocr", ocr_router)
         .nest_service("/api/yomitan", yomitan_router)
         .nest_service("/api/audio", audio_router)
+        .layer(middleware::from_fn_with_state(auth, require_token));
+
+    let app: Router<AppState> = Router::new()
+        .merge(api_router)
         .nest("/api/system", system_router)
         .merge(proxy_router)
         .fallback(serve_react_app)
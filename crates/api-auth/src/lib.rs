@@ -0,0 +1,140 @@
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::{HeaderMap, Method, StatusCode, header::AUTHORIZATION},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+use tracing::warn;
+
+/// Bearer-token guard for the Manatan API routers. Disabled when no token is configured.
+#[derive(Clone, Debug, Default)]
+pub struct ApiAuth {
+    token: Option<String>,
+    protect_reads: bool,
+}
+
+impl ApiAuth {
+    pub fn new(token: Option<String>, protect_reads: bool) -> Self {
+        let token = token
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty());
+        Self {
+            token,
+            protect_reads,
+        }
+    }
+
+    /// Reads the token from `MANATAN_API_TOKEN` and `MANATAN_AUTH_READS`, for the mobile
+    /// backends, which have no command line.
+    pub fn from_env() -> Self {
+        let protect_reads = std::env::var("MANATAN_AUTH_READS").is_ok_and(|v| {
+            matches!(
+                v.trim().to_ascii_lowercase().as_str(),
+                "1" | "true" | "yes" | "on"
+            )
+        });
+        Self::new(std::env::var("MANATAN_API_TOKEN").ok(), protect_reads)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.token.is_some()
+    }
+
+    fn requires_token(&self, method: &Method) -> bool {
+        let is_read = matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+        self.is_enabled() && (self.protect_reads || !is_read)
+    }
+
+    fn accepts(&self, header: Option<&str>) -> bool {
+        let Some(expected) = &self.token else {
+            return true;
+        };
+        let Some(provided) = header.and_then(|h| h.strip_prefix("Bearer ")) else {
+            return false;
+        };
+        constant_time_eq(provided.trim().as_bytes(), expected.as_bytes())
+    }
+
+    /// Whether a request may go through. A valid token is also taken off the request: the
+    /// audio server forwards `Authorization` upstream, and the token must not reach Suwayomi or
+    /// stream hosts. Other credentials, such as Suwayomi basic auth, are left in place.
+    fn authorize(&self, method: &Method, headers: &mut HeaderMap) -> bool {
+        let valid = self.is_enabled()
+            && self.accepts(headers.get(AUTHORIZATION).and_then(|v| v.to_str().ok()));
+        if valid {
+            headers.remove(AUTHORIZATION);
+        }
+        // CORS preflights never carry credentials.
+        valid || *method == Method::OPTIONS || !self.requires_token(method)
+    }
+}
+
+pub async fn require_token(State(auth): State<ApiAuth>, mut req: Request, next: Next) -> Response {
+    let method = req.method().clone();
+    if auth.authorize(&method, req.headers_mut()) {
+        return next.run(req).await;
+    }
+
+    warn!("🔒 [Auth] Rejected {} {}", req.method(), req.uri().path());
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(json!({ "status": "error", "error": "unauthorized", "message": "Missing or invalid API token" })),
+    )
+        .into_response()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mutating_requests_need_a_valid_token() {
+        let auth = ApiAuth::new(Some("secret".to_string()), false);
+        assert!(!auth.requires_token(&Method::GET));
+        assert!(auth.requires_token(&Method::POST));
+        assert!(auth.accepts(Some("Bearer secret")));
+        assert!(!auth.accepts(Some("Bearer secreT")));
+        assert!(!auth.accepts(None));
+
+        let open = ApiAuth::new(Some("  ".to_string()), true);
+        assert!(!open.requires_token(&Method::DELETE));
+    }
+
+    #[test]
+    fn valid_tokens_are_taken_off_the_request() {
+        let auth = ApiAuth::new(Some("secret".to_string()), false);
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(AUTHORIZATION, value.parse().expect("header value"));
+            headers
+        };
+
+        let mut with_token = headers("Bearer secret");
+        assert!(auth.authorize(&Method::POST, &mut with_token));
+        assert!(with_token.get(AUTHORIZATION).is_none());
+
+        // Reads don't need the token but must not leak it either.
+        let mut read = headers("Bearer secret");
+        assert!(auth.authorize(&Method::GET, &mut read));
+        assert!(read.get(AUTHORIZATION).is_none());
+
+        let mut basic = headers("Basic dXNlcjpwYXNz");
+        assert!(auth.authorize(&Method::GET, &mut basic));
+        assert!(basic.get(AUTHORIZATION).is_some());
+        assert!(!auth.authorize(&Method::POST, &mut basic));
+
+        // Without a token configured, nothing is ours to remove.
+        let mut open = headers("Bearer secret");
+        assert!(ApiAuth::default().authorize(&Method::POST, &mut open));
+        assert!(open.get(AUTHORIZATION).is_some());
+    }
+}
@@ -0,0 +1,37 @@
+use anyhow::{Result, anyhow};
+use base64::Engine;
+use reqwest::Client;
+use serde_json::{Value, json};
+
+pub const DEFAULT_ANKI_CONNECT_URL: &str = "http://127.0.0.1:8765";
+
+/// Uploads a file to Anki's media folder with AnkiConnect's `storeMediaFile` and returns the
+/// name it was stored under.
+pub async fn store_media_file(
+    client: &Client,
+    url: &str,
+    filename: &str,
+    data: &[u8],
+) -> Result<String> {
+    let payload = json!({
+        "action": "storeMediaFile",
+        "version": 6,
+        "params": {
+            "filename": filename,
+            "data": base64::engine::general_purpose::STANDARD.encode(data),
+        }
+    });
+
+    let response = client.post(url).json(&payload).send().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("AnkiConnect returned {}", response.status()));
+    }
+    let body: Value = response.json().await?;
+    if let Some(err) = body.get("error").and_then(|e| e.as_str()) {
+        return Err(anyhow!("AnkiConnect error: {err}"));
+    }
+    body.get("result")
+        .and_then(|r| r.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("AnkiConnect returned no filename"))
+}
@@ -0,0 +1,156 @@
+use std::{fs, path::PathBuf, sync::Mutex, time::SystemTime};
+
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+const DEFAULT_MAX_MB: u64 = 200;
+
+/// Encoded clips on disk, so previewing the same card doesn't refetch and decode the episode.
+/// Files are named after a SHA-256 of the clip parameters; the least recently read ones are
+/// evicted once the directory grows past its size limit.
+pub struct ClipCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Serializes eviction against writes so the size accounting stays sane. Reads don't take
+    /// it; they only ever see whole files, because writes are renamed into place.
+    lock: Mutex<()>,
+}
+
+/// Files and bytes removed by a purge.
+pub struct Purged {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl ClipCache {
+    /// Cache under `data_dir/audio-clips`, limited to `MANATAN_CLIP_CACHE_MB` megabytes
+    /// (default 200, `0` disables caching).
+    pub fn new(data_dir: &std::path::Path) -> Self {
+        let max_mb = std::env::var("MANATAN_CLIP_CACHE_MB")
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(DEFAULT_MAX_MB);
+        Self {
+            dir: data_dir.join("audio-clips"),
+            max_bytes: max_mb * 1024 * 1024,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_bytes > 0
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let name: String = Sha256::digest(key.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        self.dir.join(format!("{name}.clip"))
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let path = self.path(key);
+        let data = fs::read(&path).ok()?;
+        // The modification time doubles as the last access time for eviction.
+        if let Ok(file) = fs::File::options().append(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(data)
+    }
+
+    pub fn put(&self, key: &str, data: &[u8]) {
+        if self.max_bytes == 0 || data.len() as u64 > self.max_bytes {
+            return;
+        }
+        let _guard = self.lock.lock().expect("lock");
+        let path = self.path(key);
+        let partial = path.with_extension("clip.partial");
+        let written = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&partial, data))
+            .and_then(|_| fs::rename(&partial, &path));
+        if let Err(err) = written {
+            warn!("Failed to cache audio clip: {err}");
+            let _ = fs::remove_file(&partial);
+            return;
+        }
+        self.evict();
+    }
+
+    fn evict(&self) {
+        let mut files: Vec<(SystemTime, u64, PathBuf)> = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .flatten()
+                .filter_map(|entry| {
+                    let meta = entry.metadata().ok()?;
+                    let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    meta.is_file().then(|| (modified, meta.len(), entry.path()))
+                })
+                .collect(),
+            Err(_) => return,
+        };
+        let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+        files.sort_by_key(|(modified, _, _)| *modified);
+        for (_, len, path) in files {
+            if total <= self.max_bytes {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                total = total.saturating_sub(len);
+            }
+        }
+    }
+
+    pub fn purge(&self) -> Purged {
+        let _guard = self.lock.lock().expect("lock");
+        let mut purged = Purged { files: 0, bytes: 0 };
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return purged;
+        };
+        for entry in entries.flatten() {
+            let len = entry.metadata().map(|meta| meta.len()).unwrap_or(0);
+            if fs::remove_file(entry.path()).is_ok() {
+                purged.files += 1;
+                purged.bytes += len;
+            }
+        }
+        purged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(name: &str) -> ClipCache {
+        let dir =
+            std::env::temp_dir().join(format!("manatan-clip-cache-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        ClipCache {
+            dir,
+            max_bytes: 1024,
+            lock: Mutex::new(()),
+        }
+    }
+
+    #[test]
+    fn writes_whole_files_under_the_key_hash() {
+        let cache = cache("put");
+        cache.put("episode/1/0 0-5 wav", &[1; 100]);
+        cache.put("episode/1/0 0-5 wav", &[2; 200]);
+        assert_eq!(cache.get("episode/1/0 0-5 wav"), Some(vec![2; 200]));
+        assert_eq!(cache.get("episode/1/0 0-6 wav"), None);
+
+        let names: Vec<String> = fs::read_dir(&cache.dir)
+            .expect("cache dir")
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names.len(), 1, "{names:?}");
+        assert_eq!(names[0].len(), 64 + ".clip".len());
+        let _ = fs::remove_dir_all(&cache.dir);
+    }
+}
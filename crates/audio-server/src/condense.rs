@@ -0,0 +1,212 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::state::now_secs;
+
+/// Finished jobs kept for download before the oldest are dropped along with their files.
+const MAX_FINISHED_JOBS: usize = 20;
+/// Longest stretch of the episode decoded in one go. Ranges closer together than this share
+/// a batch, so the segments between them are fetched once.
+const BATCH_SECONDS: f64 = 120.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Waiting for an earlier job to finish.
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+/// A condensed-audio file being built in the background.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct CondenseJob {
+    pub id: u64,
+    pub status: JobStatus,
+    /// Dialogue ranges after padding and merging.
+    pub ranges: usize,
+    /// Seconds of audio the finished file will hold.
+    pub duration: f64,
+    pub batches: usize,
+    pub completed_batches: usize,
+    /// Error message of a failed job.
+    pub message: Option<String>,
+    pub created_at: i64,
+    pub finished_at: Option<i64>,
+    #[serde(skip)]
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Default)]
+struct JobTable {
+    next_id: u64,
+    jobs: HashMap<u64, CondenseJob>,
+}
+
+/// Registry of condense jobs. They run one at a time, since each keeps a whole episode's
+/// dialogue in memory until it's encoded.
+#[derive(Clone)]
+pub struct CondenseJobs {
+    dir: PathBuf,
+    table: Arc<Mutex<JobTable>>,
+    run_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl CondenseJobs {
+    /// Output goes to `data_dir/condensed`; files of a previous run are removed since their
+    /// jobs are gone.
+    pub fn new(data_dir: &std::path::Path) -> Self {
+        let dir = data_dir.join("condensed");
+        let _ = fs::remove_dir_all(&dir);
+        Self {
+            dir,
+            table: Arc::new(Mutex::new(JobTable::default())),
+            run_lock: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    pub fn create(&self, ranges: &[(f64, f64)]) -> CondenseJob {
+        let mut table = self.table.lock().expect("lock");
+        table.next_id += 1;
+        let job = CondenseJob {
+            id: table.next_id,
+            status: JobStatus::Queued,
+            ranges: ranges.len(),
+            duration: ranges.iter().map(|(start, end)| end - start).sum(),
+            batches: batches(ranges).len(),
+            completed_batches: 0,
+            message: None,
+            created_at: now_secs(),
+            finished_at: None,
+            file: None,
+        };
+        table.jobs.insert(job.id, job.clone());
+        job
+    }
+
+    pub fn get(&self, id: u64) -> Option<CondenseJob> {
+        self.table.lock().expect("lock").jobs.get(&id).cloned()
+    }
+
+    /// Held while a job runs so that queued jobs wait their turn.
+    pub fn run_lock(&self) -> Arc<tokio::sync::Mutex<()>> {
+        self.run_lock.clone()
+    }
+
+    pub fn file_path(&self, id: u64, extension: &str) -> PathBuf {
+        self.dir.join(format!("{id}.{extension}"))
+    }
+
+    pub fn set_running(&self, id: u64) {
+        if let Some(job) = self.table.lock().expect("lock").jobs.get_mut(&id) {
+            job.status = JobStatus::Running;
+        }
+    }
+
+    pub fn set_progress(&self, id: u64, completed_batches: usize) {
+        if let Some(job) = self.table.lock().expect("lock").jobs.get_mut(&id) {
+            job.completed_batches = completed_batches;
+        }
+    }
+
+    pub fn finish(&self, id: u64, result: Result<PathBuf, String>) {
+        let mut table = self.table.lock().expect("lock");
+        if let Some(job) = table.jobs.get_mut(&id) {
+            job.finished_at = Some(now_secs());
+            match result {
+                Ok(file) => {
+                    job.status = JobStatus::Done;
+                    job.completed_batches = job.batches;
+                    job.file = Some(file);
+                }
+                Err(message) => {
+                    job.status = JobStatus::Failed;
+                    job.message = Some(message);
+                }
+            }
+        }
+        prune(&mut table);
+    }
+}
+
+fn prune(table: &mut JobTable) {
+    let mut finished: Vec<u64> = table
+        .jobs
+        .values()
+        .filter(|job| job.finished_at.is_some())
+        .map(|job| job.id)
+        .collect();
+    if finished.len() <= MAX_FINISHED_JOBS {
+        return;
+    }
+    finished.sort_unstable();
+    let excess = finished.len() - MAX_FINISHED_JOBS;
+    for id in &finished[..excess] {
+        if let Some(file) = table.jobs.remove(id).and_then(|job| job.file) {
+            let _ = fs::remove_file(file);
+        }
+    }
+}
+
+/// Pads every range by `pad` seconds on both sides, then sorts and merges the ones that
+/// overlap, so shared audio isn't repeated.
+pub fn merge_ranges(ranges: &[(f64, f64)], pad: f64) -> Vec<(f64, f64)> {
+    let mut padded: Vec<(f64, f64)> = ranges
+        .iter()
+        .filter(|(start, end)| start.is_finite() && end.is_finite() && end > start)
+        .map(|(start, end)| ((start - pad).max(0.0), end + pad))
+        .collect();
+    padded.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut merged: Vec<(f64, f64)> = Vec::with_capacity(padded.len());
+    for (start, end) in padded {
+        match merged.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = last_end.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Splits sorted, merged ranges into runs spanning at most [`BATCH_SECONDS`] each; a single
+/// longer range gets a batch of its own.
+pub fn batches(ranges: &[(f64, f64)]) -> Vec<&[(f64, f64)]> {
+    let mut batches = Vec::new();
+    let mut first = 0;
+    for index in 1..=ranges.len() {
+        let ends_batch = ranges
+            .get(index)
+            .is_none_or(|(_, end)| end - ranges[first].0 > BATCH_SECONDS);
+        if ends_batch {
+            batches.push(&ranges[first..index]);
+            first = index;
+        }
+    }
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{batches, merge_ranges};
+
+    #[test]
+    fn merges_padded_ranges() {
+        let merged = merge_ranges(&[(10.0, 12.0), (0.2, 1.0), (12.3, 14.0), (5.0, 4.0)], 0.25);
+        assert_eq!(merged, [(0.0, 1.25), (9.75, 14.25)]);
+    }
+
+    #[test]
+    fn batches_nearby_ranges() {
+        let ranges = [(0.0, 10.0), (100.0, 110.0), (115.0, 125.0), (300.0, 500.0)];
+        let batches = batches(&ranges);
+        assert_eq!(batches, [&ranges[..2], &ranges[2..3], &ranges[3..]]);
+    }
+}
@@ -0,0 +1,528 @@
+//! MPEG-DASH manifests, reduced to what the clipper needs: for every period, the adaptation
+//! sets with their representations expanded into timed segment URLs.
+
+use std::fmt::Write;
+
+use anyhow::{Context, anyhow};
+use roxmltree::{Document, Node};
+use url::Url;
+
+use crate::handlers::rejected;
+
+const DATE_UNITS: [(char, f64); 4] = [
+    ('Y', 31_536_000.0),
+    ('M', 2_592_000.0),
+    ('W', 604_800.0),
+    ('D', 86_400.0),
+];
+const TIME_UNITS: [(char, f64); 3] = [('H', 3600.0), ('M', 60.0), ('S', 1.0)];
+
+pub struct Manifest {
+    pub periods: Vec<Period>,
+}
+
+pub struct Period {
+    /// Presentation time the period starts at, in seconds.
+    pub start: f64,
+    pub adaptation_sets: Vec<AdaptationSet>,
+}
+
+pub struct AdaptationSet {
+    pub lang: Option<String>,
+    /// Text of the `<Label>` element, the closest DASH has to an HLS rendition name.
+    pub label: Option<String>,
+    pub is_audio: bool,
+    /// Sets with `<ContentProtection>` are DRM-encrypted and can't be decoded.
+    pub protected: bool,
+    pub representations: Vec<Representation>,
+}
+
+pub struct Representation {
+    pub bandwidth: u64,
+    pub init: Option<Resource>,
+    pub segments: Vec<Segment>,
+}
+
+/// A URL, optionally limited to the byte range `start..end`.
+#[derive(Clone)]
+pub struct Resource {
+    pub url: Url,
+    pub range: Option<(usize, usize)>,
+}
+
+pub struct Segment {
+    pub resource: Resource,
+    /// Presentation time in seconds, with the period start already added.
+    pub start: f64,
+    pub duration: f64,
+}
+
+/// Attributes of `<SegmentTemplate>`, merged from the period down to the representation.
+#[derive(Clone, Default)]
+struct Template {
+    media: Option<String>,
+    initialization: Option<String>,
+    timescale: Option<u64>,
+    duration: Option<u64>,
+    start_number: Option<u64>,
+    presentation_time_offset: Option<u64>,
+    timeline: Option<Vec<TimelineEntry>>,
+}
+
+#[derive(Clone)]
+struct TimelineEntry {
+    time: Option<u64>,
+    duration: u64,
+    repeat: i64,
+}
+
+pub fn is_mpd(text: &str) -> bool {
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    text.starts_with('<') && text.contains("<MPD")
+}
+
+pub fn parse_mpd(text: &str, manifest_url: &Url) -> anyhow::Result<Manifest> {
+    let document = Document::parse(text).context("Failed to parse DASH manifest")?;
+    let mpd = document.root_element();
+    if mpd.tag_name().name() != "MPD" {
+        return Err(anyhow!("Not a DASH manifest"));
+    }
+    if mpd.attribute("type") == Some("dynamic") {
+        return Err(rejected(
+            "unsupported_stream",
+            "Live DASH manifests are not supported",
+        ));
+    }
+    let total_duration = mpd
+        .attribute("mediaPresentationDuration")
+        .and_then(parse_duration);
+    let mpd_base = base_url(mpd, manifest_url)?;
+
+    let period_nodes: Vec<Node> = children(mpd, "Period").collect();
+    let mut periods = Vec::with_capacity(period_nodes.len());
+    let mut next_start = 0.0;
+    for (index, period) in period_nodes.iter().enumerate() {
+        let start = period
+            .attribute("start")
+            .and_then(parse_duration)
+            .unwrap_or(next_start);
+        let end = period
+            .attribute("duration")
+            .and_then(parse_duration)
+            .map(|duration| start + duration)
+            .or_else(|| {
+                let next = period_nodes.get(index + 1)?;
+                next.attribute("start").and_then(parse_duration)
+            })
+            .or(total_duration);
+        next_start = end.unwrap_or(start);
+
+        let period_base = base_url(*period, &mpd_base)?;
+        let mut adaptation_sets = Vec::new();
+        for set in children(*period, "AdaptationSet") {
+            adaptation_sets.push(parse_adaptation_set(
+                *period,
+                set,
+                &period_base,
+                start,
+                end,
+            )?);
+        }
+        periods.push(Period {
+            start,
+            adaptation_sets,
+        });
+    }
+
+    Ok(Manifest { periods })
+}
+
+fn parse_adaptation_set(
+    period: Node,
+    set: Node,
+    period_base: &Url,
+    start: f64,
+    end: Option<f64>,
+) -> anyhow::Result<AdaptationSet> {
+    let set_base = base_url(set, period_base)?;
+    let is_audio_mime = |node: Node| {
+        node.attribute("mimeType")
+            .is_some_and(|mime| mime.starts_with("audio/"))
+    };
+    let is_audio = set.attribute("contentType") == Some("audio")
+        || is_audio_mime(set)
+        || children(set, "Representation").any(is_audio_mime)
+        || children(set, "ContentComponent")
+            .any(|component| component.attribute("contentType") == Some("audio"));
+
+    let mut representations = Vec::new();
+    for representation in children(set, "Representation") {
+        let base = base_url(representation, &set_base)?;
+        let id = representation.attribute("id").unwrap_or_default();
+        let bandwidth = representation
+            .attribute("bandwidth")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+
+        let mut template: Option<Template> = None;
+        for node in [period, set, representation] {
+            if let Some(element) = children(node, "SegmentTemplate").next() {
+                template = Some(merge_template(template.unwrap_or_default(), element));
+            }
+        }
+        let segment_list = children(representation, "SegmentList")
+            .next()
+            .or_else(|| children(set, "SegmentList").next());
+
+        let (init, segments) = if let Some(template) = template {
+            expand_template(&template, &base, id, bandwidth, start, end)?
+        } else if let Some(list) = segment_list {
+            expand_list(list, &base, start)?
+        } else {
+            // SegmentBase or a bare BaseURL: the whole file, init data included, is one segment.
+            let duration = end.map_or(f64::MAX, |end| end - start);
+            let segment = Segment {
+                resource: Resource {
+                    url: base,
+                    range: None,
+                },
+                start,
+                duration,
+            };
+            (None, vec![segment])
+        };
+        representations.push(Representation {
+            bandwidth,
+            init,
+            segments,
+        });
+    }
+
+    Ok(AdaptationSet {
+        lang: set.attribute("lang").map(str::to_string),
+        label: children(set, "Label")
+            .next()
+            .and_then(|label| label.text())
+            .map(|text| text.trim().to_string()),
+        is_audio,
+        protected: children(set, "ContentProtection").next().is_some()
+            || children(set, "Representation")
+                .any(|rep| children(rep, "ContentProtection").next().is_some()),
+        representations,
+    })
+}
+
+fn merge_template(mut template: Template, element: Node) -> Template {
+    let number = |name: &str| element.attribute(name).and_then(|value| value.parse().ok());
+    if let Some(media) = element.attribute("media") {
+        template.media = Some(media.to_string());
+    }
+    if let Some(initialization) = element.attribute("initialization") {
+        template.initialization = Some(initialization.to_string());
+    }
+    template.timescale = number("timescale").or(template.timescale);
+    template.duration = number("duration").or(template.duration);
+    template.start_number = number("startNumber").or(template.start_number);
+    template.presentation_time_offset =
+        number("presentationTimeOffset").or(template.presentation_time_offset);
+    if let Some(timeline) = children(element, "SegmentTimeline").next() {
+        let entries = children(timeline, "S")
+            .map(|entry| TimelineEntry {
+                time: entry.attribute("t").and_then(|value| value.parse().ok()),
+                duration: entry
+                    .attribute("d")
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(0),
+                repeat: entry
+                    .attribute("r")
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(0),
+            })
+            .collect();
+        template.timeline = Some(entries);
+    }
+    template
+}
+
+fn expand_template(
+    template: &Template,
+    base: &Url,
+    id: &str,
+    bandwidth: u64,
+    period_start: f64,
+    period_end: Option<f64>,
+) -> anyhow::Result<(Option<Resource>, Vec<Segment>)> {
+    let media = template
+        .media
+        .as_deref()
+        .ok_or_else(|| anyhow!("SegmentTemplate without a media attribute"))?;
+    let timescale = template.timescale.unwrap_or(1).max(1);
+    let start_number = template.start_number.unwrap_or(1);
+    let offset = template.presentation_time_offset.unwrap_or(0);
+    let seconds = |ticks: u64| period_start + (ticks as f64 - offset as f64) / timescale as f64;
+    let end_ticks = period_end.map(|end| offset + ((end - period_start) * timescale as f64) as u64);
+
+    let init = match &template.initialization {
+        Some(initialization) => {
+            let url = base
+                .join(&fill_template(initialization, id, bandwidth, 0, 0))
+                .context("Invalid URL")?;
+            Some(Resource { url, range: None })
+        }
+        None => None,
+    };
+
+    let mut segments = Vec::new();
+    let mut push = |number: u64, time: u64, duration: u64| -> anyhow::Result<()> {
+        let url = base
+            .join(&fill_template(media, id, bandwidth, number, time))
+            .context("Invalid URL")?;
+        segments.push(Segment {
+            resource: Resource { url, range: None },
+            start: seconds(time),
+            duration: duration as f64 / timescale as f64,
+        });
+        Ok(())
+    };
+
+    if let Some(timeline) = &template.timeline {
+        let mut number = start_number;
+        let mut time = 0;
+        for (index, entry) in timeline.iter().enumerate() {
+            time = entry.time.unwrap_or(time);
+            if entry.duration == 0 {
+                continue;
+            }
+            // A negative repeat count runs until the next entry's start or the period end.
+            let repeats = if entry.repeat >= 0 {
+                entry.repeat as u64
+            } else {
+                let until = timeline
+                    .get(index + 1)
+                    .and_then(|next| next.time)
+                    .or(end_ticks)
+                    .unwrap_or(time);
+                until
+                    .saturating_sub(time)
+                    .div_ceil(entry.duration)
+                    .saturating_sub(1)
+            };
+            for _ in 0..=repeats {
+                push(number, time, entry.duration)?;
+                number += 1;
+                time += entry.duration;
+            }
+        }
+    } else {
+        let duration = template
+            .duration
+            .filter(|duration| *duration > 0)
+            .ok_or_else(|| anyhow!("SegmentTemplate without a duration or timeline"))?;
+        let end_ticks = end_ticks.ok_or_else(|| anyhow!("DASH period has no known duration"))?;
+        let count = end_ticks.saturating_sub(offset).div_ceil(duration);
+        for index in 0..count {
+            push(start_number + index, offset + index * duration, duration)?;
+        }
+    }
+
+    Ok((init, segments))
+}
+
+fn expand_list(
+    list: Node,
+    base: &Url,
+    period_start: f64,
+) -> anyhow::Result<(Option<Resource>, Vec<Segment>)> {
+    let timescale = list
+        .attribute("timescale")
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(1)
+        .max(1);
+    let duration = list
+        .attribute("duration")
+        .and_then(|value| value.parse::<u64>().ok())
+        .ok_or_else(|| anyhow!("SegmentList without a duration"))?;
+    let duration = duration as f64 / timescale as f64;
+
+    let init = match children(list, "Initialization").next() {
+        Some(init) => Some(Resource {
+            url: match init.attribute("sourceURL") {
+                Some(source) => base.join(source).context("Invalid URL")?,
+                None => base.clone(),
+            },
+            range: init.attribute("range").and_then(parse_byte_range),
+        }),
+        None => None,
+    };
+
+    let mut segments = Vec::new();
+    for (index, segment) in children(list, "SegmentURL").enumerate() {
+        let url = match segment.attribute("media") {
+            Some(media) => base.join(media).context("Invalid URL")?,
+            None => base.clone(),
+        };
+        segments.push(Segment {
+            resource: Resource {
+                url,
+                range: segment.attribute("mediaRange").and_then(parse_byte_range),
+            },
+            start: period_start + index as f64 * duration,
+            duration,
+        });
+    }
+    Ok((init, segments))
+}
+
+/// Substitutes `$RepresentationID$`, `$Bandwidth$`, `$Number$` and `$Time$`, including the
+/// `%0Nd` width format (`$Number%05d$`), and unescapes `$$`.
+fn fill_template(template: &str, id: &str, bandwidth: u64, number: u64, time: u64) -> String {
+    let mut output = String::with_capacity(template.len());
+    // Identifiers sit at odd positions: "a$Number$b" splits into ["a", "Number", "b"].
+    for (index, part) in template.split('$').enumerate() {
+        if index % 2 == 0 {
+            output.push_str(part);
+            continue;
+        }
+        let (name, format) = part.split_once('%').unwrap_or((part, ""));
+        let value = match name {
+            "" => {
+                output.push('$');
+                continue;
+            }
+            "RepresentationID" => {
+                output.push_str(id);
+                continue;
+            }
+            "Bandwidth" => bandwidth,
+            "Number" => number,
+            "Time" => time,
+            _ => {
+                output.push('$');
+                output.push_str(part);
+                output.push('$');
+                continue;
+            }
+        };
+        let width = format
+            .strip_prefix('0')
+            .and_then(|format| format.strip_suffix('d'))
+            .and_then(|width| width.parse().ok())
+            .unwrap_or(0);
+        let _ = write!(output, "{value:0width$}");
+    }
+    output
+}
+
+/// The nearest `<BaseURL>` resolved against the parent's, or the parent's if there is none.
+fn base_url(node: Node, parent: &Url) -> anyhow::Result<Url> {
+    match children(node, "BaseURL")
+        .next()
+        .and_then(|base| base.text())
+    {
+        Some(text) => parent.join(text.trim()).context("Invalid BaseURL"),
+        None => Ok(parent.clone()),
+    }
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+/// Parses an inclusive `first-last` byte range into `start..end`.
+fn parse_byte_range(text: &str) -> Option<(usize, usize)> {
+    let (first, last) = text.trim().split_once('-')?;
+    let first: usize = first.parse().ok()?;
+    let last: usize = last.parse().ok()?;
+    (last >= first).then_some((first, last + 1))
+}
+
+/// Parses an `xs:duration` such as `PT23M40.5S` into seconds. Years and months only ever
+/// show up as zeros in manifests and are counted as 365 and 30 days.
+pub fn parse_duration(text: &str) -> Option<f64> {
+    let rest = text.trim().strip_prefix('P')?;
+    let (date, time) = rest.split_once('T').unwrap_or((rest, ""));
+    let mut seconds = 0.0;
+    for (part, units) in [(date, &DATE_UNITS[..]), (time, &TIME_UNITS[..])] {
+        let mut number = String::new();
+        for c in part.chars() {
+            if c.is_ascii_digit() || c == '.' {
+                number.push(c);
+                continue;
+            }
+            let (_, unit) = units.iter().find(|(name, _)| *name == c)?;
+            seconds += number.parse::<f64>().ok()? * unit;
+            number.clear();
+        }
+        if !number.is_empty() {
+            return None;
+        }
+    }
+    Some(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fill_template, parse_duration, parse_mpd};
+    use url::Url;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("PT23M40.5S"), Some(1420.5));
+        assert_eq!(parse_duration("P0Y0M0DT1H0M0.000S"), Some(3600.0));
+        assert_eq!(parse_duration("P1D"), Some(86400.0));
+        assert_eq!(parse_duration("PT5X"), None);
+        assert_eq!(parse_duration("23M"), None);
+    }
+
+    #[test]
+    fn fills_templates() {
+        assert_eq!(
+            fill_template("$RepresentationID$/seg-$Number%05d$.m4s", "a1", 0, 42, 0),
+            "a1/seg-00042.m4s"
+        );
+        assert_eq!(
+            fill_template("t$Time$-$Bandwidth$$$.m4s", "", 128000, 0, 9000),
+            "t9000-128000$.m4s"
+        );
+    }
+
+    #[test]
+    fn expands_segment_timelines() {
+        let mpd = r#"<?xml version="1.0"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT10S">
+  <Period>
+    <BaseURL>media/</BaseURL>
+    <AdaptationSet contentType="audio" lang="ja">
+      <SegmentTemplate timescale="1000" initialization="$RepresentationID$/init.mp4" media="$RepresentationID$/$Time$.m4s">
+        <SegmentTimeline><S t="0" d="4000" r="1"/><S d="2000"/></SegmentTimeline>
+      </SegmentTemplate>
+      <Representation id="aac" bandwidth="128000" mimeType="audio/mp4"/>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+        let base = Url::parse("https://example.com/ep/manifest.mpd").unwrap();
+        let manifest = parse_mpd(mpd, &base).unwrap();
+        let set = &manifest.periods[0].adaptation_sets[0];
+        assert!(set.is_audio);
+        assert_eq!(set.lang.as_deref(), Some("ja"));
+        let representation = &set.representations[0];
+        assert_eq!(
+            representation.init.as_ref().unwrap().url.as_str(),
+            "https://example.com/ep/media/aac/init.mp4"
+        );
+        let starts: Vec<f64> = representation
+            .segments
+            .iter()
+            .map(|segment| segment.start)
+            .collect();
+        assert_eq!(starts, [0.0, 4.0, 8.0]);
+        assert_eq!(
+            representation.segments[2].resource.url.as_str(),
+            "https://example.com/ep/media/aac/8000.m4s"
+        );
+    }
+}
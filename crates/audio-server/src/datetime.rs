@@ -0,0 +1,106 @@
+//! RFC 3339 date-times, as `EXT-X-PROGRAM-DATE-TIME` carries them, without a date library.
+
+/// Seconds since the Unix epoch of a date-time like `2024-05-01T12:00:00.250+09:00`. A missing
+/// zone, which some packagers write, means UTC.
+pub fn parse_rfc3339(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let field = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = value.get(range)?;
+        digits
+            .bytes()
+            .all(|byte| byte.is_ascii_digit())
+            .then(|| digits.parse().ok())?
+    };
+    let separators = [(4, b'-'), (7, b'-'), (13, b':'), (16, b':')];
+    if separators
+        .iter()
+        .any(|(position, separator)| value.as_bytes().get(*position) != Some(separator))
+        || !matches!(value.as_bytes().get(10), Some(b'T' | b't' | b' '))
+    {
+        return None;
+    }
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    let rest = value.get(19..)?;
+    let (fraction, zone) = match rest.strip_prefix('.') {
+        Some(rest) => {
+            let len = rest
+                .bytes()
+                .position(|byte| !byte.is_ascii_digit())
+                .unwrap_or(rest.len());
+            if len == 0 {
+                return None;
+            }
+            (
+                format!("0.{}", &rest[..len]).parse::<f64>().ok()?,
+                &rest[len..],
+            )
+        }
+        None => (0.0, rest),
+    };
+    let offset = match zone {
+        "" | "Z" | "z" => 0,
+        _ => {
+            let sign = match zone.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let (hours, minutes) = match zone.len() {
+                3 => (zone.get(1..3)?, "00"),
+                5 => (zone.get(1..3)?, zone.get(3..5)?),
+                6 if zone.as_bytes()[3] == b':' => (zone.get(1..3)?, zone.get(4..6)?),
+                _ => return None,
+            };
+            let (hours, minutes): (i64, i64) = (hours.parse().ok()?, minutes.parse().ok()?);
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+
+    let seconds =
+        days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    Some(seconds as f64 + fraction)
+}
+
+/// Days from 1970-01-01 to a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    // Days since the 1st of March, which puts the leap day at the end of the year.
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_rfc3339;
+
+    #[test]
+    fn parses_rfc3339() {
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:00Z"), Some(0.0));
+        assert_eq!(parse_rfc3339("2024-02-29T00:00:00Z"), Some(1_709_164_800.0));
+        assert_eq!(
+            parse_rfc3339("2024-05-01T12:00:00.250+09:00"),
+            Some(1_714_532_400.25)
+        );
+        assert_eq!(
+            parse_rfc3339("2024-05-01 03:00:00-0130"),
+            Some(1_714_537_800.0)
+        );
+        assert_eq!(parse_rfc3339("2024-05-01T12:00:00"), Some(1_714_564_800.0));
+        assert_eq!(parse_rfc3339("2024-13-01T00:00:00Z"), None);
+        assert_eq!(parse_rfc3339("2024-05-01T12:00:00.Z"), None);
+        assert_eq!(parse_rfc3339("yesterday"), None);
+    }
+}
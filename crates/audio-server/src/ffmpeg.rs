@@ -0,0 +1,119 @@
+//! Decoding through an external ffmpeg binary, for segments symphonia can't read, such as AC-3
+//! audio or containers it has no demuxer for. Built with the `ffmpeg` feature.
+
+use std::{
+    ffi::OsString,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, anyhow};
+
+/// `MANATAN_FFMPEG_PATH`, or `ffmpeg` from the `PATH`.
+fn binary() -> OsString {
+    std::env::var_os("MANATAN_FFMPEG_PATH")
+        .filter(|path| !path.is_empty())
+        .unwrap_or_else(|| "ffmpeg".into())
+}
+
+/// Decodes the first audio stream of `data` to interleaved 16-bit PCM, returning the samples,
+/// sample rate and channel count.
+pub fn decode(data: &[u8]) -> anyhow::Result<(Vec<i16>, u32, usize)> {
+    let mut child = Command::new(binary())
+        .args([
+            "-hide_banner",
+            "-loglevel",
+            "error",
+            "-i",
+            "pipe:0",
+            "-map",
+            "0:a:0",
+            "-c:a",
+            "pcm_s16le",
+            "-f",
+            "wav",
+            "-bitexact",
+            "pipe:1",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to start ffmpeg")?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("ffmpeg has no stdin"))?;
+    // Writing from another thread keeps a full output pipe from stalling both sides. ffmpeg may
+    // stop reading once it has what it needs, so a failed write isn't an error in itself.
+    let output = std::thread::scope(|scope| {
+        scope.spawn(move || {
+            let _ = stdin.write_all(data);
+        });
+        child.wait_with_output()
+    })
+    .context("ffmpeg failed")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ffmpeg exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    parse_wav(&output.stdout).ok_or_else(|| anyhow!("ffmpeg produced no audio"))
+}
+
+/// Samples, rate and channel count of a 16-bit PCM WAV file. Writing to a pipe, ffmpeg can't go
+/// back to fill in the sizes, so a data chunk without one runs to the end.
+fn parse_wav(data: &[u8]) -> Option<(Vec<i16>, u32, usize)> {
+    if data.get(0..4)? != b"RIFF" || data.get(8..12)? != b"WAVE" {
+        return None;
+    }
+    let mut format = None;
+    let mut position = 12;
+    while position + 8 <= data.len() {
+        let id = &data[position..position + 4];
+        let len = u32::from_le_bytes(data[position + 4..position + 8].try_into().ok()?) as usize;
+        let body = position + 8;
+        if id == b"data" {
+            let (rate, channels) = format?;
+            let end = match len {
+                0 | 0xffff_ffff => data.len(),
+                len => body.saturating_add(len).min(data.len()),
+            };
+            let samples = data[body..end]
+                .chunks_exact(2)
+                .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+                .collect();
+            return Some((samples, rate, channels));
+        }
+        if id == b"fmt " {
+            let fmt = data.get(body..body + 16)?;
+            let channels = u16::from_le_bytes([fmt[2], fmt[3]]) as usize;
+            let rate = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]);
+            let bits = u16::from_le_bytes([fmt[14], fmt[15]]);
+            if channels == 0 || rate == 0 || bits != 16 {
+                return None;
+            }
+            format = Some((rate, channels));
+        }
+        // Chunks are padded to an even length.
+        position = body.checked_add(len)?.checked_add(len & 1)?;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_wav;
+
+    #[test]
+    fn parses_piped_wav() {
+        let mut data = b"RIFF\xff\xff\xff\xffWAVE".to_vec();
+        data.extend_from_slice(b"fmt \x10\0\0\0\x01\0\x02\0\x80\xbb\0\0\0\xee\x02\0\x04\0\x10\0");
+        data.extend_from_slice(b"LIST\x03\0\0\0abc\0");
+        data.extend_from_slice(b"data\xff\xff\xff\xff\x01\0\xff\xff");
+        assert_eq!(parse_wav(&data), Some((vec![1, -1], 48000, 2)));
+        assert_eq!(parse_wav(b"RIFF\0\0\0\0WAVEdata\0\0\0\0"), None);
+    }
+}
@@ -1,18 +1,30 @@
-use std::{collections::HashMap, io::Cursor};
+use std::{
+    collections::HashMap,
+    io::{Cursor, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use std::convert::TryFrom;
 
+use aes::Aes128;
+use aes::cipher::{BlockDecrypt, KeyInit, generic_array::GenericArray};
 use anyhow::{anyhow, Context};
 use axum::{
-    extract::{Query, State},
+    body::Body,
+    extract::{Path as RoutePath, Query, State},
     http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
+    Json,
 };
 use bytes::Bytes;
-use hls_m3u8::{MasterPlaylist, MediaPlaylist};
-use hls_m3u8::tags::VariantStream;
-use hls_m3u8::types::{MediaType, ByteRange};
-use reqwest::Client;
-use serde::Deserialize;
+use futures_util::StreamExt;
+use hls_m3u8::{MasterPlaylist, MediaPlaylist, MediaSegment};
+use hls_m3u8::tags::{ExtXMedia, VariantStream};
+use hls_m3u8::types::{
+    ByteRange, DecryptionKey, EncryptionMethod, InitializationVector, KeyFormat, MediaType, PlaylistType,
+};
+use serde::{Deserialize, Serialize};
 use symphonia::core::audio::SampleBuffer;
 use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
 use symphonia::core::errors::Error as SymphoniaError;
@@ -20,22 +32,418 @@ use symphonia::core::formats::FormatOptions;
 use symphonia::core::io::MediaSourceStream;
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::mpsc;
 use tokio::task::spawn_blocking;
-use tracing::warn;
+use tracing::{info, warn};
+use utoipa::{IntoParams, ToSchema};
 use url::Url;
 
+use crate::anki;
+use crate::condense::{self, CondenseJob};
+use crate::dash;
+use crate::datetime;
+use crate::init_segments::InitSegmentCache;
+use crate::join::SegmentJoiner;
+use crate::metrics::Stage;
+use crate::opus;
+use crate::prefetch::{self, PrefetchJob};
+use crate::progressive::{self, Container};
+use crate::resample;
 use crate::state::AppState;
+use crate::stretch;
+use crate::vad;
+use crate::subtitles;
+use crate::upstream::Upstream;
+use crate::wav::{self, SampleFormat, WavStream};
+
+/// How far `snap` may move a boundary.
+const SNAP_WINDOW_SECONDS: f64 = 0.5;
+/// How far `extend_gap_ms` may move a boundary.
+const EXTEND_WINDOW_SECONDS: f64 = 10.0;
+/// Waveforms cover a wider window than clips, so the user can drag the boundaries outwards.
+const MAX_WAVEFORM_SECONDS: f64 = 120.0;
+const DEFAULT_WAVEFORM_POINTS: usize = 800;
+const MAX_WAVEFORM_POINTS: usize = 8000;
+/// Sample rate of preview clips, plenty to hear where speech starts and ends.
+const PREVIEW_SAMPLE_RATE: u32 = 16_000;
+/// The health check gives up on Suwayomi well before a gateway's own timeout.
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+/// SAMPLE-AES leaves the first 16 bytes of every audio frame unencrypted.
+const SAMPLE_AES_LEADER: usize = 16;
+const AES_BLOCK: usize = 16;
+/// Samples per AAC frame; passthrough clips can only be cut at these boundaries.
+pub(crate) const AAC_FRAME_SAMPLES: f64 = 1024.0;
+pub(crate) const ADTS_SAMPLE_RATES: [u32; 13] = [
+    96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+/// How much of the playlist response is read before deciding whether it's a media file.
+const SNIFF_LEN: usize = 64 * 1024;
+/// Audio frames of a progressive file closer than this are fetched in one request, along with
+/// the video in between.
+const MAX_RANGE_GAP: u64 = 512 * 1024;
+
+#[derive(Deserialize, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+pub struct AudioClipQuery {
+    pub animeId: Option<i64>,
+    pub episodeIndex: Option<i64>,
+    pub videoIndex: Option<i64>,
+    /// MP4 or Matroska file to clip instead of an episode, relative to the media root set with
+    /// `MANATAN_MEDIA_ROOT`. Replaces the three ids.
+    pub path: Option<String>,
+    /// Clip start in seconds.
+    #[serde(default)]
+    pub start: f64,
+    /// Clip end in seconds; clips are capped at 30 seconds unless `MANATAN_CLIP_MAX_SECONDS`
+    /// says otherwise.
+    #[serde(default)]
+    pub end: f64,
+    /// Clip start as an RFC 3339 wall-clock time such as `2024-05-01T21:00:05+09:00`, in place
+    /// of `start` and `end`, for HLS playlists with `EXT-X-PROGRAM-DATE-TIME` like DVR
+    /// recordings. Needs `end_at`.
+    pub start_at: Option<String>,
+    /// Clip end as an RFC 3339 wall-clock time, with `start_at`.
+    pub end_at: Option<String>,
+    /// Output encoding, `wav` unless given.
+    #[serde(default)]
+    pub format: ClipFormat,
+    /// Milliseconds of extra audio before `start`, for subtitle timings that clip the first mora.
+    #[serde(default)]
+    pub pad_start: u32,
+    /// Milliseconds of extra audio after `end`. Padding counts towards the duration cap.
+    #[serde(default)]
+    pub pad_end: u32,
+    /// Length in milliseconds of a linear fade-in and fade-out, to avoid clicks at hard cuts.
+    /// Ignored for `aac`, which isn't decoded.
+    #[serde(default)]
+    pub fade_ms: u32,
+    /// Language of the audio rendition to use, e.g. `ja` when a dub is also offered: an HLS
+    /// `EXT-X-MEDIA`, a DASH adaptation set or a Matroska track. Matches `ja-JP` as well.
+    pub audio_lang: Option<String>,
+    /// Name (HLS `NAME`, DASH label, Matroska track name) of the audio rendition to use,
+    /// compared case-insensitively.
+    pub audio_name: Option<String>,
+    /// `GROUP-ID` of the HLS audio rendition to use, as listed by `/renditions`. Ignored for
+    /// other sources.
+    pub audio_group: Option<String>,
+    /// Output channel count, `1` or `2`. Defaults to the first decoded segment's; segments
+    /// with a different layout are up- or downmixed to match. Ignored for `aac`.
+    pub channels: Option<u16>,
+    /// Output sample rate in Hz, from 8000 to 96000, e.g. `44100` for players that struggle
+    /// with the 22.05 kHz some sources use. Defaults to the source's. Ignored for `aac`.
+    pub sample_rate: Option<u32>,
+    /// Sample format of `wav` output: `s16` (the default), `s24` or `f32`, for editors that
+    /// work in 24-bit or floating point. Ignored for other formats.
+    #[serde(default)]
+    pub sample_format: SampleFormat,
+    /// Playback speed from `0.5` to `2.0`, changing the tempo but not the pitch. The range is
+    /// still given in source time. Ignored for `aac`.
+    pub speed: Option<f64>,
+    /// Moves `start` and `end` to the nearest speech boundaries, before padding. Ignored for
+    /// `aac`.
+    pub snap: Option<ClipSnap>,
+    /// Extends the range backwards and forwards to the nearest pauses of at least this many
+    /// milliseconds, up to 10 seconds each way and within the duration cap, so a clip taken
+    /// from a single word's timing holds the whole sentence. Applied before `snap`. Ignored
+    /// for `aac`.
+    pub extend_gap_ms: Option<u32>,
+    /// Crossfades audio that neighbouring segments both hold instead of cutting the repeat,
+    /// for streams whose segments overlap. Ignored for `aac`.
+    #[serde(default)]
+    pub crossfade_joins: bool,
+    /// Returns a quick, low-quality version for previewing a trim: 16 kHz mono decoded from
+    /// the first matching segment only, so it may end early. Overrides `channels` and
+    /// `sample_rate`. Ignored for `aac`.
+    #[serde(default)]
+    pub preview: bool,
+}
 
-const MAX_DURATION_SECONDS: f64 = 30.0;
-const MAX_SEGMENTS: usize = 128;
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipSnap {
+    /// Voice activity detection over a window of half a second around each boundary: a
+    /// boundary inside speech moves out to where it begins or ends, one in silence moves in to
+    /// the nearest speech, so words cut off by early or late subtitle timings come out whole.
+    Vad,
+}
 
-#[derive(Deserialize)]
-pub struct AudioClipQuery {
-    pub animeId: i64,
-    pub episodeIndex: i64,
-    pub videoIndex: i64,
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipFormat {
+    #[default]
+    Wav,
+    /// 128 kbps MP3, a fraction of the WAV size for Anki decks. Needs the `mp3` feature.
+    Mp3,
+    /// The source's own AAC frames as ADTS, cut at frame boundaries without re-encoding.
+    /// Only works for AAC/ADTS sources (MPEG-TS or packed audio segments).
+    Aac,
+}
+
+impl ClipFormat {
+    /// Rejects formats this build can't produce, before any audio is fetched.
+    fn check_available(self) -> anyhow::Result<()> {
+        if self == ClipFormat::Mp3 && !cfg!(feature = "mp3") {
+            return Err(rejected("unsupported_format", "MP3 output is not enabled in this build"));
+        }
+        Ok(())
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            ClipFormat::Wav => "audio/wav",
+            ClipFormat::Mp3 => "audio/mpeg",
+            ClipFormat::Aac => "audio/aac",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ClipFormat::Wav => "wav",
+            ClipFormat::Mp3 => "mp3",
+            ClipFormat::Aac => "aac",
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CondenseRequest {
+    pub animeId: Option<i64>,
+    pub episodeIndex: Option<i64>,
+    pub videoIndex: Option<i64>,
+    /// Local file under the media root, as for `/clip`.
+    pub path: Option<String>,
+    /// Dialogue ranges in seconds.
+    #[serde(default)]
+    pub ranges: Vec<TimeRange>,
+    /// SRT, WebVTT or ASS subtitle file whose cues are used as ranges, in addition to `ranges`.
+    pub subtitles: Option<String>,
+    /// `wav` or `mp3`; `aac` passthrough isn't available for condensed audio.
+    #[serde(default)]
+    pub format: ClipFormat,
+    /// Milliseconds added before and after every range. Ranges that then overlap are merged.
+    #[serde(default)]
+    pub pad_ms: u32,
+    /// Output channel count, as for `/clip`.
+    pub channels: Option<u16>,
+    pub audio_lang: Option<String>,
+    pub audio_name: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TimeRange {
+    pub start: f64,
+    pub end: f64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CondenseAccepted {
+    pub status: String,
+    /// Id to poll at `GET /condense/jobs/{id}`.
+    pub job_id: u64,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ClipToAnkiRequest {
+    /// The clip, with the same fields as the `/clip` query.
+    #[serde(flatten)]
+    pub clip: AudioClipQuery,
+    /// Name to store the clip under; the format's extension is added when missing. Defaults to
+    /// one built from the source and range.
+    pub filename: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ClipToAnkiResponse {
+    pub status: String,
+    /// Name Anki stored the file under, for a `[sound:...]` field.
+    pub filename: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WaveformQuery {
+    pub animeId: Option<i64>,
+    pub episodeIndex: Option<i64>,
+    pub videoIndex: Option<i64>,
+    /// Local file under the media root, as for `/clip`.
+    pub path: Option<String>,
+    /// Range start in seconds.
     pub start: f64,
+    /// Range end in seconds; ranges are capped at 120 seconds.
     pub end: f64,
+    /// Number of buckets, 800 unless given (at most 8000).
+    pub points: Option<usize>,
+    pub audio_lang: Option<String>,
+    pub audio_name: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WaveformResponse {
+    pub start: f64,
+    /// Seconds covered, which is less than requested when the episode ends early.
+    pub duration: f64,
+    pub sample_rate: u32,
+    /// Largest absolute amplitude per bucket, from 0 to 1, of the audio mixed down to mono.
+    pub peaks: Vec<f32>,
+    /// Root mean square amplitude per bucket, from 0 to 1.
+    pub rms: Vec<f32>,
+}
+
+/// Response of `GET /health`, sent with 503 while Suwayomi can't be reached.
+#[derive(Serialize, ToSchema)]
+pub struct HealthResponse {
+    /// `ok`, or `degraded` when Suwayomi can't be reached.
+    pub status: String,
+    pub version: String,
+    pub uptime_seconds: u64,
+    pub suwayomi: SuwayomiHealth,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SuwayomiHealth {
+    /// `MANATAN_SUWAYOMI_URL`.
+    pub url: String,
+    /// Whether Suwayomi answered at all, even with an error status.
+    pub reachable: bool,
+    /// Status of the answer, e.g. 401 when it wants credentials the request didn't carry.
+    pub status_code: Option<u16>,
+    pub latency_ms: Option<u64>,
+    /// Suwayomi's own version, when it reported one.
+    pub version: Option<String>,
+    /// Why the request failed, when it did.
+    pub error: Option<String>,
+}
+
+/// The part of Suwayomi's `/api/v1/settings/about/` response the health check reports.
+#[derive(Deserialize)]
+struct SuwayomiAbout {
+    version: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubtitlesQuery {
+    pub animeId: Option<i64>,
+    pub episodeIndex: Option<i64>,
+    /// Video whose subtitle tracks are used; the preferred one unless given.
+    pub videoIndex: Option<i64>,
+    /// Track language, e.g. `ja`, also matching `ja-JP`. The first track unless given.
+    pub lang: Option<String>,
+    /// SRT, WebVTT or ASS file to fetch instead of a Suwayomi track, such as one attached
+    /// from Jimaku. Replaces the ids.
+    pub url: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SubtitlesResponse {
+    /// Language of the Suwayomi track used; absent for `url`.
+    pub lang: Option<String>,
+    pub cues: Vec<subtitles::Cue>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RenditionsQuery {
+    pub animeId: Option<i64>,
+    pub episodeIndex: Option<i64>,
+    pub videoIndex: Option<i64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PrefetchQuery {
+    pub animeId: Option<i64>,
+    pub episodeIndex: Option<i64>,
+    pub videoIndex: Option<i64>,
+    /// Audio rendition, as for `/clip`. Clips are only served from the prefetched audio when
+    /// they ask for the same one.
+    pub audio_lang: Option<String>,
+    pub audio_name: Option<String>,
+    pub audio_group: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RenditionsResponse {
+    /// Audio `EXT-X-MEDIA` entries of the master playlist. Empty when the episode has no
+    /// master playlist, or its audio is only muxed into the video variants.
+    pub renditions: Vec<Rendition>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Rendition {
+    /// `GROUP-ID`, to pass as `audio_group`.
+    pub group_id: String,
+    /// `NAME`, to pass as `audio_name`.
+    pub name: String,
+    pub language: Option<String>,
+    pub is_default: bool,
+    pub autoselect: bool,
+    /// Whether `/clip` reads this rendition when none is requested.
+    pub selected: bool,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ClipByCueQuery {
+    pub animeId: Option<i64>,
+    pub episodeIndex: Option<i64>,
+    pub videoIndex: Option<i64>,
+    /// Local file to clip instead of an episode, as for `/clip`. Needs `url` for subtitles.
+    pub path: Option<String>,
+    /// Index of the cue in the sorted list returned by `/subtitles`.
+    pub cue: usize,
+    /// Neighbouring cues to include on each side.
+    #[serde(default)]
+    pub context: usize,
+    /// Subtitle track language, as for `/subtitles`.
+    pub lang: Option<String>,
+    /// Subtitle file to read the cues from instead of the episode's track.
+    pub url: Option<String>,
+    #[serde(default)]
+    pub format: ClipFormat,
+    #[serde(default)]
+    pub pad_start: u32,
+    #[serde(default)]
+    pub pad_end: u32,
+    #[serde(default)]
+    pub fade_ms: u32,
+    pub audio_lang: Option<String>,
+    pub audio_name: Option<String>,
+    pub audio_group: Option<String>,
+    pub channels: Option<u16>,
+    pub sample_rate: Option<u32>,
+    #[serde(default)]
+    pub sample_format: SampleFormat,
+    pub speed: Option<f64>,
+    pub snap: Option<ClipSnap>,
+    pub extend_gap_ms: Option<u32>,
+    #[serde(default)]
+    pub crossfade_joins: bool,
+    #[serde(default)]
+    pub preview: bool,
+}
+
+/// Where `/subtitles` reads a file from.
+enum SubtitleSource {
+    Episode { anime_id: i64, episode_index: i64, video_index: Option<i64>, lang: Option<String> },
+    Url(Url),
+}
+
+/// The parts of Suwayomi's video list used here.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SuwayomiVideo {
+    #[serde(default)]
+    preferred: bool,
+    #[serde(default)]
+    subtitle_tracks: Vec<SuwayomiSubtitleTrack>,
+}
+
+#[derive(Deserialize)]
+struct SuwayomiSubtitleTrack {
+    url: String,
+    lang: String,
 }
 
 #[derive(Clone)]
@@ -45,6 +453,14 @@ struct SegmentSelection {
     start_time: f64,
     map: Option<MapSelection>,
     encrypted: bool,
+    sample_aes: Option<SampleAesKey>,
+}
+
+/// Key location and IV of a `METHOD=SAMPLE-AES` segment.
+#[derive(Clone)]
+struct SampleAesKey {
+    url: Url,
+    iv: [u8; 16],
 }
 
 #[derive(Clone)]
@@ -59,12 +475,125 @@ struct ResolvedByteRange {
     end: usize,
 }
 
+/// A clip before encoding: decoded PCM, or ADTS frames copied from the source.
+enum ClipAudio {
+    Pcm(DecodedSamples),
+    Adts(Vec<u8>),
+}
+
+struct AdtsTrim {
+    data: Vec<u8>,
+    sample_rate: Option<u32>,
+    channels: Option<usize>,
+}
+
+/// Where and what to clip, after validation and padding.
+#[derive(Clone)]
+struct ClipRequest {
+    source: ClipSource,
+    /// Seconds since the Unix epoch rather than since the start of the stream.
+    wall_clock: bool,
+    start: f64,
+    duration: f64,
+    format: ClipFormat,
+    audio: AudioRendition,
+    channels: Option<usize>,
+    /// Blend overlapping segments instead of cutting; only possible when the clip is buffered.
+    crossfade_joins: bool,
+    /// Decode only the first matching segment.
+    preview: bool,
+}
+
+#[derive(Clone)]
+enum ClipSource {
+    /// An episode video served by Suwayomi.
+    Episode { anime_id: i64, episode_index: i64, video_index: i64 },
+    /// A file under the media root, already resolved and checked.
+    Local(PathBuf),
+}
+
+/// Requested audio rendition of a master playlist; empty means the default one.
+#[derive(Clone, Default)]
+struct AudioRendition {
+    lang: Option<String>,
+    name: Option<String>,
+    /// HLS `GROUP-ID`, which DASH and Matroska have no counterpart of.
+    group: Option<String>,
+}
+
+impl AudioRendition {
+    fn is_empty(&self) -> bool {
+        self.lang.is_none() && self.name.is_none() && self.group.is_none()
+    }
+
+    fn matches(&self, media: &ExtXMedia<'static>) -> bool {
+        let group_ok = self.group.as_deref().is_none_or(|wanted| *media.group_id() == *wanted.trim());
+        group_ok && self.matches_labels(media.language().map(AsRef::as_ref), Some(media.name()))
+    }
+
+    fn matches_labels(&self, lang: Option<&str>, name: Option<&str>) -> bool {
+        let lang_ok = self.lang.as_deref().is_none_or(|wanted| {
+            lang.is_some_and(|lang| language_matches(lang, wanted))
+        });
+        let name_ok = self
+            .name
+            .as_deref()
+            .is_none_or(|wanted| name.is_some_and(|name| name.eq_ignore_ascii_case(wanted.trim())));
+        lang_ok && name_ok
+    }
+}
+
+/// `ja` matches `ja` and `ja-JP`; a full tag like `pt-BR` has to match exactly.
+fn language_matches(lang: &str, wanted: &str) -> bool {
+    let lang = lang.trim().to_ascii_lowercase().replace('_', "-");
+    let wanted = wanted.trim().to_ascii_lowercase().replace('_', "-");
+    lang == wanted || lang.split('-').next() == Some(wanted.as_str())
+}
+
+/// A piece of the clip: a playlist segment still to fetch, or audio already read out of a
+/// progressive file (ADTS frames, or a local fragment with its init segment).
+enum ClipPart {
+    Segment(SegmentSelection),
+    Data { data: Vec<u8>, start_time: f64, hint_extension: &'static str },
+}
+
+impl ClipPart {
+    fn start_time(&self) -> f64 {
+        match self {
+            ClipPart::Segment(segment) => segment.start_time,
+            ClipPart::Data { start_time, .. } => *start_time,
+        }
+    }
+}
+
+/// What the playlist endpoint served.
+enum PlaylistResponse {
+    Text(String),
+    /// A single media file; only `head` was downloaded.
+    File(ProgressiveFile),
+}
+
+struct ProgressiveFile {
+    container: Container,
+    head: Vec<u8>,
+    len: u64,
+    /// Set for local files, which are read from disk instead of by range request.
+    path: Option<PathBuf>,
+}
+
 struct DecodedSamples {
     samples: Vec<i16>,
     sample_rate: u32,
     channels: usize,
 }
 
+/// Audio of one segment trimmed to the clip range, with the time its first frame plays at on
+/// the range's timeline.
+struct DecodedSegment {
+    audio: DecodedSamples,
+    start_time: f64,
+}
+
 struct PreparedAudio {
     data: Vec<u8>,
     hint_extension: Option<String>,
@@ -72,143 +601,2010 @@ struct PreparedAudio {
     force_segment_start: bool,
 }
 
-struct AdtsExtraction {
+/// The audio elementary stream of an MPEG-TS segment: ADTS frames for AAC, the payloads as
+/// they are otherwise.
+struct TsExtraction {
+    codec: TsAudioCodec,
     data: Vec<u8>,
     first_pts: Option<f64>,
     force_segment_start: bool,
 }
 
+/// Audio codecs picked out of an MPEG-TS program map table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TsAudioCodec {
+    Aac,
+    /// MPEG-1/2 audio, Layer II in older encodes.
+    Mpeg,
+    Ac3,
+    Eac3,
+    Opus { channels: u8 },
+}
+
+impl TsAudioCodec {
+    /// The codec of a PMT entry by its stream type, or for the private data streams DVB uses
+    /// by its descriptors.
+    fn from_stream(stream_type: u8, descriptors: &[u8]) -> Option<Self> {
+        match stream_type {
+            // 0xcf is ADTS AAC carrying SAMPLE-AES encrypted frames.
+            0x0f | 0x11 | 0xcf => Some(TsAudioCodec::Aac),
+            0x03 | 0x04 => Some(TsAudioCodec::Mpeg),
+            0x81 => Some(TsAudioCodec::Ac3),
+            0x87 => Some(TsAudioCodec::Eac3),
+            0x06 => {
+                let mut opus = false;
+                // Stereo unless the Opus extension descriptor says otherwise.
+                let mut channels = 2;
+                let mut i = 0;
+                while i + 2 <= descriptors.len() {
+                    let (tag, len) = (descriptors[i], descriptors[i + 1] as usize);
+                    let body = descriptors.get(i + 2..i + 2 + len).unwrap_or_default();
+                    match tag {
+                        0x6a => return Some(TsAudioCodec::Ac3),
+                        0x7a => return Some(TsAudioCodec::Eac3),
+                        // Registration descriptors.
+                        0x05 if body.starts_with(b"AC-3") => return Some(TsAudioCodec::Ac3),
+                        0x05 if body.starts_with(b"EAC3") => return Some(TsAudioCodec::Eac3),
+                        0x05 if body.starts_with(b"Opus") => opus = true,
+                        // The Opus extension descriptor, holding the channel configuration.
+                        0x7f => {
+                            if let [0x80, code @ 1..=8, ..] = body {
+                                channels = *code;
+                            }
+                        }
+                        _ => {}
+                    }
+                    i += 2 + len;
+                }
+                opus.then_some(TsAudioCodec::Opus { channels })
+            }
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            TsAudioCodec::Aac => "aac",
+            // symphonia reads all three layers the same way.
+            TsAudioCodec::Mpeg => "mp3",
+            TsAudioCodec::Ac3 => "ac3",
+            TsAudioCodec::Eac3 => "eac3",
+            // Rewrapped as Ogg Opus.
+            TsAudioCodec::Opus { .. } => "ogg",
+        }
+    }
+
+    /// symphonia has no AC-3 or Opus decoder, so those go to ffmpeg when it's built in.
+    fn decodable(self) -> bool {
+        match self {
+            TsAudioCodec::Aac | TsAudioCodec::Mpeg => true,
+            TsAudioCodec::Ac3 | TsAudioCodec::Eac3 | TsAudioCodec::Opus { .. } => cfg!(feature = "ffmpeg"),
+        }
+    }
+}
+
 struct PesPayload {
     pts: Option<u64>,
     data: Vec<u8>,
 }
 
-pub async fn clip_handler(
-    State(state): State<AppState>,
-    headers: HeaderMap,
-    Query(query): Query<AudioClipQuery>,
-) -> Response {
-    let AudioClipQuery { animeId, episodeIndex, videoIndex, start, end } = query;
-    if animeId < 0 || episodeIndex < 0 || videoIndex < 0 {
-        return (StatusCode::BAD_REQUEST, "Invalid ids").into_response();
-    }
-    if !start.is_finite() || !end.is_finite() {
-        return (StatusCode::BAD_REQUEST, "Invalid range").into_response();
+#[utoipa::path(
+    post,
+    path = "/clip",
+    tag = "audio",
+    params(AudioClipQuery),
+    responses(
+        (status = 200, description = "Audio for the requested range", content((Vec<u8> = "audio/wav"), (Vec<u8> = "audio/mpeg"), (Vec<u8> = "audio/aac"))),
+        (status = 400, description = "Invalid ids or range", body = String),
+        (status = 403, description = "Local media is disabled or the path leaves the media root", body = String),
+        (status = 404, description = "Local media file not found", body = String),
+        (status = 422, description = "The source can't be clipped; `code` says why", body = ClipError),
+        (status = 500, description = "Audio extraction failed", body = ClipError),
+        (status = 502, description = "Upstream request failed", body = ClipError),
+    )
+)]
+pub async fn clip_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AudioClipQuery>,
+) -> Response {
+    if let Err(err) = query.format.check_available() {
+        return clip_error_response(&err);
+    }
+    let prepared = match prepare_clip(&state, query).await {
+        Ok(prepared) => prepared,
+        Err((status, message)) => return (status, message).into_response(),
+    };
+    let format = prepared.request.format;
+    if let Some(bytes) = cached_clip(&state, &prepared.cache_key).await {
+        return clip_response(format, bytes);
+    }
+    // Snapping, time-stretching, resampling and crossfading joins need the whole clip, so those
+    // can't be streamed.
+    if format == ClipFormat::Wav
+        && prepared.speed == 1.0
+        && prepared.window.is_none()
+        && prepared.sample_rate.is_none()
+        && !prepared.request.crossfade_joins
+    {
+        let PreparedClip { request, fade_ms, sample_format, cache_key, .. } = prepared;
+        return stream_wav_clip(state, headers, request, fade_ms, sample_format, cache_key).await;
+    }
+    match encode_and_cache_clip(&state, &headers, prepared).await {
+        Ok(bytes) => clip_response(format, bytes),
+        Err(err) => clip_error_response(&err),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/clip/to-anki",
+    tag = "audio",
+    request_body = ClipToAnkiRequest,
+    responses(
+        (status = 200, description = "Clip stored in Anki's media folder", body = ClipToAnkiResponse),
+        (status = 400, description = "Invalid ids, range or filename", body = String),
+        (status = 403, description = "Local media is disabled or the path leaves the media root", body = String),
+        (status = 404, description = "Local media file not found", body = String),
+        (status = 422, description = "The source can't be clipped; `code` says why", body = ClipError),
+        (status = 500, description = "Audio extraction failed", body = ClipError),
+        (status = 502, description = "Upstream or AnkiConnect request failed", body = ClipError),
+    )
+)]
+pub async fn clip_to_anki_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<ClipToAnkiRequest>,
+) -> Response {
+    let ClipToAnkiRequest { clip, filename } = body;
+    let (start, end) = (clip.start, clip.end);
+    if let Err(err) = clip.format.check_available() {
+        return clip_error_response(&err);
+    }
+    let prepared = match prepare_clip(&state, clip).await {
+        Ok(prepared) => prepared,
+        Err((status, message)) => return (status, message).into_response(),
+    };
+    let format = prepared.request.format;
+    let filename = match filename.map(|name| name.trim().to_string()) {
+        Some(name) if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') => {
+            return (StatusCode::BAD_REQUEST, "Invalid filename").into_response();
+        }
+        Some(name) if name.to_ascii_lowercase().ends_with(&format!(".{}", format.extension())) => name,
+        Some(name) => format!("{name}.{}", format.extension()),
+        None => default_clip_filename(&prepared.request.source, start, end, format),
+    };
+
+    let bytes = match cached_clip(&state, &prepared.cache_key).await {
+        Some(bytes) => bytes,
+        None => match encode_and_cache_clip(&state, &headers, prepared).await {
+            Ok(bytes) => bytes,
+            Err(err) => return clip_error_response(&err),
+        },
+    };
+
+    match anki::store_media_file(state.upstream.client(), &state.anki_connect_url, &filename, &bytes).await {
+        Ok(filename) => {
+            info!("Stored audio clip {filename} in Anki");
+            Json(ClipToAnkiResponse { status: "ok".to_string(), filename }).into_response()
+        }
+        Err(err) => {
+            warn!("Failed to store audio clip in Anki: {err}");
+            let body = ClipError {
+                status: "error".to_string(),
+                code: "anki_unavailable".to_string(),
+                message: err.to_string(),
+            };
+            (StatusCode::BAD_GATEWAY, Json(body)).into_response()
+        }
+    }
+}
+
+/// `manatan_<anime>_<episode>_<start ms>-<end ms>.<ext>`, or the local file's stem in place
+/// of the ids.
+fn default_clip_filename(source: &ClipSource, start: f64, end: f64, format: ClipFormat) -> String {
+    let source = match source {
+        ClipSource::Episode { anime_id, episode_index, .. } => format!("{anime_id}_{episode_index}"),
+        ClipSource::Local(path) => path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().replace(|c: char| !c.is_alphanumeric() && c != '-', "_"))
+            .unwrap_or_default(),
+    };
+    let (start_ms, end_ms) = ((start.max(0.0) * 1000.0).round() as u64, (end.max(0.0) * 1000.0).round() as u64);
+    format!("manatan_{source}_{start_ms}-{end_ms}.{}", format.extension())
+}
+
+/// A validated clip request with the options applied after decoding.
+struct PreparedClip {
+    request: ClipRequest,
+    fade_ms: u32,
+    speed: f64,
+    sample_rate: Option<u32>,
+    sample_format: SampleFormat,
+    window: Option<ClipWindow>,
+    cache_key: String,
+}
+
+/// A requested range whose boundaries are settled once the audio around it is decoded, in
+/// seconds from the start of the decoded audio.
+struct ClipWindow {
+    start: f64,
+    end: f64,
+    pad_start: f64,
+    pad_end: f64,
+    snap: Option<ClipSnap>,
+    extend_gap: Option<f64>,
+    max_duration: f64,
+}
+
+impl ClipWindow {
+    fn apply(&self, decoded: &mut DecodedSamples) {
+        let channels = decoded.channels.max(1);
+        let speech = vad::speech_frames(&decoded.samples, channels, decoded.sample_rate);
+        let frame_seconds = vad::FRAME_MS as f64 / 1000.0;
+        let to_frame = |seconds: f64| (seconds / frame_seconds).round() as usize;
+        let (mut start, mut end) = (to_frame(self.start), to_frame(self.end));
+        if let Some(gap) = self.extend_gap {
+            (start, end) = vad::extend(&speech, start, end, to_frame(gap), to_frame(self.max_duration));
+        }
+        if self.snap == Some(ClipSnap::Vad) {
+            (start, end) = vad::snap(&speech, start, end, to_frame(SNAP_WINDOW_SECONDS));
+        }
+
+        let start = (start as f64 * frame_seconds - self.pad_start).max(0.0);
+        let end = (end as f64 * frame_seconds + self.pad_end).min(start + self.max_duration);
+        let to_sample = |seconds: f64| {
+            ((seconds * decoded.sample_rate as f64).round() as usize * channels).min(decoded.samples.len())
+        };
+        let (from, to) = (to_sample(start), to_sample(end));
+        decoded.samples = decoded.samples.get(from..to).map(<[i16]>::to_vec).unwrap_or_default();
+    }
+}
+
+async fn prepare_clip(state: &AppState, query: AudioClipQuery) -> Result<PreparedClip, (StatusCode, &'static str)> {
+    let AudioClipQuery {
+        animeId,
+        episodeIndex,
+        videoIndex,
+        path,
+        start,
+        end,
+        start_at,
+        end_at,
+        format,
+        pad_start,
+        pad_end,
+        fade_ms,
+        audio_lang,
+        audio_name,
+        audio_group,
+        channels,
+        sample_rate,
+        sample_format,
+        speed,
+        snap,
+        extend_gap_ms,
+        crossfade_joins,
+        preview,
+    } = query;
+    let source = resolve_source(state, path, animeId, episodeIndex, videoIndex).await?;
+    // Wall-clock ranges are carried as seconds since the Unix epoch all the way through and
+    // matched against the segments' EXT-X-PROGRAM-DATE-TIME.
+    let wall_clock = start_at.is_some() || end_at.is_some();
+    let (start, end) = match (start_at, end_at) {
+        (None, None) => (start, end),
+        (Some(start_at), Some(end_at)) => {
+            match (datetime::parse_rfc3339(&start_at), datetime::parse_rfc3339(&end_at)) {
+                (Some(start), Some(end)) => (start, end),
+                _ => return Err((StatusCode::BAD_REQUEST, "Invalid start_at or end_at")),
+            }
+        }
+        _ => return Err((StatusCode::BAD_REQUEST, "start_at and end_at must be given together")),
+    };
+    if wall_clock && matches!(source, ClipSource::Local(_)) {
+        return Err((StatusCode::BAD_REQUEST, "Wall-clock ranges need an HLS stream"));
+    }
+    if !start.is_finite() || !end.is_finite() {
+        return Err((StatusCode::BAD_REQUEST, "Invalid range"));
+    }
+    if end - start <= 0.0 {
+        return Err((StatusCode::BAD_REQUEST, "Invalid range"));
+    }
+    if channels.is_some_and(|channels| !(1..=2).contains(&channels)) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid channels"));
+    }
+    if sample_rate.is_some_and(|rate| !(8000..=96_000).contains(&rate)) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid sample_rate"));
+    }
+    let preview = preview && format != ClipFormat::Aac;
+    let (channels, sample_rate) = if preview { (Some(1), Some(PREVIEW_SAMPLE_RATE)) } else { (channels, sample_rate) };
+    let sample_rate = sample_rate.filter(|_| format != ClipFormat::Aac);
+    let sample_format = if format == ClipFormat::Wav { sample_format } else { SampleFormat::default() };
+    let crossfade_joins = crossfade_joins && format != ClipFormat::Aac;
+    let speed = speed.unwrap_or(1.0);
+    if !(0.5..=2.0).contains(&speed) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid speed"));
+    }
+    if extend_gap_ms.is_some_and(|gap| gap == 0) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid extend_gap_ms"));
+    }
+    let safe_start = (start - pad_start as f64 / 1000.0).max(0.0);
+    let safe_end = (end + pad_end as f64 / 1000.0).max(0.0);
+    let max_duration = state.clip_limits.max_duration;
+    let duration = (safe_end - safe_start).min(max_duration);
+    if duration <= 0.0 {
+        return Err((StatusCode::BAD_REQUEST, "Invalid range"));
+    }
+    // Snapping and extending decode the padded range widened by how far the boundaries may
+    // move, then cut the clip out of that.
+    let widen = match (snap, extend_gap_ms) {
+        _ if format == ClipFormat::Aac => None,
+        (_, Some(_)) => Some(EXTEND_WINDOW_SECONDS + SNAP_WINDOW_SECONDS),
+        (Some(_), None) => Some(SNAP_WINDOW_SECONDS),
+        (None, None) => None,
+    };
+    let (safe_start, duration, window) = match widen {
+        Some(widen) => {
+            let window_start = (safe_start - widen).max(0.0);
+            let window = ClipWindow {
+                start: start.max(0.0) - window_start,
+                end: end.max(0.0) - window_start,
+                pad_start: pad_start as f64 / 1000.0,
+                pad_end: pad_end as f64 / 1000.0,
+                snap,
+                extend_gap: extend_gap_ms.map(|gap| gap as f64 / 1000.0),
+                max_duration,
+            };
+            (window_start, safe_start + duration + widen - window_start, Some(window))
+        }
+        None => (safe_start, duration, None),
+    };
+
+    let audio = AudioRendition {
+        lang: audio_lang.filter(|lang| !lang.trim().is_empty()),
+        name: audio_name.filter(|name| !name.trim().is_empty()),
+        group: audio_group.filter(|group| !group.trim().is_empty()),
+    };
+    let source_key = match &source {
+        ClipSource::Episode { anime_id, episode_index, video_index } => {
+            format!("{anime_id}/{episode_index}/{video_index}")
+        }
+        // Size and modification time make a replaced file miss the cache.
+        ClipSource::Local(path) => {
+            let metadata = tokio::fs::metadata(path).await.ok();
+            let modified = metadata
+                .as_ref()
+                .and_then(|metadata| metadata.modified().ok())
+                .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                .map_or(0, |modified| modified.as_secs());
+            let len = metadata.map_or(0, |metadata| metadata.len());
+            format!("local/{}/{len}/{modified}", path.display())
+        }
+    };
+    // Padding is already folded into the range; other options change the output bytes.
+    let cache_key = format!(
+        "{source_key}/{}{safe_start:.3}/{duration:.3}/{format:?}/{fade_ms}/{speed}/{}/{}/{}/{}/{}/{sample_format:?}/{crossfade_joins}/{preview}/{}",
+        if wall_clock { "at" } else { "" },
+        audio.lang.as_deref().unwrap_or_default(),
+        audio.name.as_deref().unwrap_or_default(),
+        audio.group.as_deref().unwrap_or_default(),
+        channels.map(|channels| channels.to_string()).unwrap_or_default(),
+        sample_rate.map(|rate| rate.to_string()).unwrap_or_default(),
+        window
+            .as_ref()
+            .map(|window| format!("{:?}/{:?}", window.snap, window.extend_gap))
+            .unwrap_or_default(),
+    );
+    let request = ClipRequest {
+        source,
+        wall_clock,
+        start: safe_start,
+        duration,
+        format,
+        audio,
+        channels: channels.map(usize::from),
+        crossfade_joins,
+        preview,
+    };
+    Ok(PreparedClip { request, fade_ms, speed, sample_rate, sample_format, window, cache_key })
+}
+
+async fn cached_clip(state: &AppState, cache_key: &str) -> Option<Vec<u8>> {
+    let cache = state.clip_cache.clone();
+    let lookup_key = cache_key.to_string();
+    spawn_blocking(move || cache.get(&lookup_key)).await.ok().flatten()
+}
+
+/// Builds and encodes the whole clip, then caches it.
+async fn encode_and_cache_clip(
+    state: &AppState,
+    headers: &HeaderMap,
+    prepared: PreparedClip,
+) -> anyhow::Result<Vec<u8>> {
+    let PreparedClip { request, fade_ms, speed, sample_rate, sample_format, window, cache_key } = prepared;
+    let format = request.format;
+    let mut audio = build_audio_clip(state, headers, request, None).await?;
+    let cache = state.clip_cache.clone();
+    let metrics = state.metrics.clone();
+    spawn_blocking(move || {
+        let encode_started = Instant::now();
+        if let ClipAudio::Pcm(decoded) = &mut audio {
+            if let Some(window) = &window {
+                window.apply(decoded);
+            }
+            decoded.samples = stretch::time_stretch(&decoded.samples, decoded.channels, decoded.sample_rate, speed);
+            if let Some(rate) = sample_rate {
+                decoded.samples = resample::resample(&decoded.samples, decoded.channels, decoded.sample_rate, rate);
+                decoded.sample_rate = rate;
+            }
+        }
+        let bytes = encode_clip(audio, format, sample_format, fade_ms)?;
+        metrics.record(Stage::Encode, encode_started.elapsed());
+        cache.put(&cache_key, &bytes);
+        Ok(bytes)
+    })
+    .await
+    .map_err(|err| anyhow!("Audio encode task failed: {err}"))
+    .and_then(|result| result)
+}
+
+/// Sends the WAV header and samples as segments finish decoding instead of buffering the whole
+/// clip. Failures before the first chunk still produce an error response; later ones cut the
+/// stream short.
+async fn stream_wav_clip(
+    state: AppState,
+    headers: HeaderMap,
+    request: ClipRequest,
+    fade_ms: u32,
+    sample_format: SampleFormat,
+    cache_key: String,
+) -> Response {
+    let (body_tx, mut body_rx) = mpsc::channel::<anyhow::Result<Bytes>>(4);
+
+    tokio::spawn(async move {
+        let (pcm_tx, mut pcm_rx) = mpsc::channel::<DecodedSamples>(4);
+        let build_state = state.clone();
+        let build = tokio::spawn(async move {
+            build_audio_clip(&build_state, &headers, request, Some(&pcm_tx)).await
+        });
+
+        let caching = state.clip_cache.is_enabled();
+        let mut cached: Vec<u8> = Vec::new();
+        let mut stream = WavStream::new(fade_ms, sample_format);
+        while let Some(chunk) = pcm_rx.recv().await {
+            let bytes = stream.push(&chunk.samples, chunk.sample_rate, chunk.channels);
+            if caching {
+                cached.extend_from_slice(&bytes);
+            }
+            if body_tx.send(Ok(Bytes::from(bytes))).await.is_err() {
+                // The client went away; stop downloading segments for it.
+                build.abort();
+                return;
+            }
+        }
+
+        match build.await {
+            Ok(Ok(_)) => {
+                let tail = stream.finish();
+                if caching {
+                    cached.extend_from_slice(&tail);
+                    wav::finalize_sizes(&mut cached);
+                    let cache = state.clip_cache.clone();
+                    let _ = spawn_blocking(move || cache.put(&cache_key, &cached)).await;
+                }
+                let _ = body_tx.send(Ok(Bytes::from(tail))).await;
+            }
+            Ok(Err(err)) => {
+                let _ = body_tx.send(Err(err)).await;
+            }
+            Err(err) => {
+                let _ = body_tx.send(Err(anyhow!("Audio clip task failed: {err}"))).await;
+            }
+        }
+    });
+
+    match body_rx.recv().await {
+        Some(Ok(first)) => {
+            let rest = futures_util::stream::unfold(body_rx, |mut rx| async move {
+                rx.recv().await.map(|item| (item, rx))
+            });
+            let body = futures_util::stream::once(async { Ok(first) })
+                .chain(rest)
+                .inspect(|item| {
+                    if let Err(err) = item {
+                        warn!("Audio clip stream failed: {err}");
+                    }
+                });
+            (
+                StatusCode::OK,
+                [(axum::http::header::CONTENT_TYPE, ClipFormat::Wav.content_type())],
+                Body::from_stream(body),
+            )
+                .into_response()
+        }
+        Some(Err(err)) => clip_error_response(&err),
+        None => clip_error_response(&anyhow!("Audio clip task ended without output")),
+    }
+}
+
+/// A source the clip can't be made from, reported as 422 with `code`.
+#[derive(Debug)]
+pub(crate) struct Rejected {
+    code: &'static str,
+    message: String,
+}
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+pub(crate) fn rejected(code: &'static str, message: impl Into<String>) -> anyhow::Error {
+    anyhow::Error::new(Rejected { code, message: message.into() })
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ClipError {
+    pub status: String,
+    /// `upstream_not_found` (404), `upstream_unavailable` (502), `unsupported_codec`,
+    /// `unsupported_container`, `unsupported_encryption`, `unsupported_stream`, `live_stream`,
+    /// `outside_live_window`, `no_audio` or `unsupported_format` (all 422), `clip_failed` (500), or `anki_unavailable` (502) from `/clip/to-anki`.
+    pub code: String,
+    pub message: String,
+}
+
+/// Status and code for a failed clip: rejected sources first, then what the upstream answered.
+fn classify_clip_error(err: &anyhow::Error) -> (StatusCode, &'static str) {
+    if let Some(rejected) = err.chain().find_map(|cause| cause.downcast_ref::<Rejected>()) {
+        return (StatusCode::UNPROCESSABLE_ENTITY, rejected.code);
+    }
+    match err.chain().find_map(|cause| cause.downcast_ref::<reqwest::Error>()) {
+        Some(upstream)
+            if upstream
+                .status()
+                .is_some_and(|status| status == StatusCode::NOT_FOUND || status == StatusCode::GONE) =>
+        {
+            (StatusCode::NOT_FOUND, "upstream_not_found")
+        }
+        Some(_) => (StatusCode::BAD_GATEWAY, "upstream_unavailable"),
+        None => (StatusCode::INTERNAL_SERVER_ERROR, "clip_failed"),
+    }
+}
+
+fn clip_error_response(err: &anyhow::Error) -> Response {
+    warn!("Audio clip failed: {err:#}");
+    let (status, code) = classify_clip_error(err);
+    let body = ClipError { status: "error".to_string(), code: code.to_string(), message: err.to_string() };
+    (status, Json(body)).into_response()
+}
+
+fn clip_response(format: ClipFormat, bytes: Vec<u8>) -> Response {
+    (
+        StatusCode::OK,
+        [(axum::http::header::CONTENT_TYPE, format.content_type())],
+        Bytes::from(bytes),
+    )
+        .into_response()
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PurgeResult {
+    pub removed_files: usize,
+    pub freed_bytes: u64,
+}
+
+#[utoipa::path(
+    delete,
+    path = "/clip/cache",
+    tag = "audio",
+    responses((status = 200, description = "Cached clips removed", body = PurgeResult))
+)]
+pub async fn purge_clip_cache_handler(State(state): State<AppState>) -> Response {
+    let cache = state.clip_cache.clone();
+    match spawn_blocking(move || cache.purge()).await {
+        Ok(purged) => Json(PurgeResult {
+            removed_files: purged.files,
+            freed_bytes: purged.bytes,
+        })
+        .into_response(),
+        Err(err) => {
+            warn!("Clip cache purge failed: {err}");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Clip cache purge failed").into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "audio",
+    responses((status = 200, description = "Clip timings and failure counts in the Prometheus text format", body = String, content_type = "text/plain"))
+)]
+pub async fn metrics_handler(State(state): State<AppState>) -> Response {
+    ([(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")], state.metrics.render()).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/condense",
+    tag = "audio",
+    request_body = CondenseRequest,
+    responses(
+        (status = 202, description = "Condensing started", body = CondenseAccepted),
+        (status = 400, description = "Invalid ids, ranges or format", body = String),
+        (status = 403, description = "Local media is disabled or the path leaves the media root", body = String),
+        (status = 404, description = "Local media file not found", body = String),
+        (status = 422, description = "MP3 was requested but this build can't encode it", body = String),
+    )
+)]
+pub async fn condense_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<CondenseRequest>,
+) -> Response {
+    let CondenseRequest {
+        animeId,
+        episodeIndex,
+        videoIndex,
+        path,
+        ranges,
+        subtitles,
+        format,
+        pad_ms,
+        channels,
+        audio_lang,
+        audio_name,
+    } = request;
+    let source = match resolve_source(&state, path, animeId, episodeIndex, videoIndex).await {
+        Ok(source) => source,
+        Err((status, message)) => return (status, message).into_response(),
+    };
+    if format == ClipFormat::Aac {
+        return (StatusCode::BAD_REQUEST, "Condensed audio can't use aac").into_response();
+    }
+    if let Err(err) = format.check_available() {
+        return (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()).into_response();
+    }
+    if channels.is_some_and(|channels| !(1..=2).contains(&channels)) {
+        return (StatusCode::BAD_REQUEST, "Invalid channels").into_response();
+    }
+
+    let mut raw_ranges: Vec<(f64, f64)> = ranges.iter().map(|range| (range.start, range.end)).collect();
+    if let Some(subtitles) = &subtitles {
+        raw_ranges.extend(subtitles::parse(subtitles).iter().map(|cue| (cue.start, cue.end)));
+    }
+    let ranges = condense::merge_ranges(&raw_ranges, pad_ms as f64 / 1000.0);
+    if ranges.is_empty() {
+        return (StatusCode::BAD_REQUEST, "No valid ranges").into_response();
+    }
+
+    let job = state.condense_jobs.create(&ranges);
+    info!("Condensing {} ranges ({:.0}s of audio) as job {}", job.ranges, job.duration, job.id);
+    let request = ClipRequest {
+        source,
+        wall_clock: false,
+        start: 0.0,
+        duration: 0.0,
+        format,
+        audio: AudioRendition {
+            lang: audio_lang.filter(|lang| !lang.trim().is_empty()),
+            name: audio_name.filter(|name| !name.trim().is_empty()),
+            group: None,
+        },
+        channels: channels.map(usize::from),
+        crossfade_joins: false,
+        preview: false,
+    };
+    tokio::spawn(run_condense_job(state.clone(), headers, job.id, request, ranges));
+    (
+        StatusCode::ACCEPTED,
+        Json(CondenseAccepted { status: "ok".to_string(), job_id: job.id }),
+    )
+        .into_response()
+}
+
+/// Decodes the ranges batch by batch, each batch one stretch of the episode, and writes the
+/// concatenated dialogue to the job's file.
+async fn run_condense_job(state: AppState, headers: HeaderMap, id: u64, template: ClipRequest, ranges: Vec<(f64, f64)>) {
+    let run_lock = state.condense_jobs.run_lock();
+    let _turn = run_lock.lock().await;
+    state.condense_jobs.set_running(id);
+
+    let result = condense_ranges(&state, &headers, id, template, &ranges).await;
+    match &result {
+        Ok(path) => info!("Condense job {id} wrote {}", path.display()),
+        Err(err) => warn!("Condense job {id} failed: {err}"),
+    }
+    state.condense_jobs.finish(id, result.map_err(|err| err.to_string()));
+}
+
+async fn condense_ranges(
+    state: &AppState,
+    headers: &HeaderMap,
+    id: u64,
+    template: ClipRequest,
+    ranges: &[(f64, f64)],
+) -> anyhow::Result<PathBuf> {
+    let format = template.format;
+    let mut samples: Vec<i16> = Vec::new();
+    let mut sample_rate: Option<u32> = None;
+    let mut channels = template.channels;
+
+    for (index, batch) in condense::batches(ranges).into_iter().enumerate() {
+        let (Some(first), Some(last)) = (batch.first(), batch.last()) else {
+            continue;
+        };
+        let request = ClipRequest {
+            start: first.0,
+            duration: last.1 - first.0,
+            format: ClipFormat::Wav,
+            channels,
+            ..template.clone()
+        };
+        let ClipAudio::Pcm(decoded) = build_audio_clip(state, headers, request, None).await? else {
+            return Err(anyhow!("Condensed audio needs decoded samples"));
+        };
+        if sample_rate.is_some_and(|rate| rate != decoded.sample_rate) {
+            return Err(anyhow!("Mismatched sample rates across the episode"));
+        }
+        sample_rate = Some(decoded.sample_rate);
+        channels = Some(decoded.channels);
+
+        // The decoded batch starts at the first range; cut each range out of it.
+        let rate = decoded.sample_rate as f64;
+        let frame_index = |time: f64| ((time - first.0) * rate).round().max(0.0) as usize * decoded.channels;
+        for (start, end) in batch {
+            let from = frame_index(*start).min(decoded.samples.len());
+            let to = frame_index(*end).min(decoded.samples.len());
+            samples.extend_from_slice(&decoded.samples[from..to]);
+        }
+        state.condense_jobs.set_progress(id, index + 1);
+    }
+
+    let (Some(sample_rate), Some(channels)) = (sample_rate, channels) else {
+        return Err(rejected("no_audio", "No audio decoded"));
+    };
+    let path = state.condense_jobs.file_path(id, format.extension());
+    let output = path.clone();
+    spawn_blocking(move || {
+        let bytes = encode_clip(ClipAudio::Pcm(DecodedSamples { samples, sample_rate, channels }), format, SampleFormat::default(), 0)?;
+        if let Some(dir) = output.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&output, bytes)?;
+        anyhow::Ok(())
+    })
+    .await
+    .map_err(|err| anyhow!("Audio encode task failed: {err}"))??;
+    Ok(path)
+}
+
+#[utoipa::path(
+    get,
+    path = "/condense/jobs/{id}",
+    tag = "audio",
+    params(("id" = u64, Path, description = "Job id returned by `POST /condense`")),
+    responses(
+        (status = 200, description = "Job status and progress", body = CondenseJob),
+        (status = 404, description = "Unknown or expired job", body = String),
+    )
+)]
+pub async fn condense_job_handler(State(state): State<AppState>, RoutePath(id): RoutePath<u64>) -> Response {
+    match state.condense_jobs.get(id) {
+        Some(job) => Json(job).into_response(),
+        None => (StatusCode::NOT_FOUND, "Condense job not found").into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/condense/jobs/{id}/file",
+    tag = "audio",
+    params(("id" = u64, Path, description = "Job id returned by `POST /condense`")),
+    responses(
+        (status = 200, description = "The condensed audio", content((Vec<u8> = "audio/wav"), (Vec<u8> = "audio/mpeg"))),
+        (status = 404, description = "Unknown, expired or unfinished job", body = String),
+    )
+)]
+pub async fn condense_file_handler(State(state): State<AppState>, RoutePath(id): RoutePath<u64>) -> Response {
+    let Some(file) = state.condense_jobs.get(id).and_then(|job| job.file) else {
+        return (StatusCode::NOT_FOUND, "Condensed audio not available").into_response();
+    };
+    let format = match file.extension().and_then(|extension| extension.to_str()) {
+        Some("mp3") => ClipFormat::Mp3,
+        _ => ClipFormat::Wav,
+    };
+    match tokio::fs::read(&file).await {
+        Ok(bytes) => (
+            StatusCode::OK,
+            [
+                (axum::http::header::CONTENT_TYPE, format.content_type().to_string()),
+                (
+                    axum::http::header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"condensed-{id}.{}\"", format.extension()),
+                ),
+            ],
+            Bytes::from(bytes),
+        )
+            .into_response(),
+        Err(err) => {
+            warn!("Failed to read condensed audio {}: {err}", file.display());
+            (StatusCode::NOT_FOUND, "Condensed audio not available").into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/prefetch",
+    tag = "audio",
+    params(PrefetchQuery),
+    responses(
+        (status = 202, description = "Prefetching started", body = PrefetchJob),
+        (status = 200, description = "The episode is already prefetched or being prefetched", body = PrefetchJob),
+        (status = 400, description = "Invalid ids", body = String),
+    )
+)]
+pub async fn prefetch_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<PrefetchQuery>,
+) -> Response {
+    let Some(request) = prefetch_request(query) else {
+        return (StatusCode::BAD_REQUEST, "Invalid ids").into_response();
+    };
+    let Some(key) = playlist_cache_key(&request) else {
+        return (StatusCode::BAD_REQUEST, "Invalid ids").into_response();
+    };
+    let (job, created) = state.prefetches.start(&key);
+    if !created {
+        return Json(job).into_response();
+    }
+    info!("Prefetching {key} as job {}", job.id);
+    tokio::spawn(run_prefetch_job(state.clone(), headers, key, request));
+    (StatusCode::ACCEPTED, Json(job)).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/prefetch",
+    tag = "audio",
+    params(PrefetchQuery),
+    responses(
+        (status = 200, description = "Prefetch status and progress", body = PrefetchJob),
+        (status = 400, description = "Invalid ids", body = String),
+        (status = 404, description = "The episode isn't prefetched", body = String),
+    )
+)]
+pub async fn prefetch_status_handler(State(state): State<AppState>, Query(query): Query<PrefetchQuery>) -> Response {
+    let Some(key) = prefetch_request(query).as_ref().and_then(playlist_cache_key) else {
+        return (StatusCode::BAD_REQUEST, "Invalid ids").into_response();
+    };
+    match state.prefetches.get(&key) {
+        Some(job) => Json(job).into_response(),
+        None => (StatusCode::NOT_FOUND, "Episode not prefetched").into_response(),
+    }
+}
+
+/// The first chunk of the episode a prefetch query names, to be moved along minute by minute.
+fn prefetch_request(query: PrefetchQuery) -> Option<ClipRequest> {
+    let PrefetchQuery { animeId, episodeIndex, videoIndex, audio_lang, audio_name, audio_group } = query;
+    let (anime_id, episode_index, video_index) = match (animeId, episodeIndex, videoIndex) {
+        (Some(anime_id), Some(episode_index), Some(video_index))
+            if anime_id >= 0 && episode_index >= 0 && video_index >= 0 =>
+        {
+            (anime_id, episode_index, video_index)
+        }
+        _ => return None,
+    };
+    Some(ClipRequest {
+        source: ClipSource::Episode { anime_id, episode_index, video_index },
+        wall_clock: false,
+        start: 0.0,
+        duration: prefetch::CHUNK_SECONDS,
+        format: ClipFormat::Wav,
+        audio: AudioRendition {
+            lang: audio_lang.filter(|lang| !lang.trim().is_empty()),
+            name: audio_name.filter(|name| !name.trim().is_empty()),
+            group: audio_group.filter(|group| !group.trim().is_empty()),
+        },
+        channels: None,
+        crossfade_joins: false,
+        preview: false,
+    })
+}
+
+async fn run_prefetch_job(state: AppState, headers: HeaderMap, key: String, template: ClipRequest) {
+    let run_lock = state.prefetches.run_lock();
+    let _turn = run_lock.lock().await;
+    state.prefetches.set_running(&key);
+
+    let result = prefetch_episode(&state, &headers, &key, template).await;
+    match &result {
+        Ok(chunks) => info!("Prefetched {chunks} minutes of {key}"),
+        Err(err) => warn!("Prefetching {key} failed: {err}"),
+    }
+    state.prefetches.finish(&key, result.map(|_| ()).map_err(|err| err.to_string()));
+}
+
+/// Decodes the episode a minute at a time until its segments run out, writing each minute as
+/// soon as it's done so clips can use it while the rest is still fetched.
+async fn prefetch_episode(state: &AppState, headers: &HeaderMap, key: &str, template: ClipRequest) -> anyhow::Result<usize> {
+    let mut channels = None;
+    for index in 0..prefetch::MAX_CHUNKS {
+        let request = ClipRequest { start: index as f64 * prefetch::CHUNK_SECONDS, channels, ..template.clone() };
+        let decoded = match build_audio_clip(state, headers, request, None).await {
+            Ok(ClipAudio::Pcm(decoded)) => decoded,
+            Ok(ClipAudio::Adts(_)) => return Err(anyhow!("Prefetched audio needs decoded samples")),
+            // Past the last segment.
+            Err(err) if index > 0 && classify_clip_error(&err).1 == "no_audio" => return Ok(index),
+            Err(err) => return Err(err),
+        };
+        channels = Some(decoded.channels);
+        let full_chunk = (prefetch::CHUNK_SECONDS * decoded.sample_rate as f64).round() as usize * decoded.channels;
+        let last = decoded.samples.len() < full_chunk;
+
+        let prefetches = state.prefetches.clone();
+        let key = key.to_string();
+        spawn_blocking(move || prefetches.write_chunk(&key, index, &decoded.samples, decoded.sample_rate, decoded.channels))
+            .await
+            .map_err(|err| anyhow!("Prefetch write task failed: {err}"))??;
+        if last {
+            return Ok(index + 1);
+        }
+    }
+    Ok(prefetch::MAX_CHUNKS)
+}
+
+#[utoipa::path(
+    get,
+    path = "/waveform",
+    tag = "audio",
+    params(WaveformQuery),
+    responses(
+        (status = 200, description = "Peak and RMS amplitudes across the range", body = WaveformResponse),
+        (status = 400, description = "Invalid ids, range or point count", body = String),
+        (status = 403, description = "Local media is disabled or the path leaves the media root", body = String),
+        (status = 404, description = "Local media file not found", body = String),
+        (status = 422, description = "The source can't be clipped; `code` says why", body = ClipError),
+        (status = 500, description = "Audio extraction failed", body = ClipError),
+        (status = 502, description = "Upstream request failed", body = ClipError),
+    )
+)]
+pub async fn waveform_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<WaveformQuery>,
+) -> Response {
+    let WaveformQuery { animeId, episodeIndex, videoIndex, path, start, end, points, audio_lang, audio_name } = query;
+    let source = match resolve_source(&state, path, animeId, episodeIndex, videoIndex).await {
+        Ok(source) => source,
+        Err((status, message)) => return (status, message).into_response(),
+    };
+    if !start.is_finite() || !end.is_finite() || end <= start {
+        return (StatusCode::BAD_REQUEST, "Invalid range").into_response();
+    }
+    let points = points.unwrap_or(DEFAULT_WAVEFORM_POINTS);
+    if !(1..=MAX_WAVEFORM_POINTS).contains(&points) {
+        return (StatusCode::BAD_REQUEST, "Invalid points").into_response();
+    }
+    let start = start.max(0.0);
+    let duration = (end - start).min(MAX_WAVEFORM_SECONDS);
+
+    let request = ClipRequest {
+        source,
+        wall_clock: false,
+        start,
+        duration,
+        format: ClipFormat::Wav,
+        audio: AudioRendition {
+            lang: audio_lang.filter(|lang| !lang.trim().is_empty()),
+            name: audio_name.filter(|name| !name.trim().is_empty()),
+            group: None,
+        },
+        channels: Some(1),
+        crossfade_joins: false,
+        preview: false,
+    };
+    let decoded = match build_audio_clip(&state, &headers, request, None).await {
+        Ok(ClipAudio::Pcm(decoded)) => decoded,
+        Ok(ClipAudio::Adts(_)) => return clip_error_response(&anyhow!("Waveform needs decoded samples")),
+        Err(err) => return clip_error_response(&err),
+    };
+    let (peaks, rms) = waveform(&decoded.samples, points);
+    Json(WaveformResponse {
+        start,
+        duration: decoded.samples.len() as f64 / decoded.sample_rate.max(1) as f64,
+        sample_rate: decoded.sample_rate,
+        peaks,
+        rms,
+    })
+    .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "audio",
+    responses(
+        (status = 200, description = "Suwayomi is reachable", body = HealthResponse),
+        (status = 503, description = "Suwayomi can't be reached", body = HealthResponse),
+    )
+)]
+pub async fn health_handler(State(state): State<AppState>, headers: HeaderMap) -> (StatusCode, Json<HealthResponse>) {
+    let url = &state.suwayomi_base_url;
+    let mut suwayomi = SuwayomiHealth {
+        url: url.clone(),
+        reachable: false,
+        status_code: None,
+        latency_ms: None,
+        version: None,
+        error: None,
+    };
+    let about_url = match Url::parse(url).and_then(|base| base.join("/api/v1/settings/about/")) {
+        Ok(about_url) => Some(about_url),
+        Err(err) => {
+            suwayomi.error = Some(format!("Invalid URL: {err}"));
+            None
+        }
+    };
+    if let Some(about_url) = about_url {
+        let started = Instant::now();
+        // One quick attempt: the point is to tell whether clips can work right now.
+        match state.upstream.get(about_url, &headers).timeout(HEALTH_TIMEOUT).send().await {
+            Ok(response) => {
+                suwayomi.reachable = true;
+                suwayomi.latency_ms = Some(started.elapsed().as_millis() as u64);
+                suwayomi.status_code = Some(response.status().as_u16());
+                if response.status().is_success() {
+                    suwayomi.version = response.json::<SuwayomiAbout>().await.ok().and_then(|about| about.version);
+                }
+            }
+            Err(err) => suwayomi.error = Some(err.to_string()),
+        }
+    }
+
+    let (status, label) = if suwayomi.reachable {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "degraded")
+    };
+    let health = HealthResponse {
+        status: label.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_seconds: state.started_at.elapsed().as_secs(),
+        suwayomi,
+    };
+    (status, Json(health))
+}
+
+#[utoipa::path(
+    get,
+    path = "/subtitles",
+    tag = "audio",
+    params(SubtitlesQuery),
+    responses(
+        (status = 200, description = "Parsed cues, sorted by start time", body = SubtitlesResponse),
+        (status = 400, description = "Invalid ids or URL", body = String),
+        (status = 404, description = "No matching subtitle track", body = String),
+        (status = 502, description = "Subtitle file could not be fetched", body = String),
+    )
+)]
+pub async fn subtitles_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<SubtitlesQuery>,
+) -> Response {
+    let SubtitlesQuery { animeId, episodeIndex, videoIndex, lang, url } = query;
+    let source = match subtitle_source(animeId, episodeIndex, videoIndex, lang, url) {
+        Ok(source) => source,
+        Err((status, message)) => return (status, message).into_response(),
+    };
+    match load_subtitles(&state, &headers, source).await {
+        Ok((lang, cues)) => Json(SubtitlesResponse { lang, cues }).into_response(),
+        Err((status, message)) => (status, message).into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/renditions",
+    tag = "audio",
+    params(RenditionsQuery),
+    responses(
+        (status = 200, description = "Audio renditions of the episode's master playlist", body = RenditionsResponse),
+        (status = 400, description = "Invalid ids", body = String),
+        (status = 500, description = "The playlist could not be parsed", body = ClipError),
+        (status = 502, description = "Upstream request failed", body = ClipError),
+    )
+)]
+pub async fn renditions_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<RenditionsQuery>,
+) -> Response {
+    let RenditionsQuery { animeId, episodeIndex, videoIndex } = query;
+    let (anime_id, episode_index, video_index) = match (animeId, episodeIndex, videoIndex) {
+        (Some(anime_id), Some(episode_index), Some(video_index))
+            if anime_id >= 0 && episode_index >= 0 && video_index >= 0 =>
+        {
+            (anime_id, episode_index, video_index)
+        }
+        _ => return (StatusCode::BAD_REQUEST, "Invalid ids").into_response(),
+    };
+    match list_renditions(&state, &headers, anime_id, episode_index, video_index).await {
+        Ok(renditions) => Json(RenditionsResponse { renditions }).into_response(),
+        Err(err) => clip_error_response(&err),
+    }
+}
+
+async fn list_renditions(
+    state: &AppState,
+    headers: &HeaderMap,
+    anime_id: i64,
+    episode_index: i64,
+    video_index: i64,
+) -> anyhow::Result<Vec<Rendition>> {
+    let playlist_url = format!(
+        "{}/api/v1/anime/{anime_id}/episode/{episode_index}/video/{video_index}/playlist",
+        state.suwayomi_base_url
+    );
+    let playlist_url = Url::parse(&playlist_url).context("Invalid playlist URL")?;
+    let PlaylistResponse::Text(text) = fetch_playlist(&state.upstream, headers, &playlist_url).await? else {
+        return Ok(Vec::new());
+    };
+    if dash::is_mpd(&text) || MediaPlaylist::try_from(text.as_str()).is_ok() {
+        return Ok(Vec::new());
+    }
+    let master = MasterPlaylist::try_from(text.as_str())
+        .context("Failed to parse master playlist")?
+        .into_owned();
+    Ok(master_renditions(&master))
+}
+
+fn master_renditions(master: &MasterPlaylist<'static>) -> Vec<Rendition> {
+    let selected = select_audio_media(master, &AudioRendition::default());
+    master
+        .media
+        .iter()
+        .filter(|media| media.media_type == MediaType::Audio)
+        .map(|media| Rendition {
+            group_id: media.group_id().to_string(),
+            name: media.name().to_string(),
+            language: media.language().map(|language| language.to_string()),
+            is_default: media.is_default,
+            autoselect: media.is_autoselect,
+            selected: selected.is_some_and(|selected| std::ptr::eq(selected, media)),
+        })
+        .collect()
+}
+
+#[utoipa::path(
+    get,
+    path = "/clip/by-cue",
+    tag = "audio",
+    params(ClipByCueQuery),
+    responses(
+        (status = 200, description = "Audio covering the cue and its context, as for `/clip`", content((Vec<u8> = "audio/wav"), (Vec<u8> = "audio/mpeg"), (Vec<u8> = "audio/aac"))),
+        (status = 400, description = "Invalid ids, URL or range", body = String),
+        (status = 404, description = "No matching subtitle track, cue or local file", body = String),
+        (status = 502, description = "Subtitle file could not be fetched", body = String),
+        (status = 422, description = "The source can't be clipped; `code` says why", body = ClipError),
+        (status = 500, description = "Audio extraction failed", body = ClipError),
+        (status = 502, description = "Upstream request failed", body = ClipError),
+    )
+)]
+pub async fn clip_by_cue_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ClipByCueQuery>,
+) -> Response {
+    let ClipByCueQuery {
+        animeId,
+        episodeIndex,
+        videoIndex,
+        path,
+        cue,
+        context,
+        lang,
+        url,
+        format,
+        pad_start,
+        pad_end,
+        fade_ms,
+        audio_lang,
+        audio_name,
+        audio_group,
+        channels,
+        sample_rate,
+        sample_format,
+        speed,
+        snap,
+        extend_gap_ms,
+        crossfade_joins,
+        preview,
+    } = query;
+    let source = match subtitle_source(animeId, episodeIndex, videoIndex, lang, url) {
+        Ok(source) => source,
+        Err((status, message)) => return (status, message).into_response(),
+    };
+    let cues = match load_subtitles(&state, &headers, source).await {
+        Ok((_, cues)) => cues,
+        Err((status, message)) => return (status, message).into_response(),
+    };
+    if cue >= cues.len() {
+        return (StatusCode::NOT_FOUND, "Cue not found").into_response();
+    }
+    // Cues can overlap, so the range runs from the earliest start to the latest end.
+    let span = &cues[cue.saturating_sub(context)..=cue.saturating_add(context).min(cues.len() - 1)];
+    let start = span.iter().map(|cue| cue.start).fold(f64::INFINITY, f64::min);
+    let end = span.iter().map(|cue| cue.end).fold(f64::NEG_INFINITY, f64::max);
+    let query = AudioClipQuery {
+        animeId,
+        episodeIndex,
+        videoIndex,
+        path,
+        start,
+        end,
+        start_at: None,
+        end_at: None,
+        format,
+        pad_start,
+        pad_end,
+        fade_ms,
+        audio_lang,
+        audio_name,
+        audio_group,
+        channels,
+        sample_rate,
+        sample_format,
+        speed,
+        snap,
+        extend_gap_ms,
+        crossfade_joins,
+        preview,
+    };
+    clip_handler(State(state), headers, Query(query)).await
+}
+
+fn subtitle_source(
+    anime_id: Option<i64>,
+    episode_index: Option<i64>,
+    video_index: Option<i64>,
+    lang: Option<String>,
+    url: Option<String>,
+) -> Result<SubtitleSource, (StatusCode, &'static str)> {
+    if let Some(url) = url {
+        return match Url::parse(&url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(SubtitleSource::Url(url)),
+            _ => Err((StatusCode::BAD_REQUEST, "Invalid subtitle URL")),
+        };
+    }
+    match (anime_id, episode_index) {
+        (Some(anime_id), Some(episode_index))
+            if anime_id >= 0 && episode_index >= 0 && video_index.is_none_or(|index| index >= 0) =>
+        {
+            Ok(SubtitleSource::Episode {
+                anime_id,
+                episode_index,
+                video_index,
+                lang: lang.filter(|lang| !lang.trim().is_empty()),
+            })
+        }
+        _ => Err((StatusCode::BAD_REQUEST, "Invalid ids")),
+    }
+}
+
+/// Fetches and parses a subtitle file, returning the chosen track's language with its cues.
+async fn load_subtitles(
+    state: &AppState,
+    headers: &HeaderMap,
+    source: SubtitleSource,
+) -> Result<(Option<String>, Vec<subtitles::Cue>), (StatusCode, &'static str)> {
+    let client = &state.upstream;
+    let (lang, url) = match source {
+        SubtitleSource::Url(url) => (None, url),
+        SubtitleSource::Episode { anime_id, episode_index, video_index, lang } => {
+            let (track_lang, url) =
+                find_subtitle_track(state, client, headers, anime_id, episode_index, video_index, lang.as_deref())
+                    .await
+                    .map_err(|err| {
+                        warn!("Subtitle track lookup failed: {err}");
+                        (StatusCode::BAD_GATEWAY, "Subtitle track lookup failed")
+                    })?
+                    .ok_or((StatusCode::NOT_FOUND, "No matching subtitle track"))?;
+            (Some(track_lang), url)
+        }
+    };
+    let bytes = fetch_bytes(client, headers, &url, None).await.map_err(|err| {
+        warn!("Subtitle fetch failed: {err}");
+        (StatusCode::BAD_GATEWAY, "Subtitle fetch failed")
+    })?;
+    let text = String::from_utf8_lossy(&bytes);
+    Ok((lang, subtitles::parse(&text)))
+}
+
+/// The language and URL of the episode's subtitle track matching `lang`, from the requested
+/// video or else the preferred one.
+async fn find_subtitle_track(
+    state: &AppState,
+    client: &Upstream,
+    headers: &HeaderMap,
+    anime_id: i64,
+    episode_index: i64,
+    video_index: Option<i64>,
+    lang: Option<&str>,
+) -> anyhow::Result<Option<(String, Url)>> {
+    let base_url = Url::parse(&state.suwayomi_base_url).context("Invalid Suwayomi URL")?;
+    let videos_url = base_url
+        .join(&format!("/api/v1/anime/{anime_id}/episode/{episode_index}/videos"))
+        .context("Invalid videos URL")?;
+    let videos: Vec<SuwayomiVideo> = client
+        .retry("Videos request", || async {
+            client.get(videos_url.clone(), headers)
+                .send()
+                .await
+                .context("Videos request failed")?
+                .error_for_status()
+                .context("Videos request returned error status")?
+                .json()
+                .await
+                .context("Failed to parse video list")
+        })
+        .await?;
+
+    let video = match video_index {
+        Some(index) => usize::try_from(index).ok().and_then(|index| videos.get(index)),
+        None => videos.iter().find(|video| video.preferred).or(videos.first()),
+    };
+    let Some(video) = video else {
+        return Ok(None);
+    };
+    let track = video
+        .subtitle_tracks
+        .iter()
+        .find(|track| lang.is_none_or(|wanted| language_matches(&track.lang, wanted)));
+    let Some(track) = track else {
+        return Ok(None);
+    };
+    // Proxied tracks come as paths on the Suwayomi server.
+    let url = base_url.join(&track.url).context("Invalid subtitle URL")?;
+    Ok(Some((track.lang.clone(), url)))
+}
+
+/// Fetches and decodes (or for `aac`, trims) the requested range. With `progress`, decoded
+/// segments are sent there as they finish instead of being collected, and the returned
+/// samples are empty.
+async fn build_audio_clip(
+    state: &AppState,
+    headers: &HeaderMap,
+    request: ClipRequest,
+    progress: Option<&mpsc::Sender<DecodedSamples>>,
+) -> anyhow::Result<ClipAudio> {
+    let started = Instant::now();
+    let result = collect_audio_clip(state, headers, request, progress).await;
+    let failure = result.as_ref().err().map(|err| classify_clip_error(err).1);
+    state.metrics.record_clip(started.elapsed(), failure);
+    result
+}
+
+async fn collect_audio_clip(
+    state: &AppState,
+    headers: &HeaderMap,
+    request: ClipRequest,
+    progress: Option<&mpsc::Sender<DecodedSamples>>,
+) -> anyhow::Result<ClipAudio> {
+    let ClipRequest { wall_clock, start, duration, format, crossfade_joins, preview, .. } = request;
+    let target_end = start + duration;
+    // Episodes prefetched this far are cut from the decoded chunks on disk.
+    if let Some(key) = playlist_cache_key(&request).filter(|_| !wall_clock && format != ClipFormat::Aac) {
+        let prefetches = state.prefetches.clone();
+        let prefetched = spawn_blocking(move || prefetches.read(&key, start, target_end)).await.ok().flatten();
+        if let Some((mut samples, sample_rate, mut channels)) = prefetched {
+            if let Some(wanted) = request.channels.filter(|wanted| *wanted != channels) {
+                samples = remix(&samples, channels, wanted);
+                channels = wanted;
+            }
+            let decoded = DecodedSamples { samples, sample_rate, channels };
+            return match progress {
+                Some(tx) => {
+                    if tx.send(decoded).await.is_err() {
+                        return Err(anyhow!("Audio clip receiver closed"));
+                    }
+                    Ok(ClipAudio::Pcm(DecodedSamples { samples: Vec::new(), sample_rate, channels }))
+                }
+                None => Ok(ClipAudio::Pcm(decoded)),
+            };
+        }
+    }
+    let client = &state.upstream;
+    let playlist_started = Instant::now();
+    let mut parts: Vec<ClipPart> = match playlist_cache_key(&request).and_then(|key| state.playlists.get(&key)) {
+        Some((playlist, base_url)) => {
+            let segments = select_segments(&playlist, &base_url, start, target_end, wall_clock)?;
+            segments.into_iter().map(ClipPart::Segment).collect()
+        }
+        None => fetch_clip_parts(state, headers, &request, start, target_end).await?,
+    };
+    state.metrics.record(Stage::Playlist, playlist_started.elapsed());
+    if parts.is_empty() {
+        return Err(rejected("no_audio", "No matching segments found"));
+    }
+    parts.truncate(if preview { 1 } else { state.clip_limits.max_segments });
+
+    let mut key_cache: HashMap<String, [u8; 16]> = HashMap::new();
+    let mut output_samples: Vec<i16> = Vec::new();
+    let mut output_adts: Vec<u8> = Vec::new();
+    let mut output_rate: Option<u32> = None;
+    let mut output_channels: Option<usize> = None;
+    let mut joiner: Option<SegmentJoiner> = None;
+    let mut decoded_any = false;
+    let part_starts: Vec<f64> = parts.iter().map(ClipPart::start_time).collect();
+    let mut skipped = 0;
+
+    for (index, part) in parts.into_iter().enumerate() {
+        let (prepared, segment_start) = match part {
+            ClipPart::Segment(segment) => {
+                let decryption = match &segment.sample_aes {
+                    Some(key) => Some((fetch_key(client, headers, key, &mut key_cache).await?, key.iv)),
+                    None if segment.encrypted => {
+                        return Err(rejected("unsupported_encryption", "Only SAMPLE-AES encrypted HLS segments are supported"));
+                    }
+                    None => None,
+                };
+                let fetch_started = Instant::now();
+                let segment_bytes = match fetch_segment_bytes(client, headers, &segment, &state.init_segments).await {
+                    Ok(bytes) => {
+                        state.metrics.record_download(fetch_started.elapsed(), bytes.len());
+                        bytes
+                    }
+                    // A segment between two good ones that still fails after the retries is
+                    // replaced by silence of the same length, so the rest stays in time.
+                    Err(err)
+                        if format != ClipFormat::Aac
+                            && output_rate.is_some()
+                            && index + 1 < part_starts.len()
+                            && skipped < client.policy().max_skipped_segments =>
+                    {
+                        warn!("Replacing segment {} with silence: {err:#}", segment.url);
+                        skipped += 1;
+                        let sample_rate = output_rate.unwrap_or_default();
+                        let channels = output_channels.unwrap_or(1);
+                        let silence_start = segment.start_time.max(start);
+                        let gap = part_starts[index + 1].min(target_end) - silence_start;
+                        let frames = (gap.max(0.0) * sample_rate as f64).round() as usize;
+                        let mut samples = vec![0; frames * channels];
+                        if let Some(joiner) = &mut joiner {
+                            samples = joiner.join(samples, silence_start, None);
+                        }
+                        let silence = DecodedSamples { samples, sample_rate, channels };
+                        match progress {
+                            Some(tx) => {
+                                if tx.send(silence).await.is_err() {
+                                    return Err(anyhow!("Audio clip receiver closed"));
+                                }
+                            }
+                            None => output_samples.extend_from_slice(&silence.samples),
+                        }
+                        continue;
+                    }
+                    Err(err) => return Err(err),
+                };
+                let hint_extension = hint_extension_from_url(&segment.url);
+                (prepare_segment_audio(segment_bytes, hint_extension, decryption)?, segment.start_time)
+            }
+            ClipPart::Data { data, start_time, hint_extension } => {
+                let prepared = PreparedAudio {
+                    data,
+                    hint_extension: Some(hint_extension.to_string()),
+                    first_pts: None,
+                    force_segment_start: false,
+                };
+                (prepared, start_time)
+            }
+        };
+        // Media timestamps don't count from the Unix epoch.
+        let base_time = if prepared.force_segment_start || wall_clock {
+            None
+        } else {
+            prepared.first_pts
+        };
+
+        if format == ClipFormat::Aac {
+            if prepared.hint_extension.as_deref() != Some("aac") {
+                return Err(rejected("unsupported_codec", "AAC passthrough needs AAC/ADTS segments"));
+            }
+            let trim = trim_adts_frames(&prepared.data, base_time.unwrap_or(segment_start), start, target_end);
+            let (Some(sample_rate), Some(channels)) = (trim.sample_rate, trim.channels) else {
+                continue;
+            };
+            if output_rate.is_none() {
+                output_rate = Some(sample_rate);
+                output_channels = Some(channels);
+            } else if output_rate != Some(sample_rate) || output_channels != Some(channels) {
+                return Err(anyhow!("Mismatched audio formats across segments"));
+            }
+            output_adts.extend_from_slice(&trim.data);
+            continue;
+        }
+
+        let decode_started = Instant::now();
+        let decoded = spawn_blocking(move || {
+            decode_segment_samples(
+                prepared.data,
+                segment_start,
+                start,
+                target_end,
+                prepared.hint_extension,
+                base_time,
+            )
+        })
+        .await
+        .map_err(|err| anyhow!("Audio decode task failed: {err}"))??;
+        state.metrics.record(Stage::Decode, decode_started.elapsed());
+
+        let Some(DecodedSegment { audio: mut decoded, start_time }) = decoded else {
+            continue;
+        };
+
+        if output_rate.is_none() {
+            output_rate = Some(decoded.sample_rate);
+            output_channels = Some(request.channels.unwrap_or(decoded.channels));
+        } else if output_rate != Some(decoded.sample_rate) {
+            return Err(anyhow!("Mismatched sample rates across segments"));
+        }
+        // Some streams switch between mono and stereo at segment boundaries.
+        let channels = output_channels.unwrap_or(decoded.channels);
+        if decoded.channels != channels {
+            decoded.samples = remix(&decoded.samples, decoded.channels, channels);
+            decoded.channels = channels;
+        }
+        let joiner = joiner.get_or_insert_with(|| {
+            SegmentJoiner::new(start, duration, decoded.sample_rate, channels, crossfade_joins)
+        });
+        // Streamed output is already sent, so only a buffered clip can be crossfaded into.
+        let output = if progress.is_none() { Some(&mut output_samples) } else { None };
+        decoded.samples = joiner.join(decoded.samples, start_time, output);
+
+        decoded_any |= !decoded.samples.is_empty();
+        match progress {
+            Some(tx) => {
+                if tx.send(decoded).await.is_err() {
+                    return Err(anyhow!("Audio clip receiver closed"));
+                }
+            }
+            None => output_samples.extend_from_slice(&decoded.samples),
+        }
+    }
+
+    let Some(sample_rate) = output_rate else {
+        return Err(rejected("no_audio", "No audio decoded"));
+    };
+    if format == ClipFormat::Aac {
+        return Ok(ClipAudio::Adts(output_adts));
+    }
+    let channels = output_channels.unwrap_or(1);
+    if !decoded_any {
+        return Err(rejected("no_audio", "No audio decoded"));
+    }
+
+    Ok(ClipAudio::Pcm(DecodedSamples { samples: output_samples, sample_rate, channels }))
+}
+
+fn encode_clip(audio: ClipAudio, format: ClipFormat, sample_format: SampleFormat, fade_ms: u32) -> anyhow::Result<Vec<u8>> {
+    let mut decoded = match audio {
+        ClipAudio::Adts(data) => return Ok(data),
+        ClipAudio::Pcm(decoded) => decoded,
+    };
+    apply_fade(&mut decoded.samples, decoded.sample_rate, decoded.channels, fade_ms);
+    match format {
+        #[cfg(feature = "mp3")]
+        ClipFormat::Mp3 => crate::mp3::encode_mp3(&decoded.samples, decoded.sample_rate, decoded.channels),
+        #[cfg(not(feature = "mp3"))]
+        ClipFormat::Mp3 => Err(rejected("unsupported_format", "MP3 output is not enabled in this build")),
+        _ => wav::encode_wav(&decoded.samples, decoded.sample_rate, decoded.channels as u16, sample_format),
+    }
+}
+
+/// Fetches the playlist or file index of the request's source and picks the segments or
+/// fragments covering `start..end`, caching parsed HLS media playlists of episodes.
+async fn fetch_clip_parts(
+    state: &AppState,
+    headers: &HeaderMap,
+    request: &ClipRequest,
+    start: f64,
+    end: f64,
+) -> anyhow::Result<Vec<ClipPart>> {
+    let client = &state.upstream;
+    let (playlist_url, playlist) = match &request.source {
+        ClipSource::Episode { anime_id, episode_index, video_index } => {
+            let playlist_url = format!(
+                "{}/api/v1/anime/{anime_id}/episode/{episode_index}/video/{video_index}/playlist",
+                state.suwayomi_base_url
+            );
+            let playlist_url = Url::parse(&playlist_url).context("Invalid playlist URL")?;
+            let playlist = fetch_playlist(client, headers, &playlist_url).await?;
+            (playlist_url, playlist)
+        }
+        ClipSource::Local(path) => {
+            let file_url = Url::from_file_path(path).map_err(|_| anyhow!("Invalid media path"))?;
+            (file_url, PlaylistResponse::File(open_local_file(path).await?))
+        }
+    };
+    let parts = match playlist {
+        PlaylistResponse::File(_) if request.wall_clock => {
+            return Err(rejected("unsupported_stream", "Wall-clock ranges need an HLS playlist"));
+        }
+        PlaylistResponse::File(file) => {
+            progressive_parts(client, headers, &playlist_url, &file, &request.audio, start, end).await?
+        }
+        PlaylistResponse::Text(text) => {
+            let segments = if dash::is_mpd(&text) {
+                if request.wall_clock {
+                    return Err(rejected("unsupported_stream", "Wall-clock ranges need an HLS playlist"));
+                }
+                let manifest = dash::parse_mpd(&text, &playlist_url)?;
+                select_dash_segments(&manifest, &request.audio, start, end)?
+            } else {
+                let (playlist, base_url) =
+                    fetch_media_playlist(client, headers, playlist_url, &text, &request.audio).await?;
+                let segments = select_segments(&playlist, &base_url, start, end, request.wall_clock)?;
+                // Live playlists change with every target duration.
+                if let Some(key) = playlist_cache_key(request).filter(|_| !is_live(&playlist)) {
+                    state.playlists.insert(key, Arc::new(playlist), base_url);
+                }
+                segments
+            };
+            segments.into_iter().map(ClipPart::Segment).collect()
+        }
+    };
+    Ok(parts)
+}
+
+/// Key of an episode's media playlist in the playlist cache, covering the rendition picked from
+/// the master playlist. Local files have no playlist to cache.
+fn playlist_cache_key(request: &ClipRequest) -> Option<String> {
+    let ClipSource::Episode { anime_id, episode_index, video_index } = &request.source else {
+        return None;
+    };
+    Some(format!(
+        "{anime_id}/{episode_index}/{video_index}/{}/{}/{}",
+        request.audio.lang.as_deref().unwrap_or_default(),
+        request.audio.name.as_deref().unwrap_or_default(),
+        request.audio.group.as_deref().unwrap_or_default()
+    ))
+}
+
+/// Fetches the playlist, stopping early when the endpoint serves a media file instead so the
+/// file can be read by range.
+async fn fetch_playlist(client: &Upstream, headers: &HeaderMap, url: &Url) -> anyhow::Result<PlaylistResponse> {
+    client.retry("Playlist request", || sniff_playlist(client, headers, url)).await
+}
+
+async fn sniff_playlist(client: &Upstream, headers: &HeaderMap, url: &Url) -> anyhow::Result<PlaylistResponse> {
+    let mut response = client.get(url.clone(), headers)
+        .send()
+        .await
+        .context("Playlist request failed")?
+        .error_for_status()
+        .context("Playlist request returned error status")?;
+    let len = response.content_length();
+    let mut head = Vec::new();
+    while head.len() < SNIFF_LEN {
+        match response.chunk().await.context("Failed to read playlist")? {
+            Some(chunk) => head.extend_from_slice(&chunk),
+            None => break,
+        }
+    }
+    if let Some(container) = progressive::sniff(&head) {
+        let len = len.ok_or_else(|| anyhow!("Media file response has no Content-Length"))?;
+        // Dropping the response ends the download; the rest is requested by range.
+        return Ok(PlaylistResponse::File(ProgressiveFile { container, head, len, path: None }));
+    }
+    while let Some(chunk) = response.chunk().await.context("Failed to read playlist")? {
+        head.extend_from_slice(&chunk);
+    }
+    Ok(PlaylistResponse::Text(String::from_utf8_lossy(&head).into_owned()))
+}
+
+/// The episode named by the three ids, or the local file at `path` when given.
+async fn resolve_source(
+    state: &AppState,
+    path: Option<String>,
+    anime_id: Option<i64>,
+    episode_index: Option<i64>,
+    video_index: Option<i64>,
+) -> Result<ClipSource, (StatusCode, &'static str)> {
+    match (path, anime_id, episode_index, video_index) {
+        (Some(path), ..) => resolve_local_path(state.media_root.as_deref(), &path)
+            .await
+            .map(ClipSource::Local),
+        (None, Some(anime_id), Some(episode_index), Some(video_index))
+            if anime_id >= 0 && episode_index >= 0 && video_index >= 0 =>
+        {
+            Ok(ClipSource::Episode { anime_id, episode_index, video_index })
+        }
+        _ => Err((StatusCode::BAD_REQUEST, "Invalid ids")),
+    }
+}
+
+/// Checks that `path` names a file inside the media root and returns its canonical path.
+async fn resolve_local_path(root: Option<&Path>, path: &str) -> Result<PathBuf, (StatusCode, &'static str)> {
+    let Some(root) = root else {
+        return Err((StatusCode::FORBIDDEN, "Local media is disabled"));
+    };
+    // Canonicalizing resolves `..` and symlinks before the prefix check.
+    let resolved = tokio::fs::canonicalize(root.join(path))
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "Media file not found"))?;
+    if !resolved.starts_with(root) {
+        return Err((StatusCode::FORBIDDEN, "Path is outside the media root"));
+    }
+    match tokio::fs::metadata(&resolved).await {
+        Ok(metadata) if metadata.is_file() => Ok(resolved),
+        _ => Err((StatusCode::NOT_FOUND, "Media file not found")),
+    }
+}
+
+async fn open_local_file(path: &Path) -> anyhow::Result<ProgressiveFile> {
+    let mut file = tokio::fs::File::open(path).await.context("Failed to open media file")?;
+    let len = file.metadata().await.context("Failed to read media file")?.len();
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    (&mut file)
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut head)
+        .await
+        .context("Failed to read media file")?;
+    let container = progressive::sniff(&head).ok_or_else(|| rejected("unsupported_container", "Local media must be an MP4 or Matroska file"))?;
+    Ok(ProgressiveFile { container, head, len, path: Some(path.to_path_buf()) })
+}
+
+async fn progressive_parts(
+    client: &Upstream,
+    headers: &HeaderMap,
+    url: &Url,
+    file: &ProgressiveFile,
+    audio: &AudioRendition,
+    start: f64,
+    end: f64,
+) -> anyhow::Result<Vec<ClipPart>> {
+    match file.container {
+        Container::Mp4 => mp4_parts(client, headers, url, file, start, end).await,
+        Container::Matroska => matroska_parts(client, headers, url, file, audio, start, end).await,
+    }
+}
+
+/// Walks the top-level boxes for `moov` (and `sidx`), skipping `mdat` without fetching it.
+async fn mp4_parts(
+    client: &Upstream,
+    headers: &HeaderMap,
+    url: &Url,
+    file: &ProgressiveFile,
+    start: f64,
+    end: f64,
+) -> anyhow::Result<Vec<ClipPart>> {
+    let mut offset = 0u64;
+    let mut moov: Option<(Vec<u8>, u64)> = None;
+    let mut sidx: Option<(Vec<u8>, u64)> = None;
+    while offset < file.len {
+        let header_bytes = read_range(client, headers, url, file, offset, (offset + 16).min(file.len)).await?;
+        let header = progressive::box_header(&header_bytes, file.len - offset)
+            .ok_or_else(|| anyhow!("Invalid MP4 box at offset {offset}"))?;
+        let box_end = offset + header.size;
+        match &header.kind {
+            b"moov" => {
+                let body = read_range(client, headers, url, file, offset + header.header_len, box_end).await?;
+                moov = Some((body, box_end));
+            }
+            b"sidx" if sidx.is_none() => {
+                let body = read_range(client, headers, url, file, offset + header.header_len, box_end).await?;
+                sidx = Some((body, box_end));
+            }
+            b"moof" | b"mdat" if moov.is_some() => break,
+            _ => {}
+        }
+        offset = box_end;
     }
-    let safe_start = start.max(0.0);
-    let safe_end = end.max(0.0);
-    let duration = (safe_end - safe_start).min(MAX_DURATION_SECONDS);
-    if duration <= 0.0 {
-        return (StatusCode::BAD_REQUEST, "Invalid range").into_response();
+    let (moov, moov_end) = moov.ok_or_else(|| anyhow!("MP4 file has no moov box"))?;
+    let track = progressive::parse_moov(&moov)?;
+
+    if track.fragmented {
+        let (sidx, sidx_end) = sidx.ok_or_else(|| rejected("unsupported_container", "Fragmented MP4 files need a sidx index"))?;
+        // Everything up to the end of `moov` is the init segment each fragment is decoded with.
+        let map = MapSelection {
+            url: url.clone(),
+            byte_range: Some(ResolvedByteRange { start: 0, end: moov_end as usize }),
+        };
+        let subsegments = progressive::parse_sidx(&sidx, sidx_end)?;
+        let selected = subsegments
+            .iter()
+            .enumerate()
+            .filter(|(index, subsegment)| {
+                let next = subsegments.get(index + 1).map_or(f64::MAX, |next| next.time);
+                next >= start && subsegment.time <= end
+            })
+            .map(|(_, subsegment)| subsegment);
+        if file.path.is_some() {
+            let init = read_range(client, headers, url, file, 0, moov_end).await?;
+            let mut parts = Vec::new();
+            for subsegment in selected {
+                let mut data = init.clone();
+                data.extend(read_range(client, headers, url, file, subsegment.start, subsegment.end).await?);
+                parts.push(ClipPart::Data { data, start_time: subsegment.time, hint_extension: "mp4" });
+            }
+            return Ok(parts);
+        }
+        let parts = selected
+            .map(|subsegment| {
+                ClipPart::Segment(SegmentSelection {
+                    url: url.clone(),
+                    byte_range: Some(ResolvedByteRange {
+                        start: subsegment.start as usize,
+                        end: subsegment.end as usize,
+                    }),
+                    start_time: subsegment.time,
+                    map: Some(map.clone()),
+                    encrypted: false,
+                    sample_aes: None,
+                })
+            })
+            .collect();
+        return Ok(parts);
     }
 
-    let result = build_audio_clip(&state, &headers, animeId, episodeIndex, videoIndex, safe_start, duration).await;
-    match result {
-        Ok(bytes) => (
-            StatusCode::OK,
-            [(axum::http::header::CONTENT_TYPE, "audio/wav")],
-            Bytes::from(bytes),
-        )
-            .into_response(),
-        Err(err) => {
-            warn!("Audio clip failed: {err}");
-            (StatusCode::INTERNAL_SERVER_ERROR, "Audio clip failed").into_response()
-        }
+    let config = track.config.ok_or_else(|| rejected("unsupported_codec", "Only AAC audio is supported in MP4 files"))?;
+    let frame_duration = AAC_FRAME_SAMPLES / config.sample_rate() as f64;
+    let frames: Vec<progressive::Frame> = track
+        .frames
+        .into_iter()
+        .filter(|frame| frame.time + frame_duration > start && frame.time < end)
+        .collect();
+    let Some(start_time) = frames.first().map(|frame| frame.time) else {
+        return Ok(Vec::new());
+    };
+
+    let mut data = Vec::new();
+    let mut remaining = frames.as_slice();
+    for (range_start, range_end, count) in progressive::frame_ranges(&frames, MAX_RANGE_GAP) {
+        let bytes = read_range(client, headers, url, file, range_start, range_end).await?;
+        let (batch, rest) = remaining.split_at(count);
+        remaining = rest;
+        let payloads = batch.iter().filter_map(|frame| {
+            let offset = (frame.offset - range_start) as usize;
+            bytes.get(offset..offset + frame.size as usize)
+        });
+        data.extend(progressive::to_adts(config, payloads));
     }
+    Ok(vec![ClipPart::Data { data, start_time, hint_extension: "aac" }])
 }
 
-async fn build_audio_clip(
-    state: &AppState,
+/// Reads the track list and cues, then fetches the clusters between the cue points around
+/// the clip.
+async fn matroska_parts(
+    client: &Upstream,
     headers: &HeaderMap,
-    anime_id: i64,
-    episode_index: i64,
-    video_index: i64,
+    url: &Url,
+    file: &ProgressiveFile,
+    audio: &AudioRendition,
     start: f64,
-    duration: f64,
-) -> anyhow::Result<Vec<u8>> {
-    let target_end = start + duration;
-    let playlist_url = format!(
-        "{}/api/v1/anime/{}/episode/{}/video/{}/playlist",
-        state.suwayomi_base_url, anime_id, episode_index, video_index
+    end: f64,
+) -> anyhow::Result<Vec<ClipPart>> {
+    let layout = progressive::matroska_layout(&file.head)?;
+    let scale = match layout.info {
+        Some(offset) => progressive::timestamp_scale(&fetch_matroska_element(client, headers, url, file, offset).await?),
+        None => 1_000_000,
+    };
+    let tracks_offset = layout.tracks.ok_or_else(|| anyhow!("Matroska file has no track list"))?;
+    let tracks = progressive::matroska_audio_tracks(
+        &fetch_matroska_element(client, headers, url, file, tracks_offset).await?,
     );
-    let playlist_url = Url::parse(&playlist_url).context("Invalid playlist URL")?;
-    let client = Client::new();
-    let (playlist, base_url) = fetch_media_playlist(&client, headers, playlist_url).await?;
-    let segments = select_segments(&playlist, &base_url, start, target_end)?;
-    if segments.is_empty() {
-        return Err(anyhow!("No matching segments found"));
-    }
-
-    let mut map_cache: HashMap<String, Vec<u8>> = HashMap::new();
-    let mut output_samples: Vec<i16> = Vec::new();
-    let mut output_rate: Option<u32> = None;
-    let mut output_channels: Option<usize> = None;
 
-    for segment in segments {
-        if segment.encrypted {
-            return Err(anyhow!("Encrypted HLS segments are not supported"));
+    let requested = if audio.is_empty() {
+        None
+    } else {
+        let found = tracks
+            .iter()
+            .find(|track| audio.matches_labels(track.lang.as_deref(), track.name.as_deref()));
+        if found.is_none() {
+            warn!(
+                "Requested audio rendition (lang {:?}, name {:?}) not found, using the default",
+                audio.lang, audio.name
+            );
         }
-        let segment_bytes = fetch_segment_bytes(&client, headers, &segment, &mut map_cache).await?;
-        let hint_extension = hint_extension_from_url(&segment.url);
-        let prepared = prepare_segment_audio(segment_bytes, hint_extension);
-        let base_time = if prepared.force_segment_start {
-            None
-        } else {
-            prepared.first_pts
-        };
-        let segment_start = segment.start_time;
-        let decoded = spawn_blocking(move || {
-            decode_segment_samples(
-                prepared.data,
-                segment_start,
-                start,
-                target_end,
-                prepared.hint_extension,
-                base_time,
-            )
-        })
-        .await
-        .map_err(|err| anyhow!("Audio decode task failed: {err}"))??;
-
-        let Some(decoded) = decoded else {
-            continue;
-        };
+        found
+    };
+    let track = requested
+        .or_else(|| tracks.iter().find(|track| track.default))
+        .or(tracks.first())
+        .ok_or_else(|| rejected("no_audio", "No audio track in Matroska file"))?;
+    let config = track
+        .config
+        .ok_or_else(|| rejected("unsupported_codec", format!("Only AAC audio is supported in Matroska files, got {}", track.codec)))?;
+
+    let cues_offset = layout.cues.ok_or_else(|| rejected("unsupported_container", "Matroska file has no cue index"))?;
+    let cues = progressive::matroska_cues(
+        &fetch_matroska_element(client, headers, url, file, cues_offset).await?,
+        layout.segment_start,
+        scale,
+    );
+    let from = cues
+        .iter()
+        .rev()
+        .find(|(time, _)| *time <= start)
+        .or(cues.first())
+        .map(|(_, cluster)| *cluster)
+        .ok_or_else(|| anyhow!("Matroska cue index is empty"))?;
+    let to = cues
+        .iter()
+        .find(|(time, cluster)| *time > end && *cluster > from)
+        .map_or(file.len, |(_, cluster)| *cluster);
+
+    let clusters = read_range(client, headers, url, file, from, to).await?;
+    let frame_duration = AAC_FRAME_SAMPLES / config.sample_rate() as f64;
+    let frames: Vec<(f64, &[u8])> = progressive::matroska_frames(&clusters, track.number, scale, frame_duration)
+        .into_iter()
+        .filter(|(time, _)| time + frame_duration > start && *time < end)
+        .collect();
+    let Some(start_time) = frames.first().map(|(time, _)| *time) else {
+        return Ok(Vec::new());
+    };
+    let data = progressive::to_adts(config, frames.into_iter().map(|(_, frame)| frame));
+    Ok(vec![ClipPart::Data { data, start_time, hint_extension: "aac" }])
+}
 
-        if output_rate.is_none() {
-            output_rate = Some(decoded.sample_rate);
-            output_channels = Some(decoded.channels);
-        } else if output_rate != Some(decoded.sample_rate) || output_channels != Some(decoded.channels) {
-            return Err(anyhow!("Mismatched audio formats across segments"));
-        }
+async fn fetch_matroska_element(
+    client: &Upstream,
+    headers: &HeaderMap,
+    url: &Url,
+    file: &ProgressiveFile,
+    offset: u64,
+) -> anyhow::Result<Vec<u8>> {
+    let header = read_range(client, headers, url, file, offset, (offset + 12).min(file.len)).await?;
+    let len = progressive::element_len(&header)
+        .ok_or_else(|| anyhow!("Invalid Matroska element at offset {offset}"))?;
+    read_range(client, headers, url, file, offset, (offset + len).min(file.len)).await
+}
 
-        output_samples.extend_from_slice(&decoded.samples);
+/// Bytes `start..end` of a progressive file, from the already read head when it has them.
+async fn read_range(
+    client: &Upstream,
+    headers: &HeaderMap,
+    url: &Url,
+    file: &ProgressiveFile,
+    start: u64,
+    end: u64,
+) -> anyhow::Result<Vec<u8>> {
+    if let Some(bytes) = file.head.get(start as usize..end as usize) {
+        return Ok(bytes.to_vec());
     }
-
-    let Some(sample_rate) = output_rate else {
-        return Err(anyhow!("No audio decoded"));
-    };
-    let channels = output_channels.unwrap_or(1);
-    if output_samples.is_empty() {
-        return Err(anyhow!("No audio decoded"));
+    if let Some(path) = &file.path {
+        let mut local = tokio::fs::File::open(path).await.context("Failed to open media file")?;
+        local.seek(SeekFrom::Start(start)).await.context("Failed to read media file")?;
+        let mut bytes = vec![0; end.saturating_sub(start) as usize];
+        local.read_exact(&mut bytes).await.context("Failed to read media file")?;
+        return Ok(bytes);
     }
-
-    encode_wav_i16(&output_samples, sample_rate, channels as u16)
+    fetch_bytes(client, headers, url, Some(ResolvedByteRange { start: start as usize, end: end as usize })).await
 }
 
 async fn fetch_media_playlist(
-    client: &Client,
+    client: &Upstream,
     headers: &HeaderMap,
     playlist_url: Url,
+    playlist_text: &str,
+    audio: &AudioRendition,
 ) -> anyhow::Result<(MediaPlaylist<'static>, Url)> {
-    let playlist_text = fetch_text(client, headers, &playlist_url).await?;
-    if let Ok(media_playlist) = MediaPlaylist::try_from(playlist_text.as_str()) {
+    if let Ok(media_playlist) = MediaPlaylist::try_from(playlist_text) {
         return Ok((media_playlist.into_owned(), playlist_url));
     }
 
-    let master_playlist = MasterPlaylist::try_from(playlist_text.as_str())
+    let master_playlist = MasterPlaylist::try_from(playlist_text)
         .context("Failed to parse master playlist")?
         .into_owned();
-    let variant_url = select_master_variant(&master_playlist, &playlist_url)?;
+    let variant_url = select_master_variant(&master_playlist, &playlist_url, audio)?;
     let variant_text = fetch_text(client, headers, &variant_url).await?;
     let media_playlist = MediaPlaylist::try_from(variant_text.as_str())
         .context("Failed to parse media playlist")?
@@ -216,21 +2612,34 @@ async fn fetch_media_playlist(
     Ok((media_playlist, variant_url))
 }
 
-fn select_master_variant(master: &MasterPlaylist<'static>, base_url: &Url) -> anyhow::Result<Url> {
-    if let Some(media) = master
-        .media
-        .iter()
-        .find(|media| media.media_type == MediaType::Audio && media.is_default && media.uri().is_some())
-    {
-        return resolve_url(base_url, media.uri().unwrap().as_ref());
+/// The separate audio rendition of a master playlist to read: the requested one, else the
+/// `DEFAULT=YES` one, else the first. `None` when the audio is only muxed into the variants.
+fn select_audio_media<'a>(master: &'a MasterPlaylist<'static>, audio: &AudioRendition) -> Option<&'a ExtXMedia<'static>> {
+    let renditions = || {
+        master
+            .media
+            .iter()
+            .filter(|media| media.media_type == MediaType::Audio && media.uri().is_some())
+    };
+    if !audio.is_empty() {
+        match renditions().find(|media| audio.matches(media)) {
+            Some(media) => return Some(media),
+            None => warn!(
+                "Requested audio rendition (lang {:?}, name {:?}, group {:?}) not found, using the default",
+                audio.lang, audio.name, audio.group
+            ),
+        }
     }
+    renditions().find(|media| media.is_default).or_else(|| renditions().next())
+}
 
-    if let Some(media) = master
-        .media
-        .iter()
-        .find(|media| media.media_type == MediaType::Audio && media.uri().is_some())
-    {
-        return resolve_url(base_url, media.uri().unwrap().as_ref());
+fn select_master_variant(
+    master: &MasterPlaylist<'static>,
+    base_url: &Url,
+    audio: &AudioRendition,
+) -> anyhow::Result<Url> {
+    if let Some(uri) = select_audio_media(master, audio).and_then(|media| media.uri()) {
+        return resolve_url(base_url, uri.as_ref());
     }
 
     let mut best: Option<(&str, u64)> = None;
@@ -249,25 +2658,57 @@ fn select_master_variant(master: &MasterPlaylist<'static>, base_url: &Url) -> an
     resolve_url(base_url, uri)
 }
 
+/// With `wall_clock`, `start` and `end` are seconds since the Unix epoch and segments are timed
+/// by their `EXT-X-PROGRAM-DATE-TIME`, counting on by durations from the last one, so gaps in a
+/// DVR recording are skipped over rather than shifting everything after them.
+///
+/// A live playlist only lists the latest segments; the ones that dropped out are counted by
+/// `EXT-X-MEDIA-SEQUENCE` but not timed, so once it slides only wall-clock ranges can be placed
+/// in it.
 fn select_segments(
     playlist: &MediaPlaylist<'static>,
     base_url: &Url,
     start: f64,
     end: f64,
+    wall_clock: bool,
 ) -> anyhow::Result<Vec<SegmentSelection>> {
+    let sliding = is_live(playlist) && playlist.playlist_type.is_none() && playlist.media_sequence > 0;
+    if sliding && !wall_clock {
+        return Err(rejected(
+            "live_stream",
+            "This live playlist has moved past its first segment, so times from the start of the stream can't be found in it; clip it with start_at and end_at if it has EXT-X-PROGRAM-DATE-TIME",
+        ));
+    }
     let mut selections = Vec::new();
     let mut time_cursor = 0.0;
+    if wall_clock {
+        // Segments ahead of the first tag count back from it.
+        let mut offset = 0.0;
+        let mut first = None;
+        for (_, segment) in playlist.segments.iter() {
+            if let Some(date_time) = program_date_time(segment) {
+                first = Some(date_time - offset);
+                break;
+            }
+            offset += segment.duration.duration().as_secs_f64();
+        }
+        time_cursor = first.ok_or_else(|| rejected("unsupported_stream", "The playlist has no EXT-X-PROGRAM-DATE-TIME"))?;
+    }
+    let window_start = time_cursor;
     let mut last_map: Option<MapSelection> = None;
     let mut last_byte_range_end: Option<usize> = None;
     let mut previous_segment: Option<SegmentSelection> = None;
 
-    for (_, segment) in playlist.segments.iter() {
+    for (position, (_, segment)) in playlist.segments.iter().enumerate() {
         if let Some(map) = &segment.map {
             let map_url = resolve_url(base_url, map.uri().as_ref())?;
             let map_range = map.range().map(resolve_range_from_byte_range);
             last_map = Some(MapSelection { url: map_url, byte_range: map_range });
         }
 
+        if let Some(date_time) = program_date_time(segment).filter(|_| wall_clock) {
+            time_cursor = date_time;
+        }
         let duration = segment.duration.duration().as_secs_f64();
         let seg_start = time_cursor;
         let seg_end = seg_start + duration;
@@ -280,12 +2721,20 @@ fn select_segments(
         }
 
         let encrypted = segment.keys.iter().any(|key| key.is_some());
+        let sample_aes = match segment.keys.iter().find_map(|key| key.as_ref()) {
+            Some(key) => {
+                let sequence = (playlist.media_sequence + position) as u128;
+                sample_aes_key(key, base_url, sequence)?
+            }
+            None => None,
+        };
         let selection = SegmentSelection {
             url: resolve_url(base_url, segment.uri().as_ref())?,
             byte_range,
             start_time: seg_start,
             map: last_map.clone(),
             encrypted,
+            sample_aes,
         };
 
         if seg_end >= start && seg_start <= end {
@@ -295,9 +2744,6 @@ fn select_segments(
                 }
             }
             selections.push(selection.clone());
-            if selections.len() >= MAX_SEGMENTS {
-                break;
-            }
         }
 
         previous_segment = Some(selection);
@@ -308,9 +2754,115 @@ fn select_segments(
         }
     }
 
+    if selections.is_empty() && wall_clock && is_live(playlist) {
+        if end < window_start {
+            return Err(rejected("outside_live_window", "The range has already dropped out of the live playlist"));
+        }
+        if start >= time_cursor {
+            return Err(rejected("outside_live_window", "The range isn't in the live playlist yet"));
+        }
+    }
+    Ok(selections)
+}
+
+/// Playlists that may still gain segments: live ones, which also drop old segments, and
+/// `EVENT` ones, which only append.
+fn is_live(playlist: &MediaPlaylist<'_>) -> bool {
+    !playlist.has_end_list && playlist.playlist_type != Some(PlaylistType::Vod)
+}
+
+/// The segment's `EXT-X-PROGRAM-DATE-TIME` in seconds since the Unix epoch.
+fn program_date_time(segment: &MediaSegment<'_>) -> Option<f64> {
+    datetime::parse_rfc3339(segment.program_date_time.as_ref()?.date_time.as_ref())
+}
+
+/// Picks the requested (or first) unprotected audio adaptation set of every period and its
+/// lowest-bandwidth representation, like the lowest variant stream of an HLS master.
+fn select_dash_segments(
+    manifest: &dash::Manifest,
+    audio: &AudioRendition,
+    start: f64,
+    end: f64,
+) -> anyhow::Result<Vec<SegmentSelection>> {
+    let mut selections = Vec::new();
+    for period in &manifest.periods {
+        let usable: Vec<&dash::AdaptationSet> = period
+            .adaptation_sets
+            .iter()
+            .filter(|set| !set.protected && !set.representations.is_empty())
+            .collect();
+        let audio_sets: Vec<&dash::AdaptationSet> = usable.iter().copied().filter(|set| set.is_audio).collect();
+        let requested = if audio.is_empty() {
+            None
+        } else {
+            let found = audio_sets
+                .iter()
+                .copied()
+                .find(|set| audio.matches_labels(set.lang.as_deref(), set.label.as_deref()));
+            if found.is_none() {
+                warn!(
+                    "Requested audio rendition (lang {:?}, name {:?}) not found, using the default",
+                    audio.lang, audio.name
+                );
+            }
+            found
+        };
+        // Muxed streams have no audio-only set; symphonia picks the audio track out of those.
+        let Some(set) = requested.or(audio_sets.first().copied()).or(usable.first().copied()) else {
+            if period.adaptation_sets.iter().any(|set| set.protected) {
+                return Err(rejected("unsupported_encryption", "DRM-protected DASH streams are not supported"));
+            }
+            continue;
+        };
+        let Some(representation) = set.representations.iter().min_by_key(|rep| rep.bandwidth) else {
+            continue;
+        };
+
+        let map = representation.init.as_ref().map(|init| MapSelection {
+            url: init.url.clone(),
+            byte_range: init.range.map(|(start, end)| ResolvedByteRange { start, end }),
+        });
+        for segment in &representation.segments {
+            if segment.start + segment.duration < start || segment.start > end {
+                continue;
+            }
+            selections.push(SegmentSelection {
+                url: segment.resource.url.clone(),
+                byte_range: segment.resource.range.map(|(start, end)| ResolvedByteRange { start, end }),
+                start_time: segment.start,
+                map: map.clone(),
+                encrypted: false,
+                sample_aes: None,
+            });
+        }
+    }
     Ok(selections)
 }
 
+/// SAMPLE-AES with a clear key is the only encryption handled; vendor key formats (FairPlay
+/// and friends) are DRM and stay unsupported.
+fn sample_aes_key(
+    key: &DecryptionKey<'static>,
+    base_url: &Url,
+    sequence: u128,
+) -> anyhow::Result<Option<SampleAesKey>> {
+    if key.method != EncryptionMethod::SampleAes {
+        return Ok(None);
+    }
+    if key.format.as_ref().is_some_and(|format| *format != KeyFormat::Identity) {
+        return Ok(None);
+    }
+    // Without an explicit IV the media sequence number is used, as for AES-128.
+    let iv = match key.iv {
+        InitializationVector::Aes128(iv) => iv,
+        InitializationVector::Number(number) => number.to_be_bytes(),
+        InitializationVector::Missing => sequence.to_be_bytes(),
+        _ => return Err(anyhow!("Unsupported SAMPLE-AES initialization vector")),
+    };
+    let url = resolve_url(base_url, key.uri().as_ref())?;
+    Ok(Some(SampleAesKey { url, iv }))
+}
+
 fn resolve_range_from_ext_byte_range(
     range: hls_m3u8::tags::ExtXByteRange,
     last_end: &mut Option<usize>,
@@ -328,20 +2880,20 @@ fn resolve_range_from_byte_range(range: ByteRange) -> ResolvedByteRange {
 }
 
 async fn fetch_segment_bytes(
-    client: &Client,
+    client: &Upstream,
     headers: &HeaderMap,
     segment: &SegmentSelection,
-    map_cache: &mut HashMap<String, Vec<u8>>,
+    init_segments: &InitSegmentCache,
 ) -> anyhow::Result<Vec<u8>> {
     let mut data = Vec::new();
     if let Some(map) = &segment.map {
         let cache_key = map_cache_key(&map.url, map.byte_range);
-        if let Some(cached) = map_cache.get(&cache_key) {
-            data.extend_from_slice(cached);
+        if let Some(cached) = init_segments.get(&cache_key) {
+            data.extend_from_slice(&cached);
         } else {
             let bytes = fetch_bytes(client, headers, &map.url, map.byte_range).await?;
             data.extend_from_slice(&bytes);
-            map_cache.insert(cache_key, bytes);
+            init_segments.insert(cache_key, bytes.into());
         }
     }
 
@@ -350,52 +2902,65 @@ async fn fetch_segment_bytes(
     Ok(data)
 }
 
-async fn fetch_text(client: &Client, headers: &HeaderMap, url: &Url) -> anyhow::Result<String> {
-    let response = apply_forward_headers(client.get(url.clone()), headers)
-        .send()
+async fn fetch_key(
+    client: &Upstream,
+    headers: &HeaderMap,
+    key: &SampleAesKey,
+    key_cache: &mut HashMap<String, [u8; 16]>,
+) -> anyhow::Result<[u8; 16]> {
+    if let Some(cached) = key_cache.get(key.url.as_str()) {
+        return Ok(*cached);
+    }
+    let bytes = fetch_bytes(client, headers, &key.url, None).await?;
+    let key_bytes: [u8; 16] = bytes
+        .as_slice()
+        .try_into()
+        .map_err(|_| anyhow!("Invalid SAMPLE-AES key length: {} bytes", bytes.len()))?;
+    key_cache.insert(key.url.as_str().to_string(), key_bytes);
+    Ok(key_bytes)
+}
+
+async fn fetch_text(client: &Upstream, headers: &HeaderMap, url: &Url) -> anyhow::Result<String> {
+    client
+        .retry("Playlist request", || async {
+            let response = client.get(url.clone(), headers)
+                .send()
+                .await
+                .context("Playlist request failed")?
+                .error_for_status()
+                .context("Playlist request returned error status")?;
+            response.text().await.context("Failed to read playlist")
+        })
         .await
-        .context("Playlist request failed")?
-        .error_for_status()
-        .context("Playlist request returned error status")?;
-    response.text().await.context("Failed to read playlist")
 }
 
 async fn fetch_bytes(
-    client: &Client,
+    client: &Upstream,
     headers: &HeaderMap,
     url: &Url,
     range: Option<ResolvedByteRange>,
 ) -> anyhow::Result<Vec<u8>> {
-    let mut request = apply_forward_headers(client.get(url.clone()), headers);
-    if let Some(range) = range {
-        if range.end <= range.start {
-            return Err(anyhow!("Invalid byte range"));
-        }
-        let end_inclusive = range.end.saturating_sub(1);
-        let header_value = format!("bytes={}-{}", range.start, end_inclusive);
-        request = request.header("Range", header_value);
-    }
-    let response = request
-        .send()
+    let header_value = match range {
+        Some(range) if range.end <= range.start => return Err(anyhow!("Invalid byte range")),
+        Some(range) => Some(format!("bytes={}-{}", range.start, range.end.saturating_sub(1))),
+        None => None,
+    };
+    client
+        .retry("Segment request", || async {
+            let mut request = client.get(url.clone(), headers);
+            if let Some(header_value) = &header_value {
+                request = request.header("Range", header_value);
+            }
+            let response = request
+                .send()
+                .await
+                .context("Segment request failed")?
+                .error_for_status()
+                .context("Segment request returned error status")?;
+            let bytes = response.bytes().await.context("Failed to read segment")?;
+            Ok(bytes.to_vec())
+        })
         .await
-        .context("Segment request failed")?
-        .error_for_status()
-        .context("Segment request returned error status")?;
-    let bytes = response.bytes().await.context("Failed to read segment")?;
-    Ok(bytes.to_vec())
-}
-
-fn apply_forward_headers(
-    mut request: reqwest::RequestBuilder,
-    headers: &HeaderMap,
-) -> reqwest::RequestBuilder {
-    if let Some(value) = headers.get(axum::http::header::COOKIE) {
-        request = request.header(axum::http::header::COOKIE, value);
-    }
-    if let Some(value) = headers.get(axum::http::header::AUTHORIZATION) {
-        request = request.header(axum::http::header::AUTHORIZATION, value);
-    }
-    request
 }
 
 fn map_cache_key(url: &Url, range: Option<ResolvedByteRange>) -> String {
@@ -430,15 +2995,55 @@ fn decode_segment_samples(
     target_end: f64,
     hint_extension: Option<String>,
     base_time: Option<f64>,
-) -> anyhow::Result<Option<DecodedSamples>> {
-    decode_samples_from_bytes(
+) -> anyhow::Result<Option<DecodedSegment>> {
+    #[cfg(feature = "ffmpeg")]
+    let fallback = data.clone();
+    let result = decode_samples_from_bytes(
         data,
         hint_extension.as_deref(),
         segment_start,
         target_start,
         target_end,
         base_time,
-    )
+    );
+    #[cfg(feature = "ffmpeg")]
+    let result = result.or_else(|err| {
+        warn!("Decoding segment with ffmpeg: {err:#}");
+        // The original error says more about the source than a missing binary would.
+        decode_with_ffmpeg(&fallback, base_time.unwrap_or(segment_start), target_start, target_end)
+            .map_err(|ffmpeg_err| {
+                warn!("ffmpeg fallback failed: {ffmpeg_err:#}");
+                err
+            })
+    });
+    result
+}
+
+/// Decodes the whole segment with ffmpeg and cuts out the range, timing the first frame at
+/// `base_time`.
+#[cfg(feature = "ffmpeg")]
+fn decode_with_ffmpeg(
+    data: &[u8],
+    base_time: f64,
+    target_start: f64,
+    target_end: f64,
+) -> anyhow::Result<Option<DecodedSegment>> {
+    let (samples, sample_rate, channels) = crate::ffmpeg::decode(data)?;
+    let rate = sample_rate as f64;
+    let frames = samples.len() / channels;
+    let start_frame = ((target_start - base_time) * rate).round().max(0.0) as usize;
+    let end_frame = (((target_end - base_time) * rate).ceil().max(0.0) as usize).min(frames);
+    if end_frame <= start_frame {
+        return Ok(None);
+    }
+    Ok(Some(DecodedSegment {
+        audio: DecodedSamples {
+            samples: samples[start_frame * channels..end_frame * channels].to_vec(),
+            sample_rate,
+            channels,
+        },
+        start_time: base_time + start_frame as f64 / rate,
+    }))
 }
 
 fn decode_samples_from_bytes(
@@ -448,7 +3053,7 @@ fn decode_samples_from_bytes(
     target_start: f64,
     target_end: f64,
     base_time: Option<f64>,
-) -> anyhow::Result<Option<DecodedSamples>> {
+) -> anyhow::Result<Option<DecodedSegment>> {
     let mut hint = Hint::new();
     if let Some(ext) = hint_extension {
         hint.with_extension(ext);
@@ -463,7 +3068,7 @@ fn decode_samples_from_bytes(
         .tracks()
         .iter()
         .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
-        .ok_or_else(|| anyhow!("No supported audio tracks"))?;
+        .ok_or_else(|| rejected("unsupported_codec", "No supported audio tracks"))?;
     let track_id = track.id;
     let mut decoder = symphonia::default::get_codecs()
         .make(&track.codec_params, &DecoderOptions::default())
@@ -473,6 +3078,7 @@ fn decode_samples_from_bytes(
     let mut samples: Vec<i16> = Vec::new();
     let mut sample_rate: Option<u32> = None;
     let mut channels: Option<usize> = None;
+    let mut start_time: Option<f64> = None;
 
     let base_time = base_time.unwrap_or(segment_start);
 
@@ -496,9 +3102,10 @@ fn decode_samples_from_bytes(
                 if sample_rate.is_none() {
                     sample_rate = Some(current_rate);
                     channels = Some(current_channels);
-                } else if sample_rate != Some(current_rate) || channels != Some(current_channels) {
-                    return Err(anyhow!("Audio format changed within segment"));
+                } else if sample_rate != Some(current_rate) {
+                    return Err(anyhow!("Sample rate changed within segment"));
                 }
+                let output_channels = channels.unwrap_or(current_channels);
 
                 let frame_count = audio_buf.frames();
                 if frame_count == 0 {
@@ -516,14 +3123,20 @@ fn decode_samples_from_bytes(
                 let overlap_end = target_end.min(buffer_end);
 
                 if overlap_end > overlap_start {
-                    let start_frame = ((overlap_start - buffer_start) * rate_f).floor().max(0.0) as usize;
+                    let start_frame = ((overlap_start - buffer_start) * rate_f).round().max(0.0) as usize;
                     let end_frame = ((overlap_end - buffer_start) * rate_f).ceil().max(0.0) as usize;
                     let start_index = start_frame.saturating_mul(channels);
                     let end_index = end_frame
                         .saturating_mul(channels)
                         .min(frame_count.saturating_mul(channels));
                     if end_index > start_index {
-                        samples.extend_from_slice(&sample_buf.samples()[start_index..end_index]);
+                        start_time.get_or_insert(buffer_start + start_frame as f64 / rate_f);
+                        let overlap = &sample_buf.samples()[start_index..end_index];
+                        if channels == output_channels {
+                            samples.extend_from_slice(overlap);
+                        } else {
+                            samples.extend(remix(overlap, channels, output_channels));
+                        }
                     }
                 }
 
@@ -542,11 +3155,11 @@ fn decode_samples_from_bytes(
         return Ok(None);
     };
     let channels = channels.unwrap_or(1);
-    if samples.is_empty() {
+    let Some(start_time) = start_time.filter(|_| !samples.is_empty()) else {
         return Ok(None);
-    }
+    };
 
-    Ok(Some(DecodedSamples { samples, sample_rate, channels }))
+    Ok(Some(DecodedSegment { audio: DecodedSamples { samples, sample_rate, channels }, start_time }))
 }
 
 fn ts_packet_size(data: &[u8]) -> Option<usize> {
@@ -563,10 +3176,10 @@ fn ts_packet_size(data: &[u8]) -> Option<usize> {
     None
 }
 
-fn extract_adts_from_ts(data: &[u8], packet_size: usize) -> AdtsExtraction {
+fn extract_audio_from_ts(data: &[u8], packet_size: usize) -> TsExtraction {
     let sync_offset = if packet_size == 192 { 4 } else { 0 };
     let mut pmt_pid: Option<u16> = None;
-    let mut audio_pid: Option<u16> = None;
+    let mut audio: Option<(u16, TsAudioCodec)> = None;
 
     for packet in data.chunks(packet_size) {
         if packet.len() < sync_offset + 188 {
@@ -599,10 +3212,13 @@ fn extract_adts_from_ts(data: &[u8], packet_size: usize) -> AdtsExtraction {
         if pid == 0 {
             parse_pat(payload, pusi, &mut pmt_pid);
         } else if Some(pid) == pmt_pid {
-            parse_pmt(payload, pusi, &mut audio_pid);
+            parse_pmt(payload, pusi, &mut audio);
         }
     }
 
+    let audio_pid = audio.map(|(pid, _)| pid);
+    // Without a program map the segment is scanned for ADTS frames.
+    let codec = audio.map_or(TsAudioCodec::Aac, |(_, codec)| codec);
     let mut pes_payloads: Vec<PesPayload> = Vec::new();
     let mut current_pes: Option<PesPayload> = None;
     let mut force_segment_start = false;
@@ -671,12 +3287,19 @@ fn extract_adts_from_ts(data: &[u8], packet_size: usize) -> AdtsExtraction {
         payloads.extend_from_slice(&pes.data);
     }
 
+    let first_pts = first_pts.map(|pts| pts as f64 / 90_000.0);
+    if let TsAudioCodec::Opus { channels } = codec {
+        let data = opus::to_ogg(&payloads, channels).unwrap_or_default();
+        return TsExtraction { codec, data, first_pts, force_segment_start };
+    }
+    if codec != TsAudioCodec::Aac {
+        return TsExtraction { codec, data: payloads, first_pts, force_segment_start };
+    }
     let mut adts_stream = extract_adts_frames(&payloads);
     if adts_stream.is_empty() {
         adts_stream = extract_adts_frames(data);
     }
-    let first_pts = first_pts.map(|pts| pts as f64 / 90_000.0);
-    AdtsExtraction { data: adts_stream, first_pts, force_segment_start }
+    TsExtraction { codec, data: adts_stream, first_pts, force_segment_start }
 }
 
 fn parse_pat(payload: &[u8], pusi: bool, pmt_pid: &mut Option<u16>) {
@@ -711,7 +3334,9 @@ fn parse_pat(payload: &[u8], pusi: bool, pmt_pid: &mut Option<u16>) {
     }
 }
 
-fn parse_pmt(payload: &[u8], pusi: bool, audio_pid: &mut Option<u16>) {
+/// Picks the first AAC stream of the program, which symphonia decodes itself, else the first
+/// other audio stream.
+fn parse_pmt(payload: &[u8], pusi: bool, audio: &mut Option<(u16, TsAudioCodec)>) {
     let mut idx = 0usize;
     if pusi {
         if payload.is_empty() {
@@ -737,9 +3362,14 @@ fn parse_pmt(payload: &[u8], pusi: bool, audio_pid: &mut Option<u16>) {
         let stream_type = payload[i];
         let pid = (((payload[i + 1] & 0x1f) as u16) << 8) | payload[i + 2] as u16;
         let es_info_length = (((payload[i + 3] & 0x0f) as usize) << 8) | payload[i + 4] as usize;
-        if stream_type == 0x0f || stream_type == 0x11 {
-            *audio_pid = Some(pid);
-            return;
+        let descriptors = payload.get(i + 5..i + 5 + es_info_length).unwrap_or_default();
+        match TsAudioCodec::from_stream(stream_type, descriptors) {
+            Some(TsAudioCodec::Aac) => {
+                *audio = Some((pid, TsAudioCodec::Aac));
+                return;
+            }
+            Some(codec) if audio.is_none() => *audio = Some((pid, codec)),
+            _ => {}
         }
         i += 5 + es_info_length;
     }
@@ -767,6 +3397,45 @@ fn extract_adts_frames(data: &[u8]) -> Vec<u8> {
     frames
 }
 
+/// Keeps the whole ADTS frames that overlap `target_start..target_end`, timing them from
+/// `base_time` at 1024 samples per frame.
+fn trim_adts_frames(data: &[u8], base_time: f64, target_start: f64, target_end: f64) -> AdtsTrim {
+    let mut trim = AdtsTrim { data: Vec::new(), sample_rate: None, channels: None };
+    let mut time = base_time;
+    let mut i = 0usize;
+    while i + 7 <= data.len() {
+        if !is_adts_header(data, i) {
+            i += 1;
+            continue;
+        }
+        let frame_len = adts_frame_length(data, i);
+        if frame_len < 7 {
+            i += 1;
+            continue;
+        }
+        if i + frame_len > data.len() {
+            break;
+        }
+        let Some(&sample_rate) = ADTS_SAMPLE_RATES.get(((data[i + 2] >> 2) & 0x0f) as usize) else {
+            i += 1;
+            continue;
+        };
+        let frame_end = time + AAC_FRAME_SAMPLES / sample_rate as f64;
+        if frame_end > target_start && time < target_end {
+            trim.data.extend_from_slice(&data[i..i + frame_len]);
+            trim.sample_rate.get_or_insert(sample_rate);
+            trim.channels
+                .get_or_insert((((data[i + 2] & 0x01) << 2) | (data[i + 3] >> 6)) as usize);
+        }
+        time = frame_end;
+        if time >= target_end {
+            break;
+        }
+        i += frame_len;
+    }
+    trim
+}
+
 fn parse_pes_header(payload: &[u8]) -> Option<(Option<u64>, usize)> {
     if payload.len() < 9 {
         return None;
@@ -830,50 +3499,312 @@ fn adts_frame_length(data: &[u8], index: usize) -> usize {
         | (((data[index + 5] & 0xe0) as usize) >> 5)
 }
 
-fn encode_wav_i16(samples: &[i16], sample_rate: u32, channels: u16) -> anyhow::Result<Vec<u8>> {
-    let data_len = samples.len() * 2;
-    if data_len > u32::MAX as usize {
-        return Err(anyhow!("Audio clip is too large"));
+/// Decrypts a SAMPLE-AES ADTS stream in place. Each frame keeps its header and a 16-byte
+/// leader in the clear, then whole AES-128-CBC blocks starting over from `iv`; a trailing
+/// partial block is left in the clear.
+fn decrypt_sample_aes_adts(data: &mut [u8], key: &[u8; 16], iv: &[u8; 16]) {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+    let mut i = 0usize;
+    while i + 7 <= data.len() && is_adts_header(data, i) {
+        let frame_len = adts_frame_length(data, i);
+        let header_len = if data[i + 1] & 0x01 == 1 { 7 } else { 9 };
+        if frame_len < header_len || i + frame_len > data.len() {
+            break;
+        }
+        let payload = &mut data[i + header_len..i + frame_len];
+        if payload.len() > SAMPLE_AES_LEADER {
+            let encrypted = &mut payload[SAMPLE_AES_LEADER..];
+            let block_bytes = encrypted.len() / AES_BLOCK * AES_BLOCK;
+            let mut previous = *iv;
+            for block in encrypted[..block_bytes].chunks_exact_mut(AES_BLOCK) {
+                let mut ciphertext = [0u8; AES_BLOCK];
+                ciphertext.copy_from_slice(block);
+                cipher.decrypt_block(GenericArray::from_mut_slice(block));
+                for (byte, prev) in block.iter_mut().zip(previous) {
+                    *byte ^= prev;
+                }
+                previous = ciphertext;
+            }
+        }
+        i += frame_len;
     }
-    let riff_size = 36u32 + data_len as u32;
-    let byte_rate = sample_rate * channels as u32 * 2;
-    let block_align = channels * 2;
-
-    let mut output = Vec::with_capacity(44 + data_len);
-    output.extend_from_slice(b"RIFF");
-    output.extend_from_slice(&riff_size.to_le_bytes());
-    output.extend_from_slice(b"WAVE");
-    output.extend_from_slice(b"fmt ");
-    output.extend_from_slice(&16u32.to_le_bytes());
-    output.extend_from_slice(&1u16.to_le_bytes());
-    output.extend_from_slice(&channels.to_le_bytes());
-    output.extend_from_slice(&sample_rate.to_le_bytes());
-    output.extend_from_slice(&byte_rate.to_le_bytes());
-    output.extend_from_slice(&block_align.to_le_bytes());
-    output.extend_from_slice(&16u16.to_le_bytes());
-    output.extend_from_slice(b"data");
-    output.extend_from_slice(&(data_len as u32).to_le_bytes());
+}
 
-    for sample in samples {
-        output.extend_from_slice(&sample.to_le_bytes());
+/// Ramps the first and last `fade_ms` of interleaved PCM linearly from and to silence. The
+/// fade never exceeds half the clip, so short clips still reach full volume in the middle.
+fn apply_fade(samples: &mut [i16], sample_rate: u32, channels: usize, fade_ms: u32) {
+    let channels = channels.max(1);
+    let frames = samples.len() / channels;
+    let fade_frames = ((sample_rate as u64 * fade_ms as u64 / 1000) as usize).min(frames / 2);
+    if fade_frames == 0 {
+        return;
+    }
+    for frame in 0..fade_frames {
+        let gain = frame as f32 / fade_frames as f32;
+        for channel in 0..channels {
+            let head = frame * channels + channel;
+            let tail = (frames - 1 - frame) * channels + channel;
+            samples[head] = (samples[head] as f32 * gain) as i16;
+            samples[tail] = (samples[tail] as f32 * gain) as i16;
+        }
     }
+}
 
-    Ok(output)
+/// Converts interleaved PCM between channel counts. Output channel `c` averages the input
+/// channels `c`, `c + to`, `c + 2 * to`, …, so stereo folds to mono and mono is duplicated
+/// into both stereo channels.
+fn remix(samples: &[i16], from: usize, to: usize) -> Vec<i16> {
+    let (from, to) = (from.max(1), to.max(1));
+    let mut output = Vec::with_capacity(samples.len() / from * to);
+    for frame in samples.chunks_exact(from) {
+        for channel in 0..to {
+            let (sum, count) = frame
+                .iter()
+                .skip(channel)
+                .step_by(to)
+                .fold((0i32, 0i32), |(sum, count), sample| (sum + *sample as i32, count + 1));
+            let value = if count == 0 { frame[channel % from] } else { (sum / count) as i16 };
+            output.push(value);
+        }
+    }
+    output
 }
 
+/// Peak and RMS amplitude of `points` equal buckets of mono samples, normalized to 0..1.
+/// Fewer samples than points give one bucket per sample.
+fn waveform(samples: &[i16], points: usize) -> (Vec<f32>, Vec<f32>) {
+    let bucket = samples.len().div_ceil(points.max(1)).max(1);
+    samples
+        .chunks(bucket)
+        .map(|chunk| {
+            let peak = chunk.iter().map(|sample| sample.unsigned_abs()).max().unwrap_or(0);
+            let square_sum: f64 = chunk.iter().map(|sample| (*sample as f64).powi(2)).sum();
+            let rms = (square_sum / chunk.len() as f64).sqrt();
+            (peak as f32 / 32768.0, (rms / 32768.0) as f32)
+        })
+        .unzip()
+}
 
-fn prepare_segment_audio(data: Vec<u8>, hint_extension: Option<String>) -> PreparedAudio {
+fn prepare_segment_audio(
+    data: Vec<u8>,
+    hint_extension: Option<String>,
+    decryption: Option<([u8; 16], [u8; 16])>,
+) -> anyhow::Result<PreparedAudio> {
     if let Some(packet_size) = ts_packet_size(&data) {
-        let extraction = extract_adts_from_ts(&data, packet_size);
+        let mut extraction = extract_audio_from_ts(&data, packet_size);
+        if !extraction.data.is_empty() && extraction.codec != TsAudioCodec::Aac {
+            let codec = extraction.codec;
+            if decryption.is_some() {
+                return Err(rejected("unsupported_encryption", "SAMPLE-AES is only supported for AAC/ADTS audio"));
+            }
+            if !codec.decodable() {
+                return Err(rejected("unsupported_codec", "AC-3, E-AC-3 and Opus audio need a build with the ffmpeg feature"));
+            }
+            return Ok(PreparedAudio {
+                data: extraction.data,
+                hint_extension: Some(codec.extension().to_string()),
+                first_pts: if extraction.force_segment_start { None } else { extraction.first_pts },
+                force_segment_start: extraction.force_segment_start,
+            });
+        }
         if !extraction.data.is_empty() {
-            return PreparedAudio {
+            if let Some((key, iv)) = &decryption {
+                decrypt_sample_aes_adts(&mut extraction.data, key, iv);
+            }
+            return Ok(PreparedAudio {
                 data: extraction.data,
                 hint_extension: Some("aac".to_string()),
                 first_pts: if extraction.force_segment_start { None } else { extraction.first_pts },
                 force_segment_start: extraction.force_segment_start,
-            };
+            });
+        }
+    }
+
+    // Packed audio (raw ADTS behind an ID3 tag) can be SAMPLE-AES encrypted as well.
+    if let Some((key, iv)) = &decryption {
+        let mut frames = extract_adts_frames(&data);
+        if frames.is_empty() {
+            return Err(rejected("unsupported_encryption", "SAMPLE-AES is only supported for AAC/ADTS audio"));
+        }
+        decrypt_sample_aes_adts(&mut frames, key, iv);
+        return Ok(PreparedAudio {
+            data: frames,
+            hint_extension: Some("aac".to_string()),
+            first_pts: None,
+            force_segment_start: false,
+        });
+    }
+
+    // fMP4/CMAF segments carry their decode time, which is exact where the playlist durations
+    // add up to a timeline that drifts.
+    let first_pts = progressive::fragment_start(&data);
+    Ok(PreparedAudio { data, hint_extension, first_pts, force_segment_start: false })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use hls_m3u8::{MasterPlaylist, MediaPlaylist};
+    use url::Url;
+
+    use super::{
+        AudioRendition, ClipFormat, TsAudioCodec, apply_fade, classify_clip_error, language_matches, master_renditions,
+        parse_pmt, rejected, remix, select_master_variant, select_segments, trim_adts_frames, waveform,
+    };
+
+    /// A 10-byte AAC-LC frame at 48 kHz, stereo.
+    const FRAME: [u8; 10] = [0xff, 0xf1, 0x4c, 0x80, 0x01, 0x5f, 0xfc, 1, 2, 3];
+
+    #[test]
+    fn trims_adts_at_frame_boundaries() {
+        let data = FRAME.repeat(3);
+        // Frames span 0–21.3 ms, 21.3–42.7 ms and 42.7–64 ms.
+        let trim = trim_adts_frames(&data, 0.0, 0.03, 0.05);
+        assert_eq!(trim.data.len(), 2 * FRAME.len());
+        assert_eq!(trim.sample_rate, Some(48000));
+        assert_eq!(trim.channels, Some(2));
+        assert!(trim_adts_frames(&data, 1.0, 0.03, 0.05).data.is_empty());
+    }
+
+    #[test]
+    fn picks_ts_audio_streams() {
+        assert_eq!(TsAudioCodec::from_stream(0x81, &[]), Some(TsAudioCodec::Ac3));
+        assert_eq!(TsAudioCodec::from_stream(0x03, &[]), Some(TsAudioCodec::Mpeg));
+        // DVB E-AC-3 behind a language descriptor.
+        let descriptors = [0x0a, 0x04, b'j', b'p', b'n', 0, 0x7a, 0x01, 0x00];
+        assert_eq!(TsAudioCodec::from_stream(0x06, &descriptors), Some(TsAudioCodec::Eac3));
+        assert_eq!(TsAudioCodec::from_stream(0x06, &[0x59, 0x00]), None);
+        let descriptors = [0x05, 0x04, b'O', b'p', b'u', b's', 0x7f, 0x02, 0x80, 0x01];
+        assert_eq!(TsAudioCodec::from_stream(0x06, &descriptors), Some(TsAudioCodec::Opus { channels: 1 }));
+
+        // An AC-3 track listed ahead of the AAC one.
+        let pmt = [
+            0x00, 0x02, 0xb0, 26, 0x00, 0x01, 0xc1, 0x00, 0x00, 0xe1, 0x00, 0xf0, 0x00, //
+            0x06, 0xe1, 0x01, 0xf0, 0x03, 0x6a, 0x01, 0x00, //
+            0x0f, 0xe1, 0x02, 0xf0, 0x00, //
+            0, 0, 0, 0,
+        ];
+        let mut audio = None;
+        parse_pmt(&pmt, true, &mut audio);
+        assert_eq!(audio, Some((0x102, TsAudioCodec::Aac)));
+        let mut ac3_only = pmt;
+        ac3_only[21] = 0x1b;
+        let mut audio = None;
+        parse_pmt(&ac3_only, true, &mut audio);
+        assert_eq!(audio, Some((0x101, TsAudioCodec::Ac3)));
+    }
+
+    #[test]
+    fn selects_segments_by_program_date_time() {
+        // A recording that was down for a minute after its second segment.
+        let text = "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXT-X-MEDIA-SEQUENCE:0\n\
+            #EXTINF:6.0,\na.ts\n\
+            #EXT-X-PROGRAM-DATE-TIME:2024-05-01T12:00:06Z\n#EXTINF:6.0,\nb.ts\n\
+            #EXT-X-PROGRAM-DATE-TIME:2024-05-01T12:01:12Z\n#EXTINF:6.0,\nc.ts\n\
+            #EXT-X-ENDLIST\n";
+        let playlist = MediaPlaylist::try_from(text).unwrap().into_owned();
+        let base_url = Url::parse("https://example.com/live/index.m3u8").unwrap();
+        let noon = 1_714_564_800.0;
+
+        let selected = select_segments(&playlist, &base_url, noon + 73.0, noon + 75.0, true).unwrap();
+        let urls: Vec<&str> = selected.iter().map(|segment| segment.url.path()).collect();
+        assert_eq!(urls, ["/live/b.ts", "/live/c.ts"]);
+        assert_eq!(selected[1].start_time, noon + 72.0);
+        // The untagged first segment counts back from the second.
+        let selected = select_segments(&playlist, &base_url, noon + 1.0, noon + 2.0, true).unwrap();
+        assert_eq!(selected[0].start_time, noon);
+        assert!(select_segments(&playlist, &base_url, 73.0, 75.0, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn refuses_stream_times_in_sliding_playlists() {
+        let text = "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXT-X-MEDIA-SEQUENCE:120\n\
+            #EXT-X-PROGRAM-DATE-TIME:2024-05-01T12:12:00Z\n#EXTINF:6.0,\na.ts\n#EXTINF:6.0,\nb.ts\n";
+        let playlist = MediaPlaylist::try_from(text).unwrap().into_owned();
+        let base_url = Url::parse("https://example.com/live/index.m3u8").unwrap();
+        let code = |result: anyhow::Result<_>| result.map_err(|err| classify_clip_error(&err).1).err();
+
+        assert_eq!(code(select_segments(&playlist, &base_url, 0.0, 5.0, false)), Some("live_stream"));
+        let noon = 1_714_564_800.0;
+        assert_eq!(code(select_segments(&playlist, &base_url, noon, noon + 5.0, true)), Some("outside_live_window"));
+        assert_eq!(code(select_segments(&playlist, &base_url, noon + 730.0, noon + 735.0, true)), None);
+    }
+
+    #[test]
+    fn fades_both_ends() {
+        let mut samples = vec![1000i16; 20];
+        // 10 stereo frames at 1 kHz with a 2 ms fade.
+        apply_fade(&mut samples, 1000, 2, 2);
+        assert_eq!(&samples[..4], &[0, 0, 500, 500]);
+        assert_eq!(&samples[4..16], &[1000; 12]);
+        assert_eq!(&samples[16..], &[500, 500, 0, 0]);
+    }
+
+    #[test]
+    fn lists_master_renditions() {
+        let text = "#EXTM3U\n\
+            #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",LANGUAGE=\"en\",URI=\"en.m3u8\"\n\
+            #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"Japanese\",LANGUAGE=\"ja\",DEFAULT=YES,AUTOSELECT=YES,URI=\"ja.m3u8\"\n\
+            #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"ac3\",NAME=\"Japanese\",LANGUAGE=\"ja\",URI=\"ja-ac3.m3u8\"\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=1000000,AUDIO=\"aac\"\n\
+            video.m3u8\n";
+        let master = MasterPlaylist::try_from(text).unwrap().into_owned();
+        let renditions = master_renditions(&master);
+        assert_eq!(renditions.len(), 3);
+        assert!(renditions[1].selected && renditions[1].is_default);
+        assert!(!renditions[0].selected && !renditions[2].selected);
+
+        let base = Url::parse("https://example.com/master.m3u8").unwrap();
+        let audio = AudioRendition { name: Some("japanese".to_string()), group: Some("ac3".to_string()), ..Default::default() };
+        let url = select_master_variant(&master, &base, &audio).unwrap();
+        assert_eq!(url.as_str(), "https://example.com/ja-ac3.m3u8");
+        let audio = AudioRendition { lang: Some("en".to_string()), ..Default::default() };
+        let url = select_master_variant(&master, &base, &audio).unwrap();
+        assert_eq!(url.as_str(), "https://example.com/en.m3u8");
+    }
+
+    #[test]
+    fn matches_audio_languages() {
+        assert!(language_matches("ja-JP", "ja"));
+        assert!(language_matches("JA", "ja"));
+        assert!(language_matches("pt_BR", "pt-br"));
+        assert!(!language_matches("pt-PT", "pt-BR"));
+        assert!(!language_matches("jav", "ja"));
+    }
+
+    #[test]
+    fn remixes_channels() {
+        assert_eq!(remix(&[100, 300, -100, 100], 2, 1), [200, 0]);
+        assert_eq!(remix(&[100, -50], 1, 2), [100, 100, -50, -50]);
+        // 5.1 in L R C LFE Ls Rs order folds to L+C+Ls and R+LFE+Rs.
+        assert_eq!(remix(&[60, 0, 30, 0, 0, 30], 6, 2), [30, 10]);
+    }
+
+    #[test]
+    fn classifies_clip_errors() {
+        let err = rejected("unsupported_codec", "Opus").context("Segment 3");
+        assert_eq!(classify_clip_error(&err), (StatusCode::UNPROCESSABLE_ENTITY, "unsupported_codec"));
+        let err = anyhow::anyhow!("Audio decode error");
+        assert_eq!(classify_clip_error(&err), (StatusCode::INTERNAL_SERVER_ERROR, "clip_failed"));
+    }
+
+    #[test]
+    fn mp3_depends_on_the_feature() {
+        assert!(ClipFormat::Wav.check_available().is_ok());
+        let mp3 = ClipFormat::Mp3.check_available();
+        assert_eq!(mp3.is_ok(), cfg!(feature = "mp3"));
+        if let Err(err) = mp3 {
+            assert_eq!(classify_clip_error(&err), (StatusCode::UNPROCESSABLE_ENTITY, "unsupported_format"));
         }
     }
 
-    PreparedAudio { data, hint_extension, first_pts: None, force_segment_start: false }
+    #[test]
+    fn computes_waveform_buckets() {
+        let (peaks, rms) = waveform(&[16384, -16384, -32768, 0], 2);
+        assert_eq!(peaks, [0.5, 1.0]);
+        assert_eq!(rms[0], 0.5);
+        assert!((rms[1] - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+        // Fewer samples than points.
+        assert_eq!(waveform(&[-8192], 4).0, [0.25]);
+    }
 }
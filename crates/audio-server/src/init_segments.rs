@@ -0,0 +1,106 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+const DEFAULT_MAX_MB: usize = 16;
+
+struct Entry {
+    data: Arc<[u8]>,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    map: HashMap<String, Entry>,
+    bytes: usize,
+    clock: u64,
+}
+
+/// Init segments (`EXT-X-MAP`, DASH initialization) shared across requests, so that mining one
+/// episode fetches its init segment once rather than once per clip. Keyed by URL and byte
+/// range; the least recently used are dropped past the size limit.
+#[derive(Clone)]
+pub struct InitSegmentCache {
+    max_bytes: usize,
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl InitSegmentCache {
+    /// Holds up to `MANATAN_INIT_CACHE_MB` megabytes (default 16).
+    pub fn from_env() -> Self {
+        let max_mb = std::env::var("MANATAN_INIT_CACHE_MB")
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(DEFAULT_MAX_MB);
+        Self::new(max_mb.max(1) * 1024 * 1024)
+    }
+
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            entries: Arc::new(Mutex::new(Entries::default())),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<Arc<[u8]>> {
+        let mut entries = self.entries.lock().ok()?;
+        entries.clock += 1;
+        let clock = entries.clock;
+        let entry = entries.map.get_mut(key)?;
+        entry.last_used = clock;
+        Some(entry.data.clone())
+    }
+
+    pub fn insert(&self, key: String, data: Arc<[u8]>) {
+        if data.len() > self.max_bytes {
+            return;
+        }
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        entries.clock += 1;
+        let entry = Entry {
+            last_used: entries.clock,
+            data,
+        };
+        entries.bytes += entry.data.len();
+        if let Some(replaced) = entries.map.insert(key, entry) {
+            entries.bytes -= replaced.data.len();
+        }
+        while entries.bytes > self.max_bytes {
+            let Some(oldest) = entries
+                .map
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(removed) = entries.map.remove(&oldest) {
+                entries.bytes -= removed.data.len();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::InitSegmentCache;
+
+    #[test]
+    fn drops_least_recently_used() {
+        let cache = InitSegmentCache::new(10);
+        cache.insert("a".to_string(), Arc::from(&[1u8; 4][..]));
+        cache.insert("b".to_string(), Arc::from(&[2u8; 4][..]));
+        assert!(cache.get("a").is_some());
+        cache.insert("c".to_string(), Arc::from(&[3u8; 4][..]));
+        assert!(cache.get("b").is_none());
+        assert_eq!(cache.get("a").as_deref(), Some(&[1u8; 4][..]));
+        assert!(cache.get("c").is_some());
+        cache.insert("d".to_string(), Arc::from(&[4u8; 11][..]));
+        assert!(cache.get("d").is_none());
+    }
+}
@@ -0,0 +1,116 @@
+//! Joins decoded segments into one clip at sample precision.
+
+/// Segments placed further than this from where the previous one ended are assumed to follow a
+/// timestamp discontinuity and are appended as they are.
+const TOLERANCE_SECONDS: f64 = 0.1;
+
+/// Lays decoded segments end to end on the clip's timeline. Each one is placed by the time of
+/// its first frame against a count of the frames output so far, so rounding at segment edges
+/// and segments that overlap by a few frames neither repeat nor drop audio.
+pub struct SegmentJoiner {
+    start: f64,
+    sample_rate: f64,
+    channels: usize,
+    /// Length of the clip in frames.
+    total: usize,
+    /// Frames output so far.
+    written: usize,
+    crossfade: bool,
+}
+
+impl SegmentJoiner {
+    /// A clip of `duration` seconds from `start`. With `crossfade`, audio that two segments
+    /// both hold is blended from one into the other where the output can still be changed.
+    pub fn new(
+        start: f64,
+        duration: f64,
+        sample_rate: u32,
+        channels: usize,
+        crossfade: bool,
+    ) -> Self {
+        Self {
+            start,
+            sample_rate: sample_rate as f64,
+            channels: channels.max(1),
+            total: (duration.max(0.0) * sample_rate as f64).round() as usize,
+            written: 0,
+            crossfade,
+        }
+    }
+
+    /// Fits `samples`, whose first frame plays at `start_time`, after what was output before
+    /// and returns the part to append. `output` is the clip so far when it's kept in memory,
+    /// which lets an overlap be crossfaded instead of cut.
+    pub fn join(
+        &mut self,
+        mut samples: Vec<i16>,
+        start_time: f64,
+        output: Option<&mut Vec<i16>>,
+    ) -> Vec<i16> {
+        let channels = self.channels;
+        let expected = ((start_time - self.start) * self.sample_rate).round() as i64;
+        let offset = expected - self.written as i64;
+        let tolerance = (TOLERANCE_SECONDS * self.sample_rate) as i64;
+        if self.written > 0 && offset != 0 && offset.abs() <= tolerance {
+            if offset < 0 {
+                let overlap = (-offset as usize).min(samples.len() / channels);
+                let repeated: Vec<i16> = samples.drain(..overlap * channels).collect();
+                if let Some(output) = output.filter(|_| self.crossfade) {
+                    crossfade_tail(output, &repeated, channels);
+                }
+            } else {
+                let mut padded = vec![0; offset as usize * channels];
+                padded.append(&mut samples);
+                samples = padded;
+            }
+        }
+        let remaining = self.total.saturating_sub(self.written);
+        samples.truncate(remaining * channels);
+        self.written += samples.len() / channels;
+        samples
+    }
+}
+
+/// Blends the end of `output` into `incoming`, the same stretch of audio as decoded from the
+/// next segment.
+fn crossfade_tail(output: &mut [i16], incoming: &[i16], channels: usize) {
+    let frames = (incoming.len() / channels).min(output.len() / channels);
+    let tail_start = output.len() - frames * channels;
+    let incoming = &incoming[incoming.len() - frames * channels..];
+    for frame in 0..frames {
+        let weight = (frame + 1) as f32 / (frames + 1) as f32;
+        for channel in 0..channels {
+            let index = frame * channels + channel;
+            let old = output[tail_start + index] as f32;
+            let new = incoming[index] as f32;
+            output[tail_start + index] = (old + (new - old) * weight).round() as i16;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SegmentJoiner;
+
+    #[test]
+    fn joins_segments_on_the_timeline() {
+        // 1 kHz mono, a 30 ms clip from 1.0 s.
+        let mut joiner = SegmentJoiner::new(1.0, 0.03, 1000, 1, false);
+        assert_eq!(joiner.join(vec![1; 10], 1.0, None), vec![1; 10]);
+        // Repeats the last 2 frames of the first segment.
+        assert_eq!(joiner.join(vec![2; 10], 1.008, None), vec![2; 8]);
+        // Starts 2 frames after the second one ended.
+        let third = joiner.join(vec![3; 10], 1.02, None);
+        assert_eq!(third[..2], [0, 0]);
+        assert_eq!(third[2..], [3; 10]);
+        // The clip is full.
+        assert!(joiner.join(vec![4; 10], 1.03, None).is_empty());
+
+        let mut output = vec![100i16; 4];
+        let mut joiner = SegmentJoiner::new(0.0, 1.0, 1000, 2, true);
+        joiner.join(output.clone(), 0.0, None);
+        let rest = joiner.join(vec![400, 400, 700, 700], 0.001, Some(&mut output));
+        assert_eq!(output, [100, 100, 250, 250]);
+        assert_eq!(rest, [700, 700]);
+    }
+}
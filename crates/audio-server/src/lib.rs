@@ -1,14 +1,97 @@
 use std::path::PathBuf;
 
-use axum::{Router, routing::post};
+use axum::{
+    Router,
+    routing::{delete, get, post},
+};
 
+mod anki;
+mod cache;
+mod condense;
+mod dash;
+mod datetime;
+#[cfg(feature = "ffmpeg")]
+mod ffmpeg;
 mod handlers;
+mod init_segments;
+mod join;
+mod metrics;
+#[cfg(feature = "mp3")]
+mod mp3;
+mod opus;
+mod playlists;
+mod prefetch;
+mod progressive;
+mod resample;
 mod state;
+mod stretch;
+mod subtitles;
+mod upstream;
+mod vad;
+mod wav;
+
+/// OpenAPI description of the audio endpoints, relative to where the router is nested.
+#[derive(utoipa::OpenApi)]
+#[openapi(
+    paths(
+        handlers::clip_handler,
+        handlers::purge_clip_cache_handler,
+        handlers::condense_handler,
+        handlers::condense_job_handler,
+        handlers::condense_file_handler,
+        handlers::subtitles_handler,
+        handlers::renditions_handler,
+        handlers::prefetch_handler,
+        handlers::prefetch_status_handler,
+        handlers::clip_by_cue_handler,
+        handlers::waveform_handler,
+        handlers::clip_to_anki_handler,
+        handlers::health_handler,
+        handlers::metrics_handler
+    ),
+    components(schemas(
+        handlers::ClipFormat,
+        handlers::ClipSnap,
+        wav::SampleFormat,
+        handlers::ClipError,
+        handlers::PurgeResult,
+        handlers::CondenseRequest,
+        handlers::TimeRange,
+        handlers::CondenseAccepted,
+        condense::CondenseJob,
+        condense::JobStatus,
+        handlers::SubtitlesResponse,
+        handlers::RenditionsResponse,
+        handlers::Rendition,
+        prefetch::PrefetchJob,
+        subtitles::Cue,
+        handlers::WaveformResponse,
+        handlers::AudioClipQuery,
+        handlers::ClipToAnkiRequest,
+        handlers::ClipToAnkiResponse,
+        handlers::HealthResponse,
+        handlers::SuwayomiHealth
+    )),
+    tags((name = "audio", description = "Audio clip extraction from anime episodes"))
+)]
+pub struct ApiDoc;
 
 pub fn create_router(data_dir: PathBuf) -> Router {
     let state = state::AppState::new(data_dir);
 
     Router::new()
         .route("/clip", post(handlers::clip_handler))
+        .route("/clip/by-cue", get(handlers::clip_by_cue_handler))
+        .route("/clip/to-anki", post(handlers::clip_to_anki_handler))
+        .route("/clip/cache", delete(handlers::purge_clip_cache_handler))
+        .route("/condense", post(handlers::condense_handler))
+        .route("/condense/jobs/{id}", get(handlers::condense_job_handler))
+        .route("/condense/jobs/{id}/file", get(handlers::condense_file_handler))
+        .route("/subtitles", get(handlers::subtitles_handler))
+        .route("/renditions", get(handlers::renditions_handler))
+        .route("/prefetch", post(handlers::prefetch_handler).get(handlers::prefetch_status_handler))
+        .route("/waveform", get(handlers::waveform_handler))
+        .route("/health", get(handlers::health_handler))
+        .route("/metrics", get(handlers::metrics_handler))
         .with_state(state)
 }
@@ -0,0 +1,190 @@
+//! Timings and counters of the clip pipeline, served in the Prometheus text format by
+//! `GET /metrics`. Kept in process and reset on restart.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Fetching the playlist, or the index of a single file, and picking the segments.
+    Playlist,
+    /// Fetching one segment, its init section included.
+    Download,
+    /// Decoding one segment.
+    Decode,
+    /// Post-processing and encoding a whole clip.
+    Encode,
+}
+
+impl Stage {
+    const ALL: [Stage; 4] = [
+        Stage::Playlist,
+        Stage::Download,
+        Stage::Decode,
+        Stage::Encode,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Stage::Playlist => "playlist",
+            Stage::Download => "download",
+            Stage::Decode => "decode",
+            Stage::Encode => "encode",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Timing {
+    count: AtomicU64,
+    total_micros: AtomicU64,
+    max_micros: AtomicU64,
+}
+
+impl Timing {
+    fn record(&self, elapsed: Duration) {
+        let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_micros.fetch_add(micros, Ordering::Relaxed);
+        self.max_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> (u64, f64, f64) {
+        (
+            self.count.load(Ordering::Relaxed),
+            self.total_micros.load(Ordering::Relaxed) as f64 / 1e6,
+            self.max_micros.load(Ordering::Relaxed) as f64 / 1e6,
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    clips: Timing,
+    stages: [Timing; Stage::ALL.len()],
+    downloaded_bytes: AtomicU64,
+    /// Failed clips by the error code they were answered with.
+    failures: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Metrics {
+    pub fn record(&self, stage: Stage, elapsed: Duration) {
+        self.stages[stage as usize].record(elapsed);
+    }
+
+    pub fn record_download(&self, elapsed: Duration, bytes: usize) {
+        self.record(Stage::Download, elapsed);
+        self.downloaded_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// A clip built from start to finish, or failed with `failure` as its error code.
+    pub fn record_clip(&self, elapsed: Duration, failure: Option<&'static str>) {
+        self.clips.record(elapsed);
+        let Some(code) = failure else {
+            return;
+        };
+        if let Ok(mut failures) = self.failures.lock() {
+            *failures.entry(code).or_default() += 1;
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let (count, total, max) = self.clips.snapshot();
+        let _ = writeln!(
+            out,
+            "# HELP manatan_audio_clip_seconds Time to fetch and decode the audio of a clip.\n\
+             # TYPE manatan_audio_clip_seconds summary\n\
+             manatan_audio_clip_seconds_sum {total}\n\
+             manatan_audio_clip_seconds_count {count}\n\
+             # HELP manatan_audio_clip_max_seconds Slowest clip since the server started.\n\
+             # TYPE manatan_audio_clip_max_seconds gauge\n\
+             manatan_audio_clip_max_seconds {max}"
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP manatan_audio_stage_seconds Time spent in each step of building clips.\n\
+             # TYPE manatan_audio_stage_seconds summary"
+        );
+        for stage in Stage::ALL {
+            let (count, total, _) = self.stages[stage as usize].snapshot();
+            let label = stage.label();
+            let _ = writeln!(
+                out,
+                "manatan_audio_stage_seconds_sum{{stage=\"{label}\"}} {total}\n\
+                 manatan_audio_stage_seconds_count{{stage=\"{label}\"}} {count}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP manatan_audio_stage_max_seconds Slowest single step since the server started.\n\
+             # TYPE manatan_audio_stage_max_seconds gauge"
+        );
+        for stage in Stage::ALL {
+            let (_, _, max) = self.stages[stage as usize].snapshot();
+            let _ = writeln!(
+                out,
+                "manatan_audio_stage_max_seconds{{stage=\"{}\"}} {max}",
+                stage.label()
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP manatan_audio_downloaded_bytes_total Bytes of segments fetched for clips.\n\
+             # TYPE manatan_audio_downloaded_bytes_total counter\n\
+             manatan_audio_downloaded_bytes_total {}",
+            self.downloaded_bytes.load(Ordering::Relaxed)
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP manatan_audio_clip_failures_total Failed clips by error code.\n\
+             # TYPE manatan_audio_clip_failures_total counter"
+        );
+        if let Ok(failures) = self.failures.lock() {
+            for (code, count) in failures.iter() {
+                let _ = writeln!(
+                    out,
+                    "manatan_audio_clip_failures_total{{code=\"{code}\"}} {count}"
+                );
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Metrics, Stage};
+
+    #[test]
+    fn renders_prometheus_text() {
+        let metrics = Metrics::default();
+        metrics.record(Stage::Decode, Duration::from_millis(250));
+        metrics.record(Stage::Decode, Duration::from_millis(500));
+        metrics.record_download(Duration::from_millis(100), 2048);
+        metrics.record_clip(Duration::from_secs(1), None);
+        metrics.record_clip(Duration::from_secs(2), Some("upstream_unavailable"));
+
+        let text = metrics.render();
+        assert!(text.contains("manatan_audio_clip_seconds_count 2\n"));
+        assert!(text.contains("manatan_audio_stage_seconds_sum{stage=\"decode\"} 0.75\n"));
+        assert!(text.contains("manatan_audio_stage_max_seconds{stage=\"decode\"} 0.5\n"));
+        assert!(text.contains("manatan_audio_downloaded_bytes_total 2048\n"));
+        assert!(
+            text.contains("manatan_audio_clip_failures_total{code=\"upstream_unavailable\"} 1\n")
+        );
+    }
+}
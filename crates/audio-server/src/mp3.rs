@@ -0,0 +1,62 @@
+use anyhow::anyhow;
+use mp3lame_encoder::{Bitrate, Builder, FlushNoGap, InterleavedPcm, MonoPcm, Quality};
+
+/// Speech doesn't need more; a 30 second clip stays under half a megabyte.
+const BITRATE: Bitrate = Bitrate::Kbps128;
+
+/// Encodes interleaved 16-bit PCM as a constant-bitrate MP3.
+pub fn encode_mp3(samples: &[i16], sample_rate: u32, channels: usize) -> anyhow::Result<Vec<u8>> {
+    if channels == 0 || channels > 2 {
+        return Err(anyhow!("MP3 output supports mono or stereo, got {channels} channels"));
+    }
+
+    let mut builder = Builder::new().ok_or_else(|| anyhow!("Failed to create MP3 encoder"))?;
+    builder
+        .set_num_channels(channels as u8)
+        .map_err(|err| anyhow!("MP3 channels: {err}"))?;
+    builder
+        .set_sample_rate(sample_rate)
+        .map_err(|err| anyhow!("MP3 sample rate {sample_rate}: {err}"))?;
+    builder
+        .set_brate(BITRATE)
+        .map_err(|err| anyhow!("MP3 bitrate: {err}"))?;
+    builder
+        .set_quality(Quality::Good)
+        .map_err(|err| anyhow!("MP3 quality: {err}"))?;
+    let mut encoder = builder
+        .build()
+        .map_err(|err| anyhow!("Failed to initialize MP3 encoder: {err}"))?;
+
+    // The encoder writes into the spare capacity, so the buffer is sized up front.
+    let frames = samples.len() / channels;
+    let mut output = Vec::with_capacity(mp3lame_encoder::max_required_buffer_size(frames));
+    if channels == 1 {
+        encoder.encode_to_vec(MonoPcm(samples), &mut output)
+    } else {
+        encoder.encode_to_vec(InterleavedPcm(samples), &mut output)
+    }
+    .map_err(|err| anyhow!("MP3 encode failed: {err}"))?;
+
+    // LAME's documented worst case for the final frames.
+    output.reserve(7200);
+    encoder
+        .flush_to_vec::<FlushNoGap>(&mut output)
+        .map_err(|err| anyhow!("MP3 flush failed: {err}"))?;
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_whole_frames() {
+        let samples = vec![0i16; 44_100 * 2];
+        let mp3 = encode_mp3(&samples, 44_100, 2).expect("encode");
+        // Every MPEG audio frame starts with an 11-bit sync word.
+        assert!(mp3.len() > 1000);
+        assert_eq!((mp3[0], mp3[1] & 0xE0), (0xFF, 0xE0));
+        assert!(encode_mp3(&samples, 44_100, 3).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+//! Opus carried in MPEG-TS, rewrapped as Ogg Opus so that a decoder that reads files can take
+//! it.
+
+/// Vendor string of the `OpusTags` header.
+const VENDOR: &[u8] = b"manatan";
+/// Any value works for a single logical stream.
+const SERIAL: u32 = 0x4d41_4e41;
+
+/// An access unit taken out of the elementary stream.
+pub struct AccessUnit<'a> {
+    pub packet: &'a [u8],
+    /// 48 kHz samples to drop from the start of the packet, set on the first of a stream.
+    pub start_trim: u16,
+}
+
+/// Splits an Opus elementary stream into its packets. Each one follows a control header: the
+/// 11-bit prefix 0x3ff, flags, the packet size as a run of bytes summed until one below 255,
+/// then the optional trims and extension.
+pub fn access_units(data: &[u8]) -> Vec<AccessUnit<'_>> {
+    let mut units = Vec::new();
+    let mut i = 0;
+    while i + 2 <= data.len() {
+        if data[i] != 0x7f || data[i + 1] & 0xe0 != 0xe0 {
+            i += 1;
+            continue;
+        }
+        let flags = data[i + 1];
+        let mut position = i + 2;
+        let mut size = 0usize;
+        loop {
+            let Some(&byte) = data.get(position) else {
+                return units;
+            };
+            position += 1;
+            size += byte as usize;
+            if byte != 0xff {
+                break;
+            }
+        }
+        let mut start_trim = 0;
+        if flags & 0x10 != 0 {
+            let Some(bytes) = data.get(position..position + 2) else {
+                return units;
+            };
+            start_trim = u16::from_be_bytes([bytes[0], bytes[1]]) & 0x1fff;
+            position += 2;
+        }
+        if flags & 0x08 != 0 {
+            position += 2;
+        }
+        if flags & 0x04 != 0 {
+            let Some(&len) = data.get(position) else {
+                return units;
+            };
+            position += 1 + len as usize;
+        }
+        let Some(packet) = data.get(position..position + size) else {
+            return units;
+        };
+        units.push(AccessUnit { packet, start_trim });
+        i = position + size;
+    }
+    units
+}
+
+/// Stream and coupled stream counts and the channel mapping of RFC 7845 for 3 to 8 channels.
+fn vorbis_mapping(channels: u8) -> Option<(u8, u8, &'static [u8])> {
+    Some(match channels {
+        3 => (2, 1, &[0, 2, 1]),
+        4 => (2, 2, &[0, 1, 2, 3]),
+        5 => (3, 2, &[0, 4, 1, 2, 3]),
+        6 => (4, 2, &[0, 4, 1, 2, 3, 5]),
+        7 => (4, 3, &[0, 4, 1, 2, 3, 5, 6]),
+        8 => (5, 3, &[0, 6, 1, 2, 3, 4, 5, 7]),
+        _ => return None,
+    })
+}
+
+/// An Ogg Opus file holding the access units of `data`, or `None` without any or for a channel
+/// layout Ogg Opus can't describe.
+pub fn to_ogg(data: &[u8], channels: u8) -> Option<Vec<u8>> {
+    let units = access_units(data);
+    let first = units.first()?;
+
+    let mut head = b"OpusHead".to_vec();
+    head.extend_from_slice(&[1, channels]);
+    head.extend_from_slice(&first.start_trim.to_le_bytes());
+    head.extend_from_slice(&48_000u32.to_le_bytes());
+    head.extend_from_slice(&0i16.to_le_bytes());
+    match channels {
+        1 | 2 => head.push(0),
+        _ => {
+            let (streams, coupled, mapping) = vorbis_mapping(channels)?;
+            head.extend_from_slice(&[1, streams, coupled]);
+            head.extend_from_slice(mapping);
+        }
+    }
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend_from_slice(&(VENDOR.len() as u32).to_le_bytes());
+    tags.extend_from_slice(VENDOR);
+    tags.extend_from_slice(&0u32.to_le_bytes());
+
+    let mut output = Vec::new();
+    write_page(&mut output, &head, 0x02, 0, 0);
+    write_page(&mut output, &tags, 0, 0, 1);
+    let mut granule = 0u64;
+    for (index, unit) in units.iter().enumerate() {
+        granule += packet_samples(unit.packet);
+        let flags = if index + 1 == units.len() { 0x04 } else { 0 };
+        write_page(&mut output, unit.packet, flags, granule, index as u32 + 2);
+    }
+    Some(output)
+}
+
+/// 48 kHz samples in a packet, from the frame size its TOC byte gives and the frame count.
+fn packet_samples(packet: &[u8]) -> u64 {
+    let Some(&toc) = packet.first() else {
+        return 0;
+    };
+    let config = (toc >> 3) as usize;
+    let frame = match config {
+        // SILK
+        0..=11 => [480, 960, 1920, 2880][config % 4],
+        // Hybrid
+        12..=15 => [480, 960][config % 2],
+        // CELT
+        _ => [120, 240, 480, 960][config % 4],
+    };
+    let frames = match toc & 3 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => packet.get(1).map_or(0, |count| (count & 0x3f) as u64),
+    };
+    frame * frames
+}
+
+/// Writes one packet as a page of its own. Packets of a TS stream stay well under the 65025
+/// bytes a page can hold.
+fn write_page(output: &mut Vec<u8>, packet: &[u8], flags: u8, granule: u64, sequence: u32) {
+    let start = output.len();
+    output.extend_from_slice(b"OggS");
+    output.extend_from_slice(&[0, flags]);
+    output.extend_from_slice(&granule.to_le_bytes());
+    output.extend_from_slice(&SERIAL.to_le_bytes());
+    output.extend_from_slice(&sequence.to_le_bytes());
+    output.extend_from_slice(&0u32.to_le_bytes());
+    let laces = packet.len() / 255 + 1;
+    output.push(laces as u8);
+    output.extend(std::iter::repeat_n(255u8, laces - 1));
+    output.push((packet.len() % 255) as u8);
+    output.extend_from_slice(packet);
+    let crc = crc32(&output[start..]);
+    output[start + 22..start + 26].copy_from_slice(&crc.to_le_bytes());
+}
+
+/// The CRC of Ogg pages: polynomial 0x04c11db7, no reflection, zero initial value.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for &byte in data {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{access_units, crc32, packet_samples, to_ogg};
+
+    #[test]
+    fn rewraps_access_units_as_ogg() {
+        assert_eq!(crc32(b"123456789"), 0x89a1_897f);
+        // A 20 ms CELT packet, stereo, one frame.
+        assert_eq!(packet_samples(&[0xfc, 0]), 960);
+
+        // The first unit carries a start trim of 312 samples; the second is 300 bytes long.
+        let mut data = vec![0x7f, 0xf0, 3, 0x01, 0x38, 0xfc, 1, 2];
+        data.extend_from_slice(&[0x7f, 0xe0, 0xff, 45]);
+        data.extend(std::iter::repeat_n(0xfc, 300));
+        let units = access_units(&data);
+        assert_eq!(units.len(), 2);
+        assert_eq!(
+            (units[0].packet, units[0].start_trim),
+            (&[0xfc, 1, 2][..], 312)
+        );
+        assert_eq!(units[1].packet.len(), 300);
+
+        let ogg = to_ogg(&data, 2).unwrap();
+        assert_eq!(&ogg[..4], b"OggS");
+        assert_eq!(
+            ogg.windows(8)
+                .filter(|window| window == b"OpusHead")
+                .count(),
+            1
+        );
+        // Head, tags and two audio pages, the last marked as the end of the stream.
+        let pages: Vec<usize> = (0..ogg.len() - 4)
+            .filter(|&i| &ogg[i..i + 4] == b"OggS")
+            .collect();
+        assert_eq!(pages.len(), 4);
+        assert_eq!(ogg[pages[3] + 5], 0x04);
+        assert!(to_ogg(&data, 9).is_none());
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use hls_m3u8::MediaPlaylist;
+use url::Url;
+
+const DEFAULT_TTL_SECS: u64 = 60;
+
+struct Entry {
+    fetched_at: Instant,
+    playlist: Arc<MediaPlaylist<'static>>,
+    url: Url,
+}
+
+/// Parsed media playlists of recently clipped episodes, so that clipping one line after
+/// another skips fetching the master and media playlists again.
+#[derive(Clone)]
+pub struct PlaylistCache {
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<String, Entry>>>,
+}
+
+impl PlaylistCache {
+    /// Keeps playlists for `MANATAN_PLAYLIST_CACHE_SECS` (default 60); 0 turns the cache off.
+    pub fn from_env() -> Self {
+        let ttl = std::env::var("MANATAN_PLAYLIST_CACHE_SECS")
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(DEFAULT_TTL_SECS);
+        Self::new(Duration::from_secs(ttl))
+    }
+
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The media playlist stored under `key` and the URL its segments are relative to, unless
+    /// it has expired.
+    pub fn get(&self, key: &str) -> Option<(Arc<MediaPlaylist<'static>>, Url)> {
+        let entries = self.entries.lock().ok()?;
+        let entry = entries.get(key)?;
+        (entry.fetched_at.elapsed() < self.ttl).then(|| (entry.playlist.clone(), entry.url.clone()))
+    }
+
+    pub fn insert(&self, key: String, playlist: Arc<MediaPlaylist<'static>>, url: Url) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        entries.retain(|_, entry| entry.fetched_at.elapsed() < self.ttl);
+        entries.insert(
+            key,
+            Entry {
+                fetched_at: Instant::now(),
+                playlist,
+                url,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use hls_m3u8::MediaPlaylist;
+    use url::Url;
+
+    use super::PlaylistCache;
+
+    #[test]
+    fn expires_playlists() {
+        let text = "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6.0,\na.ts\n#EXT-X-ENDLIST\n";
+        let playlist = Arc::new(MediaPlaylist::try_from(text).unwrap().into_owned());
+        let url = Url::parse("https://example.com/index.m3u8").unwrap();
+
+        let cache = PlaylistCache::new(Duration::from_secs(60));
+        cache.insert("1/2/0".to_string(), playlist.clone(), url.clone());
+        assert!(cache.get("1/2/0").is_some());
+        assert!(cache.get("1/3/0").is_none());
+
+        let disabled = PlaylistCache::new(Duration::ZERO);
+        disabled.insert("1/2/0".to_string(), playlist, url);
+        assert!(disabled.get("1/2/0").is_none());
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::condense::JobStatus;
+use crate::state::now_secs;
+
+/// Length of the audio decoded into each chunk file.
+pub const CHUNK_SECONDS: f64 = 60.0;
+/// Most chunks prefetched for one episode, which also keeps a live stream from being followed
+/// forever.
+pub const MAX_CHUNKS: usize = 240;
+/// Episodes whose audio is kept before the oldest finished one is dropped. A 24 minute episode
+/// takes about 280 MB as 48 kHz stereo.
+const MAX_EPISODES: usize = 3;
+
+/// An episode's audio being decoded ahead of the clips cut from it.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct PrefetchJob {
+    pub id: u64,
+    pub status: JobStatus,
+    /// Chunks of [`CHUNK_SECONDS`] decoded so far; the last of a finished episode may be
+    /// shorter.
+    pub chunks: usize,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<usize>,
+    /// Error message of a failed job.
+    pub message: Option<String>,
+    pub created_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+#[derive(Default)]
+struct JobTable {
+    next_id: u64,
+    /// By playlist cache key, which names the episode and its audio rendition.
+    jobs: HashMap<String, PrefetchJob>,
+}
+
+/// Decoded episode audio on disk as raw 16-bit PCM, one file per minute, so that clips of an
+/// episode being mined are cut locally instead of fetched and decoded again. Prefetches run
+/// one at a time.
+#[derive(Clone)]
+pub struct Prefetches {
+    dir: PathBuf,
+    table: Arc<Mutex<JobTable>>,
+    run_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Prefetches {
+    /// Chunks go to `data_dir/prefetch`; those of a previous run are removed since their jobs
+    /// are gone.
+    pub fn new(data_dir: &std::path::Path) -> Self {
+        let dir = data_dir.join("prefetch");
+        let _ = fs::remove_dir_all(&dir);
+        Self {
+            dir,
+            table: Arc::new(Mutex::new(JobTable::default())),
+            run_lock: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    /// The job of `key`, and whether it was just created. A failed job is replaced; any other
+    /// is returned as it is.
+    pub fn start(&self, key: &str) -> (PrefetchJob, bool) {
+        let mut table = self.table.lock().expect("lock");
+        if let Some(job) = table
+            .jobs
+            .get(key)
+            .filter(|job| job.status != JobStatus::Failed)
+        {
+            return (job.clone(), false);
+        }
+        table.next_id += 1;
+        let job = PrefetchJob {
+            id: table.next_id,
+            status: JobStatus::Queued,
+            chunks: 0,
+            sample_rate: None,
+            channels: None,
+            message: None,
+            created_at: now_secs(),
+            finished_at: None,
+        };
+        if let Some(replaced) = table.jobs.insert(key.to_string(), job.clone()) {
+            let _ = fs::remove_dir_all(self.job_dir(replaced.id));
+        }
+        self.prune(&mut table);
+        (job, true)
+    }
+
+    pub fn get(&self, key: &str) -> Option<PrefetchJob> {
+        self.table.lock().expect("lock").jobs.get(key).cloned()
+    }
+
+    /// Held while a job runs so that queued jobs wait their turn.
+    pub fn run_lock(&self) -> Arc<tokio::sync::Mutex<()>> {
+        self.run_lock.clone()
+    }
+
+    pub fn set_running(&self, key: &str) {
+        if let Some(job) = self.table.lock().expect("lock").jobs.get_mut(key) {
+            job.status = JobStatus::Running;
+        }
+    }
+
+    /// Writes chunk `index`, which has to come right after the ones already written and share
+    /// their format.
+    pub fn write_chunk(
+        &self,
+        key: &str,
+        index: usize,
+        samples: &[i16],
+        sample_rate: u32,
+        channels: usize,
+    ) -> anyhow::Result<()> {
+        let job = self
+            .get(key)
+            .ok_or_else(|| anyhow!("Prefetch job is gone"))?;
+        if job.chunks != index {
+            return Err(anyhow!("Prefetch chunk {index} out of order"));
+        }
+        if job.sample_rate.is_some_and(|rate| rate != sample_rate)
+            || job.channels.is_some_and(|count| count != channels)
+        {
+            return Err(anyhow!("Mismatched audio formats across the episode"));
+        }
+        let dir = self.job_dir(job.id);
+        fs::create_dir_all(&dir)?;
+        let bytes: Vec<u8> = samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        fs::write(dir.join(format!("{index}.pcm")), bytes)?;
+        if let Some(job) = self.table.lock().expect("lock").jobs.get_mut(key) {
+            job.chunks = index + 1;
+            job.sample_rate = Some(sample_rate);
+            job.channels = Some(channels);
+        }
+        Ok(())
+    }
+
+    pub fn finish(&self, key: &str, result: Result<(), String>) {
+        let mut table = self.table.lock().expect("lock");
+        if let Some(job) = table.jobs.get_mut(key) {
+            job.finished_at = Some(now_secs());
+            match result {
+                Ok(()) => job.status = JobStatus::Done,
+                Err(message) => {
+                    job.status = JobStatus::Failed;
+                    job.message = Some(message);
+                }
+            }
+        }
+    }
+
+    /// Samples, rate and channel count of `start..end` when the chunks written so far cover it.
+    /// Past the end of a finished episode the range is cut short.
+    pub fn read(&self, key: &str, start: f64, end: f64) -> Option<(Vec<i16>, u32, usize)> {
+        let job = self.get(key)?;
+        let (Some(sample_rate), Some(channels)) = (job.sample_rate, job.channels) else {
+            return None;
+        };
+        let start = start.max(0.0);
+        let (first, last) = chunk_span(start, end);
+        if first >= job.chunks || (last >= job.chunks && job.status != JobStatus::Done) {
+            return None;
+        }
+        let dir = self.job_dir(job.id);
+        let mut samples = Vec::new();
+        for index in first..=last.min(job.chunks - 1) {
+            let bytes = fs::read(dir.join(format!("{index}.pcm"))).ok()?;
+            samples.extend(
+                bytes
+                    .chunks_exact(2)
+                    .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]])),
+            );
+        }
+        let offset = first as f64 * CHUNK_SECONDS;
+        let rate = sample_rate as f64;
+        let frame_index = |time: f64| ((time - offset) * rate).round().max(0.0) as usize * channels;
+        let (from, to) = (frame_index(start), frame_index(end));
+        // A short chunk is the last of the episode only once the job is done.
+        if to > samples.len() && job.status != JobStatus::Done {
+            return None;
+        }
+        let to = to.min(samples.len());
+        (to > from).then(|| (samples[from..to].to_vec(), sample_rate, channels))
+    }
+
+    fn job_dir(&self, id: u64) -> PathBuf {
+        self.dir.join(id.to_string())
+    }
+
+    /// Drops the oldest finished episodes past [`MAX_EPISODES`] along with their chunks.
+    fn prune(&self, table: &mut JobTable) {
+        let mut finished: Vec<(u64, String)> = table
+            .jobs
+            .iter()
+            .filter(|(_, job)| job.finished_at.is_some())
+            .map(|(key, job)| (job.id, key.clone()))
+            .collect();
+        let excess = table
+            .jobs
+            .len()
+            .saturating_sub(MAX_EPISODES)
+            .min(finished.len());
+        finished.sort_unstable();
+        for (id, key) in finished.into_iter().take(excess) {
+            table.jobs.remove(&key);
+            let _ = fs::remove_dir_all(self.job_dir(id));
+        }
+    }
+}
+
+/// First and last chunk holding audio of `start..end`.
+fn chunk_span(start: f64, end: f64) -> (usize, usize) {
+    let first = (start / CHUNK_SECONDS).floor() as usize;
+    let last = ((end / CHUNK_SECONDS).ceil() as usize).max(first + 1) - 1;
+    (first, last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Prefetches, chunk_span};
+
+    #[test]
+    fn reads_ranges_across_chunks() {
+        assert_eq!(chunk_span(59.0, 60.0), (0, 0));
+        assert_eq!(chunk_span(59.0, 61.0), (0, 1));
+        assert_eq!(chunk_span(120.0, 120.0), (2, 2));
+
+        let data_dir =
+            std::env::temp_dir().join(format!("manatan-prefetch-{}", std::process::id()));
+        let prefetches = Prefetches::new(&data_dir);
+        let (job, created) = prefetches.start("episode");
+        assert!(created);
+        assert!(!prefetches.start("episode").1);
+        // 10 Hz mono: a full first minute, then half of one.
+        prefetches
+            .write_chunk("episode", 0, &[1; 600], 10, 1)
+            .unwrap();
+        assert!(
+            prefetches
+                .write_chunk("episode", 2, &[2; 300], 10, 1)
+                .is_err()
+        );
+        prefetches
+            .write_chunk("episode", 1, &[2; 300], 10, 1)
+            .unwrap();
+
+        let (samples, rate, channels) = prefetches.read("episode", 59.0, 61.0).unwrap();
+        assert_eq!((rate, channels), (10, 1));
+        assert_eq!(samples[..10], [1; 10]);
+        assert_eq!(samples[10..], [2; 10]);
+        // Another chunk may still follow the short one while the job runs.
+        assert!(prefetches.read("episode", 80.0, 100.0).is_none());
+        prefetches.finish("episode", Ok(()));
+        assert_eq!(prefetches.read("episode", 80.0, 100.0).unwrap().0, [2; 100]);
+        assert!(prefetches.read("episode", 130.0, 140.0).is_none());
+        assert_eq!(prefetches.get("episode").unwrap().id, job.id);
+        let _ = std::fs::remove_dir_all(data_dir);
+    }
+}
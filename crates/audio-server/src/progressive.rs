@@ -0,0 +1,948 @@
+//! Single MP4 and Matroska files served in place of a playlist. Only the container index and
+//! the byte ranges around a clip get fetched: fragmented MP4s are cut into their `sidx`
+//! subsegments and decoded like DASH segments, while plain MP4s and Matroska files have their
+//! AAC frames located through the sample tables or cues and rewrapped as ADTS.
+
+use std::iter;
+
+use anyhow::anyhow;
+
+use crate::handlers::{ADTS_SAMPLE_RATES, rejected};
+
+const EBML_MAGIC: [u8; 4] = [0x1a, 0x45, 0xdf, 0xa3];
+const SEGMENT: u32 = 0x1853_8067;
+const SEEK_HEAD: u32 = 0x114d_9b74;
+const SEEK: u32 = 0x4dbb;
+const SEEK_ID: u32 = 0x53ab;
+const SEEK_POSITION: u32 = 0x53ac;
+const INFO: u32 = 0x1549_a966;
+const TIMESTAMP_SCALE: u32 = 0x2a_d7b1;
+const TRACKS: u32 = 0x1654_ae6b;
+const TRACK_ENTRY: u32 = 0xae;
+const TRACK_NUMBER: u32 = 0xd7;
+const TRACK_TYPE: u32 = 0x83;
+const FLAG_DEFAULT: u32 = 0x88;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63a2;
+const NAME: u32 = 0x536e;
+const LANGUAGE: u32 = 0x22_b59c;
+const LANGUAGE_IETF: u32 = 0x22_b59d;
+const AUDIO: u32 = 0xe1;
+const SAMPLING_FREQUENCY: u32 = 0xb5;
+const CHANNELS: u32 = 0x9f;
+const CUES: u32 = 0x1c53_bb6b;
+const CUE_POINT: u32 = 0xbb;
+const CUE_TIME: u32 = 0xb3;
+const CUE_TRACK_POSITIONS: u32 = 0xb7;
+const CUE_CLUSTER_POSITION: u32 = 0xf1;
+const CLUSTER: u32 = 0x1f43_b675;
+const TIMESTAMP: u32 = 0xe7;
+const SIMPLE_BLOCK: u32 = 0xa3;
+const BLOCK_GROUP: u32 = 0xa0;
+const BLOCK: u32 = 0xa1;
+const MATROSKA_AUDIO_TRACK: u64 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Container {
+    Mp4,
+    Matroska,
+}
+
+pub fn sniff(head: &[u8]) -> Option<Container> {
+    if head.starts_with(&EBML_MAGIC) {
+        return Some(Container::Matroska);
+    }
+    match head.get(4..8)? {
+        b"ftyp" | b"styp" | b"moov" | b"mdat" | b"free" | b"skip" | b"wide" => Some(Container::Mp4),
+        _ => None,
+    }
+}
+
+/// The parts of an AAC `AudioSpecificConfig` an ADTS header repeats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AacConfig {
+    object_type: u8,
+    frequency_index: u8,
+    channels: u8,
+}
+
+impl AacConfig {
+    /// Reads the `AudioSpecificConfig` stored in MP4 `esds` boxes and Matroska `CodecPrivate`.
+    /// HE-AAC is described by its AAC-LC core, which is all the decoder plays anyway.
+    pub fn parse(config: &[u8]) -> Option<Self> {
+        let bits = u16::from_be_bytes([*config.first()?, *config.get(1)?]);
+        let object_type = match (bits >> 11) as u8 {
+            5 | 29 => 2,
+            object_type => object_type,
+        };
+        let frequency_index = ((bits >> 7) & 0x0f) as u8;
+        let channels = ((bits >> 3) & 0x0f) as u8;
+        Self::new(object_type, frequency_index, channels)
+    }
+
+    /// AAC-LC at `sample_rate`, for Matroska tracks without `CodecPrivate`.
+    pub fn from_rate(sample_rate: u32, channels: u8) -> Option<Self> {
+        let frequency_index = ADTS_SAMPLE_RATES
+            .iter()
+            .position(|rate| *rate == sample_rate)?;
+        Self::new(2, frequency_index as u8, channels)
+    }
+
+    fn new(object_type: u8, frequency_index: u8, channels: u8) -> Option<Self> {
+        // ADTS has two bits for the profile and no escape for explicit sample rates.
+        let valid = (1..=4).contains(&object_type)
+            && (frequency_index as usize) < ADTS_SAMPLE_RATES.len()
+            && channels < 8;
+        valid.then_some(Self {
+            object_type,
+            frequency_index,
+            channels,
+        })
+    }
+
+    pub fn sample_rate(self) -> u32 {
+        ADTS_SAMPLE_RATES[self.frequency_index as usize]
+    }
+
+    fn adts_header(self, payload_len: usize) -> [u8; 7] {
+        let frame_len = payload_len + 7;
+        [
+            0xff,
+            0xf1,
+            ((self.object_type - 1) << 6) | (self.frequency_index << 2) | (self.channels >> 2),
+            ((self.channels & 3) << 6) | ((frame_len >> 11) & 3) as u8,
+            (frame_len >> 3) as u8,
+            (((frame_len & 7) << 5) as u8) | 0x1f,
+            0xfc,
+        ]
+    }
+}
+
+/// Prefixes every raw AAC frame with an ADTS header.
+pub fn to_adts<'a>(config: AacConfig, frames: impl IntoIterator<Item = &'a [u8]>) -> Vec<u8> {
+    let mut output = Vec::new();
+    for frame in frames {
+        output.extend_from_slice(&config.adts_header(frame.len()));
+        output.extend_from_slice(frame);
+    }
+    output
+}
+
+/// An audio frame located in the file but not fetched yet.
+pub struct Frame {
+    /// Presentation time in seconds.
+    pub time: f64,
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Groups frames into byte ranges to request, merging neighbours less than `max_gap` bytes
+/// apart so interleaved video doesn't turn every audio chunk into its own request. Returns
+/// `(start, end, frame count)` per range.
+pub fn frame_ranges(frames: &[Frame], max_gap: u64) -> Vec<(u64, u64, usize)> {
+    let mut ranges: Vec<(u64, u64, usize)> = Vec::new();
+    for frame in frames {
+        let frame_end = frame.offset + frame.size;
+        match ranges.last_mut() {
+            Some((_, end, count)) if frame.offset >= *end && frame.offset - *end <= max_gap => {
+                *end = frame_end;
+                *count += 1;
+            }
+            _ => ranges.push((frame.offset, frame_end, 1)),
+        }
+    }
+    ranges
+}
+
+pub struct BoxHeader {
+    pub kind: [u8; 4],
+    pub header_len: u64,
+    /// Whole box size, header included.
+    pub size: u64,
+}
+
+/// Reads the MP4 box header at the start of `data`. `remaining` is the length of the file
+/// from there on, for a box that extends to the end of the file.
+pub fn box_header(data: &[u8], remaining: u64) -> Option<BoxHeader> {
+    let size = u32_at(data, 0)? as u64;
+    let kind: [u8; 4] = data.get(4..8)?.try_into().ok()?;
+    let (size, header_len) = match size {
+        0 => (remaining, 8),
+        1 => (u64_at(data, 8)?, 16),
+        size => (size, 8),
+    };
+    (size >= header_len).then_some(BoxHeader {
+        kind,
+        header_len,
+        size,
+    })
+}
+
+/// The audio track of a `moov` box.
+pub struct Mp4Audio {
+    /// Fragmented files keep their samples in `moof` boxes and have no frame table.
+    pub fragmented: bool,
+    /// `None` unless the track is AAC.
+    pub config: Option<AacConfig>,
+    pub frames: Vec<Frame>,
+}
+
+/// Parses the body of a `moov` box.
+pub fn parse_moov(moov: &[u8]) -> anyhow::Result<Mp4Audio> {
+    let fragmented = child_box(moov, b"mvex").is_some();
+    let trak =
+        audio_trak(moov).ok_or_else(|| rejected("no_audio", "No audio track in MP4 file"))?;
+    let timescale =
+        trak_timescale(trak).ok_or_else(|| anyhow!("MP4 audio track has no timescale"))?;
+    let stbl = box_path(trak, &[b"mdia", b"minf", b"stbl"])
+        .ok_or_else(|| anyhow!("MP4 audio track has no sample table"))?;
+
+    // An `mp4a` sample entry has 28 bytes of fields before its child boxes.
+    let config = child_box(stbl, b"stsd")
+        .and_then(|stsd| stsd.get(8..))
+        .and_then(|entries| child_box(entries, b"mp4a"))
+        .and_then(|mp4a| mp4a.get(28..))
+        .and_then(|mp4a| child_box(mp4a, b"esds"))
+        .and_then(audio_specific_config)
+        .and_then(AacConfig::parse);
+    let frames = if fragmented {
+        Vec::new()
+    } else {
+        sample_frames(stbl, timescale).ok_or_else(|| anyhow!("Invalid MP4 sample table"))?
+    };
+
+    Ok(Mp4Audio {
+        fragmented,
+        config,
+        frames,
+    })
+}
+
+fn audio_trak(moov: &[u8]) -> Option<&[u8]> {
+    boxes(moov)
+        .filter(|(kind, _)| kind == b"trak")
+        .map(|(_, body)| body)
+        .find(|trak| {
+            box_path(trak, &[b"mdia", b"hdlr"]).and_then(|hdlr| hdlr.get(8..12))
+                == Some(&b"soun"[..])
+        })
+}
+
+fn trak_timescale(trak: &[u8]) -> Option<u32> {
+    let mdhd = box_path(trak, &[b"mdia", b"mdhd"])?;
+    if mdhd.first() == Some(&1) {
+        u32_at(mdhd, 20)
+    } else {
+        u32_at(mdhd, 12)
+    }
+    .filter(|timescale| *timescale > 0)
+}
+
+/// Decode time in seconds of the first audio sample in an fMP4/CMAF media segment, read from
+/// the `tfdt` of its audio track fragment. `data` is the init segment followed by the media
+/// segment, since the timescale is in the init segment's `moov`; without one, or without a
+/// `tfdt`, the earliest presentation time of a `sidx` in front of the fragments is used.
+pub fn fragment_start(data: &[u8]) -> Option<f64> {
+    if !matches!(
+        data.get(4..8),
+        Some(b"ftyp" | b"styp" | b"moov" | b"sidx" | b"moof")
+    ) {
+        return None;
+    }
+    // Track ID and timescale of the audio track.
+    let mut audio: Option<(u32, u32)> = None;
+    let mut sidx_start = None;
+    for (kind, body) in boxes(data) {
+        match &kind {
+            b"moov" => {
+                audio = audio_trak(body).and_then(|trak| {
+                    let tkhd = child_box(trak, b"tkhd")?;
+                    let track_id = u32_at(tkhd, if tkhd.first() == Some(&1) { 20 } else { 12 })?;
+                    Some((track_id, trak_timescale(trak)?))
+                });
+            }
+            b"sidx" if sidx_start.is_none() => {
+                sidx_start = sidx_earliest_time(body, audio.map(|(track_id, _)| track_id));
+            }
+            b"moof" => {
+                let Some((track_id, timescale)) = audio else {
+                    break;
+                };
+                let decode_time = boxes(body)
+                    .filter(|(kind, _)| kind == b"traf")
+                    .map(|(_, traf)| traf)
+                    .find(|traf| {
+                        child_box(traf, b"tfhd").and_then(|tfhd| u32_at(tfhd, 4)) == Some(track_id)
+                    })
+                    .and_then(|traf| child_box(traf, b"tfdt"))
+                    .and_then(|tfdt| {
+                        if tfdt.first() == Some(&1) {
+                            u64_at(tfdt, 4)
+                        } else {
+                            u32_at(tfdt, 4).map(u64::from)
+                        }
+                    });
+                if let Some(decode_time) = decode_time {
+                    return Some(decode_time as f64 / timescale as f64);
+                }
+                break;
+            }
+            _ => {}
+        }
+    }
+    sidx_start
+}
+
+/// Earliest presentation time in seconds of a `sidx` box body, unless it indexes a track other
+/// than `track_id`.
+fn sidx_earliest_time(sidx: &[u8], track_id: Option<u32>) -> Option<f64> {
+    let reference_id = u32_at(sidx, 4)?;
+    if track_id.is_some_and(|track_id| track_id != reference_id) {
+        return None;
+    }
+    let timescale = u32_at(sidx, 8).filter(|timescale| *timescale > 0)?;
+    let time = if sidx.first() == Some(&0) {
+        u32_at(sidx, 12).map(u64::from)
+    } else {
+        u64_at(sidx, 12)
+    }?;
+    Some(time as f64 / timescale as f64)
+}
+
+fn sample_frames(stbl: &[u8], timescale: u32) -> Option<Vec<Frame>> {
+    let stsz = child_box(stbl, b"stsz")?;
+    let fixed_size = u32_at(stsz, 4)?;
+    let count = u32_at(stsz, 8)? as usize;
+
+    let chunk_offsets: Vec<u64> = if let Some(stco) = child_box(stbl, b"stco") {
+        (0..u32_at(stco, 4)? as usize)
+            .map(|i| u32_at(stco, 8 + i * 4).map(u64::from))
+            .collect::<Option<_>>()?
+    } else {
+        let co64 = child_box(stbl, b"co64")?;
+        (0..u32_at(co64, 4)? as usize)
+            .map(|i| u64_at(co64, 8 + i * 8))
+            .collect::<Option<_>>()?
+    };
+    let stsc = child_box(stbl, b"stsc")?;
+    let chunk_runs: Vec<(u32, u32)> = (0..u32_at(stsc, 4)? as usize)
+        .map(|i| Some((u32_at(stsc, 8 + i * 12)?, u32_at(stsc, 12 + i * 12)?)))
+        .collect::<Option<_>>()?;
+    let stts = child_box(stbl, b"stts")?;
+    let mut durations = (0..u32_at(stts, 4)? as usize)
+        .map(|i| Some((u32_at(stts, 8 + i * 8)?, u32_at(stts, 12 + i * 8)?)))
+        .collect::<Option<Vec<_>>>()?
+        .into_iter()
+        .flat_map(|(samples, duration)| iter::repeat_n(duration, samples as usize));
+
+    let mut frames = Vec::with_capacity(count);
+    let mut ticks: u64 = 0;
+    for (index, chunk_offset) in chunk_offsets.iter().enumerate() {
+        let chunk_number = index as u32 + 1;
+        let (_, samples_per_chunk) = chunk_runs
+            .iter()
+            .rev()
+            .find(|(first, _)| *first <= chunk_number)?;
+        let mut offset = *chunk_offset;
+        for _ in 0..*samples_per_chunk {
+            if frames.len() >= count {
+                return Some(frames);
+            }
+            let size = match fixed_size {
+                0 => u32_at(stsz, 12 + frames.len() * 4)? as u64,
+                size => size as u64,
+            };
+            frames.push(Frame {
+                time: ticks as f64 / timescale as f64,
+                offset,
+                size,
+            });
+            offset += size;
+            ticks += durations.next().unwrap_or(0) as u64;
+        }
+    }
+    Some(frames)
+}
+
+/// Digs the `AudioSpecificConfig` out of an `esds` box: ES_Descriptor, then
+/// DecoderConfigDescriptor, then DecoderSpecificInfo.
+fn audio_specific_config(esds: &[u8]) -> Option<&[u8]> {
+    let (tag, es) = descriptor(esds.get(4..)?)?;
+    if tag != 0x03 {
+        return None;
+    }
+    let flags = *es.get(2)?;
+    let mut position = 3;
+    if flags & 0x80 != 0 {
+        position += 2;
+    }
+    if flags & 0x40 != 0 {
+        position += 1 + *es.get(position)? as usize;
+    }
+    if flags & 0x20 != 0 {
+        position += 2;
+    }
+    let (tag, decoder_config) = descriptor(es.get(position..)?)?;
+    if tag != 0x04 {
+        return None;
+    }
+    let (tag, specific_info) = descriptor(decoder_config.get(13..)?)?;
+    (tag == 0x05).then_some(specific_info)
+}
+
+fn descriptor(data: &[u8]) -> Option<(u8, &[u8])> {
+    let tag = *data.first()?;
+    let mut len = 0usize;
+    for index in 1..=4 {
+        let byte = *data.get(index)?;
+        len = (len << 7) | (byte & 0x7f) as usize;
+        if byte & 0x80 == 0 {
+            return Some((tag, data.get(index + 1..index + 1 + len)?));
+        }
+    }
+    None
+}
+
+pub struct Subsegment {
+    pub start: u64,
+    pub end: u64,
+    /// Presentation time in seconds.
+    pub time: f64,
+}
+
+/// Parses the body of a `sidx` box; `box_end` is the file offset right after it, which the
+/// subsegment offsets count from.
+pub fn parse_sidx(sidx: &[u8], box_end: u64) -> anyhow::Result<Vec<Subsegment>> {
+    let invalid = || anyhow!("Invalid sidx box");
+    let timescale = u32_at(sidx, 8)
+        .filter(|timescale| *timescale > 0)
+        .ok_or_else(invalid)? as f64;
+    let (time, first_offset, position) = if sidx.first() == Some(&0) {
+        (
+            u32_at(sidx, 12).map(u64::from),
+            u32_at(sidx, 16).map(u64::from),
+            20,
+        )
+    } else {
+        (u64_at(sidx, 12), u64_at(sidx, 20), 28)
+    };
+    let (mut time, first_offset) = (time.ok_or_else(invalid)?, first_offset.ok_or_else(invalid)?);
+    let count = u16_at(sidx, position + 2).ok_or_else(invalid)? as usize;
+
+    let mut offset = box_end + first_offset;
+    let mut subsegments = Vec::with_capacity(count);
+    for index in 0..count {
+        let entry = position + 4 + index * 12;
+        let reference = u32_at(sidx, entry).ok_or_else(invalid)?;
+        let duration = u32_at(sidx, entry + 4).ok_or_else(invalid)?;
+        if reference & 0x8000_0000 != 0 {
+            return Err(rejected(
+                "unsupported_container",
+                "Nested sidx indexes are not supported",
+            ));
+        }
+        let size = (reference & 0x7fff_ffff) as u64;
+        subsegments.push(Subsegment {
+            start: offset,
+            end: offset + size,
+            time: time as f64 / timescale,
+        });
+        offset += size;
+        time += duration as u64;
+    }
+    Ok(subsegments)
+}
+
+fn boxes(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut rest = data;
+    iter::from_fn(move || {
+        let header = box_header(rest, rest.len() as u64)?;
+        let end = usize::try_from(header.size).ok()?.min(rest.len());
+        let body = rest.get(header.header_len as usize..end)?;
+        rest = &rest[end..];
+        Some((header.kind, body))
+    })
+}
+
+fn child_box<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes(data)
+        .find(|(found, _)| found == kind)
+        .map(|(_, body)| body)
+}
+
+fn box_path<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    path.iter()
+        .try_fold(data, |data, kind| child_box(data, kind))
+}
+
+fn u16_at(data: &[u8], position: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(position..position + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(data: &[u8], position: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(position..position + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u64_at(data: &[u8], position: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(
+        data.get(position..position + 8)?.try_into().ok()?,
+    ))
+}
+
+/// File offsets of the top-level Matroska elements the clipper reads.
+pub struct MatroskaLayout {
+    /// Offset of the segment's data; seek and cue positions are relative to it.
+    pub segment_start: u64,
+    pub info: Option<u64>,
+    pub tracks: Option<u64>,
+    pub cues: Option<u64>,
+}
+
+/// Finds the segment and, through the SeekHead or by walking the elements in `head`, where
+/// its Info, Tracks and Cues elements are.
+pub fn matroska_layout(head: &[u8]) -> anyhow::Result<MatroskaLayout> {
+    let invalid = || anyhow!("Invalid Matroska header");
+    let (_, header_len, size) = element_header(head).ok_or_else(invalid)?;
+    let segment_offset = header_len + size.ok_or_else(invalid)? as usize;
+    let (id, header_len, _) =
+        element_header(head.get(segment_offset..).ok_or_else(invalid)?).ok_or_else(invalid)?;
+    if id != SEGMENT {
+        return Err(anyhow!("Matroska file has no segment"));
+    }
+    let segment_start = (segment_offset + header_len) as u64;
+    let mut layout = MatroskaLayout {
+        segment_start,
+        info: None,
+        tracks: None,
+        cues: None,
+    };
+
+    let mut position = segment_start as usize;
+    while let Some((id, header_len, size)) = head.get(position..).and_then(element_header) {
+        let offset = Some(position as u64);
+        match id {
+            INFO => layout.info = offset,
+            TRACKS => layout.tracks = offset,
+            CUES => layout.cues = offset,
+            SEEK_HEAD => {
+                let body = head.get(position + header_len..).unwrap_or_default();
+                let body = &body[..size.map_or(body.len(), |size| (size as usize).min(body.len()))];
+                for (_, seek) in elements(body).filter(|(id, _)| *id == SEEK) {
+                    let target = child(seek, SEEK_ID).map(uint);
+                    let seek_position =
+                        child(seek, SEEK_POSITION).map(|position| segment_start + uint(position));
+                    match target.map(|target| target as u32) {
+                        Some(INFO) => layout.info = layout.info.or(seek_position),
+                        Some(TRACKS) => layout.tracks = layout.tracks.or(seek_position),
+                        Some(CUES) => layout.cues = layout.cues.or(seek_position),
+                        _ => {}
+                    }
+                }
+            }
+            CLUSTER => break,
+            _ => {}
+        }
+        let Some(size) = size else {
+            break;
+        };
+        position += header_len + size as usize;
+    }
+    Ok(layout)
+}
+
+/// Total length of the element starting at `data`, header included.
+pub fn element_len(data: &[u8]) -> Option<u64> {
+    let (_, header_len, size) = element_header(data)?;
+    Some(header_len as u64 + size?)
+}
+
+/// Nanoseconds per timestamp tick, from a whole Info element.
+pub fn timestamp_scale(info: &[u8]) -> u64 {
+    element_body(info)
+        .and_then(|body| child(body, TIMESTAMP_SCALE))
+        .map(uint)
+        .unwrap_or(1_000_000)
+}
+
+pub struct MatroskaTrack {
+    pub number: u64,
+    pub codec: String,
+    pub lang: Option<String>,
+    pub name: Option<String>,
+    pub default: bool,
+    /// `None` unless the track is AAC.
+    pub config: Option<AacConfig>,
+}
+
+/// The audio tracks of a whole Tracks element.
+pub fn matroska_audio_tracks(tracks: &[u8]) -> Vec<MatroskaTrack> {
+    let Some(body) = element_body(tracks) else {
+        return Vec::new();
+    };
+    let text = |entry: &[u8], id| {
+        child(entry, id).map(|value| {
+            String::from_utf8_lossy(value)
+                .trim_end_matches('\0')
+                .to_string()
+        })
+    };
+    elements(body)
+        .filter(|(id, entry)| {
+            *id == TRACK_ENTRY && child(entry, TRACK_TYPE).map(uint) == Some(MATROSKA_AUDIO_TRACK)
+        })
+        .filter_map(|(_, entry)| {
+            let number = child(entry, TRACK_NUMBER).map(uint)?;
+            let codec = text(entry, CODEC_ID).unwrap_or_default();
+            let config = if codec.starts_with("A_AAC") {
+                child(entry, CODEC_PRIVATE)
+                    .and_then(AacConfig::parse)
+                    .or_else(|| {
+                        let audio = child(entry, AUDIO)?;
+                        let sample_rate = child(audio, SAMPLING_FREQUENCY).map(float)? as u32;
+                        let channels = child(audio, CHANNELS).map_or(2, uint);
+                        AacConfig::from_rate(sample_rate, channels as u8)
+                    })
+            } else {
+                None
+            };
+            Some(MatroskaTrack {
+                number,
+                codec,
+                lang: text(entry, LANGUAGE_IETF).or_else(|| text(entry, LANGUAGE)),
+                name: text(entry, NAME),
+                default: child(entry, FLAG_DEFAULT).is_none_or(|flag| uint(flag) != 0),
+                config,
+            })
+        })
+        .collect()
+}
+
+/// `(time in seconds, cluster file offset)` of every cue point in a whole Cues element,
+/// sorted by time. Cues usually only index the video track, but clusters hold every track.
+pub fn matroska_cues(cues: &[u8], segment_start: u64, scale: u64) -> Vec<(f64, u64)> {
+    let Some(body) = element_body(cues) else {
+        return Vec::new();
+    };
+    let mut points: Vec<(f64, u64)> = elements(body)
+        .filter(|(id, _)| *id == CUE_POINT)
+        .filter_map(|(_, point)| {
+            let time = child(point, CUE_TIME).map(uint)?;
+            let positions = child(point, CUE_TRACK_POSITIONS)?;
+            let cluster = child(positions, CUE_CLUSTER_POSITION).map(uint)?;
+            Some((
+                ticks_to_seconds(time as i64, scale),
+                segment_start + cluster,
+            ))
+        })
+        .collect();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    points.dedup_by_key(|(_, cluster)| *cluster);
+    points
+}
+
+/// Frames of `track` in `data`, a run of whole clusters, with their start times.
+pub fn matroska_frames(
+    data: &[u8],
+    track: u64,
+    scale: u64,
+    frame_duration: f64,
+) -> Vec<(f64, &[u8])> {
+    let mut frames = Vec::new();
+    collect_blocks(data, 0, track, scale, frame_duration, &mut frames);
+    frames
+}
+
+/// Clusters of unknown size run to the end of `data` and contain the clusters that follow,
+/// hence the recursion.
+fn collect_blocks<'a>(
+    data: &'a [u8],
+    mut cluster_time: u64,
+    track: u64,
+    scale: u64,
+    frame_duration: f64,
+    frames: &mut Vec<(f64, &'a [u8])>,
+) {
+    for (id, body) in elements(data) {
+        let block = match id {
+            CLUSTER => {
+                collect_blocks(body, 0, track, scale, frame_duration, frames);
+                continue;
+            }
+            TIMESTAMP => {
+                cluster_time = uint(body);
+                continue;
+            }
+            SIMPLE_BLOCK => body,
+            BLOCK_GROUP => match child(body, BLOCK) {
+                Some(block) => block,
+                None => continue,
+            },
+            _ => continue,
+        };
+        let Some((block_track, track_len)) = vint(block) else {
+            continue;
+        };
+        if block_track != track {
+            continue;
+        }
+        let Some(header) = block.get(track_len..track_len + 3) else {
+            continue;
+        };
+        let relative = i16::from_be_bytes([header[0], header[1]]);
+        let time = ticks_to_seconds(cluster_time as i64 + relative as i64, scale);
+        let laced = block
+            .get(track_len + 3..)
+            .and_then(|payload| unlace(payload, (header[2] >> 1) & 3));
+        for (index, frame) in laced.into_iter().flatten().enumerate() {
+            frames.push((time + index as f64 * frame_duration, frame));
+        }
+    }
+}
+
+/// Splits a block payload by its lacing mode: none, Xiph, fixed-size or EBML.
+fn unlace(payload: &[u8], lacing: u8) -> Option<Vec<&[u8]>> {
+    if lacing == 0 {
+        return Some(vec![payload]);
+    }
+    let count = *payload.first()? as usize + 1;
+    let mut position = 1;
+    let mut sizes: Vec<usize> = Vec::with_capacity(count);
+    match lacing {
+        1 => {
+            for _ in 1..count {
+                let mut size = 0;
+                loop {
+                    let byte = *payload.get(position)?;
+                    position += 1;
+                    size += byte as usize;
+                    if byte != 255 {
+                        break;
+                    }
+                }
+                sizes.push(size);
+            }
+        }
+        2 => sizes.resize(count - 1, (payload.len() - 1) / count),
+        _ => {
+            let (first, len) = vint(payload.get(position..)?)?;
+            position += len;
+            let mut size = first as i64;
+            sizes.push(size as usize);
+            for _ in 2..count {
+                let (raw, len) = vint(payload.get(position..)?)?;
+                position += len;
+                // Signed deltas are stored with a bias of half the range.
+                size += raw as i64 - ((1i64 << (7 * len - 1)) - 1);
+                sizes.push(usize::try_from(size).ok()?);
+            }
+        }
+    }
+    let mut frames = Vec::with_capacity(count);
+    for size in sizes {
+        frames.push(payload.get(position..position + size)?);
+        position += size;
+    }
+    frames.push(payload.get(position..)?);
+    Some(frames)
+}
+
+fn ticks_to_seconds(ticks: i64, scale: u64) -> f64 {
+    ticks as f64 * scale as f64 / 1_000_000_000.0
+}
+
+/// ID (marker bits included, as IDs are usually written), header length and data size of
+/// the element at the start of `data`. The size is `None` when unknown.
+fn element_header(data: &[u8]) -> Option<(u32, usize, Option<u64>)> {
+    let id_len = data.first()?.leading_zeros() as usize + 1;
+    if id_len > 4 {
+        return None;
+    }
+    let id = data
+        .get(..id_len)?
+        .iter()
+        .fold(0u32, |id, byte| (id << 8) | *byte as u32);
+    let (size, size_len) = vint(data.get(id_len..)?)?;
+    let unknown = size == (1u64 << (7 * size_len)) - 1;
+    Some((id, id_len + size_len, (!unknown).then_some(size)))
+}
+
+fn vint(data: &[u8]) -> Option<(u64, usize)> {
+    let first = *data.first()?;
+    let len = first.leading_zeros() as usize + 1;
+    if len > 8 {
+        return None;
+    }
+    let value = data
+        .get(1..len)?
+        .iter()
+        .fold((first as u64) & (0xff >> len), |value, byte| {
+            (value << 8) | *byte as u64
+        });
+    Some((value, len))
+}
+
+fn elements(data: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+    let mut rest = data;
+    iter::from_fn(move || {
+        let (id, header_len, size) = element_header(rest)?;
+        let end = size.map_or(rest.len(), |size| {
+            (header_len as u64 + size).min(rest.len() as u64) as usize
+        });
+        let body = rest.get(header_len..end)?;
+        rest = &rest[end..];
+        Some((id, body))
+    })
+}
+
+fn element_body(element: &[u8]) -> Option<&[u8]> {
+    elements(element).next().map(|(_, body)| body)
+}
+
+fn child(data: &[u8], id: u32) -> Option<&[u8]> {
+    elements(data)
+        .find(|(found, _)| *found == id)
+        .map(|(_, body)| body)
+}
+
+fn uint(data: &[u8]) -> u64 {
+    data.iter()
+        .fold(0, |value, byte| (value << 8) | *byte as u64)
+}
+
+fn float(data: &[u8]) -> f64 {
+    match data.len() {
+        4 => f32::from_be_bytes([data[0], data[1], data[2], data[3]]) as f64,
+        8 => f64::from_be_bytes(data.try_into().unwrap_or_default()),
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AacConfig, Frame, fragment_start, frame_ranges, matroska_frames, parse_sidx, to_adts,
+        unlace,
+    };
+
+    fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut data = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(body);
+        data
+    }
+
+    #[test]
+    fn wraps_frames_as_adts() {
+        // AAC-LC, 48 kHz, stereo.
+        let config = AacConfig::parse(&[0x11, 0x90]).unwrap();
+        assert_eq!(config.sample_rate(), 48000);
+        assert_eq!(
+            to_adts(config, [&[1u8, 2, 3][..]]),
+            [0xff, 0xf1, 0x4c, 0x80, 0x01, 0x5f, 0xfc, 1, 2, 3]
+        );
+        assert_eq!(AacConfig::from_rate(48000, 2), Some(config));
+    }
+
+    #[test]
+    fn merges_nearby_frames() {
+        let frame = |offset, size| Frame {
+            time: 0.0,
+            offset,
+            size,
+        };
+        let frames = [
+            frame(100, 10),
+            frame(110, 10),
+            frame(150, 10),
+            frame(1000, 10),
+        ];
+        assert_eq!(frame_ranges(&frames, 64), [(100, 160, 3), (1000, 1010, 1)]);
+    }
+
+    #[test]
+    fn reads_sidx_references() {
+        let mut sidx = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        sidx.extend_from_slice(&1000u32.to_be_bytes());
+        sidx.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 16, 0, 0, 0, 2]);
+        for (size, duration) in [(500u32, 2000u32), (700, 2000)] {
+            sidx.extend_from_slice(&size.to_be_bytes());
+            sidx.extend_from_slice(&duration.to_be_bytes());
+            sidx.extend_from_slice(&[0x90, 0, 0, 0]);
+        }
+        let subsegments = parse_sidx(&sidx, 84).unwrap();
+        assert_eq!(subsegments.len(), 2);
+        assert_eq!((subsegments[1].start, subsegments[1].end), (600, 1300));
+        assert_eq!(subsegments[1].time, 2.0);
+    }
+
+    #[test]
+    fn reads_fragment_decode_time() {
+        // Init segment with a video track 1 and an audio track 2 at 48 kHz.
+        let trak = |track_id: u32, handler: &[u8; 4], timescale: u32| {
+            let mut tkhd = [0u8; 20];
+            tkhd[12..16].copy_from_slice(&track_id.to_be_bytes());
+            let mut mdhd = [0u8; 20];
+            mdhd[12..16].copy_from_slice(&timescale.to_be_bytes());
+            let mut hdlr = [0u8; 12];
+            hdlr[8..12].copy_from_slice(handler);
+            let mdia = [mp4_box(b"mdhd", &mdhd), mp4_box(b"hdlr", &hdlr)].concat();
+            mp4_box(
+                b"trak",
+                &[mp4_box(b"tkhd", &tkhd), mp4_box(b"mdia", &mdia)].concat(),
+            )
+        };
+        let moov = [trak(1, b"vide", 90_000), trak(2, b"soun", 48_000)].concat();
+        let traf = |track_id: u32, decode_time: u64| {
+            let tfhd = [&[0u8; 4][..], &track_id.to_be_bytes()].concat();
+            let tfdt = [&[1u8, 0, 0, 0][..], &decode_time.to_be_bytes()].concat();
+            mp4_box(
+                b"traf",
+                &[mp4_box(b"tfhd", &tfhd), mp4_box(b"tfdt", &tfdt)].concat(),
+            )
+        };
+        let moof = mp4_box(b"moof", &[traf(1, 900_000), traf(2, 504_000)].concat());
+        let data = [mp4_box(b"moov", &moov), moof.clone(), mp4_box(b"mdat", &[])].concat();
+        assert_eq!(fragment_start(&data), Some(10.5));
+
+        // Without the init segment only the sidx says when the segment starts.
+        let mut sidx = vec![0, 0, 0, 0, 0, 0, 0, 2];
+        sidx.extend_from_slice(&1000u32.to_be_bytes());
+        sidx.extend_from_slice(&10_250u32.to_be_bytes());
+        let data = [mp4_box(b"sidx", &sidx), moof].concat();
+        assert_eq!(fragment_start(&data), Some(10.25));
+        assert_eq!(
+            fragment_start(&[0xff, 0xf1, 0x50, 0x80, 0, 0x1f, 0xfc, 0]),
+            None
+        );
+    }
+
+    #[test]
+    fn reads_matroska_blocks() {
+        // A cluster at 1000 ms holding a block for track 1 at +24 ms and one for track 2.
+        let cluster = [
+            0x1f, 0x43, 0xb6, 0x75, 0x93, 0xe7, 0x82, 0x03, 0xe8, 0xa3, 0x86, 0x81, 0x00, 0x18,
+            0x80, 7, 8, 0xa3, 0x85, 0x82, 0x00, 0x00, 0x80, 9,
+        ];
+        let frames = matroska_frames(&cluster, 1, 1_000_000, 0.02);
+        assert_eq!(frames, [(1.024, &[7u8, 8][..])]);
+    }
+
+    #[test]
+    fn splits_laced_blocks() {
+        // Three frames of sizes 2, 1 and 3.
+        assert_eq!(
+            unlace(&[2, 2, 1, 1, 1, 2, 3, 3, 3], 1).unwrap(),
+            [&[1, 1][..], &[2], &[3, 3, 3]]
+        );
+        // EBML lacing: first size 2, then a delta of -1.
+        assert_eq!(
+            unlace(&[2, 0x82, 0xbe, 1, 1, 2, 3, 3, 3], 3).unwrap(),
+            [&[1, 1][..], &[2], &[3, 3, 3]]
+        );
+        assert_eq!(unlace(&[1, 1, 2, 3, 4], 2).unwrap(), [&[1, 2][..], &[3, 4]]);
+    }
+}
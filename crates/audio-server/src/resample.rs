@@ -0,0 +1,98 @@
+use std::f64::consts::PI;
+
+/// Zero crossings of the sinc on each side of an output sample. 16 keeps the passband flat to
+/// well above the range of speech at a modest cost per sample.
+const HALF_TAPS: usize = 16;
+
+/// Converts interleaved PCM from `from_rate` to `to_rate` by band-limited interpolation: every
+/// output frame is a Hann-windowed sinc sum over the input frames around it, with the cutoff
+/// lowered to the output's Nyquist frequency when downsampling so nothing aliases.
+pub fn resample(samples: &[i16], channels: usize, from_rate: u32, to_rate: u32) -> Vec<i16> {
+    let channels = channels.max(1);
+    let frames = samples.len() / channels;
+    if from_rate == to_rate || from_rate == 0 || to_rate == 0 || frames == 0 {
+        return samples.to_vec();
+    }
+    let step = from_rate as f64 / to_rate as f64;
+    let cutoff = (1.0 / step).min(1.0);
+    // Input frames the window reaches on either side, widened with the cutoff.
+    let reach = (HALF_TAPS as f64 / cutoff).ceil() as isize;
+    let out_frames = (frames as f64 / step).round() as usize;
+
+    let mut output = Vec::with_capacity(out_frames * channels);
+    let mut sums = vec![0f64; channels];
+    for out_frame in 0..out_frames {
+        let position = out_frame as f64 * step;
+        let center = position.floor() as isize;
+        sums.fill(0.0);
+        let mut weight_sum = 0.0;
+        for frame in (center - reach + 1).max(0)..=(center + reach).min(frames as isize - 1) {
+            let distance = (frame as f64 - position) * cutoff;
+            if distance.abs() >= HALF_TAPS as f64 {
+                continue;
+            }
+            let weight = sinc(distance) * (0.5 + 0.5 * (PI * distance / HALF_TAPS as f64).cos());
+            weight_sum += weight;
+            let frame = frame as usize * channels;
+            for (channel, sum) in sums.iter_mut().enumerate() {
+                *sum += samples[frame + channel] as f64 * weight;
+            }
+        }
+        // Normalising by the weights keeps the gain at one near the edges, where part of the
+        // window falls outside the clip.
+        let scale = if weight_sum.abs() > 1e-9 {
+            1.0 / weight_sum
+        } else {
+            0.0
+        };
+        output.extend(sums.iter().map(|sum| {
+            (sum * scale)
+                .round()
+                .clamp(i16::MIN as f64, i16::MAX as f64) as i16
+        }));
+    }
+    output
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resample;
+
+    fn tone(rate: u32, frequency: f64, seconds: f64) -> Vec<i16> {
+        (0..(rate as f64 * seconds) as usize)
+            .map(|i| {
+                ((i as f64 * frequency * 2.0 * std::f64::consts::PI / rate as f64).sin() * 10_000.0)
+                    as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resamples_keeping_pitch_and_level() {
+        let input = tone(22_050, 440.0, 1.0);
+        let output = resample(&input, 1, 22_050, 44_100);
+        assert_eq!(output.len(), 44_100);
+        let expected = tone(44_100, 440.0, 1.0);
+        // Away from the edges the interpolated tone matches one generated at the new rate.
+        let error = output[1000..43_000]
+            .iter()
+            .zip(&expected[1000..43_000])
+            .map(|(a, b)| (*a as i32 - *b as i32).abs())
+            .max()
+            .unwrap_or_default();
+        assert!(error < 100, "max error {error}");
+
+        let stereo: Vec<i16> = input.iter().flat_map(|sample| [*sample, 0]).collect();
+        let down = resample(&stereo, 2, 22_050, 16_000);
+        assert_eq!(down.len(), 2 * 16_000);
+        assert!(down.chunks_exact(2).all(|frame| frame[1] == 0));
+    }
+}
@@ -1,18 +1,114 @@
-use std::path::PathBuf;
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use tracing::warn;
+
+use crate::cache::ClipCache;
+use crate::condense::CondenseJobs;
+use crate::init_segments::InitSegmentCache;
+use crate::metrics::Metrics;
+use crate::playlists::PlaylistCache;
+use crate::prefetch::Prefetches;
+use crate::upstream::Upstream;
+
+const DEFAULT_MAX_CLIP_SECONDS: f64 = 30.0;
+/// Ceiling on `MANATAN_CLIP_MAX_SECONDS`, since a clip is decoded into memory as a whole.
+const MAX_CLIP_SECONDS_LIMIT: f64 = 600.0;
+const DEFAULT_MAX_SEGMENTS: usize = 128;
+const MAX_SEGMENTS_LIMIT: usize = 2048;
+
+/// How much audio a single clip may cover.
+#[derive(Clone, Copy, Debug)]
+pub struct ClipLimits {
+    /// Longest clip in seconds, padding included.
+    pub max_duration: f64,
+    /// Most playlist segments or file fragments fetched for one clip.
+    pub max_segments: usize,
+}
+
+impl ClipLimits {
+    /// Reads `MANATAN_CLIP_MAX_SECONDS` (default 30, at most 600) and
+    /// `MANATAN_CLIP_MAX_SEGMENTS` (default 128, at most 2048).
+    fn from_env() -> Self {
+        let max_duration = std::env::var("MANATAN_CLIP_MAX_SECONDS")
+            .ok()
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+            .unwrap_or(DEFAULT_MAX_CLIP_SECONDS);
+        let max_segments = std::env::var("MANATAN_CLIP_MAX_SEGMENTS")
+            .ok()
+            .and_then(|value| value.trim().parse::<usize>().ok())
+            .filter(|segments| *segments > 0)
+            .unwrap_or(DEFAULT_MAX_SEGMENTS);
+        if max_duration > MAX_CLIP_SECONDS_LIMIT || max_segments > MAX_SEGMENTS_LIMIT {
+            warn!(
+                "Clip limits capped at {MAX_CLIP_SECONDS_LIMIT} seconds and {MAX_SEGMENTS_LIMIT} segments"
+            );
+        }
+        Self {
+            max_duration: max_duration.min(MAX_CLIP_SECONDS_LIMIT),
+            max_segments: max_segments.min(MAX_SEGMENTS_LIMIT),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct AppState {
     pub suwayomi_base_url: String,
     pub data_dir: PathBuf,
+    pub clip_cache: Arc<ClipCache>,
+    /// Canonical `MANATAN_MEDIA_ROOT`; local files can only be clipped when it's set.
+    pub media_root: Option<PathBuf>,
+    pub condense_jobs: CondenseJobs,
+    pub anki_connect_url: String,
+    pub upstream: Upstream,
+    pub clip_limits: ClipLimits,
+    pub started_at: Instant,
+    pub metrics: Arc<Metrics>,
+    pub playlists: PlaylistCache,
+    pub init_segments: InitSegmentCache,
+    pub prefetches: Prefetches,
 }
 
 impl AppState {
     pub fn new(data_dir: PathBuf) -> Self {
         let suwayomi_base_url = std::env::var("MANATAN_SUWAYOMI_URL")
             .unwrap_or_else(|_| "http://127.0.0.1:4567".to_string());
+        let media_root = std::env::var_os("MANATAN_MEDIA_ROOT")
+            .filter(|root| !root.is_empty())
+            .and_then(|root| match std::fs::canonicalize(&root) {
+                Ok(root) => Some(root),
+                Err(err) => {
+                    warn!("Ignoring MANATAN_MEDIA_ROOT {}: {err}", PathBuf::from(&root).display());
+                    None
+                }
+            });
+        let anki_connect_url = std::env::var("MANATAN_ANKICONNECT_URL")
+            .unwrap_or_else(|_| crate::anki::DEFAULT_ANKI_CONNECT_URL.to_string());
         Self {
             suwayomi_base_url,
+            anki_connect_url,
+            upstream: Upstream::from_env(),
+            clip_limits: ClipLimits::from_env(),
+            started_at: Instant::now(),
+            metrics: Arc::new(Metrics::default()),
+            playlists: PlaylistCache::from_env(),
+            init_segments: InitSegmentCache::from_env(),
+            media_root,
+            clip_cache: Arc::new(ClipCache::new(&data_dir)),
+            condense_jobs: CondenseJobs::new(&data_dir),
+            prefetches: Prefetches::new(&data_dir),
             data_dir,
         }
     }
 }
+
+pub(crate) fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,133 @@
+use std::f32::consts::PI;
+
+/// Length of the overlapping windows. Around 20 ms keeps speech pitch periods inside one window
+/// without smearing consonants.
+const WINDOW_MS: usize = 20;
+/// Every n-th frame is compared when looking for the best-aligned window, which is plenty for
+/// the low frequencies that dominate the alignment.
+const CORRELATION_STEP: usize = 4;
+
+/// Changes the tempo of interleaved PCM by `speed` (0.5 is half speed) while keeping the pitch,
+/// using WSOLA: windows are read `speed` times faster or slower than they're written, each one
+/// shifted within a small tolerance to line up with the waveform of the previous one, so the
+/// overlap-add doesn't cancel out.
+pub fn time_stretch(samples: &[i16], channels: usize, sample_rate: u32, speed: f64) -> Vec<i16> {
+    let channels = channels.max(1);
+    let frames = samples.len() / channels;
+    if !speed.is_finite() || speed <= 0.0 || (speed - 1.0).abs() < 1e-3 || frames == 0 {
+        return samples.to_vec();
+    }
+    let window_len = ((sample_rate as usize * WINDOW_MS / 1000).max(64)) & !1;
+    let hop = window_len / 2;
+    let tolerance = hop / 2;
+    // Periodic Hann windows at half overlap sum to one.
+    let window: Vec<f32> = (0..window_len)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / window_len as f32).cos())
+        .collect();
+    let mono: Vec<f32> = samples
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().map(|sample| *sample as f32).sum::<f32>() / channels as f32)
+        .collect();
+
+    let out_frames = (frames as f64 / speed).round() as usize;
+    let mut output = vec![0f32; (out_frames + window_len) * channels];
+    let mut weight = vec![0f32; out_frames + window_len];
+    let mut previous: Option<usize> = None;
+    for out_pos in (0..out_frames).step_by(hop) {
+        let nominal = ((out_pos as f64 * speed) as usize).min(frames - 1);
+        let pos = match previous {
+            Some(previous) => best_alignment(&mono, previous + hop, nominal, tolerance, hop),
+            None => nominal,
+        };
+        for (i, w) in window.iter().enumerate() {
+            let src = pos + i;
+            if src >= frames {
+                break;
+            }
+            for channel in 0..channels {
+                output[(out_pos + i) * channels + channel] +=
+                    samples[src * channels + channel] as f32 * w;
+            }
+            weight[out_pos + i] += w;
+        }
+        previous = Some(pos);
+    }
+
+    output
+        .chunks_exact(channels)
+        .zip(&weight)
+        .take(out_frames)
+        .flat_map(|(frame, w)| {
+            let scale = if *w > 1e-6 { 1.0 / w } else { 0.0 };
+            frame.iter().map(move |sample| {
+                (sample * scale)
+                    .round()
+                    .clamp(i16::MIN as f32, i16::MAX as f32) as i16
+            })
+        })
+        .collect()
+}
+
+/// The start within `nominal ± tolerance` whose next `len` frames correlate best with the
+/// natural continuation of the previous window at `natural`.
+fn best_alignment(
+    mono: &[f32],
+    natural: usize,
+    nominal: usize,
+    tolerance: usize,
+    len: usize,
+) -> usize {
+    if natural + len > mono.len() {
+        return nominal;
+    }
+    let reference = &mono[natural..natural + len];
+    let last = (nominal + tolerance).min(mono.len().saturating_sub(len));
+    let mut best = (nominal, f32::NEG_INFINITY);
+    for candidate in nominal.saturating_sub(tolerance)..=last {
+        let score: f32 = reference
+            .iter()
+            .zip(&mono[candidate..candidate + len])
+            .step_by(CORRELATION_STEP)
+            .map(|(a, b)| a * b)
+            .sum();
+        if score > best.1 {
+            best = (candidate, score);
+        }
+    }
+    best.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::time_stretch;
+
+    fn zero_crossings(samples: &[i16]) -> usize {
+        samples
+            .windows(2)
+            .filter(|pair| (pair[0] < 0) != (pair[1] < 0))
+            .count()
+    }
+
+    #[test]
+    fn stretches_without_changing_pitch() {
+        let rate = 16_000;
+        let sine: Vec<i16> = (0..rate)
+            .map(|i| {
+                ((i as f32 * 440.0 * 2.0 * std::f32::consts::PI / rate as f32).sin() * 10_000.0)
+                    as i16
+            })
+            .collect();
+
+        let slow = time_stretch(&sine, 1, rate as u32, 0.5);
+        assert_eq!(slow.len(), 2 * sine.len());
+        // Twice the length at the same pitch means twice the zero crossings, give or take the
+        // odd one at a window seam.
+        let expected = 2 * zero_crossings(&sine);
+        assert!(zero_crossings(&slow).abs_diff(expected) < expected / 50);
+
+        let stereo: Vec<i16> = sine.iter().flat_map(|sample| [*sample, -*sample]).collect();
+        let fast = time_stretch(&stereo, 2, rate as u32, 2.0);
+        assert_eq!(fast.len(), stereo.len() / 2);
+        assert!(fast.chunks_exact(2).all(|frame| frame[0] == -frame[1]));
+    }
+}
@@ -0,0 +1,172 @@
+//! SRT, WebVTT and ASS/SSA subtitles, reduced to timed cues.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct Cue {
+    /// Start and end in seconds.
+    pub start: f64,
+    pub end: f64,
+    /// Plain text with formatting tags removed; lines are separated by `\n`.
+    pub text: String,
+    /// ASS `Name` field or WebVTT `<v>` voice, when given.
+    pub speaker: Option<String>,
+}
+
+/// Parses any of the supported formats, telling ASS apart by its `[Events]` section. Cues
+/// that fail to parse are skipped; the rest come back sorted by start time.
+pub fn parse(text: &str) -> Vec<Cue> {
+    let text = text.trim_start_matches('\u{feff}');
+    let mut cues = if text.lines().any(|line| line.trim().eq_ignore_ascii_case("[events]")) {
+        parse_ass(text)
+    } else {
+        parse_srt_vtt(text)
+    };
+    cues.sort_by(|a, b| a.start.total_cmp(&b.start));
+    cues
+}
+
+/// SRT and WebVTT share the `start --> end` line followed by text up to a blank line. WebVTT
+/// may add cue settings after the end time, which are ignored.
+fn parse_srt_vtt(text: &str) -> Vec<Cue> {
+    let mut cues = Vec::new();
+    let mut lines = text.lines().peekable();
+    while let Some(line) = lines.next() {
+        let Some((start, rest)) = line.split_once("-->") else {
+            continue;
+        };
+        let end = rest.split_whitespace().next().unwrap_or_default();
+        let (Some(start), Some(end)) = (parse_timestamp(start), parse_timestamp(end)) else {
+            continue;
+        };
+
+        let mut body = Vec::new();
+        while let Some(line) = lines.next_if(|line| !line.trim().is_empty()) {
+            body.push(line.trim());
+        }
+        let joined = body.join("\n");
+        let speaker = voice(&joined);
+        let text = strip_tags(&joined, '<', '>');
+        cues.push(Cue { start, end, text, speaker });
+    }
+    cues
+}
+
+/// Speaker of a WebVTT `<v Name>` span.
+fn voice(text: &str) -> Option<String> {
+    let rest = &text[text.find("<v")? + 2..];
+    let name = rest.get(..rest.find('>')?)?.trim();
+    let name = name.strip_prefix('.').map_or(name, |classes| {
+        classes.split_once(char::is_whitespace).map_or("", |(_, name)| name)
+    });
+    (!name.is_empty()).then(|| name.trim().to_string())
+}
+
+fn parse_ass(text: &str) -> Vec<Cue> {
+    let mut cues = Vec::new();
+    let mut in_events = false;
+    // Field order as declared by the section's `Format:` line, with the usual default.
+    let mut fields: Vec<String> = ["layer", "start", "end", "style", "name", "marginl", "marginr", "marginv", "effect", "text"]
+        .iter()
+        .map(|field| field.to_string())
+        .collect();
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_events = line.eq_ignore_ascii_case("[events]");
+            continue;
+        }
+        if !in_events {
+            continue;
+        }
+        if let Some(format) = line.strip_prefix("Format:") {
+            fields = format.split(',').map(|field| field.trim().to_ascii_lowercase()).collect();
+            continue;
+        }
+        let Some(dialogue) = line.strip_prefix("Dialogue:") else {
+            continue;
+        };
+        // The text is the last field and may itself contain commas.
+        let values: Vec<&str> = dialogue.splitn(fields.len(), ',').collect();
+        let field = |name: &str| {
+            let index = fields.iter().position(|field| field == name)?;
+            values.get(index).map(|value| value.trim())
+        };
+        let (Some(start), Some(end)) = (field("start").and_then(parse_timestamp), field("end").and_then(parse_timestamp))
+        else {
+            continue;
+        };
+        let raw = field("text").unwrap_or_default().replace("\\N", "\n").replace("\\n", "\n").replace("\\h", " ");
+        cues.push(Cue {
+            start,
+            end,
+            text: strip_tags(&raw, '{', '}'),
+            speaker: field("name").filter(|name| !name.is_empty()).map(str::to_string),
+        });
+    }
+    cues
+}
+
+/// `HH:MM:SS,mmm` (SRT), `HH:MM:SS.mmm` or `MM:SS.mmm` (WebVTT) and `H:MM:SS.cc` (ASS).
+pub fn parse_timestamp(text: &str) -> Option<f64> {
+    let text = text.trim().replace(',', ".");
+    let parts: Vec<&str> = text.split(':').collect();
+    if parts.len() < 2 || parts.len() > 3 {
+        return None;
+    }
+    let mut seconds = 0.0;
+    for part in &parts[..parts.len() - 1] {
+        seconds = seconds * 60.0 + part.parse::<u32>().ok()? as f64;
+    }
+    let last: f64 = parts[parts.len() - 1].parse().ok()?;
+    Some(seconds * 60.0 + last)
+}
+
+/// Drops everything between `open` and `close`, e.g. `<i>` or `{\an8}`, and trims each line.
+fn strip_tags(text: &str, open: char, close: char) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut depth = 0;
+    for c in text.chars() {
+        if c == open {
+            depth += 1;
+        } else if c == close && depth > 0 {
+            depth -= 1;
+        } else if depth == 0 {
+            output.push(c);
+        }
+    }
+    output.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    #[test]
+    fn parses_srt_and_vtt() {
+        let srt = "1\r\n00:00:01,500 --> 00:00:03,000\r\n<i>こんにちは</i>\r\n世界\r\n\r\n2\r\n00:01:00,000 --> 00:01:02,250\r\nまた\r\n";
+        let cues = parse(srt);
+        assert_eq!(cues.len(), 2);
+        assert_eq!((cues[0].start, cues[0].end), (1.5, 3.0));
+        assert_eq!(cues[0].text, "こんにちは\n世界");
+        assert_eq!(cues[1].end, 62.25);
+
+        let vtt = "WEBVTT\n\n00:05.000 --> 00:06.000 align:start\n<v.loud Mika>行くよ！</v>\n";
+        let cues = parse(vtt);
+        assert_eq!(cues[0].start, 5.0);
+        assert_eq!(cues[0].text, "行くよ！");
+        assert_eq!(cues[0].speaker.as_deref(), Some("Mika"));
+    }
+
+    #[test]
+    fn parses_ass_events() {
+        let ass = "[Script Info]\nTitle: x\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\nComment: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,note\nDialogue: 0,0:00:02.50,0:00:04.00,Default,Mika,0,0,0,,{\\an8}待って、\\Nまだだ\n";
+        let cues = parse(ass);
+        assert_eq!(cues.len(), 1);
+        assert_eq!((cues[0].start, cues[0].end), (2.5, 4.0));
+        assert_eq!(cues[0].text, "待って、\nまだだ");
+        assert_eq!(cues[0].speaker.as_deref(), Some("Mika"));
+    }
+}
@@ -0,0 +1,285 @@
+use std::{collections::HashMap, future::Future, time::Duration};
+
+use reqwest::{
+    Client, RequestBuilder, StatusCode,
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+};
+use tracing::warn;
+use url::Url;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 20;
+const DEFAULT_RETRIES: u32 = 2;
+const DEFAULT_BACKOFF_MS: u64 = 250;
+const DEFAULT_MAX_SKIPPED_SEGMENTS: usize = 2;
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_POOL_MAX_IDLE: usize = 16;
+const DEFAULT_POOL_IDLE_SECS: u64 = 90;
+
+fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+/// Timeouts and retries for requests to Suwayomi and the stream hosts behind it.
+#[derive(Clone, Copy, Debug)]
+pub struct FetchPolicy {
+    /// Limit on a whole request, body included.
+    pub timeout: Duration,
+    /// Extra attempts after a timeout, connection error or 5xx/429 response.
+    pub retries: u32,
+    /// Wait before the first retry, doubled for every further one.
+    pub backoff: Duration,
+    /// Segments in the middle of a clip that may fail for good and be replaced by silence
+    /// before the clip fails as a whole.
+    pub max_skipped_segments: usize,
+}
+
+impl FetchPolicy {
+    /// Reads `MANATAN_AUDIO_FETCH_TIMEOUT_SECS` (default 20), `MANATAN_AUDIO_FETCH_RETRIES`
+    /// (default 2), `MANATAN_AUDIO_FETCH_BACKOFF_MS` (default 250) and
+    /// `MANATAN_AUDIO_MAX_SKIPPED_SEGMENTS` (default 2).
+    pub fn from_env() -> Self {
+        Self {
+            timeout: Duration::from_secs(
+                var("MANATAN_AUDIO_FETCH_TIMEOUT_SECS", DEFAULT_TIMEOUT_SECS).max(1),
+            ),
+            retries: var("MANATAN_AUDIO_FETCH_RETRIES", DEFAULT_RETRIES),
+            backoff: Duration::from_millis(var(
+                "MANATAN_AUDIO_FETCH_BACKOFF_MS",
+                DEFAULT_BACKOFF_MS,
+            )),
+            max_skipped_segments: var(
+                "MANATAN_AUDIO_MAX_SKIPPED_SEGMENTS",
+                DEFAULT_MAX_SKIPPED_SEGMENTS,
+            ),
+        }
+    }
+}
+
+/// Headers sent upstream besides reqwest's own, for sources that want a session cookie, a
+/// particular `Referer` or `User-Agent`, or a custom header.
+#[derive(Clone, Debug)]
+pub struct HeaderPolicy {
+    /// Copied from the client's request when present.
+    pub forward: Vec<HeaderName>,
+    /// Sent with every request, in place of a forwarded header of the same name.
+    pub fixed: HeaderMap,
+}
+
+impl Default for HeaderPolicy {
+    fn default() -> Self {
+        Self {
+            forward: vec![header::COOKIE, header::AUTHORIZATION],
+            fixed: HeaderMap::new(),
+        }
+    }
+}
+
+impl HeaderPolicy {
+    /// Reads `MANATAN_AUDIO_FORWARD_HEADERS`, a comma-separated list of header names (default
+    /// `Cookie, Authorization`), and `MANATAN_AUDIO_UPSTREAM_HEADERS`, a JSON object of header
+    /// values, e.g. `{"Referer": "https://example.com/"}`.
+    pub fn from_env() -> Self {
+        Self::parse(
+            std::env::var("MANATAN_AUDIO_FORWARD_HEADERS")
+                .ok()
+                .as_deref(),
+            std::env::var("MANATAN_AUDIO_UPSTREAM_HEADERS")
+                .ok()
+                .as_deref(),
+        )
+    }
+
+    fn parse(forward: Option<&str>, fixed: Option<&str>) -> Self {
+        let mut policy = Self::default();
+        if let Some(forward) = forward {
+            policy.forward = forward
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .filter_map(|name| match HeaderName::from_bytes(name.as_bytes()) {
+                    Ok(name) if !forwardable(&name) => {
+                        warn!("Not forwarding the {name} header");
+                        None
+                    }
+                    Ok(name) => Some(name),
+                    Err(_) => {
+                        warn!(
+                            "Ignoring invalid header name {name:?} in MANATAN_AUDIO_FORWARD_HEADERS"
+                        );
+                        None
+                    }
+                })
+                .collect();
+        }
+        let Some(fixed) = fixed.filter(|fixed| !fixed.trim().is_empty()) else {
+            return policy;
+        };
+        match serde_json::from_str::<HashMap<String, String>>(fixed) {
+            Ok(fixed) => {
+                for (name, value) in fixed {
+                    match (
+                        HeaderName::from_bytes(name.as_bytes()),
+                        HeaderValue::from_str(&value),
+                    ) {
+                        (Ok(name), Ok(value)) => {
+                            policy.fixed.insert(name, value);
+                        }
+                        _ => warn!("Ignoring invalid upstream header {name:?}"),
+                    }
+                }
+            }
+            Err(err) => warn!("Ignoring MANATAN_AUDIO_UPSTREAM_HEADERS: {err}"),
+        }
+        policy
+    }
+
+    /// Adds the forwarded headers of `incoming` and the fixed ones to `request`.
+    pub fn apply(&self, mut request: RequestBuilder, incoming: &HeaderMap) -> RequestBuilder {
+        for name in &self.forward {
+            if self.fixed.contains_key(name) {
+                continue;
+            }
+            for value in incoming.get_all(name) {
+                request = request.header(name, value);
+            }
+        }
+        request.headers(self.fixed.clone())
+    }
+}
+
+/// Whether a header may be forwarded; those about the connection to this server and `Range`,
+/// which segment requests set themselves, may not.
+fn forwardable(name: &HeaderName) -> bool {
+    ![
+        header::HOST,
+        header::CONNECTION,
+        header::CONTENT_LENGTH,
+        header::TRANSFER_ENCODING,
+        header::RANGE,
+    ]
+    .contains(name)
+}
+
+/// HTTP client for upstream media requests, applying the [`FetchPolicy`]. Shared by all
+/// requests, so connections and TLS sessions to Suwayomi and the stream hosts are reused;
+/// clones share the pool.
+#[derive(Clone)]
+pub struct Upstream {
+    client: Client,
+    policy: FetchPolicy,
+    headers: HeaderPolicy,
+}
+
+impl Upstream {
+    /// The policies from [`FetchPolicy::from_env`] and [`HeaderPolicy::from_env`] and a
+    /// connection pool keeping up to
+    /// `MANATAN_AUDIO_POOL_MAX_IDLE` (default 16) idle connections per host for
+    /// `MANATAN_AUDIO_POOL_IDLE_SECS` (default 90), connecting within
+    /// `MANATAN_AUDIO_CONNECT_TIMEOUT_SECS` (default 10).
+    pub fn from_env() -> Self {
+        let client = Client::builder()
+            .pool_max_idle_per_host(var("MANATAN_AUDIO_POOL_MAX_IDLE", DEFAULT_POOL_MAX_IDLE))
+            .pool_idle_timeout(Duration::from_secs(var(
+                "MANATAN_AUDIO_POOL_IDLE_SECS",
+                DEFAULT_POOL_IDLE_SECS,
+            )))
+            .connect_timeout(Duration::from_secs(
+                var(
+                    "MANATAN_AUDIO_CONNECT_TIMEOUT_SECS",
+                    DEFAULT_CONNECT_TIMEOUT_SECS,
+                )
+                .max(1),
+            ))
+            .build()
+            .unwrap_or_else(|err| {
+                warn!("Failed to configure the audio HTTP client, using defaults: {err}");
+                Client::new()
+            });
+        Self {
+            client,
+            policy: FetchPolicy::from_env(),
+            headers: HeaderPolicy::from_env(),
+        }
+    }
+
+    pub fn policy(&self) -> &FetchPolicy {
+        &self.policy
+    }
+
+    /// The pooled client itself, for requests outside the fetch policy.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// A GET request with the policy's timeout and the [`HeaderPolicy`] headers, forwarded ones
+    /// taken from `incoming`.
+    pub fn get(&self, url: Url, incoming: &HeaderMap) -> RequestBuilder {
+        let request = self.client.get(url).timeout(self.policy.timeout);
+        self.headers.apply(request, incoming)
+    }
+
+    /// Runs `attempt` until it succeeds, fails with an error that retrying won't fix, or the
+    /// retries run out. `attempt` should send the request and read the body, so that a
+    /// connection dropped halfway is retried as well.
+    pub async fn retry<T, F, Fut>(&self, what: &str, mut attempt: F) -> anyhow::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let mut delay = self.policy.backoff;
+        let mut retries = self.policy.retries;
+        loop {
+            match attempt().await {
+                Err(err) if retries > 0 && is_transient(&err) => {
+                    warn!("{what} failed, retrying in {delay:?}: {err:#}");
+                    retries -= 1;
+                    tokio::time::sleep(delay).await;
+                    delay = delay.saturating_mul(2);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Timeouts, connection failures, bodies cut short and server-side errors.
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<reqwest::Error>())
+        .any(|err| {
+            err.is_timeout()
+                || err.is_connect()
+                || err.is_body()
+                || err.status().is_some_and(|status| {
+                    status.is_server_error()
+                        || status == StatusCode::TOO_MANY_REQUESTS
+                        || status == StatusCode::REQUEST_TIMEOUT
+                })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header;
+
+    use super::HeaderPolicy;
+
+    #[test]
+    fn parses_header_policy() {
+        let policy = HeaderPolicy::parse(
+            Some("Cookie, user-agent, Host, bad name"),
+            Some(r#"{"Referer": "https://example.com/", "X-Token": "abc"}"#),
+        );
+        assert_eq!(policy.forward, [header::COOKIE, header::USER_AGENT]);
+        assert_eq!(policy.fixed[header::REFERER], "https://example.com/");
+        assert_eq!(policy.fixed["x-token"], "abc");
+
+        let policy = HeaderPolicy::parse(None, Some("not json"));
+        assert_eq!(policy.forward, [header::COOKIE, header::AUTHORIZATION]);
+        assert!(policy.fixed.is_empty());
+        assert!(HeaderPolicy::parse(Some(""), None).forward.is_empty());
+    }
+}
@@ -0,0 +1,178 @@
+//! Energy-based voice activity detection, good enough to find where dialogue starts and stops
+//! against the quieter background of an episode.
+
+/// Length of the frames speech is detected in.
+pub const FRAME_MS: usize = 10;
+/// Speech has to be this much louder than the quietest tenth of the window.
+const THRESHOLD_ABOVE_FLOOR_DB: f64 = 12.0;
+/// Frames quieter than this are never speech, however quiet the floor.
+const MIN_SPEECH_DB: f64 = -55.0;
+/// Pauses shorter than this, like the closure before a plosive, don't end speech.
+const MIN_PAUSE_MS: usize = 120;
+/// Louder stretches shorter than this are clicks rather than speech.
+const MIN_SPEECH_MS: usize = 40;
+
+/// Number of interleaved samples in one frame.
+pub fn frame_len(sample_rate: u32, channels: usize) -> usize {
+    (sample_rate as usize * FRAME_MS / 1000).max(1) * channels.max(1)
+}
+
+/// Whether each [`FRAME_MS`] frame of interleaved PCM holds speech.
+pub fn speech_frames(samples: &[i16], channels: usize, sample_rate: u32) -> Vec<bool> {
+    let levels: Vec<f64> = samples
+        .chunks(frame_len(sample_rate, channels))
+        .map(|frame| {
+            let square_sum: f64 = frame.iter().map(|sample| (*sample as f64).powi(2)).sum();
+            let rms = (square_sum / frame.len() as f64).sqrt() / 32768.0;
+            20.0 * rms.max(1e-6).log10()
+        })
+        .collect();
+    if levels.is_empty() {
+        return Vec::new();
+    }
+    let mut sorted = levels.clone();
+    sorted.sort_by(f64::total_cmp);
+    let floor = sorted[sorted.len() / 10];
+    let threshold = (floor + THRESHOLD_ABOVE_FLOOR_DB).max(MIN_SPEECH_DB);
+
+    let mut speech: Vec<bool> = levels.iter().map(|level| *level >= threshold).collect();
+    fill_runs(&mut speech, false, MIN_PAUSE_MS / FRAME_MS);
+    fill_runs(&mut speech, true, MIN_SPEECH_MS / FRAME_MS);
+    speech
+}
+
+/// Flips interior runs of `value` shorter than `min_len` frames.
+fn fill_runs(flags: &mut [bool], value: bool, min_len: usize) {
+    let mut index = 0;
+    while index < flags.len() {
+        let run_end = flags[index..]
+            .iter()
+            .position(|flag| *flag != flags[index])
+            .map_or(flags.len(), |len| index + len);
+        let interior = index > 0 && run_end < flags.len();
+        if flags[index] == value && interior && run_end - index < min_len {
+            flags[index..run_end].fill(!value);
+        }
+        index = run_end;
+    }
+}
+
+/// Moves `start` to the onset of the speech around it and `end` to its offset, by at most
+/// `max_shift` frames each. A start inside speech moves back to where that speech began, one
+/// in silence forward to where the next speech begins; the end mirrors this. Boundaries with
+/// no onset or offset in reach stay put.
+pub fn snap(speech: &[bool], start: usize, end: usize, max_shift: usize) -> (usize, usize) {
+    let is_speech = |frame: usize| speech.get(frame).copied().unwrap_or(false);
+    let onset = |frame: usize| is_speech(frame) && (frame == 0 || !is_speech(frame - 1));
+    let offset = |frame: usize| !is_speech(frame) && frame > 0 && is_speech(frame - 1);
+
+    let low = start.saturating_sub(max_shift);
+    let snapped_start = if is_speech(start) {
+        (low..=start).rev().find(|frame| onset(*frame))
+    } else {
+        (start..=start + max_shift).find(|frame| onset(*frame))
+    };
+    let high = (end + max_shift).min(speech.len());
+    let snapped_end = if is_speech(end.saturating_sub(1)) {
+        (end..=high).find(|frame| offset(*frame) || *frame == speech.len())
+    } else {
+        (end.saturating_sub(max_shift)..=end)
+            .rev()
+            .find(|frame| offset(*frame))
+    };
+
+    let snapped = (snapped_start.unwrap_or(start), snapped_end.unwrap_or(end));
+    if snapped.0 < snapped.1 {
+        snapped
+    } else {
+        (start, end)
+    }
+}
+
+/// Widens `start..end` to the silences of at least `gap` frames on either side, so that it
+/// holds the whole sentence. Each side may grow by at most half of what's left of `max_len`;
+/// a side that finds no gap in reach stops there. Silence at the new edges is left out.
+pub fn extend(
+    speech: &[bool],
+    start: usize,
+    end: usize,
+    gap: usize,
+    max_len: usize,
+) -> (usize, usize) {
+    let (start, end) = (start.min(speech.len()), end.min(speech.len()));
+    let budget = max_len.saturating_sub(end.saturating_sub(start)) / 2;
+    let gap = gap.max(1);
+
+    let mut new_start = start;
+    let mut silent = 0;
+    while new_start > 0 && silent < gap && start - new_start < budget {
+        new_start -= 1;
+        silent = if speech[new_start] { 0 } else { silent + 1 };
+    }
+    let new_start = (new_start + silent).min(start);
+
+    let mut new_end = end;
+    let mut silent = 0;
+    while new_end < speech.len() && silent < gap && new_end - end < budget {
+        silent = if speech[new_end] { 0 } else { silent + 1 };
+        new_end += 1;
+    }
+    let new_end = (new_end - silent).max(end);
+
+    (new_start, new_end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extend, fill_runs, snap, speech_frames};
+
+    #[test]
+    fn detects_speech_over_noise() {
+        // 16 kHz: 300 ms of quiet noise, 500 ms of a loud tone, 300 ms of noise again.
+        let samples: Vec<i16> = (0..17_600)
+            .map(|i| {
+                if (4_800..12_800).contains(&i) {
+                    ((i as f32 * 0.2).sin() * 8_000.0) as i16
+                } else if i % 2 == 0 {
+                    40
+                } else {
+                    -40
+                }
+            })
+            .collect();
+        let speech = speech_frames(&samples, 1, 16_000);
+        assert_eq!(speech.len(), 110);
+        assert_eq!(speech.iter().position(|flag| *flag), Some(30));
+        assert_eq!(speech.iter().rposition(|flag| *flag), Some(79));
+    }
+
+    #[test]
+    fn fills_short_runs_and_snaps() {
+        let mut flags = [true, false, true, true, false, false, false, true];
+        fill_runs(&mut flags, false, 2);
+        assert_eq!(flags, [true, true, true, true, false, false, false, true]);
+
+        //            0      1      2     3     4     5     6      7      8
+        let speech = [false, false, true, true, true, true, false, false, false];
+        // Start late inside speech, end early inside it.
+        assert_eq!(snap(&speech, 3, 4, 3), (2, 6));
+        // Start early in silence, end late in silence.
+        assert_eq!(snap(&speech, 0, 8, 3), (2, 6));
+        // Nothing in reach.
+        assert_eq!(snap(&speech, 0, 8, 1), (0, 8));
+    }
+
+    #[test]
+    fn extends_to_silence_gaps() {
+        //            0      1      2     3     4      5     6     7      8      9     10
+        let speech = [
+            true, false, false, true, true, false, true, true, false, false, true,
+        ];
+        // The one-frame pause at 5 isn't a gap of two; the pauses at 1..3 and 8..10 are.
+        assert_eq!(extend(&speech, 4, 5, 2, 20), (3, 8));
+        // Capped at four frames: one more on each side of the original two.
+        assert_eq!(extend(&speech, 5, 7, 2, 4), (4, 8));
+        // Runs into the ends without finding a gap.
+        assert_eq!(extend(&speech, 4, 5, 3, 20), (0, 11));
+    }
+}
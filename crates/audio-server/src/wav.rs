@@ -0,0 +1,219 @@
+use anyhow::anyhow;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+const HEADER_LEN: usize = 44;
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IEEE_FLOAT: u16 = 3;
+
+/// How samples are stored in a WAV file. Clips are decoded to 16 bits, so the wider formats
+/// hold the same audio, for tools that expect them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleFormat {
+    /// 16-bit integer PCM.
+    #[default]
+    S16,
+    /// 24-bit integer PCM.
+    S24,
+    /// 32-bit IEEE float, from -1 to 1.
+    F32,
+}
+
+impl SampleFormat {
+    fn bytes(self) -> usize {
+        match self {
+            SampleFormat::S16 => 2,
+            SampleFormat::S24 => 3,
+            SampleFormat::F32 => 4,
+        }
+    }
+
+    fn format_tag(self) -> u16 {
+        match self {
+            SampleFormat::F32 => FORMAT_IEEE_FLOAT,
+            SampleFormat::S16 | SampleFormat::S24 => FORMAT_PCM,
+        }
+    }
+
+    fn write(self, sample: i16, output: &mut Vec<u8>) {
+        match self {
+            SampleFormat::S16 => output.extend_from_slice(&sample.to_le_bytes()),
+            SampleFormat::S24 => {
+                output.extend_from_slice(&((sample as i32) << 8).to_le_bytes()[..3])
+            }
+            SampleFormat::F32 => output.extend_from_slice(&(sample as f32 / 32768.0).to_le_bytes()),
+        }
+    }
+}
+
+/// Canonical 44-byte header. A streamed clip doesn't know its length up front and uses the
+/// maximum size, which players read as "until the end of the file".
+fn header(sample_rate: u32, channels: u16, format: SampleFormat, data_len: Option<u32>) -> Vec<u8> {
+    let data_len = data_len.unwrap_or(u32::MAX - 36);
+    let sample_bytes = format.bytes() as u16;
+    let byte_rate = sample_rate * channels as u32 * sample_bytes as u32;
+    let block_align = channels * sample_bytes;
+
+    let mut output = Vec::with_capacity(HEADER_LEN);
+    output.extend_from_slice(b"RIFF");
+    output.extend_from_slice(&(36 + data_len).to_le_bytes());
+    output.extend_from_slice(b"WAVE");
+    output.extend_from_slice(b"fmt ");
+    output.extend_from_slice(&16u32.to_le_bytes());
+    output.extend_from_slice(&format.format_tag().to_le_bytes());
+    output.extend_from_slice(&channels.to_le_bytes());
+    output.extend_from_slice(&sample_rate.to_le_bytes());
+    output.extend_from_slice(&byte_rate.to_le_bytes());
+    output.extend_from_slice(&block_align.to_le_bytes());
+    output.extend_from_slice(&(sample_bytes * 8).to_le_bytes());
+    output.extend_from_slice(b"data");
+    output.extend_from_slice(&data_len.to_le_bytes());
+    output
+}
+
+pub fn encode_wav(
+    samples: &[i16],
+    sample_rate: u32,
+    channels: u16,
+    format: SampleFormat,
+) -> anyhow::Result<Vec<u8>> {
+    let data_len = samples.len() * format.bytes();
+    if data_len > (u32::MAX - 36) as usize {
+        return Err(anyhow!("Audio clip is too large"));
+    }
+
+    let mut output = header(sample_rate, channels, format, Some(data_len as u32));
+    output.reserve(data_len);
+    for sample in samples {
+        format.write(*sample, &mut output);
+    }
+
+    Ok(output)
+}
+
+/// Writes the real sizes into a streamed WAV once all of it is known.
+pub fn finalize_sizes(wav: &mut [u8]) {
+    if wav.len() < HEADER_LEN {
+        return;
+    }
+    let data_len = (wav.len() - HEADER_LEN) as u32;
+    wav[4..8].copy_from_slice(&(36 + data_len).to_le_bytes());
+    wav[40..44].copy_from_slice(&data_len.to_le_bytes());
+}
+
+/// Turns decoded chunks into WAV bytes as they arrive. With a fade, the last `fade` worth of
+/// frames is held back until [`finish`](Self::finish), since the fade-out can only be applied
+/// once the end is known.
+pub struct WavStream {
+    fade_ms: u32,
+    format: SampleFormat,
+    fade_frames: usize,
+    channels: usize,
+    emitted_frames: usize,
+    pending: Vec<i16>,
+    started: bool,
+}
+
+impl WavStream {
+    pub fn new(fade_ms: u32, format: SampleFormat) -> Self {
+        Self {
+            fade_ms,
+            format,
+            fade_frames: 0,
+            channels: 1,
+            emitted_frames: 0,
+            pending: Vec::new(),
+            started: false,
+        }
+    }
+
+    /// Bytes ready to send after `samples`; the first call also produces the header.
+    pub fn push(&mut self, samples: &[i16], sample_rate: u32, channels: usize) -> Vec<u8> {
+        let mut output = Vec::new();
+        if !self.started {
+            self.started = true;
+            self.channels = channels.max(1);
+            self.fade_frames = (sample_rate as u64 * self.fade_ms as u64 / 1000) as usize;
+            output = header(sample_rate, self.channels as u16, self.format, None);
+        }
+
+        self.pending.extend_from_slice(samples);
+        let held = self.fade_frames * self.channels;
+        if self.pending.len() > held {
+            let ready: Vec<i16> = self.pending.drain(..self.pending.len() - held).collect();
+            self.write(&ready, false, &mut output);
+        }
+        output
+    }
+
+    /// The held-back tail, faded out.
+    pub fn finish(mut self) -> Vec<u8> {
+        let mut output = Vec::new();
+        let tail = std::mem::take(&mut self.pending);
+        self.write(&tail, true, &mut output);
+        output
+    }
+
+    fn write(&mut self, samples: &[i16], fade_out: bool, output: &mut Vec<u8>) {
+        let frames = samples.len() / self.channels;
+        output.reserve(samples.len() * self.format.bytes());
+        for (index, sample) in samples.iter().enumerate() {
+            let frame = index / self.channels;
+            let mut gain = 1.0f32;
+            let position = self.emitted_frames + frame;
+            if position < self.fade_frames {
+                gain *= position as f32 / self.fade_frames as f32;
+            }
+            if fade_out && self.fade_frames > 0 {
+                gain *= ((frames - 1 - frame) as f32 / self.fade_frames as f32).min(1.0);
+            }
+            let value = if gain < 1.0 {
+                (*sample as f32 * gain) as i16
+            } else {
+                *sample
+            };
+            self.format.write(value, output);
+        }
+        self.emitted_frames += frames;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SampleFormat, WavStream, encode_wav, finalize_sizes};
+
+    #[test]
+    fn streamed_wav_matches_whole_file() {
+        // 10 stereo frames at 1 kHz with a 2 ms fade, pushed in uneven chunks.
+        let samples = [1000i16; 20];
+        let mut stream = WavStream::new(2, SampleFormat::S16);
+        let mut streamed = stream.push(&samples[..6], 1000, 2);
+        streamed.extend(stream.push(&samples[6..], 1000, 2));
+        streamed.extend(stream.finish());
+        finalize_sizes(&mut streamed);
+
+        let mut faded = samples;
+        for (frame, gain) in [(0, 0.0), (1, 0.5), (9, 0.0), (8, 0.5)] {
+            faded[frame * 2] = (1000.0 * gain) as i16;
+            faded[frame * 2 + 1] = (1000.0 * gain) as i16;
+        }
+        assert_eq!(
+            streamed,
+            encode_wav(&faded, 1000, 2, SampleFormat::S16).unwrap()
+        );
+    }
+
+    #[test]
+    fn writes_wider_sample_formats() {
+        let s24 = encode_wav(&[-32768, 1], 8000, 1, SampleFormat::S24).unwrap();
+        // Block align 3, 24 bits per sample.
+        assert_eq!(&s24[32..36], &[3, 0, 24, 0]);
+        assert_eq!(&s24[44..], &[0, 0, 0x80, 0, 1, 0]);
+
+        let f32 = encode_wav(&[16384], 8000, 1, SampleFormat::F32).unwrap();
+        assert_eq!(&f32[20..22], &[3, 0]);
+        assert_eq!(&f32[40..44], &4u32.to_le_bytes());
+        assert_eq!(&f32[44..], &0.5f32.to_le_bytes());
+    }
+}
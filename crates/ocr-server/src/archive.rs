@@ -0,0 +1,188 @@
+//! Pages of uploaded CBZ/ZIP archives and PDFs, for chapters that aren't in Suwayomi. They are
+//! OCR'd by the usual chapter jobs, with page URLs of the form `archive/<id>/<index>` or
+//! `pdf/<id>/<dpi>dpi/<page number>` standing in for Suwayomi's so results are cached the same
+//! way.
+
+use std::{
+    cmp::Ordering,
+    io::{Cursor, Read},
+};
+
+use anyhow::{Context, bail};
+use sha2::{Digest, Sha256};
+
+/// Largest archive `/ocr/archive` accepts.
+pub const MAX_ARCHIVE_BYTES: usize = 1024 * 1024 * 1024;
+/// Largest page image read out of an archive.
+pub(crate) const MAX_PAGE_BYTES: u64 = 64 * 1024 * 1024;
+/// Largest total size of the page images read out of one archive, whatever the entries claim
+/// their sizes are.
+pub(crate) const MAX_EXTRACTED_BYTES: u64 = 2 * MAX_ARCHIVE_BYTES as u64;
+/// Resolution PDFs are rendered at unless `MANATAN_PDF_DPI` or the request sets one.
+const DEFAULT_PDF_DPI: u32 = 200;
+/// Resolutions a PDF can be rendered at; past 600 DPI pages get huge without reading better.
+const PDF_DPI_RANGE: std::ops::RangeInclusive<u32> = 72..=600;
+/// Extensions of the entries read as pages; anything else in the archive is skipped.
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp", "gif", "bmp", "avif"];
+
+/// Id of an archive, from its contents, so uploading the same file again finds its cached
+/// pages.
+pub fn archive_id(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// The archive's page images in reading order, by entry name. Fails on a page larger than 64
+/// MiB, or once the pages add up to more than 2 GiB.
+pub fn read_pages(bytes: &[u8]) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).context("reading archive")?;
+    let mut pages = Vec::new();
+    let mut extracted = 0;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let name = entry.name().to_string();
+        if !entry.is_file() || name.starts_with("__MACOSX/") || !is_image(&name) {
+            continue;
+        }
+        // The sizes in the archive are only claims, so reads are capped whatever they say.
+        let limit = MAX_PAGE_BYTES.min(MAX_EXTRACTED_BYTES - extracted);
+        let mut data = Vec::with_capacity(entry.size().min(limit) as usize);
+        (&mut entry)
+            .take(limit + 1)
+            .read_to_end(&mut data)
+            .with_context(|| format!("extracting {name}"))?;
+        if data.len() as u64 > limit {
+            if limit < MAX_PAGE_BYTES {
+                bail!("The pages add up to more than {MAX_EXTRACTED_BYTES} bytes");
+            }
+            bail!("{name} is larger than {MAX_PAGE_BYTES} bytes");
+        }
+        extracted += data.len() as u64;
+        pages.push((name, data));
+    }
+    pages.sort_by(|(a, _), (b, _)| natural_cmp(a, b));
+    Ok(pages)
+}
+
+/// Whether a chapter's base URL stands for an uploaded archive or PDF rather than a Suwayomi
+/// chapter.
+pub fn is_upload_url(base_url: &str) -> bool {
+    base_url.starts_with("archive/") || base_url.starts_with("pdf/")
+}
+
+/// Whether `bytes` is a PDF rather than a ZIP archive.
+pub fn is_pdf(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"%PDF")
+}
+
+/// Resolution to render a PDF at: `requested`, else `MANATAN_PDF_DPI`, else 200, kept within
+/// 72 to 600.
+pub fn pdf_dpi(requested: Option<u32>) -> u32 {
+    requested
+        .or_else(|| {
+            std::env::var("MANATAN_PDF_DPI")
+                .ok()
+                .and_then(|dpi| dpi.trim().parse().ok())
+        })
+        .unwrap_or(DEFAULT_PDF_DPI)
+        .clamp(*PDF_DPI_RANGE.start(), *PDF_DPI_RANGE.end())
+}
+
+/// The PDF's pages rendered at `dpi`, as PNG images in page order.
+#[cfg(feature = "pdf")]
+pub fn render_pdf(bytes: &[u8], dpi: u32) -> anyhow::Result<Vec<Vec<u8>>> {
+    crate::pdf::render_pages(bytes, dpi)
+}
+
+#[cfg(not(feature = "pdf"))]
+pub fn render_pdf(_bytes: &[u8], _dpi: u32) -> anyhow::Result<Vec<Vec<u8>>> {
+    Err(anyhow::anyhow!(
+        "This server was built without the pdf feature"
+    ))
+}
+
+fn is_image(name: &str) -> bool {
+    name.rsplit_once('.').is_some_and(|(_, extension)| {
+        IMAGE_EXTENSIONS
+            .iter()
+            .any(|known| extension.eq_ignore_ascii_case(known))
+    })
+}
+
+/// Orders names with their numbers compared by value, so `2.jpg` comes before `10.jpg`.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let a_end = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+                let b_end = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+                let (a_digits, b_digits) = (
+                    a[..a_end].trim_start_matches('0'),
+                    b[..b_end].trim_start_matches('0'),
+                );
+                let order = a_digits
+                    .len()
+                    .cmp(&b_digits.len())
+                    .then_with(|| a_digits.cmp(b_digits));
+                if order != Ordering::Equal {
+                    return order;
+                }
+                (a, b) = (&a[a_end..], &b[b_end..]);
+            }
+            (Some(x), Some(y)) => {
+                let order = x.to_lowercase().cmp(y.to_lowercase());
+                if order != Ordering::Equal {
+                    return order;
+                }
+                (a, b) = (&a[x.len_utf8()..], &b[y.len_utf8()..]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn natural_cmp_compares_numbers_by_value() {
+        assert_eq!(natural_cmp("2.jpg", "10.jpg"), Ordering::Less);
+        assert_eq!(natural_cmp("page002.jpg", "page10.jpg"), Ordering::Less);
+        assert_eq!(natural_cmp("007.png", "7.png"), Ordering::Equal);
+        assert_eq!(natural_cmp("ch1/10.png", "ch1/9.png"), Ordering::Greater);
+    }
+
+    #[test]
+    fn natural_cmp_ignores_case() {
+        assert_eq!(natural_cmp("Page2.jpg", "page10.jpg"), Ordering::Less);
+        assert_eq!(natural_cmp("a.JPG", "a.jpg"), Ordering::Equal);
+        assert_eq!(natural_cmp("B.jpg", "a.jpg"), Ordering::Greater);
+    }
+
+    #[test]
+    fn natural_cmp_handles_non_ascii_names() {
+        assert_eq!(natural_cmp("第2話.jpg", "第10話.jpg"), Ordering::Less);
+        assert_eq!(natural_cmp("Ä1.png", "ä2.png"), Ordering::Less);
+        // Non-ASCII digits aren't read as numbers.
+        assert_eq!(natural_cmp("１.png", "2.png"), Ordering::Greater);
+    }
+
+    #[test]
+    fn read_pages_rejects_oversized_entries() {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("1.png", options).expect("start entry");
+        std::io::Write::write_all(&mut zip, &vec![0; MAX_PAGE_BYTES as usize + 1])
+            .expect("write entry");
+        let bytes = zip.finish().expect("finish archive").into_inner();
+
+        let err = read_pages(&bytes).expect_err("oversized page was read");
+        assert!(err.to_string().contains("1.png"), "{err}");
+    }
+}
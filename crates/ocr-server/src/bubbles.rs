@@ -0,0 +1,141 @@
+//! Text block detection with a comic-text-detector ONNX model, whose blocks (mostly speech
+//! bubbles) decide which OCR lines are merged. Built with the `bubbles` feature; the model is
+//! read from `MANATAN_BUBBLE_MODEL`. Without it, merging falls back to the line geometry alone.
+
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::anyhow;
+use image::{DynamicImage, imageops::FilterType};
+use ort::{session::Session, value::Tensor};
+
+use crate::logic::BoundingBox;
+
+/// Side of the square image the model takes.
+const INPUT_SIZE: u32 = 1024;
+/// Confidence a block needs to be kept.
+const CONFIDENCE_THRESHOLD: f32 = 0.4;
+/// Overlap above which the less confident of two blocks is dropped.
+const NMS_THRESHOLD: f64 = 0.35;
+
+/// The model, loaded on first use and kept for the life of the process.
+fn model() -> Option<&'static Mutex<Session>> {
+    static MODEL: OnceLock<Option<Mutex<Session>>> = OnceLock::new();
+    MODEL
+        .get_or_init(|| {
+            let path = std::env::var_os("MANATAN_BUBBLE_MODEL")
+                .filter(|path| !path.is_empty())
+                .map(std::path::PathBuf::from)?;
+            let session = Session::builder().and_then(|builder| builder.commit_from_file(&path));
+            match session {
+                Ok(session) => {
+                    tracing::info!("[Bubbles] Loaded text block model");
+                    Some(Mutex::new(session))
+                }
+                Err(err) => {
+                    tracing::warn!("[Bubbles] Failed to load {}: {err:?}", path.display());
+                    None
+                }
+            }
+        })
+        .as_ref()
+}
+
+/// Whether a text block model is loaded.
+pub fn available() -> bool {
+    model().is_some()
+}
+
+/// Text blocks on `image`, in its pixel coordinates. Runs the model on the calling thread.
+pub fn detect(image: &DynamicImage) -> anyhow::Result<Vec<BoundingBox>> {
+    let model = model().ok_or_else(|| anyhow!("No text block model loaded"))?;
+    let (width, height) = (image.width(), image.height());
+    if width == 0 || height == 0 {
+        return Ok(Vec::new());
+    }
+
+    // Letterbox into the model's square, padding the right and bottom.
+    let scale = INPUT_SIZE as f64 / width.max(height) as f64;
+    let scaled_width = ((width as f64 * scale).round() as u32).clamp(1, INPUT_SIZE);
+    let scaled_height = ((height as f64 * scale).round() as u32).clamp(1, INPUT_SIZE);
+    let resized = image::imageops::resize(
+        &image.to_rgb8(),
+        scaled_width,
+        scaled_height,
+        FilterType::Triangle,
+    );
+
+    let side = INPUT_SIZE as usize;
+    let plane = side * side;
+    let mut input = vec![0f32; 3 * plane];
+    for (x, y, pixel) in resized.enumerate_pixels() {
+        let index = y as usize * side + x as usize;
+        for (channel, value) in pixel.0.into_iter().enumerate() {
+            input[channel * plane + index] = value as f32 / 255.0;
+        }
+    }
+
+    let tensor = Tensor::from_array(([1usize, 3, side, side], input))?;
+    let mut session = model.lock().expect("lock poisoned");
+    let outputs = session.run(ort::inputs![tensor])?;
+    // `blk`: one row per candidate of cx, cy, w, h, objectness, then class scores.
+    let (shape, rows) = outputs["blk"].try_extract_tensor::<f32>()?;
+    let columns = *shape
+        .last()
+        .ok_or_else(|| anyhow!("Text block model returned a scalar"))? as usize;
+    if columns < 6 {
+        return Err(anyhow!("Unexpected text block output shape {shape:?}"));
+    }
+
+    let mut candidates: Vec<(f32, BoundingBox)> = rows
+        .chunks_exact(columns)
+        .filter_map(|row| {
+            let class_score = row[5..].iter().copied().fold(0f32, f32::max);
+            let confidence = row[4] * class_score;
+            if confidence < CONFIDENCE_THRESHOLD {
+                return None;
+            }
+            let (cx, cy, w, h) = (
+                row[0] as f64 / scale,
+                row[1] as f64 / scale,
+                row[2] as f64 / scale,
+                row[3] as f64 / scale,
+            );
+            let left = (cx - w / 2.0).max(0.0);
+            let top = (cy - h / 2.0).max(0.0);
+            let right = (cx + w / 2.0).min(width as f64);
+            let bottom = (cy + h / 2.0).min(height as f64);
+            (right > left && bottom > top).then_some((
+                confidence,
+                BoundingBox {
+                    x: left,
+                    y: top,
+                    width: right - left,
+                    height: bottom - top,
+                    rotation: None,
+                },
+            ))
+        })
+        .collect();
+
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let mut blocks: Vec<BoundingBox> = Vec::new();
+    for (_, candidate) in candidates {
+        if blocks
+            .iter()
+            .all(|block| intersection_over_union(block, &candidate) <= NMS_THRESHOLD)
+        {
+            blocks.push(candidate);
+        }
+    }
+    Ok(blocks)
+}
+
+fn intersection_over_union(a: &BoundingBox, b: &BoundingBox) -> f64 {
+    let overlap_width = (a.x + a.width).min(b.x + b.width) - a.x.max(b.x);
+    let overlap_height = (a.y + a.height).min(b.y + b.height) - a.y.max(b.y);
+    if overlap_width <= 0.0 || overlap_height <= 0.0 {
+        return 0.0;
+    }
+    let intersection = overlap_width * overlap_height;
+    intersection / (a.width * a.height + b.width * b.height - intersection)
+}
@@ -0,0 +1,239 @@
+//! OCR engines, which find the lines of text on a page chunk before they are merged into
+//! bubbles. The `engine` parameter of the OCR endpoints picks one, so the same page can be read
+//! by each to compare them.
+
+use std::{future::Future, io::Cursor, pin::Pin};
+
+use anyhow::anyhow;
+use chrome_lens_ocr::LensClient;
+use image::{DynamicImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    language::OcrLanguage,
+    logic::{BoundingBox, OcrResult},
+};
+
+pub type EngineFuture<'a> =
+    Pin<Box<dyn Future<Output = anyhow::Result<Vec<OcrResult>>> + Send + 'a>>;
+
+pub trait OcrEngine: Send + Sync {
+    /// Lines of text on `image`, in its pixel coordinates and not merged yet.
+    fn recognize<'a>(&'a self, image: &'a DynamicImage, language: OcrLanguage) -> EngineFuture<'a>;
+}
+
+/// Which engine reads a page.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EngineKind {
+    /// PaddleOCR when it has a model for the language, Google Lens otherwise.
+    #[default]
+    Auto,
+    Lens,
+    /// Local PaddleOCR models; needs the `paddle` feature.
+    Paddle,
+}
+
+impl EngineKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EngineKind::Auto => "auto",
+            EngineKind::Lens => "lens",
+            EngineKind::Paddle => "paddle",
+        }
+    }
+
+    /// Cache key of a page or chapter read by this engine. Results of `auto` keep the plain
+    /// key, so caches from before engines could be picked stay valid.
+    pub fn cache_key(&self, key: String) -> String {
+        match self {
+            EngineKind::Auto => key,
+            engine => format!("engine/{}/{key}", engine.as_str()),
+        }
+    }
+
+    /// The engine `auto` stands for with `language`.
+    fn resolve(self, language: OcrLanguage) -> EngineKind {
+        match self {
+            EngineKind::Auto if paddle_supports(language) => EngineKind::Paddle,
+            EngineKind::Auto => EngineKind::Lens,
+            engine => engine,
+        }
+    }
+}
+
+/// Sets up the engine `kind` stands for. Lens goes through the proxy configured in Suwayomi,
+/// read with `user`/`pass`.
+pub async fn create(
+    kind: EngineKind,
+    language: OcrLanguage,
+    user: Option<String>,
+    pass: Option<String>,
+) -> anyhow::Result<Box<dyn OcrEngine>> {
+    match kind.resolve(language) {
+        EngineKind::Paddle => paddle_engine(language),
+        _ => Ok(Box::new(LensEngine {
+            client: crate::logic::lens_client(user, pass).await?,
+        })),
+    }
+}
+
+#[cfg(feature = "paddle")]
+fn paddle_supports(language: OcrLanguage) -> bool {
+    crate::paddle::supports(language)
+}
+
+#[cfg(not(feature = "paddle"))]
+fn paddle_supports(_language: OcrLanguage) -> bool {
+    false
+}
+
+#[cfg(feature = "paddle")]
+fn paddle_engine(language: OcrLanguage) -> anyhow::Result<Box<dyn OcrEngine>> {
+    if !crate::paddle::supports(language) {
+        return Err(anyhow!(
+            "No PaddleOCR model for {} in MANATAN_PADDLE_MODEL_DIR",
+            language.as_str()
+        ));
+    }
+    Ok(Box::new(PaddleEngine))
+}
+
+#[cfg(not(feature = "paddle"))]
+fn paddle_engine(_language: OcrLanguage) -> anyhow::Result<Box<dyn OcrEngine>> {
+    Err(anyhow!("This server was built without the paddle feature"))
+}
+
+/// Google Lens, which reads most languages well and needs no local models.
+pub struct LensEngine {
+    client: LensClient,
+}
+
+impl OcrEngine for LensEngine {
+    fn recognize<'a>(&'a self, image: &'a DynamicImage, language: OcrLanguage) -> EngineFuture<'a> {
+        Box::pin(async move {
+            let width = image.width();
+            let height = image.height();
+            let mut image_buffer = Cursor::new(Vec::new());
+            image
+                .write_to(&mut image_buffer, ImageFormat::Png)
+                .map_err(|err| anyhow!("Failed write_to: {err:?}"))?;
+            let png_bytes = image_buffer.into_inner();
+
+            let lens_response = self
+                .client
+                .process_image_bytes(&png_bytes, Some("jp"))
+                .await
+                .map_err(|err| anyhow!("Failed process_image_bytes: {err:?}"))?;
+
+            let mut lines = Vec::new();
+            for paragraph in lens_response.paragraphs {
+                for line in paragraph.lines {
+                    let Some(geometry) = line.geometry else {
+                        continue;
+                    };
+                    let rotation = geometry.rotation_z as f64;
+                    let cx = (geometry.center_x * width as f32) as f64;
+                    let cy = (geometry.center_y * height as f32) as f64;
+                    let w = (geometry.width * width as f32) as f64;
+                    let h = (geometry.height * height as f32) as f64;
+
+                    let hw = w / 2.0;
+                    let hh = h / 2.0;
+                    let cos_a = rotation.cos();
+                    let sin_a = rotation.sin();
+
+                    let corners = [(-hw, -hh), (hw, -hh), (hw, hh), (-hw, hh)];
+
+                    let mut min_x = f64::INFINITY;
+                    let mut max_x = f64::NEG_INFINITY;
+                    let mut min_y = f64::INFINITY;
+                    let mut max_y = f64::NEG_INFINITY;
+
+                    for (lx, ly) in corners {
+                        let rx = lx * cos_a - ly * sin_a + cx;
+                        let ry = lx * sin_a + ly * cos_a + cy;
+                        min_x = min_x.min(rx);
+                        max_x = max_x.max(rx);
+                        min_y = min_y.min(ry);
+                        max_y = max_y.max(ry);
+                    }
+
+                    let aabb_w = max_x - min_x;
+                    let aabb_h = max_y - min_y;
+
+                    let is_vertical = if language.prefers_vertical() {
+                        if rotation.abs() > 0.1 {
+                            (rotation.abs() - std::f32::consts::FRAC_PI_2 as f64).abs() < 0.5
+                        } else {
+                            aabb_w <= aabb_h
+                        }
+                    } else {
+                        false
+                    };
+
+                    lines.push(line_result(
+                        line.text,
+                        language,
+                        is_vertical,
+                        BoundingBox {
+                            x: min_x,
+                            y: min_y,
+                            width: aabb_w,
+                            height: aabb_h,
+                            rotation: None,
+                        },
+                    ));
+                }
+            }
+            Ok(lines)
+        })
+    }
+}
+
+/// Local PaddleOCR models, strongest on Chinese and Korean.
+#[cfg(feature = "paddle")]
+pub struct PaddleEngine;
+
+#[cfg(feature = "paddle")]
+impl OcrEngine for PaddleEngine {
+    fn recognize<'a>(&'a self, image: &'a DynamicImage, language: OcrLanguage) -> EngineFuture<'a> {
+        let image = image.clone();
+        Box::pin(async move {
+            let detected =
+                tokio::task::spawn_blocking(move || crate::paddle::recognize(&image, language))
+                    .await
+                    .map_err(|err| anyhow!("PaddleOCR task failed: {err}"))??;
+
+            Ok(detected
+                .into_iter()
+                .map(|line| {
+                    let bounding_box = line.bounding_box;
+                    let is_vertical =
+                        language.prefers_vertical() && bounding_box.width <= bounding_box.height;
+                    line_result(line.text, language, is_vertical, bounding_box)
+                })
+                .collect())
+        })
+    }
+}
+
+/// An unmerged line as the merge step expects it.
+fn line_result(
+    text: String,
+    language: OcrLanguage,
+    is_vertical: bool,
+    bounding_box: BoundingBox,
+) -> OcrResult {
+    OcrResult {
+        text: crate::logic::post_process_text(text, language),
+        is_merged: Some(false),
+        forced_orientation: Some(if is_vertical {
+            "vertical".into()
+        } else {
+            "horizontal".into()
+        }),
+        tight_bounding_box: bounding_box,
+    }
+}
@@ -2,27 +2,41 @@ use std::sync::atomic::Ordering;
 
 use axum::{
     Json,
-    extract::{Query, State},
+    extract::{Multipart, Path, Query, State},
     http::StatusCode,
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
 use tracing::{info, warn};
+use utoipa::{IntoParams, ToSchema};
 
 use crate::{
-    jobs, logic,
+    archive,
+    engine::EngineKind,
+    jobs::{ChapterJob, JobPriority},
+    logic,
     language::OcrLanguage,
-    state::{AppState, CacheEntry},
+    merge::{MergeConfig, MergeOverrides},
+    mokuro,
+    state::{AppState, CacheEntry, CacheStats, JobEvent, JobFailures, JobSummary},
 };
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct OcrRequest {
     pub url: String,
     pub user: Option<String>,
     pub pass: Option<String>,
     #[serde(default = "default_context")]
     pub context: String,
-    pub add_space_on_merge: Option<bool>,
     pub language: Option<OcrLanguage>,
+    /// Engine that reads the page; `auto` by default. Each engine's results are cached
+    /// separately.
+    pub engine: Option<EngineKind>,
 }
 
 fn default_context() -> String {
@@ -31,6 +45,12 @@ fn default_context() -> String {
 
 // --- Handlers ---
 
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "ocr",
+    responses((status = 200, description = "Server status and cache statistics", body = Object))
+)]
 pub async fn status_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
     let cache_size = state.cache_len();
     Json(serde_json::json!({
@@ -42,16 +62,36 @@ pub async fn status_handler(State(state): State<AppState>) -> Json<serde_json::V
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/ocr",
+    tag = "ocr",
+    params(OcrRequest, MergeOverrides),
+    responses(
+        (status = 200, description = "Text blocks recognised on the image", body = Vec<logic::OcrResult>),
+        (status = 500, description = "OCR failed", body = String),
+    )
+)]
 pub async fn ocr_handler(
     State(state): State<AppState>,
     Query(params): Query<OcrRequest>,
+    Query(merge): Query<MergeOverrides>,
 ) -> Result<Json<Vec<crate::logic::OcrResult>>, (StatusCode, String)> {
     let language = params.language.unwrap_or_default();
-    let cache_key = logic::get_cache_key(&params.url, Some(language));
+    let engine = params.engine.unwrap_or_default();
+    let cache_key = engine.cache_key(logic::get_cache_key(&params.url, Some(language)));
+    let merge_config = merge.apply(state.merge_config(language));
+    // Pages read with tuned merging are for trying settings out, so they bypass the cache.
+    let use_cache = !merge.tunes_grouping();
     info!("OCR Handler: Incoming request for cache_key={}", cache_key);
 
     info!("OCR Handler: Checking cache...");
-    if let Some(entry) = state.get_cache_entry(&cache_key) {
+    let cached = if use_cache {
+        state.get_cache_entry(&cache_key)
+    } else {
+        None
+    };
+    if let Some(entry) = cached {
         info!("OCR Handler: Cache HIT for cache_key={}", cache_key);
         state.requests_processed.fetch_add(1, Ordering::Relaxed);
         return Ok(Json(entry.data));
@@ -65,8 +105,9 @@ pub async fn ocr_handler(
         &params.url,
         params.user.clone(),
         params.pass.clone(),
-        params.add_space_on_merge,
+        &merge_config,
         language,
+        engine,
     )
     .await;
 
@@ -78,15 +119,17 @@ pub async fn ocr_handler(
                 cache_key
             );
 
-            info!("OCR Handler: Writing cache entry to DB...");
-            state.insert_cache_entry(
-                &cache_key,
-                &CacheEntry {
-                    context: params.context,
-                    data: data.clone(),
-                },
-            );
-            info!("OCR Handler: Cache write complete.");
+            if use_cache {
+                info!("OCR Handler: Writing cache entry to DB...");
+                state.insert_cache_entry(
+                    &cache_key,
+                    &CacheEntry {
+                        context: params.context,
+                        data: data.clone(),
+                    },
+                );
+                info!("OCR Handler: Cache write complete.");
+            }
 
             Ok(Json(data))
         }
@@ -100,23 +143,83 @@ pub async fn ocr_handler(
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
+pub struct CorrectionRequest {
+    /// The block's text as it should read.
+    pub text: String,
+}
+
+#[utoipa::path(
+    patch,
+    path = "/ocr/{cache_key}/results/{index}",
+    tag = "ocr",
+    params(
+        ("cache_key" = String, Path, description = "Cache key of the page, URL-encoded"),
+        ("index" = usize, Path, description = "Position of the block in the page's results"),
+    ),
+    request_body = CorrectionRequest,
+    responses(
+        (status = 200, description = "The corrected block, as later reads of the page return it", body = logic::OcrResult),
+        (status = 404, description = "The page isn't cached or has no block at this index", body = Object),
+        (status = 500, description = "The correction couldn't be saved", body = Object),
+    )
+)]
+pub async fn correct_result_handler(
+    State(state): State<AppState>,
+    Path((cache_key, index)): Path<(String, usize)>,
+    Json(req): Json<CorrectionRequest>,
+) -> Result<Json<logic::OcrResult>, (StatusCode, Json<serde_json::Value>)> {
+    match state.correct_cache_result(&cache_key, index, &req.text) {
+        Ok(Some(result)) => {
+            info!("Corrected block {index} of {cache_key}");
+            Ok(Json(result))
+        }
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": "No cached block at this index" })),
+        )),
+        Err(err) => {
+            warn!("Failed to save correction for {cache_key}: {err}");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "error": "Failed to save correction" })),
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct JobRequest {
     pub base_url: String,
     pub user: Option<String>,
     pub pass: Option<String>,
     pub context: String,
     pub pages: Option<Vec<String>>,
-    pub add_space_on_merge: Option<bool>,
     pub language: Option<OcrLanguage>,
+    /// `foreground` for the chapter being read, which goes ahead of `background` (the
+    /// default) jobs.
+    pub priority: Option<JobPriority>,
+    /// Engine that reads the pages; `auto` by default.
+    pub engine: Option<EngineKind>,
+    /// Merge settings for these pages on top of the server's defaults.
+    #[serde(flatten)]
+    pub merge: MergeOverrides,
 }
 
+#[utoipa::path(
+    post,
+    path = "/is-chapter-preprocessed",
+    tag = "ocr",
+    request_body = JobRequest,
+    responses((status = 200, description = "`processed`, `processing` or `idle` with page counts", body = Object))
+)]
 pub async fn is_chapter_preprocessed_handler(
     State(state): State<AppState>,
     Json(req): Json<JobRequest>,
 ) -> Json<serde_json::Value> {
     let language = req.language.unwrap_or_default();
-    let job_key = logic::get_cache_key(&req.base_url, Some(language));
+    let engine = req.engine.unwrap_or_default();
+    let job_key = engine.cache_key(logic::get_cache_key(&req.base_url, Some(language)));
     let progress = {
         state
             .active_chapter_jobs
@@ -129,6 +232,8 @@ pub async fn is_chapter_preprocessed_handler(
     if let Some(p) = progress {
         return Json(serde_json::json!({
             "status": "processing",
+            "job_id": p.id,
+            "queue_position": state.job_queue.position(p.id),
             "progress": p.current,
             "total": p.total
         }));
@@ -168,6 +273,13 @@ pub async fn is_chapter_preprocessed_handler(
     )
 }
 
+#[utoipa::path(
+    post,
+    path = "/preprocess-chapter",
+    tag = "ocr",
+    request_body = JobRequest,
+    responses((status = 200, description = "`started` or `already_processing`, with the job id and its place in the queue", body = Object))
+)]
 pub async fn preprocess_handler(
     State(state): State<AppState>,
     Json(req): Json<JobRequest>,
@@ -178,34 +290,690 @@ pub async fn preprocess_handler(
         None => return Json(serde_json::json!({ "error": "No pages provided" })),
     };
 
-    let is_processing = {
-        state
-            .active_chapter_jobs
-            .read()
-            .expect("lock poisoned")
-            .contains_key(&logic::get_cache_key(&req.base_url, Some(language)))
-    };
+    let priority = req.priority.unwrap_or_default();
+    let engine = req.engine.unwrap_or_default();
+    let job_key = engine.cache_key(logic::get_cache_key(&req.base_url, Some(language)));
 
-    if is_processing {
-        return Json(serde_json::json!({ "status": "already_processing" }));
+    // A reload asks for the chapter again; it attaches to the job already running, and opening
+    // a chapter that's being prefetched moves it up the queue.
+    let (job, created) = state.register_chapter_job(&job_key, pages.len(), &req.context, priority);
+    let job_id = job.id;
+    if !created {
+        info!("Chapter job {job_id} already covers {job_key}");
+        return Json(serde_json::json!({
+            "status": "already_processing",
+            "job_id": job_id,
+            "queue_position": state.job_queue.position(job_id),
+        }));
     }
 
-    let state_clone = state.clone();
-    tokio::spawn(async move {
-        jobs::run_chapter_job(
-            state_clone,
-            req.base_url,
+    state.job_queue.push(
+        job,
+        ChapterJob {
+            base_url: req.base_url,
             pages,
-            req.user,
-            req.pass,
-            req.context,
-            req.add_space_on_merge,
+            user: req.user,
+            pass: req.pass,
+            context: req.context,
+            merge_config: req.merge.apply(state.merge_config(language)),
             language,
+            engine,
+            images: None,
+        },
+    );
+
+    Json(serde_json::json!({
+        "status": "started",
+        "job_id": job_id,
+        "queue_position": state.job_queue.position(job_id),
+    }))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ArchiveRequest {
+    /// Context to cache the pages under; the uploaded file's name by default.
+    pub context: Option<String>,
+    pub language: Option<OcrLanguage>,
+    pub priority: Option<JobPriority>,
+    /// Engine that reads the pages; `auto` by default.
+    pub engine: Option<EngineKind>,
+    /// Suwayomi credentials, which Lens needs to read the proxy settings.
+    pub user: Option<String>,
+    pub pass: Option<String>,
+    /// Resolution PDF pages are rendered at; `MANATAN_PDF_DPI`, or 200, by default.
+    pub dpi: Option<u32>,
+}
+
+/// Multipart form accepted by `POST /ocr/archive`.
+#[derive(ToSchema)]
+pub struct ArchiveUpload {
+    /// CBZ or ZIP archive of page images, or a PDF.
+    #[schema(value_type = String, format = Binary)]
+    pub file: Vec<u8>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/ocr/archive",
+    tag = "ocr",
+    params(ArchiveRequest, MergeOverrides),
+    request_body(content = ArchiveUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "`started` or `already_processing`, with the job id, the chapter's cache key and its page names in order", body = Object),
+        (status = 400, description = "No archive uploaded, or it has no page images", body = Object),
+        (status = 413, description = "The archive is larger than the upload limit", body = Object),
+        (status = 415, description = "A PDF was uploaded to a server built without the `pdf` feature", body = Object),
+        (status = 500, description = "Reading the archive stopped unexpectedly", body = Object),
+    )
+)]
+pub async fn archive_handler(
+    State(state): State<AppState>,
+    Query(params): Query<ArchiveRequest>,
+    Query(merge): Query<MergeOverrides>,
+    mut multipart: Multipart,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    let bad_request = |error: String| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": error })),
         )
-        .await;
+    };
+
+    let mut upload = None;
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(|err| bad_request(format!("Multipart error: {err}")))?
+    {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let name = field.file_name().unwrap_or("archive").to_string();
+        let mut data = Vec::new();
+        while let Some(chunk) = field
+            .chunk()
+            .await
+            .map_err(|err| bad_request(format!("Upload failed: {err}")))?
+        {
+            if data.len() + chunk.len() > archive::MAX_ARCHIVE_BYTES {
+                return Err((
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    Json(serde_json::json!({ "error": "Archive is too large" })),
+                ));
+            }
+            data.extend_from_slice(&chunk);
+        }
+        upload = Some((name, data));
+        break;
+    }
+    let Some((name, data)) = upload else {
+        return Err(bad_request("No file field found".to_string()));
+    };
+
+    let id = archive::archive_id(&data);
+    let is_pdf = archive::is_pdf(&data);
+    if is_pdf && !cfg!(feature = "pdf") {
+        return Err((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Json(serde_json::json!({ "error": "This server was built without PDF support" })),
+        ));
+    }
+    let dpi = archive::pdf_dpi(params.dpi);
+    // PDF pages keep their page numbers, from 1, and are cached per resolution.
+    let (base_url, first_page) = if is_pdf {
+        (format!("pdf/{id}/{dpi}dpi"), 1)
+    } else {
+        (format!("archive/{id}"), 0)
+    };
+    let pages = tokio::task::spawn_blocking(move || {
+        if is_pdf {
+            archive::render_pdf(&data, dpi).map(|pages| {
+                pages
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, image)| (format!("Page {}", index + 1), image))
+                    .collect::<Vec<_>>()
+            })
+        } else {
+            archive::read_pages(&data)
+        }
+    })
+    .await
+    .map_err(|err| {
+        warn!("Reading archive {id} failed: {err}");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": "Reading the archive failed" })),
+        )
+    })?
+    .map_err(|err| bad_request(format!("Invalid archive: {err:#}")))?;
+    if pages.is_empty() {
+        return Err(bad_request("No page images in the archive".to_string()));
+    }
+
+    let language = params.language.unwrap_or_default();
+    let engine = params.engine.unwrap_or_default();
+    let priority = params.priority.unwrap_or_default();
+    let context = params.context.unwrap_or(name);
+    let job_key = engine.cache_key(logic::get_cache_key(&base_url, Some(language)));
+    let page_names: Vec<String> = pages.iter().map(|(name, _)| name.clone()).collect();
+
+    let (job, created) = state.register_chapter_job(&job_key, pages.len(), &context, priority);
+    let job_id = job.id;
+    if !created {
+        info!("Chapter job {job_id} already covers {job_key}");
+        return Ok(Json(serde_json::json!({
+            "status": "already_processing",
+            "job_id": job_id,
+            "queue_position": state.job_queue.position(job_id),
+            "chapter": job_key,
+            "pages": page_names,
+        })));
+    }
+
+    let images: std::collections::HashMap<String, Vec<u8>> = pages
+        .into_iter()
+        .enumerate()
+        .map(|(index, (_, image))| (format!("{base_url}/{}", index + first_page), image))
+        .collect();
+    info!(
+        "Queued archive {context} ({} pages) as job {job_id}",
+        images.len()
+    );
+    state.job_queue.push(
+        job,
+        ChapterJob {
+            pages: (first_page..first_page + images.len())
+                .map(|page| format!("{base_url}/{page}"))
+                .collect(),
+            base_url,
+            user: params.user,
+            pass: params.pass,
+            context,
+            merge_config: merge.apply(state.merge_config(language)),
+            language,
+            engine,
+            images: Some(std::sync::Arc::new(images)),
+        },
+    );
+
+    Ok(Json(serde_json::json!({
+        "status": "started",
+        "job_id": job_id,
+        "queue_position": state.job_queue.position(job_id),
+        "chapter": job_key,
+        "pages": page_names,
+    })))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ArchiveResultsQuery {
+    /// Cache key of the upload, the `chapter` returned by `POST /ocr/archive`.
+    pub chapter: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ArchiveResults {
+    pub chapter: String,
+    /// Results of the pages OCR'd so far, by page number for PDFs and by position from 0 for
+    /// archives.
+    pub pages: std::collections::BTreeMap<usize, Vec<logic::OcrResult>>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/ocr/archive/results",
+    tag = "ocr",
+    params(ArchiveResultsQuery),
+    responses(
+        (status = 200, description = "Cached results of an uploaded archive or PDF, by page", body = ArchiveResults),
+        (status = 500, description = "The cache database couldn't be read", body = Object),
+    )
+)]
+pub async fn archive_results_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ArchiveResultsQuery>,
+) -> Result<Json<ArchiveResults>, (StatusCode, Json<serde_json::Value>)> {
+    let entries = state.chapter_cache_entries(&query.chapter).map_err(|err| {
+        warn!("Failed to read cached pages of {}: {err}", query.chapter);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": "Failed to read the cache" })),
+        )
+    })?;
+    let pages = entries
+        .into_iter()
+        .filter_map(|(key, results)| Some((mokuro::page_number(&key)?, results)))
+        .collect();
+    Ok(Json(ArchiveResults {
+        chapter: query.chapter,
+        pages,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/jobs",
+    tag = "ocr",
+    responses((status = 200, description = "Queued and running chapter jobs with their progress, pace and estimated time left", body = Object))
+)]
+pub async fn list_jobs_handler(State(state): State<AppState>) -> Json<Vec<JobSummary>> {
+    Json(state.list_chapter_jobs())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/jobs/{id}",
+    tag = "ocr",
+    params(("id" = u64, Path, description = "Job id returned by `/preprocess-chapter`")),
+    responses(
+        (status = 200, description = "The job was cancelled; pages already OCR'd stay cached", body = Object),
+        (status = 404, description = "No running job with this id", body = Object),
+    )
+)]
+pub async fn cancel_job_handler(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    match state.cancel_chapter_job(id) {
+        Some(job_key) => {
+            info!("Cancelled chapter job {id} ({job_key})");
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({ "status": "cancelled", "job_id": id })),
+            )
+        }
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": "Job not found" })),
+        ),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}/events",
+    tag = "ocr",
+    params(("id" = u64, Path, description = "Job id returned by `/preprocess-chapter`")),
+    responses(
+        (status = 200, description = "Server-sent `page` events as pages finish, then a `finished` event", content_type = "text/event-stream", body = String),
+        (status = 404, description = "No running job with this id", body = Object),
+    )
+)]
+pub async fn job_events_handler(State(state): State<AppState>, Path(id): Path<u64>) -> Response {
+    let Some(receiver) = state.subscribe_chapter_job(id) else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": "Job not found" })),
+        )
+            .into_response();
+    };
+
+    // Ends when the job drops its sender after the `finished` event.
+    let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Job events client fell behind by {skipped} events");
+                    continue;
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            let name = match event {
+                JobEvent::Page(_) => "page",
+                JobEvent::Finished { .. } => "finished",
+            };
+            return Some((Event::default().event(name).json_data(&event), receiver));
+        }
+    });
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}/failures",
+    tag = "ocr",
+    params(("id" = u64, Path, description = "Job id returned by `/preprocess-chapter`")),
+    responses(
+        (status = 200, description = "Pages of the finished job that failed every attempt, with their last error", body = Object),
+        (status = 404, description = "No finished job with failed pages has this id", body = Object),
+    )
+)]
+pub async fn job_failures_handler(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<Json<JobFailures>, (StatusCode, Json<serde_json::Value>)> {
+    state.job_failures(id).map(Json).ok_or((
+        StatusCode::NOT_FOUND,
+        Json(serde_json::json!({ "error": "No failures recorded for this job" })),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/jobs/{id}/retry-failures",
+    tag = "ocr",
+    params(("id" = u64, Path, description = "Job id returned by `/preprocess-chapter`")),
+    responses(
+        (status = 200, description = "`started` with the id of a new job for just the failed pages, or `already_processing` when the chapter has a job", body = Object),
+        (status = 404, description = "No finished job with failed pages has this id", body = Object),
+        (status = 409, description = "The job read an uploaded archive, which has to be uploaded again", body = Object),
+    )
+)]
+pub async fn retry_failures_handler(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Some(report) = state.job_failures(id) else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": "No failures recorded for this job" })),
+        );
+    };
+
+    // Reports don't keep an archive's images; uploading it again retries just the pages that
+    // aren't cached.
+    if archive::is_upload_url(&report.job.base_url) {
+        return (
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({
+                "error": "Upload the archive again to retry its failed pages",
+                "chapter": report.chapter,
+            })),
+        );
+    }
+
+    let chapter = ChapterJob {
+        pages: report.pages.iter().map(|page| page.url.clone()).collect(),
+        ..report.job
+    };
+    let (job, created) = state.register_chapter_job(
+        &report.chapter,
+        chapter.pages.len(),
+        &chapter.context,
+        report.priority,
+    );
+    let job_id = job.id;
+    if !created {
+        info!("Chapter job {job_id} already covers {}", report.chapter);
+        return (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "status": "already_processing",
+                "job_id": job_id,
+                "queue_position": state.job_queue.position(job_id),
+            })),
+        );
+    }
+
+    info!(
+        "Retrying {} failed pages of job {id} as job {job_id}",
+        chapter.pages.len()
+    );
+    state.remove_job_failures(id);
+    state.job_queue.push(job, chapter);
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "status": "started",
+            "job_id": job_id,
+            "queue_position": state.job_queue.position(job_id),
+        })),
+    )
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MergeConfigQuery {
+    /// Language whose profile the effective settings start from; Japanese by default.
+    pub language: Option<OcrLanguage>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MergeConfigResponse {
+    /// Settings stored with `PUT /merge-config`, applied to every language.
+    pub overrides: MergeOverrides,
+    pub language: OcrLanguage,
+    /// The language's merge profile with the stored settings applied.
+    pub config: MergeConfig,
+}
+
+fn merge_config_response(state: &AppState, language: OcrLanguage) -> MergeConfigResponse {
+    MergeConfigResponse {
+        overrides: state.merge_defaults.read().expect("lock poisoned").clone(),
+        language,
+        config: state.merge_config(language),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/merge-config",
+    tag = "ocr",
+    params(MergeConfigQuery),
+    responses((status = 200, description = "Default merge settings used by OCR requests in the language", body = MergeConfigResponse))
+)]
+pub async fn get_merge_config_handler(
+    State(state): State<AppState>,
+    Query(query): Query<MergeConfigQuery>,
+) -> Json<MergeConfigResponse> {
+    Json(merge_config_response(
+        &state,
+        query.language.unwrap_or_default(),
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/merge-config",
+    tag = "ocr",
+    request_body = MergeOverrides,
+    responses(
+        (status = 200, description = "The stored settings replaced; settings left out go back to the built-in ones", body = MergeConfigResponse),
+        (status = 500, description = "The settings couldn't be saved", body = Object),
+    )
+)]
+pub async fn put_merge_config_handler(
+    State(state): State<AppState>,
+    Json(overrides): Json<MergeOverrides>,
+) -> Result<Json<MergeConfigResponse>, (StatusCode, Json<serde_json::Value>)> {
+    state.set_merge_defaults(overrides).map_err(|err| {
+        warn!("Failed to save merge config: {err}");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": "Failed to save merge config" })),
+        )
+    })?;
+    Ok(Json(merge_config_response(&state, OcrLanguage::default())))
+}
+
+#[utoipa::path(
+    get,
+    path = "/cache-stats",
+    tag = "ocr",
+    responses(
+        (status = 200, description = "Cached pages and their size, overall and per language", body = Object),
+        (status = 500, description = "The cache database couldn't be read", body = Object),
+    )
+)]
+pub async fn cache_stats_handler(
+    State(state): State<AppState>,
+) -> Result<Json<CacheStats>, (StatusCode, Json<serde_json::Value>)> {
+    state.cache_stats().map(Json).map_err(|err| {
+        warn!("Failed to read OCR cache stats: {err}");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": "Failed to read cache stats" })),
+        )
+    })
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MokuroExportQuery {
+    /// Context the chapter's pages were OCR'd with.
+    pub context: String,
+    /// Title written to the file; the context by default.
+    pub title: Option<String>,
+    pub user: Option<String>,
+    pub pass: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/ocr/export/mokuro",
+    tag = "ocr",
+    params(MokuroExportQuery),
+    responses(
+        (status = 200, description = "The chapter's cached results as a `.mokuro` file", body = Object),
+        (status = 404, description = "No cached pages have this context", body = Object),
+        (status = 500, description = "The cache database couldn't be read", body = Object),
+    )
+)]
+pub async fn export_mokuro_handler(
+    State(state): State<AppState>,
+    Query(params): Query<MokuroExportQuery>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    let mut entries = state
+        .context_cache_entries(&params.context)
+        .map_err(|err| {
+            warn!("Failed to read cached pages for Mokuro export: {err}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "error": "Failed to read the cache" })),
+            )
+        })?;
+    if entries.is_empty() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": "No cached pages for this context" })),
+        ));
+    }
+    // Page keys end in the page index; fall back to the key for anything else.
+    entries.sort_by(|(a, _), (b, _)| {
+        mokuro::page_number(a)
+            .cmp(&mokuro::page_number(b))
+            .then_with(|| a.cmp(b))
     });
 
-    Json(serde_json::json!({ "status": "started" }))
+    let mut pages = Vec::with_capacity(entries.len());
+    for (position, (cache_key, results)) in entries.iter().enumerate() {
+        // Mokuro stores pixel coordinates, so each page's size comes from its image.
+        let url = format!("http://127.0.0.1:4567/{}", mokuro::page_path(cache_key));
+        let image = logic::fetch_page_image(&url, params.user.as_deref(), params.pass.as_deref())
+            .await
+            .and_then(|bytes| mokuro::image_info(&bytes));
+        let (width, height, extension) = image.unwrap_or_else(|err| {
+            warn!("Mokuro export: no size for {cache_key}, using a nominal one: {err}");
+            (mokuro::NOMINAL_WIDTH, mokuro::NOMINAL_HEIGHT, "jpg")
+        });
+        let img_path = format!("{:03}.{extension}", position + 1);
+        pages.push(mokuro::page_from_results(results, width, height, img_path));
+    }
+
+    let title = params.title.unwrap_or_else(|| params.context.clone());
+    let volume = mokuro::MokuroVolume {
+        version: mokuro::MOKURO_VERSION.to_string(),
+        title_uuid: mokuro::name_uuid(&title),
+        volume_uuid: mokuro::name_uuid(&params.context),
+        title,
+        volume: params.context,
+        pages,
+    };
+    let filename: String = volume
+        .volume
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    Ok((
+        [(
+            axum::http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{filename}.mokuro\""),
+        )],
+        Json(volume),
+    )
+        .into_response())
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MokuroImportRequest {
+    /// URLs of the chapter's pages, in the order of the file's pages, as sent to
+    /// `/preprocess-chapter`.
+    pub pages: Vec<String>,
+    /// Context to cache the pages under; the file's volume name by default.
+    pub context: Option<String>,
+    pub language: Option<OcrLanguage>,
+    /// Engine whose cache the pages go into; `auto` by default.
+    pub engine: Option<EngineKind>,
+    /// Contents of the `.mokuro` file.
+    #[schema(value_type = Object)]
+    pub mokuro: mokuro::MokuroVolume,
+}
+
+#[utoipa::path(
+    post,
+    path = "/ocr/import/mokuro",
+    tag = "ocr",
+    request_body = MokuroImportRequest,
+    responses(
+        (status = 200, description = "Pages added to the cache; pages already cached are kept as they are", body = Object),
+        (status = 400, description = "The file doesn't have one page per URL", body = Object),
+    )
+)]
+pub async fn import_mokuro_handler(
+    State(state): State<AppState>,
+    Json(req): Json<MokuroImportRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    if req.pages.len() != req.mokuro.pages.len() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": format!(
+                    "{} page URLs for {} pages in the file",
+                    req.pages.len(),
+                    req.mokuro.pages.len()
+                )
+            })),
+        ));
+    }
+
+    let language = req.language.unwrap_or_default();
+    let engine = req.engine.unwrap_or_default();
+    let context = req.context.unwrap_or_else(|| req.mokuro.volume.clone());
+    let entries: std::collections::HashMap<String, CacheEntry> = req
+        .pages
+        .iter()
+        .zip(&req.mokuro.pages)
+        .map(|(url, page)| {
+            (
+                engine.cache_key(logic::get_cache_key(url, Some(language))),
+                CacheEntry {
+                    context: context.clone(),
+                    data: mokuro::results_from_page(page, language),
+                },
+            )
+        })
+        .collect();
+    let total = entries.len();
+    let added = state.import_cache(entries);
+    info!("Imported {added} of {total} Mokuro pages for {context}");
+    Ok(Json(serde_json::json!({
+        "message": "Import successful",
+        "added": added,
+        "skipped": total - added,
+    })))
 }
 
 pub async fn purge_cache_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
@@ -1,115 +1,697 @@
-use std::sync::{
-    Arc,
-    atomic::{AtomicUsize, Ordering},
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
 };
 
-use futures::StreamExt;
+use futures::{StreamExt, future::BoxFuture};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use utoipa::ToSchema;
 
 use crate::{
+    engine::EngineKind,
     language::OcrLanguage,
-    state::{AppState, JobProgress},
+    logic::OcrResult,
+    merge::MergeConfig,
+    state::{AppState, JobEvent, JobFailures, JobProgress, JobStatus, PageEvent, PageFailure},
 };
 
-pub async fn run_chapter_job(
-    state: AppState,
-    base_url: String,
-    pages: Vec<String>,
-    user: Option<String>,
-    pass: Option<String>,
-    context: String,
-    add_space_on_merge: Option<bool>,
-    language: OcrLanguage,
-) {
-    let total = pages.len();
-    let job_id = crate::logic::get_cache_key(&base_url, Some(language));
-
-    {
-        state
-            .active_chapter_jobs
-            .write()
+/// Attempts at a page that keeps failing, each after the retries within `fetch_and_process`.
+const PAGE_ATTEMPTS: u32 = 4;
+/// Wait before the first retry of a job's failed pages, doubled for each one after.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(5);
+
+/// How urgently a chapter job runs.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum JobPriority {
+    /// Chapters preprocessed ahead of the reader.
+    #[default]
+    Background,
+    /// The chapter open in the reader. Running background jobs stop taking new pages until
+    /// it's done.
+    Foreground,
+}
+
+/// A chapter's pages and how to OCR them.
+#[derive(Clone)]
+pub struct ChapterJob {
+    pub base_url: String,
+    pub pages: Vec<String>,
+    pub user: Option<String>,
+    pub pass: Option<String>,
+    pub context: String,
+    pub merge_config: MergeConfig,
+    pub language: OcrLanguage,
+    pub engine: EngineKind,
+    /// Images of pages that don't come from Suwayomi, such as an uploaded archive's, by page
+    /// URL. Pages found here are read from memory instead of being fetched.
+    pub images: Option<Arc<HashMap<String, Vec<u8>>>>,
+}
+
+struct QueuedJob {
+    progress: JobProgress,
+    chapter: ChapterJob,
+    /// First page not started yet; a preempted job resumes from here.
+    next_page: usize,
+    /// Pages that failed before the job was preempted, with their errors, retried once it's
+    /// done.
+    failed: BTreeMap<usize, String>,
+}
+
+/// Chapter jobs waiting for a worker, taken by priority and then in the order they were
+/// registered.
+#[derive(Default)]
+pub struct JobQueue {
+    pending: Mutex<Vec<QueuedJob>>,
+    notify: Notify,
+}
+
+impl JobQueue {
+    /// Queues a job registered with [`AppState::register_chapter_job`].
+    pub fn push(&self, progress: JobProgress, chapter: ChapterJob) {
+        self.requeue(QueuedJob {
+            progress,
+            chapter,
+            next_page: 0,
+            failed: BTreeMap::new(),
+        });
+    }
+
+    fn requeue(&self, job: QueuedJob) {
+        self.pending.lock().expect("lock poisoned").push(job);
+        self.notify.notify_one();
+    }
+
+    fn pop(&self) -> Option<QueuedJob> {
+        let mut pending = self.pending.lock().expect("lock poisoned");
+        let next = (0..pending.len()).min_by_key(|&index| queue_order(&pending[index].progress))?;
+        Some(pending.remove(next))
+    }
+
+    /// 1-based place of job `id` among the waiting jobs.
+    pub fn position(&self, id: u64) -> Option<usize> {
+        let pending = self.pending.lock().expect("lock poisoned");
+        let job = pending.iter().find(|job| job.progress.id == id)?;
+        let order = queue_order(&job.progress);
+        Some(
+            pending
+                .iter()
+                .filter(|other| queue_order(&other.progress) < order)
+                .count()
+                + 1,
+        )
+    }
+
+    /// Raises the priority of waiting job `id`.
+    pub fn raise(&self, id: u64, priority: JobPriority) {
+        let mut pending = self.pending.lock().expect("lock poisoned");
+        if let Some(job) = pending.iter_mut().find(|job| job.progress.id == id) {
+            job.progress.priority = job.progress.priority.max(priority);
+        }
+    }
+
+    /// Whether a job more urgent than `priority` is waiting.
+    fn has_waiting_above(&self, priority: JobPriority) -> bool {
+        self.pending
+            .lock()
             .expect("lock poisoned")
-            .insert(job_id.clone(), JobProgress { current: 0, total });
+            .iter()
+            .any(|job| job.progress.priority > priority && !job.progress.cancel.is_cancelled())
     }
+}
 
-    state.active_jobs.fetch_add(1, Ordering::Relaxed);
-    tracing::info!("[Job] Started for {} ({} pages)", context, total);
+/// Sort key of a waiting job: the most urgent first, then the oldest.
+fn queue_order(progress: &JobProgress) -> (std::cmp::Reverse<JobPriority>, u64) {
+    (std::cmp::Reverse(progress.priority), progress.id)
+}
+
+/// Chapter jobs run at once: `MANATAN_OCR_JOB_WORKERS`, by default 1 on Android and 2
+/// elsewhere.
+fn worker_count() -> usize {
+    std::env::var("MANATAN_OCR_JOB_WORKERS")
+        .ok()
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .filter(|workers| *workers > 0)
+        .unwrap_or(if cfg!(target_os = "android") { 1 } else { 2 })
+}
 
-    let completed_counter = Arc::new(AtomicUsize::new(0));
-    let stream = futures::stream::iter(pages.into_iter());
+/// Starts the workers that take jobs off the queue. Needs a Tokio runtime.
+pub fn spawn_workers(state: &AppState) {
+    for _ in 0..worker_count() {
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                match state.job_queue.pop() {
+                    Some(job) => run_chapter_job(state.clone(), job, &EnginePageRunner).await,
+                    None => state.job_queue.notify.notified().await,
+                }
+            }
+        });
+    }
+}
 
-    // Change from 6 to 2 or 3 for Android stability
-    let concurrency_limit = if cfg!(target_os = "android") { 2 } else { 6 };
+/// OCRs the pages of a queued chapter job until they are done, the job is cancelled, or a more
+/// urgent job is waiting, in which case it goes back to the queue. Pages that fail are retried
+/// after the rest, with growing delays, before they count as failed.
+async fn run_chapter_job(state: AppState, queued: QueuedJob, runner: &dyn PageRunner) {
+    let QueuedJob {
+        progress: job,
+        chapter,
+        next_page,
+        failed,
+    } = queued;
+    let total = chapter.pages.len();
+    let job_id = chapter.engine.cache_key(crate::logic::get_cache_key(
+        &chapter.base_url,
+        Some(chapter.language),
+    ));
+    let cancel = job.cancel.clone();
+
+    state.active_jobs.fetch_add(1, Ordering::Relaxed);
+    state.update_chapter_job(&job_id, job.id, |progress| {
+        progress.status = JobStatus::Running;
+    });
+    tracing::info!("[Job] Started for {} ({} pages)", chapter.context, total);
+
+    let run = ChapterRun {
+        state: &state,
+        job: &job,
+        job_id: &job_id,
+        chapter: &chapter,
+        runner,
+        completed: AtomicUsize::new(
+            state
+                .chapter_job(&job_id, job.id)
+                .map_or(0, |progress| progress.current),
+        ),
+        failed: Mutex::new(failed),
+    };
+    let pulled = AtomicUsize::new(next_page);
+    // Pages not started yet are dropped once the job is cancelled or preempted.
+    let stream = futures::stream::iter(next_page..total)
+        .take_until(cancel.clone().cancelled_owned())
+        .take_while(|_| futures::future::ready(!state.job_queue.has_waiting_above(job.priority)));
 
     stream
-        .for_each_concurrent(concurrency_limit, |url| {
-            let state = state.clone();
-            let job_id = job_id.clone();
-            let user = user.clone();
-            let pass = pass.clone();
-            let context = context.clone();
-            let completed_counter = completed_counter.clone();
-
-            let page_id = url.split('/').next_back().unwrap_or("unknown").to_string();
-
-            async move {
-                let cache_key = crate::logic::get_cache_key(&url, Some(language));
-                let exists = state.has_cache_entry(&cache_key);
-                if exists {
-                    tracing::info!("[Page {page_id}] Skip (Cached)");
-                } else {
-                    tracing::info!("[Page {page_id}] Starting fetch_and_process (Async)...");
-
-                    // None defaults to Smart Detection for space merging
-                    match crate::logic::fetch_and_process(
-                        &url,
-                        user,
-                        pass,
-                        add_space_on_merge,
-                        language,
-                    )
-                        .await
-                    {
-                        Ok(res) => state.insert_cache_entry(
-                            &cache_key,
-                            &crate::state::CacheEntry {
-                                context: context.clone(),
-                                data: res,
-                            },
-                        ),
-                        Err(err) => {
-                            tracing::warn!("[Page {page_id}] Failed: {err:?}");
-                        }
+        .for_each_concurrent(concurrency_limit(), |index| {
+            pulled.fetch_max(index + 1, Ordering::Relaxed);
+            run.process_page(index, 1)
+        })
+        .await;
+
+    let next_page = pulled.load(Ordering::Relaxed);
+    if !cancel.is_cancelled() && next_page < total {
+        state.active_jobs.fetch_sub(1, Ordering::Relaxed);
+        tracing::info!("[Job {job_id}] Paused at page {next_page} for a more urgent job");
+        state.update_chapter_job(&job_id, job.id, |progress| {
+            progress.status = JobStatus::Queued;
+        });
+        let failed = run.take_failed();
+        state.job_queue.requeue(QueuedJob {
+            progress: job,
+            chapter,
+            next_page,
+            failed,
+        });
+        return;
+    }
+
+    for attempt in 2..=PAGE_ATTEMPTS {
+        let retry = run.take_failed();
+        if retry.is_empty() || cancel.is_cancelled() {
+            run.failed.lock().expect("lock poisoned").extend(retry);
+            break;
+        }
+        let delay = RETRY_BASE_DELAY * 2u32.pow(attempt - 2);
+        tracing::info!(
+            "[Job {job_id}] Retrying {} failed pages in {delay:?} (attempt {attempt})",
+            retry.len()
+        );
+        tokio::select! {
+            () = tokio::time::sleep(delay) => {}
+            () = cancel.cancelled() => {
+                run.failed.lock().expect("lock poisoned").extend(retry);
+                break;
+            }
+        }
+        futures::stream::iter(retry.into_keys())
+            .for_each_concurrent(concurrency_limit(), |index| {
+                run.process_page(index, attempt)
+            })
+            .await;
+    }
+
+    state.active_jobs.fetch_sub(1, Ordering::Relaxed);
+    tracing::info!("[Job {job_id}] Finalize...");
+
+    let failed = run.take_failed();
+    let _ = job.events.send(JobEvent::Finished {
+        current: run.completed.load(Ordering::Relaxed),
+        total,
+        failed: failed.len(),
+        cancelled: cancel.is_cancelled(),
+    });
+    if !failed.is_empty() {
+        tracing::warn!("[Job {job_id}] {} pages failed after retries", failed.len());
+        state.record_job_failures(JobFailures {
+            id: job.id,
+            chapter: job_id.clone(),
+            context: chapter.context.clone(),
+            priority: job.priority,
+            total,
+            cancelled: cancel.is_cancelled(),
+            finished_at: crate::state::now_unix(),
+            pages: failed
+                .into_iter()
+                .map(|(index, error)| {
+                    let url = chapter.pages[index].clone();
+                    PageFailure {
+                        index,
+                        page_id: page_id(&url),
+                        url,
+                        error,
                     }
+                })
+                .collect(),
+            // Reports can stay around for a long time; an archive's pages are uploaded again to
+            // retry them instead.
+            job: ChapterJob {
+                images: None,
+                ..chapter.clone()
+            },
+        });
+    }
+
+    // A cancelled job was already removed, possibly in favour of a new one for the chapter.
+    state.remove_chapter_job(&job_id, job.id);
+
+    if cancel.is_cancelled() {
+        tracing::info!("[Job {job_id}] Cancelled for {}", chapter.context);
+    } else {
+        tracing::info!("[Job {job_id}] Finished for {}", chapter.context);
+    }
+}
+
+// Change from 6 to 2 or 3 for Android stability
+fn concurrency_limit() -> usize {
+    if cfg!(target_os = "android") { 2 } else { 6 }
+}
+
+/// Last segment of a page URL, which names the page in logs and events.
+fn page_id(url: &str) -> String {
+    url.split('/').next_back().unwrap_or("unknown").to_string()
+}
+
+/// OCRs single pages of chapter jobs.
+trait PageRunner: Send + Sync {
+    fn run<'a>(
+        &'a self,
+        chapter: &'a ChapterJob,
+        index: usize,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<OcrResult>>>;
+}
+
+/// Reads pages with the chapter's engine, from its images when it has them and from Suwayomi
+/// otherwise.
+struct EnginePageRunner;
+
+impl PageRunner for EnginePageRunner {
+    fn run<'a>(
+        &'a self,
+        chapter: &'a ChapterJob,
+        index: usize,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<OcrResult>>> {
+        let ChapterJob {
+            pages,
+            user,
+            pass,
+            merge_config,
+            language,
+            engine,
+            images,
+            ..
+        } = chapter;
+        let url = &pages[index];
+        let image = images.as_ref().and_then(|images| images.get(url));
+        Box::pin(async move {
+            match image {
+                Some(image) => {
+                    crate::logic::process_image(
+                        url,
+                        image,
+                        user.clone(),
+                        pass.clone(),
+                        merge_config,
+                        *language,
+                        *engine,
+                    )
+                    .await
+                }
+                None => {
+                    crate::logic::fetch_and_process(
+                        url,
+                        user.clone(),
+                        pass.clone(),
+                        merge_config,
+                        *language,
+                        *engine,
+                    )
+                    .await
                 }
+            }
+        })
+    }
+}
 
-                let current = completed_counter.fetch_add(1, Ordering::Relaxed) + 1;
+/// A chapter job while one of the workers runs it.
+struct ChapterRun<'a> {
+    state: &'a AppState,
+    job: &'a JobProgress,
+    job_id: &'a str,
+    chapter: &'a ChapterJob,
+    runner: &'a dyn PageRunner,
+    /// Pages through their first attempt, failed or not.
+    completed: AtomicUsize,
+    /// Indexes of the pages whose last attempt failed, with its error.
+    failed: Mutex<BTreeMap<usize, String>>,
+}
 
-                {
-                    if let Some(prog) = state
-                        .active_chapter_jobs
-                        .write()
-                        .expect("lock")
-                        .get_mut(&job_id)
-                    {
-                        prog.current = current;
-                    }
+impl ChapterRun<'_> {
+    fn take_failed(&self) -> BTreeMap<usize, String> {
+        std::mem::take(&mut *self.failed.lock().expect("lock poisoned"))
+    }
+
+    /// OCRs page `index` unless it's cached, reporting the outcome of this attempt.
+    async fn process_page(&self, index: usize, attempt: u32) {
+        let ChapterJob {
+            pages,
+            context,
+            language,
+            engine,
+            ..
+        } = self.chapter;
+        let url = &pages[index];
+        let page_id = page_id(url);
+        let cache_key = engine.cache_key(crate::logic::get_cache_key(url, Some(*language)));
+        let exists = self.state.has_cache_entry(&cache_key);
+        let error = if exists {
+            tracing::info!("[Page {page_id}] Skip (Cached)");
+            None
+        } else {
+            tracing::info!("[Page {page_id}] Starting fetch_and_process (Async)...");
+
+            // None defaults to Smart Detection for space merging
+            let result = tokio::select! {
+                result = self.runner.run(self.chapter, index) => result,
+                () = self.job.cancel.cancelled() => {
+                    tracing::info!("[Page {page_id}] Cancelled");
+                    return;
+                }
+            };
+            match result {
+                Ok(res) => {
+                    self.state.insert_cache_entry(
+                        &cache_key,
+                        &crate::state::CacheEntry {
+                            context: context.clone(),
+                            data: res,
+                        },
+                    );
+                    None
                 }
+                Err(err) => {
+                    tracing::warn!("[Page {page_id}] Failed (attempt {attempt}): {err:?}");
+                    let error = err.to_string();
+                    self.failed
+                        .lock()
+                        .expect("lock poisoned")
+                        .insert(index, error.clone());
+                    Some(error)
+                }
+            }
+        };
+
+        // Retries don't move the page count, only the failure count.
+        let current = if attempt == 1 {
+            self.completed.fetch_add(1, Ordering::Relaxed) + 1
+        } else {
+            self.completed.load(Ordering::Relaxed)
+        };
+        let failed = self.failed.lock().expect("lock poisoned").len();
+        self.state
+            .update_chapter_job(self.job_id, self.job.id, |progress| {
+                progress.current = current;
+                progress.failed = failed;
+            });
+
+        // Nobody listening isn't an error.
+        let _ = self.job.events.send(JobEvent::Page(PageEvent {
+            page_id,
+            index,
+            current,
+            total: pages.len(),
+            attempt,
+            success: error.is_none(),
+            cached: exists,
+            cache_key,
+            error,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_state(name: &str) -> AppState {
+        let dir = std::env::temp_dir().join(format!(
+            "manatan-ocr-jobs-{name}-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        AppState::new(dir)
+    }
+
+    fn chapter(base_url: &str, pages: usize) -> ChapterJob {
+        ChapterJob {
+            base_url: base_url.to_string(),
+            pages: (0..pages).map(|index| format!("{base_url}/{index}")).collect(),
+            user: None,
+            pass: None,
+            context: "test".to_string(),
+            merge_config: MergeConfig::for_language(OcrLanguage::default()),
+            language: OcrLanguage::default(),
+            engine: EngineKind::default(),
+            images: None,
+        }
+    }
+
+    fn job_key(chapter: &ChapterJob) -> String {
+        chapter.engine.cache_key(crate::logic::get_cache_key(
+            &chapter.base_url,
+            Some(chapter.language),
+        ))
+    }
 
+    /// Registers and queues `chapter` the way the handlers do.
+    fn queue(state: &AppState, chapter: ChapterJob, priority: JobPriority) -> JobProgress {
+        let (progress, created) =
+            state.register_chapter_job(&job_key(&chapter), chapter.pages.len(), "test", priority);
+        assert!(created);
+        state.job_queue.push(progress.clone(), chapter);
+        progress
+    }
+
+    /// Stands in for the engine. Each page fails as many times as `failures` says before it
+    /// succeeds, and once page `preempt.0` runs, a foreground job is queued.
+    #[derive(Default)]
+    struct FakeRunner {
+        failures: HashMap<usize, u32>,
+        preempt: Option<(usize, AppState)>,
+        attempts: Mutex<BTreeMap<usize, u32>>,
+    }
+
+    impl FakeRunner {
+        fn attempts(&self) -> BTreeMap<usize, u32> {
+            self.attempts.lock().expect("lock poisoned").clone()
+        }
+    }
+
+    impl PageRunner for FakeRunner {
+        fn run<'a>(
+            &'a self,
+            _chapter: &'a ChapterJob,
+            index: usize,
+        ) -> BoxFuture<'a, anyhow::Result<Vec<OcrResult>>> {
+            let attempt = {
+                let mut attempts = self.attempts.lock().expect("lock poisoned");
+                let attempt = attempts.entry(index).or_default();
+                *attempt += 1;
+                *attempt
+            };
+            if let Some((_, state)) = self.preempt.as_ref().filter(|(at, _)| *at == index) {
+                queue(state, chapter("urgent", 1), JobPriority::Foreground);
             }
-        })
-        .await;
+            let result = if attempt <= self.failures.get(&index).copied().unwrap_or(0) {
+                Err(anyhow::anyhow!("page {index} failed on attempt {attempt}"))
+            } else {
+                Ok(Vec::new())
+            };
+            Box::pin(async move { result })
+        }
+    }
 
-    tracing::info!("[Job {job_id}] Finalize...");
+    #[test]
+    fn queue_takes_urgent_jobs_first_then_the_oldest() {
+        let state = test_state("queue");
+        let first = queue(&state, chapter("first", 1), JobPriority::Background);
+        let second = queue(&state, chapter("second", 1), JobPriority::Background);
+        let urgent = queue(&state, chapter("urgent", 1), JobPriority::Foreground);
+        let queue = &state.job_queue;
+        assert_eq!(queue.position(urgent.id), Some(1));
+        assert_eq!(queue.position(first.id), Some(2));
+        assert_eq!(queue.position(second.id), Some(3));
 
-    state.active_jobs.fetch_sub(1, Ordering::Relaxed);
+        queue.raise(second.id, JobPriority::Foreground);
+        assert_eq!(queue.position(second.id), Some(1));
+        assert_eq!(queue.position(urgent.id), Some(2));
+        assert_eq!(queue.position(first.id), Some(3));
 
-    {
-        state
-            .active_chapter_jobs
-            .write()
-            .expect("lock poisoned")
-            .remove(&job_id);
+        // Raising never lowers a job.
+        queue.raise(second.id, JobPriority::Background);
+        assert_eq!(queue.position(second.id), Some(1));
+
+        let order: Vec<u64> = std::iter::from_fn(|| queue.pop())
+            .map(|job| job.progress.id)
+            .collect();
+        assert_eq!(order, [second.id, urgent.id, first.id]);
+        assert_eq!(queue.position(first.id), None);
     }
 
-    tracing::info!("[Job {job_id}] Finished for {}", context);
+    #[tokio::test]
+    async fn preempted_jobs_resume_from_the_next_page() {
+        let state = test_state("preempt");
+        let background = chapter("background", 10);
+        let key = job_key(&background);
+        let progress = queue(&state, background, JobPriority::Background);
+        let runner = FakeRunner {
+            preempt: Some((0, state.clone())),
+            ..Default::default()
+        };
+
+        let job = state.job_queue.pop().expect("queued job");
+        run_chapter_job(state.clone(), job, &runner).await;
+
+        let urgent = state.job_queue.pop().expect("urgent job");
+        assert_eq!(urgent.progress.priority, JobPriority::Foreground);
+        let paused = state.job_queue.pop().expect("paused job");
+        assert_eq!(paused.progress.id, progress.id);
+        assert!(
+            (1..10).contains(&paused.next_page),
+            "paused at {}",
+            paused.next_page
+        );
+        let paused_progress = state.chapter_job(&key, progress.id).expect("paused job");
+        assert_eq!(paused_progress.status, JobStatus::Queued);
+        assert_eq!(paused_progress.current, paused.next_page);
+
+        run_chapter_job(state.clone(), paused, &runner).await;
+        assert_eq!(
+            runner.attempts(),
+            (0..10).map(|index| (index, 1)).collect::<BTreeMap<_, _>>()
+        );
+        assert!(state.chapter_job(&key, progress.id).is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_pages_are_retried_with_growing_delays() {
+        let state = test_state("retry");
+        let progress = queue(&state, chapter("retry", 4), JobPriority::Background);
+        let mut events = progress.events.subscribe();
+        let runner = FakeRunner {
+            failures: HashMap::from([(1, 2), (3, u32::MAX)]),
+            ..Default::default()
+        };
+
+        let job = state.job_queue.pop().expect("queued job");
+        let started = tokio::time::Instant::now();
+        run_chapter_job(state.clone(), job, &runner).await;
+
+        assert_eq!(
+            runner.attempts(),
+            BTreeMap::from([(0, 1), (1, 3), (2, 1), (3, PAGE_ATTEMPTS)])
+        );
+        // Waits of 5, 10 and 20 seconds before the three retries.
+        assert!(started.elapsed() >= RETRY_BASE_DELAY * 7);
+
+        let mut finished = None;
+        while let Ok(event) = events.try_recv() {
+            if let JobEvent::Finished { .. } = event {
+                finished = Some(event);
+            }
+        }
+        match finished {
+            Some(JobEvent::Finished {
+                current,
+                total,
+                failed,
+                cancelled,
+            }) => assert_eq!((current, total, failed, cancelled), (4, 4, 1, false)),
+            other => panic!("expected a finished event, got {other:?}"),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failures_are_recorded_without_the_images() {
+        let state = test_state("failures");
+        let mut archive = chapter("archive/abc", 2);
+        archive.images = Some(Arc::new(HashMap::from([
+            ("archive/abc/0".to_string(), vec![0; 16]),
+            ("archive/abc/1".to_string(), vec![0; 16]),
+        ])));
+        let key = job_key(&archive);
+        let progress = queue(&state, archive, JobPriority::Foreground);
+        let runner = FakeRunner {
+            failures: HashMap::from([(1, u32::MAX)]),
+            ..Default::default()
+        };
+
+        let job = state.job_queue.pop().expect("queued job");
+        run_chapter_job(state.clone(), job, &runner).await;
+
+        let report = state.job_failures(progress.id).expect("failure report");
+        assert_eq!(report.chapter, key);
+        assert_eq!(report.priority, JobPriority::Foreground);
+        assert_eq!(report.total, 2);
+        assert!(!report.cancelled);
+        assert!(report.job.images.is_none());
+        let pages: Vec<(usize, &str, &str, &str)> = report
+            .pages
+            .iter()
+            .map(|page| {
+                (
+                    page.index,
+                    page.page_id.as_str(),
+                    page.url.as_str(),
+                    page.error.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            pages,
+            [(
+                1,
+                "1",
+                "archive/abc/1",
+                "page 1 failed on attempt 4"
+            )]
+        );
+    }
 }
@@ -1,8 +1,17 @@
+pub mod archive;
+#[cfg(feature = "bubbles")]
+mod bubbles;
+pub mod engine;
 pub mod handlers;
 pub mod jobs;
 pub mod language;
 pub mod logic;
 pub mod merge;
+pub mod mokuro;
+#[cfg(feature = "paddle")]
+mod paddle;
+#[cfg(feature = "pdf")]
+mod pdf;
 pub mod state;
 
 use std::path::PathBuf;
@@ -10,25 +19,76 @@ use std::path::PathBuf;
 use axum::{
     Router,
     extract::DefaultBodyLimit,
-    routing::{get, post},
+    routing::{delete, get, patch, post},
 };
 use state::AppState;
 
+/// OpenAPI description of the OCR endpoints, relative to where the router is nested.
+#[derive(utoipa::OpenApi)]
+#[openapi(
+    paths(
+        handlers::status_handler,
+        handlers::ocr_handler,
+        handlers::correct_result_handler,
+        handlers::is_chapter_preprocessed_handler,
+        handlers::preprocess_handler,
+        handlers::archive_handler,
+        handlers::archive_results_handler,
+        handlers::list_jobs_handler,
+        handlers::cancel_job_handler,
+        handlers::job_events_handler,
+        handlers::job_failures_handler,
+        handlers::retry_failures_handler,
+        handlers::get_merge_config_handler,
+        handlers::put_merge_config_handler,
+        handlers::cache_stats_handler,
+        handlers::export_mokuro_handler,
+        handlers::import_mokuro_handler,
+    ),
+    tags((name = "ocr", description = "Manga page OCR and chapter preprocessing"))
+)]
+pub struct ApiDoc;
+
 /// Creates the OCR Router.
 pub fn create_router(cache_dir: PathBuf) -> Router {
     let state = AppState::new(cache_dir);
 
-    // Spawn the job worker if you want strict concurrency,
-    // or we just spawn tasks per request (handled in handlers).
+    // Chapter jobs wait in a queue for a fixed number of workers.
+    jobs::spawn_workers(&state);
 
     Router::new()
         .route("/", get(handlers::status_handler))
         .route("/ocr", get(handlers::ocr_handler))
+        .route(
+            "/ocr/{cache_key}/results/{index}",
+            patch(handlers::correct_result_handler),
+        )
+        .route("/ocr/export/mokuro", get(handlers::export_mokuro_handler))
+        .route("/ocr/import/mokuro", post(handlers::import_mokuro_handler))
         .route(
             "/is-chapter-preprocessed",
             post(handlers::is_chapter_preprocessed_handler),
         )
         .route("/preprocess-chapter", post(handlers::preprocess_handler))
+        .route(
+            "/ocr/archive",
+            post(handlers::archive_handler)
+                .layer(DefaultBodyLimit::max(archive::MAX_ARCHIVE_BYTES)),
+        )
+        .route("/ocr/archive/results", get(handlers::archive_results_handler))
+        .route("/jobs", get(handlers::list_jobs_handler))
+        .route("/jobs/{id}", delete(handlers::cancel_job_handler))
+        .route("/jobs/{id}/events", get(handlers::job_events_handler))
+        .route("/jobs/{id}/failures", get(handlers::job_failures_handler))
+        .route(
+            "/jobs/{id}/retry-failures",
+            post(handlers::retry_failures_handler),
+        )
+        .route(
+            "/merge-config",
+            get(handlers::get_merge_config_handler).put(handlers::put_merge_config_handler),
+        )
+        .route("/cache-stats", get(handlers::cache_stats_handler))
         .route("/purge-cache", post(handlers::purge_cache_handler))
         .route("/export-cache", get(handlers::export_cache_handler))
         .route("/import-cache", post(handlers::import_cache_handler))
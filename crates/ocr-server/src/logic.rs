@@ -5,7 +5,9 @@ use chrome_lens_ocr::LensClient;
 use image::{DynamicImage, GenericImageView, ImageBuffer, ImageFormat, ImageReader};
 use reqwest::header::{ACCEPT, CONTENT_TYPE};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
+use crate::engine::{self, EngineKind, OcrEngine};
 use crate::language::OcrLanguage;
 use crate::merge::{self, MergeConfig};
 
@@ -185,6 +187,62 @@ async fn get_proxy_settings(
     Ok(proxy_settings)
 }
 
+/// A Lens client going through the SOCKS proxy configured in Suwayomi, if any.
+pub(crate) async fn lens_client(
+    user: Option<String>,
+    pass: Option<String>,
+) -> anyhow::Result<LensClient> {
+    // Fetch proxy settings
+    let proxy_settings = get_proxy_settings(user, pass).await.ok().flatten();
+
+    // Create LensClient with optional proxy
+    let lens_client = if let Some(ref proxy) = proxy_settings {
+        if proxy.socks_proxy_enabled && !proxy.socks_proxy_host.is_empty() {
+            // Build proxy URL with authentication if provided
+            let proxy_url = if let (Some(username), Some(password)) =
+                (&proxy.socks_proxy_username, &proxy.socks_proxy_password)
+            {
+                if !username.is_empty() && !password.is_empty() {
+                    format!(
+                        "socks{}://{}:{}@{}:{}",
+                        proxy.socks_proxy_version,
+                        username,
+                        password,
+                        proxy.socks_proxy_host,
+                        proxy.socks_proxy_port
+                    )
+                } else {
+                    format!(
+                        "socks{}://{}:{}",
+                        proxy.socks_proxy_version, proxy.socks_proxy_host, proxy.socks_proxy_port
+                    )
+                }
+            } else {
+                format!(
+                    "socks{}://{}:{}",
+                    proxy.socks_proxy_version, proxy.socks_proxy_host, proxy.socks_proxy_port
+                )
+            };
+
+            tracing::info!(
+                "Using SOCKS{} proxy for Google Lens: {}:{}",
+                proxy.socks_proxy_version,
+                proxy.socks_proxy_host,
+                proxy.socks_proxy_port
+            );
+
+            LensClient::new_with_proxy(None, Some(&proxy_url))
+                .map_err(|e| anyhow!("Failed to create LensClient with proxy: {}", e))?
+        } else {
+            LensClient::new(None)
+        }
+    } else {
+        LensClient::new(None)
+    };
+
+    Ok(lens_client)
+}
+
 pub async fn resolve_total_pages_from_graphql(
     chapter_base_url: &str,
     user: Option<String>,
@@ -256,7 +314,7 @@ pub async fn resolve_total_pages_from_graphql(
     Ok(page_count)
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 pub struct OcrResult {
     pub text: String,
 
@@ -270,7 +328,7 @@ pub struct OcrResult {
     pub forced_orientation: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
 pub struct BoundingBox {
     pub x: f64,
     pub y: f64,
@@ -300,7 +358,7 @@ pub fn get_cache_key(url: &str, language: Option<OcrLanguage>) -> String {
     }
 }
 
-fn post_process_text(text: String, language: OcrLanguage) -> String {
+pub(crate) fn post_process_text(text: String, language: OcrLanguage) -> String {
     if language.prefers_no_space() {
         text.replace(char::is_whitespace, "")
     } else {
@@ -370,28 +428,63 @@ pub async fn fetch_and_process(
     url: &str,
     user: Option<String>,
     pass: Option<String>,
-    add_space_on_merge: Option<bool>,
+    merge_config: &MergeConfig,
     language: OcrLanguage,
+    engine: EngineKind,
 ) -> anyhow::Result<Vec<OcrResult>> {
-    let mut last_error = anyhow!("Unknown error");
-
-    for attempt_number in 1..=3 {
-        match fetch_and_process_internal(
+    with_retries(url, || {
+        fetch_and_process_internal(
             url,
             user.clone(),
             pass.clone(),
-            add_space_on_merge,
+            merge_config,
             language,
+            engine,
         )
-        .await
-        {
+    })
+    .await
+}
+
+/// Like [`fetch_and_process`] for an image that isn't served by Suwayomi, such as a page of an
+/// uploaded archive. `name` identifies it in logs.
+pub async fn process_image(
+    name: &str,
+    image_bytes: &[u8],
+    user: Option<String>,
+    pass: Option<String>,
+    merge_config: &MergeConfig,
+    language: OcrLanguage,
+    engine: EngineKind,
+) -> anyhow::Result<Vec<OcrResult>> {
+    with_retries(name, || {
+        process_image_bytes(
+            image_bytes,
+            user.clone(),
+            pass.clone(),
+            merge_config,
+            language,
+            engine,
+        )
+    })
+    .await
+}
+
+async fn with_retries<F, Fut>(name: &str, mut attempt: F) -> anyhow::Result<Vec<OcrResult>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<Vec<OcrResult>>>,
+{
+    let mut last_error = anyhow!("Unknown error");
+
+    for attempt_number in 1..=3 {
+        match attempt().await {
             Ok(result) => return Ok(result),
             Err(error) => {
                 last_error = error;
                 tracing::warn!(
                     "Attempt {} failed for {}: {:?}",
                     attempt_number,
-                    url,
+                    name,
                     last_error
                 );
                 tokio::time::sleep(Duration::from_secs(attempt_number)).await;
@@ -411,6 +504,9 @@ pub struct RawChunk {
     pub global_y: u32,
     pub full_width: u32,
     pub full_height: u32,
+    /// Text blocks found by the bubble detector, in chunk pixels; empty without one.
+    #[serde(default)]
+    pub regions: Vec<BoundingBox>,
 }
 
 // --- Public Helper for Testing ---
@@ -419,6 +515,17 @@ pub async fn get_raw_ocr_data(
     user: Option<String>,
     pass: Option<String>,
     language: OcrLanguage,
+) -> anyhow::Result<Vec<RawChunk>> {
+    let engine = engine::create(EngineKind::Auto, language, user, pass).await?;
+    read_raw_chunks(engine.as_ref(), image_bytes, language).await
+}
+
+/// Decodes the page and has `engine` read it in chunks of at most 3000 rows, which keeps long
+/// webtoon strips within what the engines handle well.
+async fn read_raw_chunks(
+    engine: &dyn OcrEngine,
+    image_bytes: &[u8],
+    language: OcrLanguage,
 ) -> anyhow::Result<Vec<RawChunk>> {
     let reader = ImageReader::new(Cursor::new(image_bytes))
         .with_guessed_format()
@@ -438,57 +545,6 @@ pub async fn get_raw_ocr_data(
 
     let mut raw_chunks = Vec::new();
 
-    // Fetch proxy settings
-    let proxy_settings = get_proxy_settings(user.clone(), pass.clone())
-        .await
-        .ok()
-        .flatten();
-
-    // Create LensClient with optional proxy
-    let lens_client = if let Some(ref proxy) = proxy_settings {
-        if proxy.socks_proxy_enabled && !proxy.socks_proxy_host.is_empty() {
-            // Build proxy URL with authentication if provided
-            let proxy_url = if let (Some(username), Some(password)) =
-                (&proxy.socks_proxy_username, &proxy.socks_proxy_password)
-            {
-                if !username.is_empty() && !password.is_empty() {
-                    format!(
-                        "socks{}://{}:{}@{}:{}",
-                        proxy.socks_proxy_version,
-                        username,
-                        password,
-                        proxy.socks_proxy_host,
-                        proxy.socks_proxy_port
-                    )
-                } else {
-                    format!(
-                        "socks{}://{}:{}",
-                        proxy.socks_proxy_version, proxy.socks_proxy_host, proxy.socks_proxy_port
-                    )
-                }
-            } else {
-                format!(
-                    "socks{}://{}:{}",
-                    proxy.socks_proxy_version, proxy.socks_proxy_host, proxy.socks_proxy_port
-                )
-            };
-
-            tracing::info!(
-                "Using SOCKS{} proxy for Google Lens: {}:{}",
-                proxy.socks_proxy_version,
-                proxy.socks_proxy_host,
-                proxy.socks_proxy_port
-            );
-
-            LensClient::new_with_proxy(None, Some(&proxy_url))
-                .map_err(|e| anyhow!("Failed to create LensClient with proxy: {}", e))?
-        } else {
-            LensClient::new(None)
-        }
-    } else {
-        LensClient::new(None)
-    };
-
     let mut current_y_position = 0;
     while current_y_position < full_image_height {
         let current_chunk_height =
@@ -497,93 +553,24 @@ pub async fn get_raw_ocr_data(
             break;
         }
 
-        let chunk_image = decoded_image
-            .view(
-                0,
-                current_y_position,
-                full_image_width,
-                current_chunk_height,
-            )
-            .to_image();
-        let mut image_buffer = Cursor::new(Vec::new());
-        chunk_image
-            .write_to(&mut image_buffer, ImageFormat::Png)
-            .map_err(|err| anyhow!("Failed write_to: {err:?}"))?;
-        let chunk_png_bytes = image_buffer.into_inner();
-
-        let lens_response = lens_client
-            .process_image_bytes(&chunk_png_bytes, Some("jp"))
-            .await
-            .map_err(|err| anyhow!("Failed process_image_bytes: {err:?}"))?;
-
-        let mut flat_ocr_lines = Vec::new();
-        for paragraph in lens_response.paragraphs {
-            for line in paragraph.lines {
-                if let Some(geometry) = line.geometry {
-                    let clean_text = post_process_text(line.text, language);
-                    if clean_text.trim().is_empty() {
-                        continue;
-                    }
-
-                    let rotation = geometry.rotation_z as f64;
-                    let cx = (geometry.center_x * full_image_width as f32) as f64;
-                    let cy = (geometry.center_y * current_chunk_height as f32) as f64;
-                    let w = (geometry.width * full_image_width as f32) as f64;
-                    let h = (geometry.height * current_chunk_height as f32) as f64;
-
-                    let hw = w / 2.0;
-                    let hh = h / 2.0;
-                    let cos_a = rotation.cos();
-                    let sin_a = rotation.sin();
-
-                    let corners = [(-hw, -hh), (hw, -hh), (hw, hh), (-hw, hh)];
-
-                    let mut min_x = f64::INFINITY;
-                    let mut max_x = f64::NEG_INFINITY;
-                    let mut min_y = f64::INFINITY;
-                    let mut max_y = f64::NEG_INFINITY;
-
-                    for (lx, ly) in corners {
-                        let rx = lx * cos_a - ly * sin_a + cx;
-                        let ry = lx * sin_a + ly * cos_a + cy;
-                        min_x = min_x.min(rx);
-                        max_x = max_x.max(rx);
-                        min_y = min_y.min(ry);
-                        max_y = max_y.max(ry);
-                    }
-
-                    let aabb_w = max_x - min_x;
-                    let aabb_h = max_y - min_y;
-
-                    let is_vertical = if language.prefers_vertical() {
-                        if rotation.abs() > 0.1 {
-                            (rotation.abs() - std::f32::consts::FRAC_PI_2 as f64).abs() < 0.5
-                        } else {
-                            aabb_w <= aabb_h
-                        }
-                    } else {
-                        false
-                    };
-
-                    flat_ocr_lines.push(OcrResult {
-                        text: clean_text,
-                        is_merged: Some(false),
-                        forced_orientation: Some(if is_vertical {
-                            "vertical".into()
-                        } else {
-                            "horizontal".into()
-                        }),
-                        tight_bounding_box: BoundingBox {
-                            x: min_x,
-                            y: min_y,
-                            width: aabb_w,
-                            height: aabb_h,
-                            rotation: None,
-                        },
-                    });
-                }
-            }
-        }
+        let chunk_image = DynamicImage::ImageRgba8(
+            decoded_image
+                .view(
+                    0,
+                    current_y_position,
+                    full_image_width,
+                    current_chunk_height,
+                )
+                .to_image(),
+        );
+
+        let mut flat_ocr_lines = engine.recognize(&chunk_image, language).await?;
+        flat_ocr_lines.retain(|line| !line.text.trim().is_empty());
+
+        #[cfg(feature = "bubbles")]
+        let regions = detect_regions(chunk_image).await;
+        #[cfg(not(feature = "bubbles"))]
+        let regions = Vec::new();
 
         raw_chunks.push(RawChunk {
             lines: flat_ocr_lines,
@@ -592,6 +579,7 @@ pub async fn get_raw_ocr_data(
             global_y: current_y_position,
             full_width: full_image_width,
             full_height: full_image_height,
+            regions,
         });
 
         current_y_position += chunk_height_limit;
@@ -600,14 +588,34 @@ pub async fn get_raw_ocr_data(
     Ok(raw_chunks)
 }
 
-async fn fetch_and_process_internal(
+/// Text blocks on a chunk, or none when there's no detector model or it fails; merging then
+/// goes by the line geometry alone.
+#[cfg(feature = "bubbles")]
+async fn detect_regions(chunk_image: DynamicImage) -> Vec<BoundingBox> {
+    if !crate::bubbles::available() {
+        return Vec::new();
+    }
+    match tokio::task::spawn_blocking(move || crate::bubbles::detect(&chunk_image)).await {
+        Ok(Ok(regions)) => regions,
+        Ok(Err(err)) => {
+            tracing::warn!("Bubble detection failed: {err:?}");
+            Vec::new()
+        }
+        Err(err) => {
+            tracing::warn!("Bubble detection task failed: {err}");
+            Vec::new()
+        }
+    }
+}
+
+/// Downloads a page image from the Suwayomi server running alongside, whatever host `url`
+/// names.
+pub(crate) async fn fetch_page_image(
     url: &str,
-    user: Option<String>,
-    pass: Option<String>,
-    add_space_on_merge: Option<bool>,
-    language: OcrLanguage,
-) -> anyhow::Result<Vec<OcrResult>> {
-    // 0. Force URL to Localhost
+    user: Option<&str>,
+    pass: Option<&str>,
+) -> anyhow::Result<Vec<u8>> {
+    // Force URL to Localhost
     let target_url = match reqwest::Url::parse(url) {
         Ok(mut parsed) => {
             let _ = parsed.set_scheme("http");
@@ -618,30 +626,59 @@ async fn fetch_and_process_internal(
         Err(_) => url.to_string(),
     };
 
-    // 1. Fetch
     let client = reqwest::Client::new();
     let mut request = client.get(&target_url);
-    if let Some(username) = &user {
-        request = request.basic_auth(username, pass.as_ref());
+    if let Some(username) = user {
+        request = request.basic_auth(username, pass);
     }
     let response = request
         .send()
         .await?
         .error_for_status()
         .map_err(|err| anyhow!("Failed error_for_status (URL: {target_url}): {err:?}"))?;
-    let image_bytes = response.bytes().await?.to_vec();
+    Ok(response.bytes().await?.to_vec())
+}
 
+async fn fetch_and_process_internal(
+    url: &str,
+    user: Option<String>,
+    pass: Option<String>,
+    merge_config: &MergeConfig,
+    language: OcrLanguage,
+    engine: EngineKind,
+) -> anyhow::Result<Vec<OcrResult>> {
+    // 0-1. Fetch from the local Suwayomi
+    let image_bytes = fetch_page_image(url, user.as_deref(), pass.as_deref()).await?;
+    process_image_bytes(&image_bytes, user, pass, merge_config, language, engine).await
+}
+
+async fn process_image_bytes(
+    image_bytes: &[u8],
+    user: Option<String>,
+    pass: Option<String>,
+    merge_config: &MergeConfig,
+    language: OcrLanguage,
+    engine: EngineKind,
+) -> anyhow::Result<Vec<OcrResult>> {
     // 2. Decode & OCR (Wrapped) - now passes user/pass for proxy settings
-    let raw_chunks = get_raw_ocr_data(&image_bytes, user, pass, language).await?;
+    let engine = engine::create(engine, language, user, pass).await?;
+    let raw_chunks = read_raw_chunks(engine.as_ref(), image_bytes, language).await?;
 
     // 3. Merge & Normalize
     let mut final_results = Vec::new();
-    let mut merge_config = MergeConfig::default();
-    merge_config.add_space_on_merge = add_space_on_merge;
-    merge_config.language = language;
+    let merge_config = MergeConfig {
+        language,
+        ..merge_config.clone()
+    };
 
     for chunk in raw_chunks {
-        let merged_lines = merge::auto_merge(chunk.lines, chunk.width, chunk.height, &merge_config);
+        let merged_lines = merge::auto_merge_in_regions(
+            chunk.lines,
+            chunk.width,
+            chunk.height,
+            &chunk.regions,
+            &merge_config,
+        );
 
         for mut result in merged_lines {
             // Adjust Coordinates: Chunk Pixels -> Global Pixels -> Global Normalized
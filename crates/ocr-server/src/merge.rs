@@ -2,6 +2,8 @@ use std::cmp::Ordering;
 
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
 use crate::language::OcrLanguage;
 use crate::logic::{BoundingBox, OcrResult};
@@ -13,11 +15,34 @@ lazy_static! {
     static ref KATAKANA_REGEX: Regex = Regex::new(r"[\p{Katakana}]").unwrap();
 }
 
-#[derive(Clone)]
+/// How OCR lines are merged into bubbles. Gaps are multiples of the smaller font size of the
+/// two lines, measured across the lines (`cross`) or along them (`main`).
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
 pub struct MergeConfig {
     pub enabled: bool,
+    /// Lines whose font sizes differ by more than this factor are never merged.
     pub font_size_ratio: f64,
     pub add_space_on_merge: Option<bool>,
+    /// Lines this close across are merged whatever else differs.
+    pub touching_gap: f64,
+    /// Font sizes within this factor count as the same font and get the wider gaps below.
+    pub similar_font_ratio: f64,
+    /// Cross gap allowed between similar lines overlapping by more than 80% along their length.
+    pub high_overlap_gap: f64,
+    /// Cross gap allowed between similar lines overlapping by 40-80%; kept tight so that
+    /// neighbouring bubbles split.
+    pub medium_overlap_gap: f64,
+    /// Cross gap allowed between similar lines overlapping by less than 40%, so staggered
+    /// lines still merge.
+    pub low_overlap_gap: f64,
+    /// Cross gap allowed between lines of dissimilar fonts overlapping by more than half.
+    pub dissimilar_gap: f64,
+    /// Gap along the lines allowed between lines that don't overlap at all.
+    pub main_gap: f64,
+    /// Read ambiguous lines as vertical, as in Japanese and Chinese manga.
+    pub prefer_vertical: bool,
+    #[serde(skip)]
     pub language: OcrLanguage,
 }
 
@@ -27,11 +52,126 @@ impl Default for MergeConfig {
             enabled: true,
             font_size_ratio: 3.0,
             add_space_on_merge: None,
+            touching_gap: 0.2,
+            similar_font_ratio: 1.25,
+            high_overlap_gap: 2.0,
+            medium_overlap_gap: 0.9,
+            low_overlap_gap: 1.3,
+            dissimilar_gap: 0.8,
+            main_gap: 0.6,
+            prefer_vertical: true,
             language: OcrLanguage::default(),
         }
     }
 }
 
+impl MergeConfig {
+    /// The profile tuned for `language`. The default gaps were tuned on Japanese manga, where
+    /// columns sit close together; Korean and space-separated scripts are laid out in wider
+    /// horizontal lines and over-merge with them.
+    pub fn for_language(language: OcrLanguage) -> Self {
+        let base = Self {
+            language,
+            prefer_vertical: language.prefers_vertical(),
+            add_space_on_merge: Some(!language.prefers_no_space()),
+            ..Self::default()
+        };
+        match language {
+            OcrLanguage::Japanese | OcrLanguage::Chinese | OcrLanguage::Cantonese => base,
+            // Hangul is set in short horizontal lines with tight leading.
+            OcrLanguage::Korean => Self {
+                high_overlap_gap: 1.2,
+                medium_overlap_gap: 0.7,
+                low_overlap_gap: 0.9,
+                main_gap: 0.4,
+                ..base
+            },
+            // Lettered scripts: centered lines of varying length, so overlap says little and
+            // bubbles stacked on top of each other must not run together.
+            _ => Self {
+                touching_gap: 0.15,
+                high_overlap_gap: 1.0,
+                medium_overlap_gap: 0.8,
+                low_overlap_gap: 0.8,
+                dissimilar_gap: 0.6,
+                main_gap: 0.4,
+                ..base
+            },
+        }
+    }
+}
+
+/// Changes to a [`MergeConfig`], taken from OCR requests and stored as the server's default.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MergeOverrides {
+    /// `false` returns the lines as the engine read them.
+    pub merge: Option<bool>,
+    /// Join merged lines with spaces; by default only for languages written with them.
+    pub add_space_on_merge: Option<bool>,
+    pub font_size_ratio: Option<f64>,
+    pub touching_gap: Option<f64>,
+    pub similar_font_ratio: Option<f64>,
+    pub high_overlap_gap: Option<f64>,
+    pub medium_overlap_gap: Option<f64>,
+    pub low_overlap_gap: Option<f64>,
+    pub dissimilar_gap: Option<f64>,
+    pub main_gap: Option<f64>,
+}
+
+impl MergeOverrides {
+    /// Whether these change how lines are grouped, rather than only how merged text is joined.
+    pub fn tunes_grouping(&self) -> bool {
+        let Self {
+            merge,
+            add_space_on_merge: _,
+            font_size_ratio,
+            touching_gap,
+            similar_font_ratio,
+            high_overlap_gap,
+            medium_overlap_gap,
+            low_overlap_gap,
+            dissimilar_gap,
+            main_gap,
+        } = self;
+        merge.is_some()
+            || [
+                font_size_ratio,
+                touching_gap,
+                similar_font_ratio,
+                high_overlap_gap,
+                medium_overlap_gap,
+                low_overlap_gap,
+                dissimilar_gap,
+                main_gap,
+            ]
+            .iter()
+            .any(|value| value.is_some())
+    }
+
+    /// `config` with the values set here replacing its own.
+    pub fn apply(&self, mut config: MergeConfig) -> MergeConfig {
+        config.enabled = self.merge.unwrap_or(config.enabled);
+        config.add_space_on_merge = self.add_space_on_merge.or(config.add_space_on_merge);
+        let values = [
+            (&mut config.font_size_ratio, self.font_size_ratio),
+            (&mut config.touching_gap, self.touching_gap),
+            (&mut config.similar_font_ratio, self.similar_font_ratio),
+            (&mut config.high_overlap_gap, self.high_overlap_gap),
+            (&mut config.medium_overlap_gap, self.medium_overlap_gap),
+            (&mut config.low_overlap_gap, self.low_overlap_gap),
+            (&mut config.dissimilar_gap, self.dissimilar_gap),
+            (&mut config.main_gap, self.main_gap),
+        ];
+        for (field, value) in values {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+        config
+    }
+}
+
 // --- Geometry Helpers ---
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -128,7 +268,7 @@ fn filter_bad_boxes(
         }
 
         if box_area < page_area * 0.0005 {
-            if !config.language.prefers_vertical() || !JAPANESE_REGEX.is_match(text) {
+            if !config.prefer_vertical || !JAPANESE_REGEX.is_match(text) {
                 keep[i] = false;
                 continue;
             }
@@ -167,7 +307,7 @@ fn filter_bad_boxes(
                 if intersection_area > b_area * 0.3 {
                     let a_area = a.tight_bounding_box.width * a.tight_bounding_box.height;
                     if a_area > b_area * 3.0 && intersection_area > b_area * 0.8 {
-                        if config.language.prefers_vertical() && JAPANESE_REGEX.is_match(&b.text) {
+                        if config.prefer_vertical && JAPANESE_REGEX.is_match(&b.text) {
                             if !a.text.contains(&b.text) {
                                 continue;
                             }
@@ -319,32 +459,32 @@ fn are_lines_mergeable(a: &ProcessedLine, b: &ProcessedLine, config: &MergeConfi
     // --- REFINED TIERED STRATEGY (INVERTED LOGIC) ---
 
     // 1. TOUCHING: Merge anything that touches horizontally.
-    if gap_cross < base_metric * 0.2 {
+    if gap_cross < base_metric * config.touching_gap {
         return true;
     }
 
-    let is_highly_similar = font_ratio < 1.25;
+    let is_highly_similar = font_ratio < config.similar_font_ratio;
     let mut allowed_gap: f64 = 0.0;
 
     if is_highly_similar {
         // TIER 2A: High Overlap (>80%) -> Wide Gap (2.0x)
         if global_overlap > 0.8 {
-            allowed_gap = 2.0;
+            allowed_gap = config.high_overlap_gap;
         }
         // TIER 2B: Medium Overlap (40%-80%) -> STRICT GAP (0.9x)
         // [FIX] This forces Distinct Bubbles (Right Side) to split.
         else if global_overlap > 0.4 {
-            allowed_gap = 0.9;
+            allowed_gap = config.medium_overlap_gap;
         }
         // TIER 2C: Low Overlap (<40%) -> LOOSE GAP (1.3x)
         // [FIX] This allows Staggered Lines (Left Side) to merge.
         else {
-            allowed_gap = 1.3;
+            allowed_gap = config.low_overlap_gap;
         }
     } else {
         // TIER 3: Dissimilar Fonts -> Strict
         if global_overlap > 0.5 {
-            allowed_gap = 0.8;
+            allowed_gap = config.dissimilar_gap;
         }
     }
 
@@ -370,7 +510,7 @@ fn are_lines_mergeable(a: &ProcessedLine, b: &ProcessedLine, config: &MergeConfi
         let gap_main = 0.0f64
             .max(b.min_main - a.max_main)
             .max(a.min_main - b.max_main);
-        if gap_main > base_metric * 0.6 {
+        if gap_main > base_metric * config.main_gap {
             return false;
         }
     }
@@ -378,7 +518,44 @@ fn are_lines_mergeable(a: &ProcessedLine, b: &ProcessedLine, config: &MergeConfi
     true
 }
 
+/// Share of a line's area that has to lie in a text block for the line to belong to it.
+const REGION_COVERAGE: f64 = 0.5;
+
+/// Index of the text block covering most of `line`, if it covers enough of it.
+fn region_of(line: &BoundingBox, regions: &[BoundingBox]) -> Option<usize> {
+    let area = line.width * line.height;
+    if area <= 0.0 {
+        return None;
+    }
+    regions
+        .iter()
+        .enumerate()
+        .map(|(index, region)| {
+            let overlap_w =
+                (line.x + line.width).min(region.x + region.width) - line.x.max(region.x);
+            let overlap_h =
+                (line.y + line.height).min(region.y + region.height) - line.y.max(region.y);
+            (index, overlap_w.max(0.0) * overlap_h.max(0.0) / area)
+        })
+        .filter(|(_, coverage)| *coverage >= REGION_COVERAGE)
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+        .map(|(index, _)| index)
+}
+
 pub fn auto_merge(lines: Vec<OcrResult>, w: u32, h: u32, config: &MergeConfig) -> Vec<OcrResult> {
+    auto_merge_in_regions(lines, w, h, &[], config)
+}
+
+/// Like [`auto_merge`], but lines inside the same detected text block are merged whenever
+/// they share an orientation and a similar font size, and lines in different blocks never
+/// are. Lines outside every block fall back to the geometric heuristics among themselves.
+pub fn auto_merge_in_regions(
+    lines: Vec<OcrResult>,
+    w: u32,
+    h: u32,
+    regions: &[BoundingBox],
+    config: &MergeConfig,
+) -> Vec<OcrResult> {
     if !config.enabled || lines.is_empty() {
         return lines;
     }
@@ -389,7 +566,7 @@ pub fn auto_merge(lines: Vec<OcrResult>, w: u32, h: u32, config: &MergeConfig) -
         .iter()
         .map(|l| {
             let b = &l.tight_bounding_box;
-            let prefers_vertical = config.language.prefers_vertical();
+            let prefers_vertical = config.prefer_vertical;
             let lens_is_vertical = l.forced_orientation.as_deref() == Some("vertical");
             let char_count = l.text.chars().count();
 
@@ -422,10 +599,25 @@ pub fn auto_merge(lines: Vec<OcrResult>, w: u32, h: u32, config: &MergeConfig) -
         })
         .collect();
 
+    let line_regions: Vec<Option<usize>> = clean_lines
+        .iter()
+        .map(|l| region_of(&l.tight_bounding_box, regions))
+        .collect();
+
     let mut uf = UnionFind::new(processed.len());
     for i in 0..processed.len() {
         for j in (i + 1)..processed.len() {
-            if are_lines_mergeable(&processed[i], &processed[j], config) {
+            let mergeable = match (line_regions[i], line_regions[j]) {
+                (None, None) => are_lines_mergeable(&processed[i], &processed[j], config),
+                (Some(a), Some(b)) if a == b => {
+                    let (a, b) = (&processed[i], &processed[j]);
+                    a.is_vertical == b.is_vertical
+                        && a.font_size.max(b.font_size) / a.font_size.min(b.font_size)
+                            <= config.font_size_ratio
+                }
+                _ => false,
+            };
+            if mergeable {
                 uf.union(i, j);
             }
         }
@@ -0,0 +1,214 @@
+//! Conversion between cached OCR results and the `.mokuro` format read by mokuro-reader and
+//! other Mokuro-compatible readers.
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    language::OcrLanguage,
+    logic::{BoundingBox, OcrResult},
+};
+
+/// Version of the Mokuro format written.
+pub const MOKURO_VERSION: &str = "0.2.1";
+/// Page size assumed when a page's image can't be fetched.
+pub const NOMINAL_WIDTH: u32 = 1200;
+pub const NOMINAL_HEIGHT: u32 = 1800;
+
+/// A `.mokuro` file: one volume, here one chapter.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MokuroVolume {
+    pub version: String,
+    pub title: String,
+    pub title_uuid: String,
+    pub volume: String,
+    pub volume_uuid: String,
+    pub pages: Vec<MokuroPage>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MokuroPage {
+    pub version: String,
+    pub img_width: u32,
+    pub img_height: u32,
+    pub blocks: Vec<MokuroBlock>,
+    pub img_path: String,
+}
+
+/// A text block; coordinates are in image pixels.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MokuroBlock {
+    /// `[x1, y1, x2, y2]`.
+    #[serde(rename = "box")]
+    pub bounding_box: [f64; 4],
+    pub vertical: bool,
+    pub font_size: f64,
+    /// Corners of each line, clockwise from the top left.
+    pub lines_coords: Vec<[[f64; 2]; 4]>,
+    pub lines: Vec<String>,
+}
+
+/// Index of the page a cache key stands for, its last segment on Suwayomi page URLs.
+pub fn page_number(cache_key: &str) -> Option<usize> {
+    cache_key.rsplit('/').next()?.parse().ok()
+}
+
+/// Path of the page image a cache key was made from, without the engine and language prefixes
+/// `EngineKind::cache_key` and `logic::get_cache_key` add.
+pub fn page_path(cache_key: &str) -> &str {
+    let mut path = cache_key;
+    for prefix in ["engine/", "lang/"] {
+        if let Some(rest) = path.strip_prefix(prefix) {
+            path = rest.split_once('/').map_or("", |(_, rest)| rest);
+        }
+    }
+    path.trim_start_matches('/')
+}
+
+/// Pixel size and file extension of an encoded page image.
+pub fn image_info(bytes: &[u8]) -> anyhow::Result<(u32, u32, &'static str)> {
+    let format = image::guess_format(bytes)?;
+    let (width, height) =
+        image::ImageReader::with_format(std::io::Cursor::new(bytes), format).into_dimensions()?;
+    let extension = format
+        .extensions_str()
+        .first()
+        .copied()
+        .ok_or_else(|| anyhow!("No file extension for {format:?}"))?;
+    Ok((width, height, extension))
+}
+
+/// A UUID derived from `name`, so exporting the same chapter twice gives the same ids and
+/// readers see it as the same volume. Built like a version 5 UUID, from the first 16 bytes of
+/// the name's SHA-256 digest.
+pub fn name_uuid(name: &str) -> String {
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&Sha256::digest(name.as_bytes())[..16]);
+    bytes[6] = (bytes[6] & 0x0f) | 0x50;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// A cached page as a Mokuro page of `width` x `height` pixels. The cache keeps coordinates
+/// relative to the page, and each block's lines are laid out evenly inside it since the cache
+/// only keeps the merged block.
+pub fn page_from_results(
+    results: &[OcrResult],
+    width: u32,
+    height: u32,
+    img_path: String,
+) -> MokuroPage {
+    let (page_width, page_height) = (width as f64, height as f64);
+    let blocks = results
+        .iter()
+        .filter(|result| !result.text.trim().is_empty())
+        .map(|result| {
+            let bbox = &result.tight_bounding_box;
+            let x1 = bbox.x * page_width;
+            let y1 = bbox.y * page_height;
+            let x2 = (bbox.x + bbox.width) * page_width;
+            let y2 = (bbox.y + bbox.height) * page_height;
+            let vertical = result.forced_orientation.as_deref() == Some("vertical");
+            let lines: Vec<String> = result.text.lines().map(str::to_string).collect();
+            let count = lines.len().max(1) as f64;
+
+            // Vertical lines are columns read right to left, horizontal ones rows top down.
+            let lines_coords = (0..lines.len())
+                .map(|index| {
+                    let index = index as f64;
+                    let (lx1, ly1, lx2, ly2) = if vertical {
+                        let step = (x2 - x1) / count;
+                        (x2 - step * (index + 1.0), y1, x2 - step * index, y2)
+                    } else {
+                        let step = (y2 - y1) / count;
+                        (x1, y1 + step * index, x2, y1 + step * (index + 1.0))
+                    };
+                    [[lx1, ly1], [lx2, ly1], [lx2, ly2], [lx1, ly2]]
+                })
+                .collect();
+
+            MokuroBlock {
+                bounding_box: [x1, y1, x2, y2],
+                vertical,
+                font_size: if vertical { x2 - x1 } else { y2 - y1 } / count,
+                lines_coords,
+                lines,
+            }
+        })
+        .collect();
+
+    MokuroPage {
+        version: MOKURO_VERSION.to_string(),
+        img_width: width,
+        img_height: height,
+        blocks,
+        img_path,
+    }
+}
+
+/// A Mokuro page as the results `/ocr` caches for it, in coordinates relative to the page. A
+/// block's lines become one merged result, joined with newlines as the merge step joins them.
+pub fn results_from_page(page: &MokuroPage, language: OcrLanguage) -> Vec<OcrResult> {
+    let (page_width, page_height) = (page.img_width.max(1) as f64, page.img_height.max(1) as f64);
+    page.blocks
+        .iter()
+        .filter_map(|block| {
+            let text = block
+                .lines
+                .iter()
+                .map(|line| crate::logic::post_process_text(line.clone(), language))
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n");
+            if text.is_empty() {
+                return None;
+            }
+            let [x1, y1, x2, y2] = block.bounding_box;
+            Some(OcrResult {
+                text,
+                tight_bounding_box: BoundingBox {
+                    x: (x1 / page_width).clamp(0.0, 1.0),
+                    y: (y1 / page_height).clamp(0.0, 1.0),
+                    width: ((x2 - x1) / page_width).clamp(0.0, 1.0),
+                    height: ((y2 - y1) / page_height).clamp(0.0, 1.0),
+                    rotation: None,
+                },
+                is_merged: Some(block.lines.len() > 1),
+                forced_orientation: Some(if block.vertical {
+                    "vertical".into()
+                } else {
+                    "horizontal".into()
+                }),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_uuid_is_a_stable_version_5_uuid() {
+        let uuid = name_uuid("Chapter 1");
+        assert_eq!(uuid, name_uuid("Chapter 1"));
+        assert_ne!(uuid, name_uuid("Chapter 2"));
+
+        let groups: Vec<&str> = uuid.split('-').collect();
+        assert_eq!(
+            groups.iter().map(|group| group.len()).collect::<Vec<_>>(),
+            [8, 4, 4, 4, 12]
+        );
+        assert!(groups[2].starts_with('5'), "{uuid}");
+        assert!(groups[3].starts_with(['8', '9', 'a', 'b']), "{uuid}");
+    }
+}
@@ -0,0 +1,321 @@
+//! Local OCR with PaddleOCR models exported to ONNX, for Chinese and Korean pages where Lens
+//! does noticeably worse than on Japanese. Built with the `paddle` feature.
+//!
+//! `MANATAN_PADDLE_MODEL_DIR` holds the shared detection model and a recognition model with its
+//! character list per language:
+//!
+//! ```text
+//! det.onnx
+//! rec_chinese.onnx   rec_chinese.txt
+//! rec_korean.onnx    rec_korean.txt
+//! ```
+//!
+//! A language whose files are missing keeps using Lens.
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+};
+
+use anyhow::{Context, anyhow};
+use image::{DynamicImage, RgbImage, imageops::FilterType};
+use ort::{session::Session, value::Tensor};
+
+use crate::{language::OcrLanguage, logic::BoundingBox};
+
+/// Longest side of the image the detector sees, as in PaddleOCR's `det_limit_side_len`.
+const DET_LIMIT_SIDE: u32 = 960;
+/// Probability above which a pixel of the detector's map counts as text.
+const DET_THRESHOLD: f32 = 0.3;
+/// Mean probability a detected region needs to be kept.
+const DET_BOX_THRESHOLD: f32 = 0.6;
+/// How far a region grows past the shrunk text kernel the detector marks.
+const DET_UNCLIP_RATIO: f64 = 1.5;
+/// Regions thinner than this, in detector pixels, are noise.
+const DET_MIN_SIZE: usize = 3;
+/// Height of the line images the recognizer reads.
+const REC_HEIGHT: u32 = 48;
+/// A crop this much taller than it is wide is a vertical line, read rotated.
+const VERTICAL_RATIO: f64 = 1.5;
+
+/// A line of text found on the image, with its box in image pixels.
+pub struct DetectedLine {
+    pub text: String,
+    pub bounding_box: BoundingBox,
+}
+
+struct Recognizer {
+    session: Mutex<Session>,
+    /// Characters by model output index, after the CTC blank at 0.
+    charset: Vec<String>,
+}
+
+struct Models {
+    detector: Mutex<Session>,
+    recognizers: HashMap<&'static str, Recognizer>,
+}
+
+fn model_dir() -> Option<PathBuf> {
+    std::env::var_os("MANATAN_PADDLE_MODEL_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Name of the recognition model for `language`, if PaddleOCR is meant to read it.
+fn model_name(language: OcrLanguage) -> Option<&'static str> {
+    match language {
+        OcrLanguage::Chinese | OcrLanguage::Cantonese => Some("chinese"),
+        OcrLanguage::Korean => Some("korean"),
+        _ => None,
+    }
+}
+
+/// Models found at startup; loaded on first use and kept for the life of the process.
+fn models() -> Option<&'static Models> {
+    static MODELS: OnceLock<Option<Models>> = OnceLock::new();
+    MODELS
+        .get_or_init(|| {
+            let dir = model_dir()?;
+            let detector = match load_session(&dir.join("det.onnx")) {
+                Ok(session) => session,
+                Err(err) => {
+                    tracing::warn!("[Paddle] No detection model in {}: {err:?}", dir.display());
+                    return None;
+                }
+            };
+            let mut recognizers = HashMap::new();
+            for name in ["chinese", "korean"] {
+                match load_recognizer(&dir, name) {
+                    Ok(recognizer) => {
+                        tracing::info!("[Paddle] Loaded {name} recognition model");
+                        recognizers.insert(name, recognizer);
+                    }
+                    Err(err) => tracing::info!("[Paddle] No {name} recognition model: {err:?}"),
+                }
+            }
+            Some(Models {
+                detector: Mutex::new(detector),
+                recognizers,
+            })
+        })
+        .as_ref()
+}
+
+fn load_session(path: &std::path::Path) -> anyhow::Result<Session> {
+    Session::builder()?
+        .commit_from_file(path)
+        .with_context(|| format!("loading {}", path.display()))
+}
+
+fn load_recognizer(dir: &std::path::Path, name: &str) -> anyhow::Result<Recognizer> {
+    let session = load_session(&dir.join(format!("rec_{name}.onnx")))?;
+    let charset_path = dir.join(format!("rec_{name}.txt"));
+    let charset = std::fs::read_to_string(&charset_path)
+        .with_context(|| format!("reading {}", charset_path.display()))?;
+    // PaddleOCR appends a space to the dictionary file's characters.
+    let charset = charset
+        .lines()
+        .map(str::to_string)
+        .chain(std::iter::once(" ".to_string()))
+        .collect();
+    Ok(Recognizer {
+        session: Mutex::new(session),
+        charset,
+    })
+}
+
+/// Whether pages in `language` are read with PaddleOCR instead of Lens.
+pub fn supports(language: OcrLanguage) -> bool {
+    model_name(language)
+        .zip(models())
+        .is_some_and(|(name, models)| models.recognizers.contains_key(name))
+}
+
+/// Finds and reads the lines of text on `image`. Runs the models on the calling thread.
+pub fn recognize(image: &DynamicImage, language: OcrLanguage) -> anyhow::Result<Vec<DetectedLine>> {
+    let models = models().ok_or_else(|| anyhow!("PaddleOCR models aren't available"))?;
+    let recognizer = model_name(language)
+        .and_then(|name| models.recognizers.get(name))
+        .ok_or_else(|| anyhow!("No PaddleOCR model for {}", language.as_str()))?;
+
+    let image = image.to_rgb8();
+    let mut lines = Vec::new();
+    for region in detect(&models.detector, &image)? {
+        let crop =
+            image::imageops::crop_imm(&image, region.x, region.y, region.width, region.height)
+                .to_image();
+        let text = read_line(recognizer, crop)?;
+        if text.trim().is_empty() {
+            continue;
+        }
+        lines.push(DetectedLine {
+            text,
+            bounding_box: BoundingBox {
+                x: region.x as f64,
+                y: region.y as f64,
+                width: region.width as f64,
+                height: region.height as f64,
+                rotation: None,
+            },
+        });
+    }
+    Ok(lines)
+}
+
+struct Region {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Runs the DB text detector and turns its probability map into boxes on `image`.
+fn detect(detector: &Mutex<Session>, image: &RgbImage) -> anyhow::Result<Vec<Region>> {
+    let (width, height) = image.dimensions();
+    let scale = (DET_LIMIT_SIDE as f64 / width.max(height) as f64).min(1.0);
+    let round = |side: u32| ((side as f64 * scale / 32.0).round() as u32).max(1) * 32;
+    let (det_width, det_height) = (round(width), round(height));
+    let resized = image::imageops::resize(image, det_width, det_height, FilterType::Triangle);
+
+    // ImageNet normalization over BGR channels, as PaddleOCR reads images with OpenCV.
+    let mean = [0.485, 0.456, 0.406];
+    let std = [0.229, 0.224, 0.225];
+    let plane = (det_width * det_height) as usize;
+    let mut input = vec![0f32; 3 * plane];
+    for (index, pixel) in resized.pixels().enumerate() {
+        let [r, g, b] = pixel.0;
+        for (channel, value) in [b, g, r].into_iter().enumerate() {
+            input[channel * plane + index] = (value as f32 / 255.0 - mean[channel]) / std[channel];
+        }
+    }
+
+    let tensor = Tensor::from_array(([1usize, 3, det_height as usize, det_width as usize], input))?;
+    let mut session = detector.lock().expect("lock poisoned");
+    let outputs = session.run(ort::inputs![tensor])?;
+    let (_, probabilities) = outputs[0].try_extract_tensor::<f32>()?;
+
+    let map_width = det_width as usize;
+    let map_height = det_height as usize;
+    let scale_x = width as f64 / det_width as f64;
+    let scale_y = height as f64 / det_height as f64;
+
+    let mut regions = Vec::new();
+    let mut seen = vec![false; map_width * map_height];
+    for start in 0..seen.len() {
+        if seen[start] || probabilities[start] <= DET_THRESHOLD {
+            continue;
+        }
+        // Flood fill the connected text pixels.
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (map_width, map_height, 0, 0);
+        let mut score = 0f32;
+        let mut count = 0usize;
+        let mut stack = vec![start];
+        seen[start] = true;
+        while let Some(pixel) = stack.pop() {
+            let (x, y) = (pixel % map_width, pixel / map_width);
+            min_x = min_x.min(x);
+            max_x = max_x.max(x);
+            min_y = min_y.min(y);
+            max_y = max_y.max(y);
+            score += probabilities[pixel];
+            count += 1;
+            let neighbours = [
+                if x > 0 { Some(pixel - 1) } else { None },
+                if x + 1 < map_width {
+                    Some(pixel + 1)
+                } else {
+                    None
+                },
+                if y > 0 { Some(pixel - map_width) } else { None },
+                if y + 1 < map_height {
+                    Some(pixel + map_width)
+                } else {
+                    None
+                },
+            ];
+            for next in neighbours.into_iter().flatten() {
+                if !seen[next] && probabilities[next] > DET_THRESHOLD {
+                    seen[next] = true;
+                    stack.push(next);
+                }
+            }
+        }
+
+        let box_width = max_x - min_x + 1;
+        let box_height = max_y - min_y + 1;
+        if box_width.min(box_height) < DET_MIN_SIZE || score / (count as f32) < DET_BOX_THRESHOLD {
+            continue;
+        }
+
+        // Grow the kernel back to the full text by area * ratio / perimeter on each side.
+        let distance = (box_width * box_height) as f64 * DET_UNCLIP_RATIO
+            / (2 * (box_width + box_height)) as f64;
+        let left = ((min_x as f64 - distance) * scale_x).max(0.0);
+        let top = ((min_y as f64 - distance) * scale_y).max(0.0);
+        let right = (((max_x + 1) as f64 + distance) * scale_x).min(width as f64);
+        let bottom = (((max_y + 1) as f64 + distance) * scale_y).min(height as f64);
+        if right - left < 1.0 || bottom - top < 1.0 {
+            continue;
+        }
+        regions.push(Region {
+            x: left as u32,
+            y: top as u32,
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+        });
+    }
+
+    // Reading order is left to the merge step; top to bottom keeps the output stable.
+    regions.sort_by_key(|region| (region.y, region.x));
+    Ok(regions)
+}
+
+/// Reads a single line with the CRNN/SVTR recognizer and a greedy CTC decode.
+fn read_line(recognizer: &Recognizer, crop: RgbImage) -> anyhow::Result<String> {
+    let (width, height) = crop.dimensions();
+    let crop = if height as f64 >= width as f64 * VERTICAL_RATIO {
+        image::imageops::rotate270(&crop)
+    } else {
+        crop
+    };
+    let (width, height) = crop.dimensions();
+    let line_width = ((REC_HEIGHT as f64 * width as f64 / height as f64).ceil() as u32).max(1);
+    let resized = image::imageops::resize(&crop, line_width, REC_HEIGHT, FilterType::Triangle);
+
+    let plane = (line_width * REC_HEIGHT) as usize;
+    let mut input = vec![0f32; 3 * plane];
+    for (index, pixel) in resized.pixels().enumerate() {
+        let [r, g, b] = pixel.0;
+        for (channel, value) in [b, g, r].into_iter().enumerate() {
+            input[channel * plane + index] = (value as f32 / 255.0 - 0.5) / 0.5;
+        }
+    }
+
+    let tensor =
+        Tensor::from_array(([1usize, 3, REC_HEIGHT as usize, line_width as usize], input))?;
+    let mut session = recognizer.session.lock().expect("lock poisoned");
+    let outputs = session.run(ort::inputs![tensor])?;
+    let (shape, probabilities) = outputs[0].try_extract_tensor::<f32>()?;
+    let classes = *shape
+        .last()
+        .ok_or_else(|| anyhow!("Recognizer returned a scalar"))? as usize;
+    if classes == 0 {
+        return Ok(String::new());
+    }
+
+    let mut text = String::new();
+    let mut previous = 0;
+    for step in probabilities.chunks_exact(classes) {
+        let best = step
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map_or(0, |(index, _)| index);
+        if best != 0 && best != previous {
+            text.push_str(recognizer.charset.get(best - 1).map_or("", String::as_str));
+        }
+        previous = best;
+    }
+    Ok(text)
+}
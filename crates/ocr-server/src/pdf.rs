@@ -0,0 +1,73 @@
+//! Rendering of uploaded PDFs, mostly scanned light novels and doujinshi, into page images for
+//! `/ocr/archive`. Built with the `pdf` feature; pages are rendered by the pdfium library at
+//! `MANATAN_PDFIUM_LIBRARY`, or the system's when that isn't set.
+
+use std::io::Cursor;
+
+use anyhow::{Context, anyhow, bail};
+use image::{ImageFormat, RgbaImage};
+use pdfium_render::prelude::{PdfRenderConfig, Pdfium};
+
+use crate::archive::{MAX_EXTRACTED_BYTES, MAX_PAGE_BYTES};
+
+/// PDF page sizes are in points, 72 to the inch.
+const POINTS_PER_INCH: f32 = 72.0;
+/// Longest side of a rendered page in pixels, whatever size the PDF claims its pages are. An A4
+/// page at 600 DPI is about 7000 pixels tall.
+const MAX_PAGE_PIXELS: i32 = 8192;
+/// Most pages rendered out of one PDF.
+const MAX_PAGES: usize = 2000;
+
+fn pdfium() -> anyhow::Result<Pdfium> {
+    let bindings = match std::env::var_os("MANATAN_PDFIUM_LIBRARY").filter(|path| !path.is_empty())
+    {
+        Some(path) => Pdfium::bind_to_library(std::path::PathBuf::from(&path))
+            .with_context(|| format!("loading pdfium from {}", path.to_string_lossy()))?,
+        None => Pdfium::bind_to_system_library().context("loading the system pdfium")?,
+    };
+    Ok(Pdfium::new(bindings))
+}
+
+/// Renders every page of the PDF in `bytes` at `dpi`, as PNG images in page order. Runs on the
+/// calling thread. Fails on a PDF of more than 2000 pages, and with the same page and total size
+/// limits as [`crate::archive::read_pages`].
+pub fn render_pages(bytes: &[u8], dpi: u32) -> anyhow::Result<Vec<Vec<u8>>> {
+    let pdfium = pdfium()?;
+    let document = pdfium
+        .load_pdf_from_byte_slice(bytes, None)
+        .context("reading PDF")?;
+    let page_count = usize::from(document.pages().len());
+    if page_count > MAX_PAGES {
+        bail!("The PDF has {page_count} pages, more than {MAX_PAGES}");
+    }
+    let config = PdfRenderConfig::new()
+        .scale_page_by_factor(dpi as f32 / POINTS_PER_INCH)
+        .set_maximum_width(MAX_PAGE_PIXELS)
+        .set_maximum_height(MAX_PAGE_PIXELS);
+
+    let mut pages = Vec::with_capacity(page_count);
+    let mut extracted = 0;
+    for (index, page) in document.pages().iter().enumerate() {
+        let bitmap = page
+            .render_with_config(&config)
+            .with_context(|| format!("rendering page {}", index + 1))?;
+        let (width, height) = (bitmap.width() as u32, bitmap.height() as u32);
+        let image = RgbaImage::from_raw(width, height, bitmap.as_rgba_bytes())
+            .ok_or_else(|| anyhow!("Page {} rendered to a short bitmap", index + 1))?;
+
+        let mut png = Cursor::new(Vec::new());
+        image
+            .write_to(&mut png, ImageFormat::Png)
+            .map_err(|err| anyhow!("Failed write_to: {err:?}"))?;
+        let png = png.into_inner();
+        if png.len() as u64 > MAX_PAGE_BYTES {
+            bail!("Page {} is larger than {MAX_PAGE_BYTES} bytes", index + 1);
+        }
+        extracted += png.len() as u64;
+        if extracted > MAX_EXTRACTED_BYTES {
+            bail!("The pages add up to more than {MAX_EXTRACTED_BYTES} bytes");
+        }
+        pages.push(png);
+    }
+    Ok(pages)
+}
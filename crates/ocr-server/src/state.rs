@@ -1,22 +1,155 @@
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
-    sync::{atomic::AtomicUsize, Arc, RwLock},
-    time::{SystemTime, UNIX_EPOCH},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::{params, OptionalExtension};
 use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
 use tracing::{info, warn};
 
+use crate::jobs::{ChapterJob, JobPriority, JobQueue};
+use crate::language::OcrLanguage;
 use crate::logic::OcrResult;
+use crate::merge::{MergeConfig, MergeOverrides};
 
-#[derive(Clone, Copy, Serialize, Debug)]
+/// Events a chapter job buffers for a slow `/jobs/{id}/events` client before it skips ahead.
+const JOB_EVENT_CAPACITY: usize = 256;
+/// How long a write waits for another connection's transaction before giving up.
+const DB_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Failure reports of finished chapter jobs kept for `/jobs/{id}/failures`.
+const MAX_JOB_FAILURE_REPORTS: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    /// Waiting for a worker, or paused for a more urgent job.
+    Queued,
+    Running,
+}
+
+#[derive(Clone, Serialize, Debug)]
 pub struct JobProgress {
+    /// Id for the `/jobs/{id}` endpoints.
+    pub id: u64,
+    pub status: JobStatus,
+    pub priority: JobPriority,
+    pub current: usize,
+    pub total: usize,
+    /// Pages whose last attempt failed; they are retried once the others are done.
+    pub failed: usize,
+    pub context: String,
+    /// Unix time the job was registered.
+    pub started_at: i64,
+    #[serde(skip)]
+    pub started: Instant,
+    #[serde(skip)]
+    pub cancel: CancellationToken,
+    #[serde(skip)]
+    pub events: broadcast::Sender<JobEvent>,
+}
+
+impl JobProgress {
+    /// Pages per minute since the job started, and the seconds left at that pace once a page
+    /// has finished.
+    pub fn throughput(&self) -> (f64, Option<f64>) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if self.current == 0 || elapsed <= 0.0 {
+            return (0.0, None);
+        }
+        let per_page = elapsed / self.current as f64;
+        let remaining = self.total.saturating_sub(self.current) as f64;
+        (60.0 / per_page, Some(remaining * per_page))
+    }
+}
+
+/// A queued or running chapter job as listed by `/jobs`.
+#[derive(Serialize, Debug)]
+pub struct JobSummary {
+    pub id: u64,
+    pub status: JobStatus,
+    pub priority: JobPriority,
+    /// 1-based place among the queued jobs.
+    pub queue_position: Option<usize>,
+    /// Cache key of the chapter, which its pages' keys start with.
+    pub chapter: String,
+    pub context: String,
     pub current: usize,
     pub total: usize,
+    pub failed: usize,
+    pub started_at: i64,
+    pub pages_per_minute: f64,
+    /// Estimated seconds until the last page is done; absent until the first one is.
+    pub eta_seconds: Option<f64>,
+}
+
+/// Progress of a chapter job, streamed by `/jobs/{id}/events`.
+#[derive(Clone, Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobEvent {
+    Page(PageEvent),
+    /// The last event of a job, sent once every page is done or the job is cancelled.
+    Finished {
+        current: usize,
+        total: usize,
+        /// Pages that failed every attempt.
+        failed: usize,
+        cancelled: bool,
+    },
+}
+
+#[derive(Clone, Serialize, Debug)]
+pub struct PageEvent {
+    /// Last segment of the page URL.
+    pub page_id: String,
+    /// Position of the page in the chapter.
+    pub index: usize,
+    /// Pages finished so far, this one included.
+    pub current: usize,
+    pub total: usize,
+    /// 1 for the first try; failed pages are tried again after the rest of the chapter.
+    pub attempt: u32,
+    pub success: bool,
+    /// The page was already in the cache and wasn't OCR'd again.
+    pub cached: bool,
+    pub cache_key: String,
+    pub error: Option<String>,
+}
+
+/// Pages a finished chapter job couldn't OCR, listed by `/jobs/{id}/failures`.
+#[derive(Clone, Serialize)]
+pub struct JobFailures {
+    pub id: u64,
+    /// Cache key of the chapter.
+    pub chapter: String,
+    pub context: String,
+    pub priority: JobPriority,
+    pub total: usize,
+    pub cancelled: bool,
+    /// Unix time the job finished.
+    pub finished_at: i64,
+    pub pages: Vec<PageFailure>,
+    /// The chapter as it was requested, to re-run the failed pages with.
+    #[serde(skip)]
+    pub job: ChapterJob,
+}
+
+#[derive(Clone, Serialize, Debug)]
+pub struct PageFailure {
+    /// Position of the page in the job.
+    pub index: usize,
+    pub page_id: String,
+    pub url: String,
+    /// Error of the last attempt.
+    pub error: String,
 }
 
 #[derive(Clone)]
@@ -26,6 +159,38 @@ pub struct AppState {
     pub active_jobs: Arc<AtomicUsize>,
     pub requests_processed: Arc<AtomicUsize>,
     pub active_chapter_jobs: Arc<RwLock<HashMap<String, JobProgress>>>,
+    pub next_job_id: Arc<AtomicU64>,
+    pub job_queue: Arc<JobQueue>,
+    /// Finished chapter jobs with pages that failed, by job id. Kept in memory only, like the
+    /// jobs themselves, since re-running them needs the credentials they were started with.
+    pub job_failures: Arc<RwLock<HashMap<u64, JobFailures>>>,
+    /// Merge settings applied to every request, kept in the `metadata` table.
+    pub merge_defaults: Arc<RwLock<MergeOverrides>>,
+    /// Most pages kept in the cache, from `MANATAN_OCR_CACHE_MAX_ENTRIES`; the least recently
+    /// read ones go first.
+    pub cache_limit: Option<usize>,
+}
+
+/// Size of the OCR cache, as reported by `/cache-stats`.
+#[derive(Serialize, Debug)]
+pub struct CacheStats {
+    pub entries: usize,
+    /// Size of the stored results.
+    pub bytes: u64,
+    pub max_entries: Option<usize>,
+    pub corrections: usize,
+    /// Unix time of the least recently read page.
+    pub oldest_access: Option<i64>,
+    pub newest_access: Option<i64>,
+    pub languages: Vec<LanguageCacheStats>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LanguageCacheStats {
+    /// `null` for pages cached without a language.
+    pub language: Option<String>,
+    pub entries: usize,
+    pub bytes: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -50,7 +215,9 @@ impl AppState {
         }
 
         let db_path = cache_dir.join("ocr-cache.db");
-        let manager = SqliteConnectionManager::file(&db_path);
+        // Chapter jobs write pages from several tasks at once.
+        let manager = SqliteConnectionManager::file(&db_path)
+            .with_init(|conn| conn.busy_timeout(DB_BUSY_TIMEOUT));
         let pool = Pool::new(manager).expect("Failed to create OCR DB pool");
         let mut conn = pool.get().expect("Failed to get OCR DB connection");
 
@@ -65,6 +232,7 @@ impl AppState {
 
              CREATE TABLE IF NOT EXISTS ocr_cache (
                 cache_key TEXT PRIMARY KEY,
+                language TEXT,
                 context TEXT NOT NULL,
                 data BLOB NOT NULL,
                 created_at INTEGER NOT NULL,
@@ -84,23 +252,222 @@ impl AppState {
              );
 
              CREATE INDEX IF NOT EXISTS idx_chapter_pages_accessed
-                ON chapter_pages(last_accessed_at);",
+                ON chapter_pages(last_accessed_at);
+
+             CREATE TABLE IF NOT EXISTS ocr_corrections (
+                cache_key TEXT NOT NULL,
+                result_index INTEGER NOT NULL,
+                original_text TEXT NOT NULL,
+                text TEXT NOT NULL,
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (cache_key, result_index)
+             );",
         )
         .expect("Failed to initialize OCR cache database");
 
+        migrate_language_column(&mut conn);
         migrate_legacy_cache(&mut conn, &cache_dir);
 
+        let merge_defaults = conn
+            .query_row(
+                "SELECT value FROM metadata WHERE key = 'merge_config'",
+                [],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .unwrap_or(None)
+            .and_then(|value| match serde_json::from_str(&value) {
+                Ok(overrides) => Some(overrides),
+                Err(err) => {
+                    warn!("Ignoring unreadable stored merge config: {err}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+
         Self {
             pool,
             cache_dir,
             active_jobs: Arc::new(AtomicUsize::new(0)),
             requests_processed: Arc::new(AtomicUsize::new(0)),
             active_chapter_jobs: Arc::new(RwLock::new(HashMap::new())),
+            next_job_id: Arc::new(AtomicU64::new(1)),
+            job_queue: Arc::new(JobQueue::default()),
+            job_failures: Arc::new(RwLock::new(HashMap::new())),
+            merge_defaults: Arc::new(RwLock::new(merge_defaults)),
+            cache_limit: std::env::var("MANATAN_OCR_CACHE_MAX_ENTRIES")
+                .ok()
+                .and_then(|value| value.trim().parse::<usize>().ok())
+                .filter(|limit| *limit > 0),
         }
     }
 }
 
 impl AppState {
+    /// Registers a chapter job under `job_key` (the chapter's cache key) so it shows up as
+    /// processing while it waits in the queue. When the chapter already has a job, that one is
+    /// returned with `false` instead, raised to `priority` if that's higher; checking and
+    /// inserting under one lock keeps two requests for the chapter from both starting a job.
+    pub fn register_chapter_job(
+        &self,
+        job_key: &str,
+        total: usize,
+        context: &str,
+        priority: JobPriority,
+    ) -> (JobProgress, bool) {
+        let mut jobs = self.active_chapter_jobs.write().expect("lock poisoned");
+        if let Some(existing) = jobs.get_mut(job_key) {
+            existing.priority = existing.priority.max(priority);
+            self.job_queue.raise(existing.id, priority);
+            return (existing.clone(), false);
+        }
+        let progress = JobProgress {
+            id: self.next_job_id.fetch_add(1, Ordering::Relaxed),
+            status: JobStatus::Queued,
+            priority,
+            current: 0,
+            total,
+            failed: 0,
+            context: context.to_string(),
+            started_at: now_unix(),
+            started: Instant::now(),
+            cancel: CancellationToken::new(),
+            events: broadcast::channel(JOB_EVENT_CAPACITY).0,
+        };
+        jobs.insert(job_key.to_string(), progress.clone());
+        (progress, true)
+    }
+
+    pub fn chapter_job(&self, job_key: &str, id: u64) -> Option<JobProgress> {
+        self.active_chapter_jobs
+            .read()
+            .expect("lock poisoned")
+            .get(job_key)
+            .filter(|job| job.id == id)
+            .cloned()
+    }
+
+    /// Applies `update` to chapter job `id` while it's still registered.
+    pub fn update_chapter_job(
+        &self,
+        job_key: &str,
+        id: u64,
+        update: impl FnOnce(&mut JobProgress),
+    ) {
+        let mut jobs = self.active_chapter_jobs.write().expect("lock poisoned");
+        if let Some(job) = jobs.get_mut(job_key).filter(|job| job.id == id) {
+            update(job);
+        }
+    }
+
+    /// Removes the chapter job `id` from `active_chapter_jobs`. A newer job for the same
+    /// chapter is left alone.
+    pub fn remove_chapter_job(&self, job_key: &str, id: u64) {
+        let mut jobs = self.active_chapter_jobs.write().expect("lock poisoned");
+        if jobs.get(job_key).is_some_and(|job| job.id == id) {
+            jobs.remove(job_key);
+        }
+    }
+
+    /// Queued and running chapter jobs, oldest first.
+    pub fn list_chapter_jobs(&self) -> Vec<JobSummary> {
+        let mut jobs: Vec<JobSummary> = self
+            .active_chapter_jobs
+            .read()
+            .expect("lock poisoned")
+            .iter()
+            .map(|(chapter, job)| {
+                let (pages_per_minute, eta_seconds) = job.throughput();
+                JobSummary {
+                    id: job.id,
+                    status: job.status,
+                    priority: job.priority,
+                    queue_position: self.job_queue.position(job.id),
+                    chapter: chapter.clone(),
+                    context: job.context.clone(),
+                    current: job.current,
+                    total: job.total,
+                    failed: job.failed,
+                    started_at: job.started_at,
+                    pages_per_minute,
+                    eta_seconds,
+                }
+            })
+            .collect();
+        jobs.sort_by_key(|job| job.id);
+        jobs
+    }
+
+    /// Receives the events of the running chapter job `id`.
+    pub fn subscribe_chapter_job(&self, id: u64) -> Option<broadcast::Receiver<JobEvent>> {
+        self.active_chapter_jobs
+            .read()
+            .expect("lock poisoned")
+            .values()
+            .find(|job| job.id == id)
+            .map(|job| job.events.subscribe())
+    }
+
+    /// Cancels the running chapter job `id` and removes it, returning its key.
+    pub fn cancel_chapter_job(&self, id: u64) -> Option<String> {
+        let mut jobs = self.active_chapter_jobs.write().expect("lock poisoned");
+        let job_key = jobs
+            .iter()
+            .find(|(_, job)| job.id == id)
+            .map(|(key, _)| key.clone())?;
+        if let Some(job) = jobs.remove(&job_key) {
+            job.cancel.cancel();
+        }
+        Some(job_key)
+    }
+
+    /// Keeps the failure report of a finished job, dropping the oldest ones past
+    /// [`MAX_JOB_FAILURE_REPORTS`].
+    pub fn record_job_failures(&self, report: JobFailures) {
+        let mut reports = self.job_failures.write().expect("lock poisoned");
+        reports.insert(report.id, report);
+        while reports.len() > MAX_JOB_FAILURE_REPORTS {
+            let Some(oldest) = reports.keys().min().copied() else {
+                break;
+            };
+            reports.remove(&oldest);
+        }
+    }
+
+    pub fn job_failures(&self, id: u64) -> Option<JobFailures> {
+        self.job_failures
+            .read()
+            .expect("lock poisoned")
+            .get(&id)
+            .cloned()
+    }
+
+    /// Forgets the failure report of job `id` once its pages are queued again.
+    pub fn remove_job_failures(&self, id: u64) {
+        self.job_failures
+            .write()
+            .expect("lock poisoned")
+            .remove(&id);
+    }
+
+    /// Merge settings for a request in `language`: its language profile with the stored
+    /// defaults applied, before the request's own overrides.
+    pub fn merge_config(&self, language: OcrLanguage) -> MergeConfig {
+        let defaults = self.merge_defaults.read().expect("lock poisoned");
+        defaults.apply(MergeConfig::for_language(language))
+    }
+
+    /// Replaces the stored merge defaults.
+    pub fn set_merge_defaults(&self, overrides: MergeOverrides) -> anyhow::Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO metadata (key, value) VALUES ('merge_config', ?)",
+            params![serde_json::to_string(&overrides)?],
+        )?;
+        *self.merge_defaults.write().expect("lock poisoned") = overrides;
+        Ok(())
+    }
+
     pub fn cache_len(&self) -> usize {
         let Ok(conn) = self.pool.get() else {
             warn!("Failed to get DB connection for cache_len");
@@ -148,17 +515,62 @@ impl AppState {
             .optional()
             .unwrap_or(None);
 
-        if entry.is_some() {
-            let now = now_unix();
-            let _ = conn.execute(
-                "UPDATE ocr_cache
-                 SET last_accessed_at = ?, access_count = access_count + 1
-                 WHERE cache_key = ?",
-                params![now, cache_key],
-            );
-        }
+        let mut entry = entry?;
+        let now = now_unix();
+        let _ = conn.execute(
+            "UPDATE ocr_cache
+             SET last_accessed_at = ?, access_count = access_count + 1
+             WHERE cache_key = ?",
+            params![now, cache_key],
+        );
+
+        apply_corrections(&conn, cache_key, &mut entry.data);
+        Some(entry)
+    }
+
+    /// Stores `text` as the correction of result `index` of a cached page and returns the
+    /// corrected result, or `None` when the page isn't cached or has no such result. Setting a
+    /// result back to what was recognised drops its correction.
+    pub fn correct_cache_result(
+        &self,
+        cache_key: &str,
+        index: usize,
+        text: &str,
+    ) -> anyhow::Result<Option<OcrResult>> {
+        let conn = self.pool.get()?;
+        let data: Option<Vec<u8>> = conn
+            .query_row(
+                "SELECT data FROM ocr_cache WHERE cache_key = ?",
+                params![cache_key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(mut result) = data
+            .and_then(|data| serde_json::from_slice::<Vec<OcrResult>>(&data).ok())
+            .and_then(|mut results| (index < results.len()).then(|| results.swap_remove(index)))
+        else {
+            return Ok(None);
+        };
 
-        entry
+        if result.text == text {
+            conn.execute(
+                "DELETE FROM ocr_corrections WHERE cache_key = ? AND result_index = ?",
+                params![cache_key, index as i64],
+            )?;
+        } else {
+            conn.execute(
+                "INSERT INTO ocr_corrections
+                    (cache_key, result_index, original_text, text, updated_at)
+                 VALUES (?, ?, ?, ?, ?)
+                 ON CONFLICT(cache_key, result_index) DO UPDATE SET
+                    original_text = excluded.original_text,
+                    text = excluded.text,
+                    updated_at = excluded.updated_at",
+                params![cache_key, index as i64, result.text, text, now_unix()],
+            )?;
+            result.text = text.to_string();
+        }
+        Ok(Some(result))
     }
 
     pub fn insert_cache_entry(&self, cache_key: &str, entry: &CacheEntry) {
@@ -170,9 +582,10 @@ impl AppState {
         let data_blob = serde_json::to_vec(&entry.data).unwrap_or_default();
         let _ = conn.execute(
             "INSERT INTO ocr_cache
-                (cache_key, context, data, created_at, last_processed_at, last_accessed_at, access_count)
-             VALUES (?, ?, ?, ?, ?, ?, ?)
+                (cache_key, language, context, data, created_at, last_processed_at, last_accessed_at, access_count)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)
              ON CONFLICT(cache_key) DO UPDATE SET
+                language = excluded.language,
                 context = excluded.context,
                 data = excluded.data,
                 last_processed_at = excluded.last_processed_at,
@@ -180,6 +593,7 @@ impl AppState {
                 access_count = ocr_cache.access_count + 1",
             params![
                 cache_key,
+                cache_key_language(cache_key),
                 entry.context.as_str(),
                 data_blob,
                 now,
@@ -188,6 +602,121 @@ impl AppState {
                 1i64
             ],
         );
+        self.evict_over_limit(&conn);
+    }
+
+    /// Drops the least recently read pages past [`Self::cache_limit`].
+    fn evict_over_limit(&self, conn: &rusqlite::Connection) {
+        let Some(limit) = self.cache_limit else {
+            return;
+        };
+        match conn.execute(
+            "DELETE FROM ocr_cache WHERE cache_key IN (
+                SELECT cache_key FROM ocr_cache
+                ORDER BY last_accessed_at ASC
+                LIMIT MAX(0, (SELECT COUNT(*) FROM ocr_cache) - ?)
+             )",
+            params![limit as i64],
+        ) {
+            Ok(0) => {}
+            Ok(evicted) => {
+                info!("Evicted {evicted} OCR cache entries over the limit of {limit}");
+                // Corrections only apply on top of a cached page, so they go with it.
+                if let Err(err) = conn.execute(
+                    "DELETE FROM ocr_corrections
+                     WHERE cache_key NOT IN (SELECT cache_key FROM ocr_cache)",
+                    [],
+                ) {
+                    warn!("Failed to drop corrections of evicted OCR cache entries: {err}");
+                }
+            }
+            Err(err) => warn!("Failed to evict OCR cache entries: {err}"),
+        }
+    }
+
+    pub fn cache_stats(&self) -> anyhow::Result<CacheStats> {
+        let conn = self.pool.get()?;
+        let (entries, bytes, oldest_access, newest_access) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(LENGTH(data)), 0), MIN(last_accessed_at), MAX(last_accessed_at)
+             FROM ocr_cache",
+            [],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, Option<i64>>(2)?,
+                    row.get::<_, Option<i64>>(3)?,
+                ))
+            },
+        )?;
+        let corrections = conn.query_row("SELECT COUNT(*) FROM ocr_corrections", [], |row| {
+            row.get::<_, i64>(0)
+        })?;
+        let mut stmt = conn.prepare(
+            "SELECT language, COUNT(*), COALESCE(SUM(LENGTH(data)), 0)
+             FROM ocr_cache GROUP BY language ORDER BY COUNT(*) DESC",
+        )?;
+        let languages = stmt
+            .query_map([], |row| {
+                Ok(LanguageCacheStats {
+                    language: row.get(0)?,
+                    entries: row.get::<_, i64>(1)? as usize,
+                    bytes: row.get::<_, i64>(2)? as u64,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(CacheStats {
+            entries: entries as usize,
+            bytes: bytes as u64,
+            max_entries: self.cache_limit,
+            corrections: corrections as usize,
+            oldest_access,
+            newest_access,
+            languages,
+        })
+    }
+
+    /// Cached pages recorded under `context`, with the reader's corrections, by cache key.
+    pub fn context_cache_entries(
+        &self,
+        context: &str,
+    ) -> anyhow::Result<Vec<(String, Vec<OcrResult>)>> {
+        self.cache_entries_where("context = ?", context)
+    }
+
+    /// Cached pages of the chapter whose key is `chapter_key`, with the reader's corrections,
+    /// by cache key.
+    pub fn chapter_cache_entries(
+        &self,
+        chapter_key: &str,
+    ) -> anyhow::Result<Vec<(String, Vec<OcrResult>)>> {
+        let prefix = format!("{}/", chapter_key.trim_end_matches('/'));
+        self.cache_entries_where("cache_key LIKE ?", &format!("{prefix}%"))
+    }
+
+    fn cache_entries_where(
+        &self,
+        condition: &str,
+        value: &str,
+    ) -> anyhow::Result<Vec<(String, Vec<OcrResult>)>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT cache_key, data FROM ocr_cache WHERE {condition}"
+        ))?;
+        let rows = stmt
+            .query_map(params![value], |row| {
+                let key: String = row.get(0)?;
+                let data_blob: Vec<u8> = row.get(1)?;
+                Ok((key, serde_json::from_slice(&data_blob).unwrap_or_default()))
+            })?
+            .collect::<Result<Vec<(String, Vec<OcrResult>)>, _>>()?;
+        Ok(rows
+            .into_iter()
+            .map(|(key, mut data)| {
+                apply_corrections(&conn, &key, &mut data);
+                (key, data)
+            })
+            .collect())
     }
 
     pub fn count_cached_for_prefix(&self, prefix: &str) -> usize {
@@ -211,6 +740,7 @@ impl AppState {
             return;
         };
         let _ = conn.execute("DELETE FROM ocr_cache", []);
+        let _ = conn.execute("DELETE FROM ocr_corrections", []);
     }
 
     pub fn export_cache(&self) -> HashMap<String, CacheEntry> {
@@ -261,9 +791,18 @@ impl AppState {
             let data_blob = serde_json::to_vec(&entry.data).unwrap_or_default();
             if let Ok(changes) = tx.execute(
                 "INSERT OR IGNORE INTO ocr_cache
-                    (cache_key, context, data, created_at, last_processed_at, last_accessed_at, access_count)
-                 VALUES (?, ?, ?, ?, ?, ?, ?)",
-                params![key, entry.context, data_blob, now, now, now, 1i64],
+                    (cache_key, language, context, data, created_at, last_processed_at, last_accessed_at, access_count)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    key,
+                    cache_key_language(&key),
+                    entry.context,
+                    data_blob,
+                    now,
+                    now,
+                    now,
+                    1i64
+                ],
             ) {
                 if changes > 0 {
                     added += 1;
@@ -271,6 +810,7 @@ impl AppState {
             }
         }
         let _ = tx.commit();
+        self.evict_over_limit(&conn);
         added
     }
 
@@ -316,13 +856,98 @@ impl AppState {
     }
 }
 
-fn now_unix() -> i64 {
+pub(crate) fn now_unix() -> i64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs() as i64
 }
 
+/// Replaces the text of results the reader corrected. A correction is only applied while the
+/// result still reads as it did when it was made, so re-OCR'ing the page with different results
+/// doesn't put it on the wrong block.
+fn apply_corrections(conn: &rusqlite::Connection, cache_key: &str, results: &mut [OcrResult]) {
+    let mut stmt = match conn.prepare_cached(
+        "SELECT result_index, original_text, text FROM ocr_corrections WHERE cache_key = ?",
+    ) {
+        Ok(stmt) => stmt,
+        Err(err) => {
+            warn!("Failed to prepare apply_corrections: {err}");
+            return;
+        }
+    };
+    let Ok(rows) = stmt.query_map(params![cache_key], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+        ))
+    }) else {
+        return;
+    };
+    for (index, original_text, text) in rows.flatten() {
+        if let Some(result) = results
+            .get_mut(index as usize)
+            .filter(|result| result.text == original_text)
+        {
+            result.text = text;
+        }
+    }
+}
+
+/// Language segment of a cache key made by `logic::get_cache_key`, e.g. `japanese` in
+/// `lang/japanese/api/v1/...`.
+fn cache_key_language(cache_key: &str) -> Option<&str> {
+    let mut segments = cache_key.split('/');
+    segments.find(|segment| *segment == "lang")?;
+    segments.next().filter(|language| !language.is_empty())
+}
+
+/// Adds the `language` column to caches created before it existed, filled in from the keys.
+fn migrate_language_column(conn: &mut rusqlite::Connection) {
+    let has_language = conn
+        .prepare("SELECT language FROM ocr_cache LIMIT 0")
+        .is_ok();
+    if !has_language {
+        if let Err(err) = conn.execute("ALTER TABLE ocr_cache ADD COLUMN language TEXT", []) {
+            warn!("Failed to add language column to OCR cache: {err}");
+            return;
+        }
+
+        let keys: Vec<String> = match conn.prepare("SELECT cache_key FROM ocr_cache") {
+            Ok(mut stmt) => stmt
+                .query_map([], |row| row.get(0))
+                .map(|rows| rows.flatten().collect())
+                .unwrap_or_default(),
+            Err(err) => {
+                warn!("Failed to read OCR cache keys: {err}");
+                Vec::new()
+            }
+        };
+        let tx = match conn.transaction() {
+            Ok(tx) => tx,
+            Err(err) => {
+                warn!("Failed to start language migration transaction: {err}");
+                return;
+            }
+        };
+        for key in &keys {
+            let _ = tx.execute(
+                "UPDATE ocr_cache SET language = ? WHERE cache_key = ?",
+                params![cache_key_language(key), key],
+            );
+        }
+        if tx.commit().is_ok() {
+            info!("Recorded the language of {} OCR cache entries", keys.len());
+        }
+    }
+
+    let _ = conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_ocr_cache_language ON ocr_cache(language)",
+        [],
+    );
+}
+
 fn migrate_legacy_cache(conn: &mut rusqlite::Connection, cache_dir: &Path) {
     let migrated: Option<String> = conn
         .query_row(
@@ -372,9 +997,18 @@ fn migrate_legacy_cache(conn: &mut rusqlite::Connection, cache_dir: &Path) {
         let data_blob = serde_json::to_vec(&entry.data).unwrap_or_default();
         if let Ok(changes) = tx.execute(
             "INSERT OR IGNORE INTO ocr_cache
-                (cache_key, context, data, created_at, last_processed_at, last_accessed_at, access_count)
-             VALUES (?, ?, ?, ?, ?, ?, ?)",
-            params![key, entry.context, data_blob, now, now, now, 1i64],
+                (cache_key, language, context, data, created_at, last_processed_at, last_accessed_at, access_count)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                key,
+                cache_key_language(&key),
+                entry.context,
+                data_blob,
+                now,
+                now,
+                now,
+                1i64
+            ],
         ) {
             if changes > 0 {
                 imported += 1;
@@ -0,0 +1,13 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/yomitan.proto");
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("Vendored protoc not found");
+        // SAFETY: build scripts are single-threaded.
+        unsafe { std::env::set_var("PROTOC", protoc) };
+        tonic_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/yomitan.proto"], &["proto"])
+            .expect("Failed to compile gRPC protos");
+    }
+}
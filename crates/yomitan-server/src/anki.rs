@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+pub const DEFAULT_ANKI_CONNECT_URL: &str = "http://127.0.0.1:8765";
+
+/// Field names used when the client does not send its own mapping.
+const DEFAULT_FIELD_NAMES: [(&str, &str); 5] = [
+    ("expression", "Expression"),
+    ("reading", "Reading"),
+    ("glossary", "Glossary"),
+    ("furigana", "Furigana"),
+    ("sentence", "Sentence"),
+];
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnkiNoteOptions {
+    pub deck_name: Option<String>,
+    pub model_name: Option<String>,
+    /// Maps our field keys (`expression`, `reading`, `glossary`, `furigana`, `sentence`) to the
+    /// field names of the note type. Keys mapped to an empty string are left out of the note.
+    #[serde(default)]
+    pub field_map: HashMap<String, String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub allow_duplicate: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteContent {
+    pub expression: String,
+    pub reading: String,
+    pub glossary: String,
+    pub furigana: String,
+    pub sentence: String,
+}
+
+impl NoteContent {
+    fn field_value(&self, key: &str) -> Option<&str> {
+        match key {
+            "expression" => Some(&self.expression),
+            "reading" => Some(&self.reading),
+            "glossary" => Some(&self.glossary),
+            "furigana" => Some(&self.furigana),
+            "sentence" => Some(&self.sentence),
+            _ => None,
+        }
+    }
+}
+
+/// Formats furigana segments in Anki's `漢字[かんじ]` notation.
+pub fn furigana_to_anki(segments: &[(String, String)]) -> String {
+    let mut out = String::new();
+    for (base, ruby) in segments {
+        if ruby.is_empty() {
+            out.push_str(base);
+        } else {
+            if !out.is_empty() {
+                out.push(' ');
+            }
+            out.push_str(&format!("{base}[{ruby}]"));
+        }
+    }
+    out
+}
+
+/// Carries a dictionary form's furigana over to the inflected text it was matched from (食べた
+/// for 食べる), keeping the ruby of the leading segments both share. Whatever follows the point
+/// where they differ is left without ruby.
+pub fn surface_furigana(surface: &str, segments: &[(String, String)]) -> Vec<(String, String)> {
+    let mut out = Vec::new();
+    let mut rest = surface;
+    for (base, ruby) in segments {
+        let Some(after) = rest.strip_prefix(base.as_str()) else {
+            break;
+        };
+        out.push((base.clone(), ruby.clone()));
+        rest = after;
+    }
+    if !rest.is_empty() {
+        out.push((rest.to_string(), String::new()));
+    }
+    out
+}
+
+/// Renders one definition entry (a list of plain strings and/or serialized structured content)
+/// into an HTML fragment.
+pub fn definition_to_html(dictionary_name: &str, content: &Value) -> String {
+    let mut body = String::new();
+    render_content(content, &mut body);
+    format!(
+        "<div class=\"definition\"><i>({})</i> {}</div>",
+        escape_html(dictionary_name),
+        body
+    )
+}
+
+fn render_content(value: &Value, out: &mut String) {
+    match value {
+        Value::String(s) => {
+            let trimmed = s.trim_start();
+            if trimmed.starts_with('{') || trimmed.starts_with('[') {
+                if let Ok(parsed) = serde_json::from_str::<Value>(trimmed) {
+                    render_structured(&parsed, out);
+                    return;
+                }
+            }
+            out.push_str(&escape_html(s));
+        }
+        Value::Array(items) => {
+            if items.len() > 1 {
+                out.push_str("<ul>");
+                for item in items {
+                    out.push_str("<li>");
+                    render_content(item, out);
+                    out.push_str("</li>");
+                }
+                out.push_str("</ul>");
+            } else if let Some(item) = items.first() {
+                render_content(item, out);
+            }
+        }
+        Value::Object(obj) => {
+            // Tagged content variants: render whatever they wrap
+            for inner in obj.values() {
+                render_content(inner, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn render_structured(value: &Value, out: &mut String) {
+    match value {
+        Value::String(s) => out.push_str(&escape_html(s)),
+        Value::Array(items) => {
+            for item in items {
+                render_structured(item, out);
+            }
+        }
+        Value::Object(obj) => {
+            let kind = obj.get("type").and_then(|v| v.as_str());
+            if kind == Some("image") {
+                return;
+            }
+            if kind == Some("text") {
+                if let Some(text) = obj.get("text").and_then(|v| v.as_str()) {
+                    out.push_str(&escape_html(text));
+                }
+                return;
+            }
+            let raw_tag = obj.get("tag").and_then(|v| v.as_str());
+            if raw_tag == Some("img") {
+                return;
+            }
+            let tag = raw_tag.filter(|tag| is_allowed_tag(tag));
+            if let Some(tag) = tag {
+                out.push_str(&format!("<{tag}>"));
+            }
+            if let Some(content) = obj.get("content") {
+                render_structured(content, out);
+            }
+            if let Some(tag) = tag {
+                if tag != "br" {
+                    out.push_str(&format!("</{tag}>"));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_allowed_tag(tag: &str) -> bool {
+    matches!(
+        tag,
+        "br" | "ruby"
+            | "rt"
+            | "rp"
+            | "table"
+            | "thead"
+            | "tbody"
+            | "tfoot"
+            | "tr"
+            | "td"
+            | "th"
+            | "span"
+            | "div"
+            | "ol"
+            | "ul"
+            | "li"
+            | "details"
+            | "summary"
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Sends an `addNote` request to AnkiConnect and returns the created note id.
+pub async fn add_note(
+    client: &Client,
+    url: &str,
+    note: &NoteContent,
+    options: &AnkiNoteOptions,
+) -> Result<i64> {
+    let mut fields = serde_json::Map::new();
+    for (key, default_name) in DEFAULT_FIELD_NAMES {
+        let field_name = options
+            .field_map
+            .get(key)
+            .map(|s| s.as_str())
+            .unwrap_or(default_name);
+        if field_name.is_empty() {
+            continue;
+        }
+        if let Some(value) = note.field_value(key) {
+            fields.insert(field_name.to_string(), Value::String(value.to_string()));
+        }
+    }
+
+    let payload = json!({
+        "action": "addNote",
+        "version": 6,
+        "params": {
+            "note": {
+                "deckName": options.deck_name.as_deref().unwrap_or("Default"),
+                "modelName": options.model_name.as_deref().unwrap_or("Basic"),
+                "fields": fields,
+                "tags": options.tags,
+                "options": { "allowDuplicate": options.allow_duplicate },
+            }
+        }
+    });
+
+    let response = client.post(url).json(&payload).send().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("AnkiConnect returned {}", response.status()));
+    }
+    let body: Value = response.json().await?;
+    if let Some(err) = body.get("error").and_then(|e| e.as_str()) {
+        return Err(anyhow!("AnkiConnect error: {err}"));
+    }
+    body.get("result")
+        .and_then(|r| r.as_i64())
+        .ok_or_else(|| anyhow!("AnkiConnect returned no note id"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{definition_to_html, furigana_to_anki, surface_furigana};
+
+    #[test]
+    fn formats_furigana_for_anki() {
+        let segments = vec![
+            ("引".to_string(), "ひ".to_string()),
+            ("っ".to_string(), String::new()),
+            ("越".to_string(), "こ".to_string()),
+            ("す".to_string(), String::new()),
+        ];
+        assert_eq!(furigana_to_anki(&segments), "引[ひ]っ 越[こ]す");
+    }
+
+    #[test]
+    fn keeps_furigana_on_inflected_forms() {
+        let segments = vec![
+            ("食".to_string(), "た".to_string()),
+            ("べる".to_string(), String::new()),
+        ];
+        assert_eq!(furigana_to_anki(&surface_furigana("食べた", &segments)), "食[た]べた");
+        assert_eq!(furigana_to_anki(&surface_furigana("たべた", &segments)), "たべた");
+    }
+
+    #[test]
+    fn renders_structured_content_without_images() {
+        let structured = json!({
+            "type": "structured-content",
+            "content": [
+                { "tag": "ul", "content": [{ "tag": "li", "content": "to move <house>" }] },
+                { "tag": "img", "path": "img/a.png" }
+            ]
+        })
+        .to_string();
+        let html = definition_to_html("JMdict", &json!([structured]));
+        assert_eq!(
+            html,
+            "<div class=\"definition\"><i>(JMdict)</i> <ul><li>to move &lt;house&gt;</li></ul></div>"
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::{handlers::AudioSource, state::AppState};
+
+const AUDIO_EXTENSIONS: [&str; 7] = ["mp3", "ogg", "opus", "m4a", "aac", "wav", "flac"];
+
+/// One entry in the ordered list of places `/audio-uri` looks for pronunciation audio.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum AudioSourceConfig {
+    /// One of the scraped sources also available through `/audio`.
+    Builtin { source: AudioSource },
+    /// A URL template; `{term}`, `{reading}` and `{language}` are substituted (URL-encoded).
+    UrlPattern { url: String },
+    /// A folder of audio files named `<term>.<ext>`, `<reading>.<ext>` or
+    /// `<term>[<reading>].<ext>`, inside the audio root (see [`audio_root`]). Relative paths are
+    /// resolved against the root, which is also the default.
+    LocalDirectory {
+        #[serde(default)]
+        path: Option<String>,
+    },
+}
+
+pub fn default_sources() -> Vec<AudioSourceConfig> {
+    vec![
+        AudioSourceConfig::Builtin {
+            source: AudioSource::Jpod101,
+        },
+        AudioSourceConfig::LocalDirectory { path: None },
+        AudioSourceConfig::Builtin {
+            source: AudioSource::Jisho,
+        },
+    ]
+}
+
+pub fn load_sources(state: &AppState) -> Vec<AudioSourceConfig> {
+    let stored: Option<String> = state.pool.get().ok().and_then(|conn| {
+        conn.query_row(
+            "SELECT config FROM audio_sources WHERE id = 1",
+            [],
+            |row| row.get(0),
+        )
+        .ok()
+    });
+
+    match stored {
+        Some(raw) => serde_json::from_str(&raw).unwrap_or_else(|e| {
+            error!("❌ [Audio] Invalid stored audio source config: {}", e);
+            default_sources()
+        }),
+        None => default_sources(),
+    }
+}
+
+pub fn store_sources(state: &AppState, sources: &[AudioSourceConfig]) -> anyhow::Result<()> {
+    let raw = serde_json::to_string(sources)?;
+    let conn = state.pool.get()?;
+    conn.execute(
+        "INSERT OR REPLACE INTO audio_sources (id, config) VALUES (1, ?)",
+        [raw],
+    )?;
+    Ok(())
+}
+
+/// The only tree local audio is read from: `MANATAN_AUDIO_ROOT` when set, otherwise
+/// `<data_dir>/audio`.
+pub fn audio_root(state: &AppState) -> PathBuf {
+    std::env::var_os("MANATAN_AUDIO_ROOT")
+        .filter(|root| !root.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| state.data_dir.join("audio"))
+}
+
+/// Resolves a configured local directory inside `root`. Rejects `..` outright and, after
+/// canonicalizing resolves symlinks, anything that lands outside the root.
+fn resolve_directory(root: &Path, path: Option<&str>) -> Result<PathBuf, String> {
+    let relative = Path::new(path.unwrap_or(""));
+    if relative.components().any(|c| c == Component::ParentDir) {
+        return Err(format!("{} may not contain '..'", relative.display()));
+    }
+    let root = std::fs::canonicalize(root)
+        .map_err(|e| format!("Audio root {} is unavailable: {e}", root.display()))?;
+    let dir = std::fs::canonicalize(root.join(relative))
+        .map_err(|e| format!("{} is unavailable: {e}", relative.display()))?;
+    if !dir.starts_with(&root) {
+        return Err(format!(
+            "{} is outside the audio root {}",
+            relative.display(),
+            root.display()
+        ));
+    }
+    Ok(dir)
+}
+
+/// Checks that every local directory in `sources` resolves inside the audio root.
+pub fn validate_sources(state: &AppState, sources: &[AudioSourceConfig]) -> Result<(), String> {
+    let root = audio_root(state);
+    for source in sources {
+        if let AudioSourceConfig::LocalDirectory { path: Some(path) } = source {
+            resolve_directory(&root, Some(path))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn expand_url_pattern(pattern: &str, term: &str, reading: &str, language: &str) -> String {
+    pattern
+        .replace("{term}", &urlencoding::encode(term))
+        .replace("{reading}", &urlencoding::encode(reading))
+        .replace("{language}", language)
+}
+
+/// Looks up a local audio file for the term, preferring the most specific file name.
+pub fn find_local_audio(
+    state: &AppState,
+    path: Option<&str>,
+    term: &str,
+    reading: &str,
+) -> Option<PathBuf> {
+    let dir = match resolve_directory(&audio_root(state), path) {
+        Ok(dir) if dir.is_dir() => dir,
+        Ok(_) => return None,
+        Err(e) => {
+            if path.is_some() {
+                warn!("Skipping local audio directory: {e}");
+            }
+            return None;
+        }
+    };
+
+    let mut stems = Vec::new();
+    if !reading.is_empty() && reading != term {
+        stems.push(format!("{term}[{reading}]"));
+        stems.push(format!("{term} - {reading}"));
+    }
+    stems.push(term.to_string());
+    if !reading.is_empty() && reading != term {
+        stems.push(reading.to_string());
+    }
+
+    let dir = &dir;
+    stems
+        .iter()
+        .filter(|stem| is_safe_file_stem(stem))
+        .flat_map(|stem| {
+            AUDIO_EXTENSIONS
+                .iter()
+                .map(move |ext| dir.join(format!("{stem}.{ext}")))
+        })
+        // A symlinked file could still point out of the directory.
+        .filter_map(|candidate| std::fs::canonicalize(candidate).ok())
+        .find(|candidate| candidate.starts_with(dir) && candidate.is_file())
+}
+
+/// Rejects names that could escape the audio directory.
+fn is_safe_file_stem(stem: &str) -> bool {
+    !stem.is_empty() && !stem.contains(['/', '\\']) && !stem.starts_with('.')
+}
+
+pub fn content_type_for(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("mp3") => "audio/mpeg",
+        Some("ogg") | Some("opus") => "audio/ogg",
+        Some("m4a") | Some("aac") => "audio/aac",
+        Some("wav") => "audio/wav",
+        Some("flac") => "audio/flac",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve_directory;
+
+    #[test]
+    fn confines_local_directories_to_the_root() {
+        let base = std::env::temp_dir().join(format!("manatan-audio-{}", std::process::id()));
+        let root = base.join("root");
+        std::fs::create_dir_all(root.join("forvo")).expect("create root");
+        std::fs::create_dir_all(base.join("outside")).expect("create outside");
+        let root_canonical = std::fs::canonicalize(&root).expect("canonical root");
+
+        assert_eq!(resolve_directory(&root, None), Ok(root_canonical.clone()));
+        assert_eq!(
+            resolve_directory(&root, Some("forvo")),
+            Ok(root_canonical.join("forvo"))
+        );
+        assert!(resolve_directory(&root, Some("../outside")).is_err());
+        assert!(resolve_directory(&root, Some("forvo/../../outside")).is_err());
+        let outside = base.join("outside");
+        assert!(resolve_directory(&root, outside.to_str()).is_err());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&outside, root.join("escape")).expect("symlink");
+            assert!(resolve_directory(&root, Some("escape")).is_err());
+        }
+
+        let _ = std::fs::remove_dir_all(&base);
+    }
+}
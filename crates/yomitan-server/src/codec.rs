@@ -0,0 +1,172 @@
+//! Encoding of the `terms.json` column.
+//!
+//! Rows are snappy-compressed [`StoredRecord`]s. New rows use MessagePack, marked by a leading
+//! byte that never starts a JSON document or a valid MessagePack value; rows written before
+//! that are JSON and are re-encoded in the background on startup.
+
+use std::time::Instant;
+
+use anyhow::Result;
+use rusqlite::{Connection, ErrorCode, OptionalExtension};
+use tracing::{error, info, warn};
+
+use crate::{
+    import::record_hash,
+    integrity,
+    state::{DbPool, StoredRecord},
+};
+
+/// `0xC1` is reserved ("never used") in MessagePack and can't begin JSON.
+const MSGPACK_MARKER: u8 = 0xC1;
+const FORMAT_KEY: &str = "record_format";
+const FORMAT_MSGPACK: &str = "msgpack";
+const MIGRATION_BATCH: i64 = 2000;
+
+/// Serializes a record for storage (before compression).
+pub fn encode(stored: &StoredRecord) -> Result<Vec<u8>> {
+    let mut bytes = vec![MSGPACK_MARKER];
+    // Named fields keep `#[serde(default)]` working when StoredRecord grows.
+    rmp_serde::encode::write_named(&mut bytes, stored)?;
+    Ok(bytes)
+}
+
+/// Parses a decompressed row in either format.
+pub fn decode(bytes: &[u8]) -> Option<StoredRecord> {
+    match bytes.split_first() {
+        Some((&MSGPACK_MARKER, rest)) => rmp_serde::from_slice(rest).ok(),
+        _ => serde_json::from_slice(bytes).ok(),
+    }
+}
+
+/// Decompresses and parses a `terms.json` value.
+pub fn decode_row(decoder: &mut snap::raw::Decoder, compressed: &[u8]) -> Option<StoredRecord> {
+    decode(&decoder.decompress_vec(compressed).ok()?)
+}
+
+fn is_migrated(conn: &Connection) -> bool {
+    conn.query_row(
+        "SELECT value FROM metadata WHERE key = ?",
+        [FORMAT_KEY],
+        |row| row.get::<_, String>(0),
+    )
+    .optional()
+    .ok()
+    .flatten()
+    .is_some_and(|value| value == FORMAT_MSGPACK)
+}
+
+/// Re-encodes legacy JSON rows on a background thread. Lookups read both formats, so they keep
+/// working while this runs.
+pub fn spawn_migration(pool: DbPool) {
+    let Ok(conn) = pool.get() else {
+        return;
+    };
+    if is_migrated(&conn) {
+        return;
+    }
+    drop(conn);
+
+    std::thread::spawn(move || match migrate(&pool) {
+        Ok(0) => {}
+        Ok(rows) => info!("✅ [Storage] Re-encoded {} legacy rows as MessagePack", rows),
+        Err(e) => error!("❌ [Storage] Record migration failed: {}", e),
+    });
+}
+
+fn migrate(pool: &DbPool) -> Result<usize> {
+    let started = Instant::now();
+    let mut conn = pool.get()?;
+    let mut encoder = snap::raw::Encoder::new();
+    let mut decoder = snap::raw::Decoder::new();
+    let mut last_rowid = 0;
+    let mut converted = 0;
+
+    loop {
+        let rows: Vec<(i64, Vec<u8>)> = conn
+            .prepare("SELECT rowid, json FROM terms WHERE rowid > ? ORDER BY rowid LIMIT ?")?
+            .query_map([last_rowid, MIGRATION_BATCH], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        let Some((last, _)) = rows.last() else {
+            break;
+        };
+        last_rowid = *last;
+
+        let tx = conn.transaction()?;
+        for (rowid, compressed) in rows {
+            let Ok(raw) = decoder.decompress_vec(&compressed) else {
+                continue;
+            };
+            if raw.first() == Some(&MSGPACK_MARKER) {
+                continue;
+            }
+            let Ok(stored) = serde_json::from_slice::<StoredRecord>(&raw) else {
+                warn!("⚠️ [Storage] Skipping unreadable row {}", rowid);
+                continue;
+            };
+            let encoded = encode(&stored)?;
+            let hash = record_hash(&encoded);
+            let updated = tx.execute(
+                "UPDATE terms SET json = ?, hash = ? WHERE rowid = ?",
+                rusqlite::params![encoder.compress_vec(&encoded)?, hash, rowid],
+            );
+            match updated {
+                Ok(_) => converted += 1,
+                // Legacy imports could store the same entry twice; the unique hash index now
+                // catches that, so drop the copy.
+                Err(rusqlite::Error::SqliteFailure(e, _))
+                    if e.code == ErrorCode::ConstraintViolation =>
+                {
+                    tx.execute("DELETE FROM terms WHERE rowid = ?", [rowid])?;
+                    tx.execute("DELETE FROM glosses_fts WHERE rowid = ?", [rowid])?;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        tx.commit()?;
+    }
+
+    integrity::rebuild_checksums(&conn)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO metadata (key, value) VALUES (?, ?)",
+        [FORMAT_KEY, FORMAT_MSGPACK],
+    )?;
+    if converted > 0 {
+        info!(
+            "🧹 [Storage] Migration took {:.1}s, vacuuming...",
+            started.elapsed().as_secs_f32()
+        );
+        conn.execute("VACUUM", [])?;
+    }
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wordbase_api::{DictionaryId, Record, dict::yomitan::Glossary};
+
+    #[test]
+    fn reads_both_formats() {
+        let stored = StoredRecord {
+            dictionary_id: DictionaryId(3),
+            record: Record::YomitanGlossary(Glossary {
+                popularity: 5,
+                tags: vec![],
+                content: vec![],
+            }),
+            term_tags: None,
+            reading: Some("たべる".to_string()),
+            headword: Some("食べる".to_string()),
+            sequence: Some(42),
+        };
+
+        let binary = encode(&stored).unwrap();
+        let json = serde_json::to_vec(&stored).unwrap();
+        assert!(binary.len() < json.len());
+        for bytes in [binary, json] {
+            let decoded = decode(&bytes).unwrap();
+            assert_eq!(decoded.headword.as_deref(), Some("食べる"));
+            assert_eq!(decoded.sequence, Some(42));
+        }
+    }
+}
@@ -4,3 +4,34 @@ pub fn transformer() -> LanguageTransformer {
     LanguageTransformer::from_json(include_str!("transforms.json"))
         .expect("Failed to parse Japanese deinflector data")
 }
+
+/// Readable name for a suffix-style transform id; `None` for ids that already are words
+/// (`passive`, `causative`).
+pub fn transform_label(id: &str) -> Option<&'static str> {
+    Some(match id {
+        "-ば" => "conditional",
+        "-ゃ" | "-ちゃ" => "contracted conditional",
+        "-ちゃう" | "-ちまう" | "-しまう" => "completion",
+        "-なさい" => "polite imperative",
+        "-そう" => "seemingly",
+        "-すぎる" | "-過ぎる" => "excess",
+        "-たい" => "desire",
+        "-たら" => "conditional (-tara)",
+        "-たり" => "listing (-tari)",
+        "-て" => "te-form",
+        "-ず" | "-ぬ" | "-ん" | "-ざる" => "negative (classical)",
+        "-ねば" => "obligation",
+        "-く" => "adverbial",
+        "-さ" => "nominalized",
+        "-た" => "past",
+        "-ます" => "polite",
+        "-まい" => "negative volitional",
+        "-おく" => "in advance",
+        "-いる" => "progressive",
+        "-がる" => "showing signs",
+        "-げ" => "appearance",
+        "-き" => "attributive (classical)",
+        "-え" => "imperative (slang)",
+        _ => return None,
+    })
+}
@@ -20,6 +20,22 @@ pub fn deinflect(transformer: &LanguageTransformer, text: &str) -> Vec<String> {
     results
 }
 
+pub fn deinflect_with_chains(
+    transformer: &LanguageTransformer,
+    text: &str,
+) -> Vec<(String, Vec<String>)> {
+    let disassembled = disassemble(text);
+    let mut results = Vec::new();
+    let mut seen = HashSet::new();
+    for (term, chain) in transformer.deinflect_with_chains(&disassembled) {
+        let recomposed = reassemble_hangul(&term);
+        if seen.insert(recomposed.clone()) {
+            results.push((recomposed, chain));
+        }
+    }
+    results
+}
+
 pub fn disassemble(text: &str) -> String {
     disassemble_hangul(text)
 }
@@ -128,4 +128,30 @@ impl Deinflector {
             _ => transformer.deinflect_terms(text),
         }
     }
+
+    /// [`deinflect`](Self::deinflect) with the transform ids applied to reach each form.
+    pub fn deinflect_with_chains(
+        &self,
+        language: Language,
+        text: &str,
+    ) -> Vec<(String, Vec<String>)> {
+        let transformer = self
+            .transformers
+            .get(&language)
+            .expect("Missing deinflector");
+        match language {
+            Language::Korean => korean::deinflect_with_chains(transformer, text),
+            _ => transformer.deinflect_with_chains(text),
+        }
+    }
+}
+
+/// Label for a transform id in results; falls back to the id, which for most languages is
+/// already descriptive (`past`, `plural`).
+pub fn transform_label(language: Language, id: &str) -> String {
+    let label = match language {
+        Language::Japanese => japanese::transform_label(id),
+        _ => None,
+    };
+    label.unwrap_or(id).to_string()
 }
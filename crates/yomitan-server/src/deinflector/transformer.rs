@@ -376,6 +376,20 @@ impl LanguageTransformer {
         results
     }
 
+    /// Deinflected forms paired with the ids of the transforms that lead to them, starting from
+    /// the one nearest the dictionary form. The source text itself comes first with no chain.
+    pub fn deinflect_with_chains(&self, source_text: &str) -> Vec<(String, Vec<String>)> {
+        let mut seen = HashSet::new();
+        self.transform_with_trace(source_text)
+            .into_iter()
+            .filter(|item| seen.insert(item.text.clone()))
+            .map(|item| {
+                let chain = item.trace.into_iter().map(|f| f.transform_id).collect();
+                (item.text, chain)
+            })
+            .collect()
+    }
+
     pub fn deinflect_terms(&self, source_text: &str) -> Vec<String> {
         let mut seen = HashSet::new();
         let mut results = Vec::new();
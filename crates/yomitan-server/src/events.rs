@@ -0,0 +1,44 @@
+use serde::Serialize;
+use wordbase_api::DictionaryId;
+
+use crate::state::{DictionaryData, Readiness};
+
+/// Buffered events per subscriber before slow WebSocket clients start lagging.
+pub const EVENT_CHANNEL_CAPACITY: usize = 64;
+
+/// Dictionary state changes pushed to `/ws` subscribers.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DictionaryEvent {
+    /// Sent once on connect (and after a client lagged) so it can render without polling.
+    Snapshot {
+        dictionaries: Vec<DictionaryData>,
+        loading: bool,
+        readiness: Readiness,
+    },
+    ImportStarted {
+        name: String,
+    },
+    ImportFinished {
+        id: DictionaryId,
+        name: String,
+        term_count: i64,
+    },
+    ImportFailed {
+        message: String,
+    },
+    DictionaryDeleted {
+        id: DictionaryId,
+    },
+    /// Enabled flags or priorities changed.
+    DictionariesUpdated {
+        dictionaries: Vec<DictionaryData>,
+    },
+    /// Every dictionary was removed by a database reset.
+    DictionariesCleared,
+    /// `loading` is true whenever `readiness` is anything but `ready`.
+    LoadingChanged {
+        loading: bool,
+        readiness: Readiness,
+    },
+}
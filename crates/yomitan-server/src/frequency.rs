@@ -0,0 +1,139 @@
+use anyhow::Result;
+use serde::Serialize;
+use utoipa::ToSchema;
+use wordbase_api::{DictionaryId, Record, dict::yomitan::structured::Content};
+
+use crate::{
+    codec, lookup,
+    profiles::FrequencyMode,
+    state::AppState,
+    vocab::{self, VocabState},
+};
+
+/// One term of a frequency dictionary, in ranked order.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct FrequencyEntry {
+    /// 1-based position in the full list (before `unknown_only` filtering).
+    pub rank: usize,
+    pub term: String,
+    pub reading: Option<String>,
+    pub value: i64,
+    /// Value as shown by the dictionary, e.g. `1234㋕`.
+    pub display: String,
+    pub vocab_state: VocabState,
+}
+
+fn display_value(record: &Record) -> String {
+    let Record::YomitanGlossary(gloss) = record else {
+        return String::new();
+    };
+    match gloss.content.first() {
+        Some(Content::String(text)) => text
+            .strip_prefix("Frequency: ")
+            .unwrap_or(text)
+            .split(" (")
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Every frequency row of `dictionary_id`, most common first. Rows whose value isn't numeric
+/// can't be ranked and are left out.
+pub fn ranked_terms(
+    state: &AppState,
+    dictionary_id: DictionaryId,
+    mode: FrequencyMode,
+) -> Result<Vec<FrequencyEntry>> {
+    let conn = state.pool.get()?;
+    // Reading rows share the headword row's record, so DISTINCT yields each entry once.
+    let mut stmt = conn.prepare("SELECT DISTINCT json FROM terms WHERE dictionary_id = ?")?;
+    let rows = stmt.query_map([dictionary_id.0], |row| row.get::<_, Vec<u8>>(0))?;
+
+    let mut decoder = snap::raw::Decoder::new();
+    let mut entries = Vec::new();
+    for compressed in rows {
+        let Some(stored) = codec::decode_row(&mut decoder, &compressed?) else {
+            continue;
+        };
+        if !lookup::is_frequency_record(&stored.record) {
+            continue;
+        }
+        let (Some(term), Some(value)) = (
+            stored.headword.clone(),
+            lookup::frequency_value(&stored.record),
+        ) else {
+            continue;
+        };
+        entries.push(FrequencyEntry {
+            rank: 0,
+            term,
+            reading: stored.reading.filter(|r| !r.is_empty()),
+            value,
+            display: display_value(&stored.record),
+            vocab_state: VocabState::Unknown,
+        });
+    }
+
+    entries.sort_by(|a, b| {
+        let by_value = match mode {
+            FrequencyMode::Rank => a.value.cmp(&b.value),
+            FrequencyMode::Occurrence => b.value.cmp(&a.value),
+        };
+        by_value
+            .then_with(|| a.term.cmp(&b.term))
+            .then_with(|| a.reading.cmp(&b.reading))
+    });
+    for (index, entry) in entries.iter_mut().enumerate() {
+        entry.rank = index + 1;
+    }
+    Ok(entries)
+}
+
+/// Takes `limit` entries after skipping `offset`, filling in vocabulary states. With
+/// `unknown_only`, words marked learning or known are dropped before paging, so the list is
+/// walked in chunks until the page is full.
+pub fn page(
+    state: &AppState,
+    entries: Vec<FrequencyEntry>,
+    offset: usize,
+    limit: usize,
+    unknown_only: bool,
+) -> Vec<FrequencyEntry> {
+    if !unknown_only {
+        let page: Vec<_> = entries.into_iter().skip(offset).take(limit).collect();
+        return with_vocab_states(state, page);
+    }
+
+    let chunk_size = limit.max(100);
+    let mut skipped = 0;
+    let mut page = Vec::with_capacity(limit);
+    let mut entries = entries.into_iter().peekable();
+    while page.len() < limit && entries.peek().is_some() {
+        let chunk: Vec<_> = entries.by_ref().take(chunk_size).collect();
+        for entry in with_vocab_states(state, chunk) {
+            if entry.vocab_state != VocabState::Unknown {
+                continue;
+            }
+            if skipped < offset {
+                skipped += 1;
+            } else if page.len() < limit {
+                page.push(entry);
+            }
+        }
+    }
+    page
+}
+
+fn with_vocab_states(state: &AppState, mut entries: Vec<FrequencyEntry>) -> Vec<FrequencyEntry> {
+    let keys: Vec<(String, String)> = entries
+        .iter()
+        .map(|e| (e.term.clone(), e.reading.clone().unwrap_or_default()))
+        .collect();
+    let states = vocab::load_states(state, &keys);
+    for (entry, key) in entries.iter_mut().zip(&keys) {
+        entry.vocab_state = states.get(key).copied().unwrap_or_default();
+    }
+    entries
+}
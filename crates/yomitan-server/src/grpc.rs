@@ -0,0 +1,253 @@
+use std::net::SocketAddr;
+
+use tonic::{Request, Response, Status};
+use tracing::{error, info};
+
+use crate::{
+    ServerState,
+    handlers::{self, ApiGroupedResult, DictionaryLanguage},
+    profiles,
+};
+
+pub mod proto {
+    tonic::include_proto!("manatan.yomitan.v1");
+}
+
+use proto::yomitan_server::{Yomitan, YomitanServer};
+
+/// Requests accepted in one `BatchLookup` call.
+const MAX_BATCH_SIZE: usize = 1000;
+
+pub struct GrpcService {
+    state: ServerState,
+}
+
+/// Starts the gRPC server on `MANATAN_YOMITAN_GRPC_ADDR` (e.g. `0.0.0.0:4570`) if it is set.
+pub fn spawn_from_env(state: ServerState) {
+    let Ok(addr) = std::env::var("MANATAN_YOMITAN_GRPC_ADDR") else {
+        return;
+    };
+    let addr: SocketAddr = match addr.parse() {
+        Ok(addr) => addr,
+        Err(e) => {
+            error!("❌ [gRPC] Invalid MANATAN_YOMITAN_GRPC_ADDR '{}': {}", addr, e);
+            return;
+        }
+    };
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+        error!("❌ [gRPC] No Tokio runtime available; gRPC server not started");
+        return;
+    };
+
+    runtime.spawn(async move {
+        info!("🔌 [gRPC] Yomitan service listening on {}", addr);
+        let result = tonic::transport::Server::builder()
+            .add_service(YomitanServer::new(GrpcService { state }))
+            .serve(addr)
+            .await;
+        if let Err(e) = result {
+            error!("❌ [gRPC] Server stopped: {}", e);
+        }
+    });
+}
+
+impl GrpcService {
+    fn ensure_ready(&self) -> Result<(), Status> {
+        if self.state.app.is_loading() {
+            return Err(Status::unavailable("Dictionaries are importing..."));
+        }
+        Ok(())
+    }
+
+    /// Runs blocking lookup work off the async executor.
+    async fn blocking<T, F>(&self, f: F) -> Result<T, Status>
+    where
+        T: Send + 'static,
+        F: FnOnce(&ServerState) -> Result<T, Status> + Send + 'static,
+    {
+        let state = self.state.clone();
+        tokio::task::spawn_blocking(move || f(&state))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+    }
+}
+
+fn parse_language(state: &ServerState, value: &str) -> Result<DictionaryLanguage, Status> {
+    if value.trim().is_empty() {
+        return Ok(handlers::resolve_language(&state.app, None));
+    }
+    DictionaryLanguage::from_str(value)
+        .ok_or_else(|| Status::invalid_argument(format!("Unknown language '{value}'")))
+}
+
+fn lookup_one(
+    state: &ServerState,
+    req: proto::LookupRequest,
+) -> Result<proto::LookupResponse, Status> {
+    let language = parse_language(state, &req.language)?;
+    let profile = match req.profile.trim() {
+        "" => None,
+        name => match profiles::get_profile(&state.app, name) {
+            Ok(Some(profile)) => Some(profile),
+            Ok(None) => {
+                return Err(Status::invalid_argument(format!(
+                    "Unknown sorting profile '{name}'"
+                )));
+            }
+            Err(e) => return Err(Status::internal(e.to_string())),
+        },
+    };
+
+    let raw_results = state.lookup.search(
+        &state.app,
+        &req.text,
+        req.index as usize,
+        language.to_deinflect_language(),
+        profile.as_ref(),
+        None,
+    );
+    let results =
+        handlers::build_api_results(&state.app, raw_results, req.group.unwrap_or(true), language);
+
+    Ok(proto::LookupResponse {
+        entries: results.into_iter().map(to_proto_entry).collect(),
+    })
+}
+
+fn to_proto_entry(result: ApiGroupedResult) -> proto::Entry {
+    proto::Entry {
+        headword: result.headword,
+        reading: result.reading,
+        match_len: result.match_len as u32,
+        glossary: result
+            .glossary
+            .into_iter()
+            .map(|d| proto::Definition {
+                dictionary_name: d.dictionary_name,
+                tags: d.tags,
+                content_json: d.content.to_string(),
+            })
+            .collect(),
+        frequencies: result
+            .frequencies
+            .into_iter()
+            .map(|f| proto::Frequency {
+                dictionary_name: f.dictionary_name,
+                value: f.value,
+            })
+            .collect(),
+        furigana: result
+            .furigana
+            .into_iter()
+            .map(|(text, ruby)| proto::FuriganaSegment { text, ruby })
+            .collect(),
+        forms: result
+            .forms
+            .into_iter()
+            .map(|f| proto::Form {
+                headword: f.headword,
+                reading: f.reading,
+            })
+            .collect(),
+        term_tags: result.term_tags.into_iter().map(|t| t.name).collect(),
+        vocab_state: result.vocab_state.as_str().to_string(),
+        span_start: result.span_chars.start as u32,
+        span_end: result.span_chars.end as u32,
+    }
+}
+
+#[tonic::async_trait]
+impl Yomitan for GrpcService {
+    async fn lookup(
+        &self,
+        request: Request<proto::LookupRequest>,
+    ) -> Result<Response<proto::LookupResponse>, Status> {
+        self.ensure_ready()?;
+        let req = request.into_inner();
+        self.blocking(move |state| lookup_one(state, req))
+            .await
+            .map(Response::new)
+    }
+
+    async fn batch_lookup(
+        &self,
+        request: Request<proto::BatchLookupRequest>,
+    ) -> Result<Response<proto::BatchLookupResponse>, Status> {
+        self.ensure_ready()?;
+        let requests = request.into_inner().requests;
+        if requests.len() > MAX_BATCH_SIZE {
+            return Err(Status::invalid_argument(format!(
+                "At most {MAX_BATCH_SIZE} lookups per batch"
+            )));
+        }
+        let responses = self
+            .blocking(move |state| {
+                requests
+                    .into_iter()
+                    .map(|req| lookup_one(state, req))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .await?;
+        Ok(Response::new(proto::BatchLookupResponse { responses }))
+    }
+
+    async fn tokenize(
+        &self,
+        request: Request<proto::TokenizeRequest>,
+    ) -> Result<Response<proto::TokenizeResponse>, Status> {
+        self.ensure_ready()?;
+        let req = request.into_inner();
+        let tokens = self
+            .blocking(move |state| {
+                let language = parse_language(state, &req.language)?;
+                Ok(state
+                    .lookup
+                    .tokenize(&state.app, &req.text, language.to_deinflect_language()))
+            })
+            .await?;
+
+        let tokens = tokens
+            .into_iter()
+            .map(|token| {
+                let (headword, reading) = match token.entry.as_ref().map(|r| &r.entry.term) {
+                    Some(term) => {
+                        let (headword, reading) = crate::lookup::term_parts(term);
+                        (Some(headword), Some(reading).filter(|r| !r.is_empty()))
+                    }
+                    None => (None, None),
+                };
+                proto::Token {
+                    text: token.text,
+                    start: token.start as u32,
+                    end: token.end as u32,
+                    headword,
+                    reading,
+                }
+            })
+            .collect();
+        Ok(Response::new(proto::TokenizeResponse { tokens }))
+    }
+
+    async fn list_dictionaries(
+        &self,
+        _request: Request<proto::ListDictionariesRequest>,
+    ) -> Result<Response<proto::ListDictionariesResponse>, Status> {
+        let dictionaries = self
+            .state
+            .app
+            .sorted_dictionaries()
+            .into_iter()
+            .map(|d| proto::Dictionary {
+                id: d.id.0,
+                name: d.name,
+                priority: d.priority,
+                enabled: d.enabled,
+                term_count: d.term_count,
+            })
+            .collect();
+        Ok(Response::new(proto::ListDictionariesResponse {
+            dictionaries,
+            loading: self.state.app.is_loading(),
+        }))
+    }
+}
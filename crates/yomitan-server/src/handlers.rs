@@ -1,9 +1,26 @@
-use crate::{ServerState, import};
+use crate::{
+    ServerState, anki,
+    audio_sources::{self, AudioSourceConfig},
+    events::DictionaryEvent,
+    frequency, history, import, integrity,
+    jobs::ImportJob,
+    language,
+    lookup::{self, LookupResult},
+    media,
+    openapi::{ApiError, DictionaryList, Health, ImportAccepted, ImportUpload},
+    pitch, profiles,
+    vocab::{self, VocabState},
+};
 use axum::{
     Json,
-    extract::{Multipart, Query, State},
-    http::StatusCode,
+    extract::{
+        Multipart, Path, Query, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
 };
+use base64::Engine;
 use reqwest::Client;
 use regex::Regex;
 use scraper::{Html, Selector};
@@ -11,10 +28,12 @@ use sha2::{Digest, Sha256};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, Value as JsonValue, json};
 use std::collections::HashMap;
-use tracing::{error, info};
-use wordbase_api::{DictionaryId, Record, Term, dict::yomitan::GlossaryTag};
+use tokio::sync::broadcast;
+use utoipa::{IntoParams, ToSchema};
+use tracing::{error, info, warn};
+use wordbase_api::{DictionaryId, Record, Span, Term, dict::yomitan::GlossaryTag};
 
-use crate::state::AppState;
+use crate::state::{AppState, Readiness};
 
 #[cfg(target_os = "ios")]
 unsafe extern "C" {
@@ -22,16 +41,24 @@ unsafe extern "C" {
     fn malloc_zone_pressure_relief(zone: *mut std::ffi::c_void, goal: usize);
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct LookupParams {
     pub text: String,
+    /// Character offset of the cursor within `text`.
     pub index: Option<usize>,
     // Optional toggle for grouping results (defaults to true in handler)
     pub group: Option<bool>,
+    #[serde(alias = "lang")]
     pub language: Option<DictionaryLanguage>,
+    /// Name of a sorting profile whose frequency dictionary orders the results.
+    pub profile: Option<String>,
+    /// Comma-separated dictionary ids (`1,3,5`) to restrict results to, without touching the
+    /// global enabled flags. Disabled dictionaries stay excluded.
+    pub dicts: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Copy, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum AudioSource {
     Jpod101,
@@ -55,40 +82,77 @@ pub struct AudioResponse {
     pub url: Option<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiForm {
     pub headword: String,
     pub reading: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiDefinition {
     pub dictionary_name: String,
     pub tags: Vec<String>,
+    #[schema(value_type = Object)]
     pub content: JsonValue,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Clone, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiFrequency {
     pub dictionary_name: String,
     pub value: String,
 }
 
-#[derive(Serialize)]
+/// Pitch accent of one reading, from a pitch-accent dictionary.
+#[derive(Serialize, Clone, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPitch {
+    pub dictionary_name: String,
+    /// Mora after which the pitch drops; `0` for heiban. Render with `GET /pitch-graph`.
+    pub position: usize,
+}
+
+#[derive(Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiGroupedResult {
     pub headword: String,
     pub reading: String,
+    /// `[text, ruby]` pairs; ruby is empty for kana segments.
+    #[schema(value_type = Vec<Vec<String>>)]
     pub furigana: Vec<(String, String)>,
     pub glossary: Vec<ApiDefinition>,
     pub frequencies: Vec<ApiFrequency>,
+    pub pitches: Vec<ApiPitch>,
     pub forms: Vec<ApiForm>,
+    #[schema(value_type = Vec<Object>)]
     pub term_tags: Vec<GlossaryTag>,
     // ADDED: Return the length of the match so the frontend can highlight it
     pub match_len: usize,
+    /// Matched region of the request `text`, in characters.
+    pub span_chars: ApiSpan,
+    /// Matched region of the request `text`, in UTF-8 bytes.
+    pub span_bytes: ApiSpan,
+    pub vocab_state: VocabState,
+    /// How the best entry of this result matched the text.
+    pub match_info: lookup::MatchInfo,
+}
+
+/// Half-open `[start, end)` range within the looked-up text.
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq, ToSchema)]
+pub struct ApiSpan {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl From<&Span> for ApiSpan {
+    fn from(span: &Span) -> Self {
+        Self {
+            start: span.start,
+            end: span.end,
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -99,12 +163,16 @@ pub enum DictionaryAction {
     Reorder { order: Vec<i64> },
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum DictionaryLanguage {
+    #[serde(alias = "ja")]
     Japanese,
+    #[serde(alias = "en")]
     English,
+    #[serde(alias = "zh")]
     Chinese,
+    #[serde(alias = "ko")]
     Korean,
     Arabic,
     Spanish,
@@ -143,6 +211,7 @@ pub enum DictionaryLanguage {
     Ukrainian,
     Vietnamese,
     Welsh,
+    #[serde(alias = "yue")]
     Cantonese,
 }
 
@@ -194,7 +263,7 @@ impl DictionaryLanguage {
         }
     }
 
-    fn to_deinflect_language(&self) -> crate::deinflector::Language {
+    pub(crate) fn to_deinflect_language(&self) -> crate::deinflector::Language {
         match self {
             DictionaryLanguage::Japanese => crate::deinflector::Language::Japanese,
             DictionaryLanguage::English => crate::deinflector::Language::English,
@@ -241,12 +310,12 @@ impl DictionaryLanguage {
         }
     }
 
-    fn from_str(value: &str) -> Option<Self> {
+    pub(crate) fn from_str(value: &str) -> Option<Self> {
         match value.trim().to_lowercase().as_str() {
-            "japanese" => Some(DictionaryLanguage::Japanese),
-            "english" => Some(DictionaryLanguage::English),
-            "chinese" => Some(DictionaryLanguage::Chinese),
-            "korean" => Some(DictionaryLanguage::Korean),
+            "japanese" | "ja" => Some(DictionaryLanguage::Japanese),
+            "english" | "en" => Some(DictionaryLanguage::English),
+            "chinese" | "zh" => Some(DictionaryLanguage::Chinese),
+            "korean" | "ko" => Some(DictionaryLanguage::Korean),
             "arabic" => Some(DictionaryLanguage::Arabic),
             "spanish" => Some(DictionaryLanguage::Spanish),
             "french" => Some(DictionaryLanguage::French),
@@ -284,7 +353,7 @@ impl DictionaryLanguage {
             "ukrainian" => Some(DictionaryLanguage::Ukrainian),
             "vietnamese" => Some(DictionaryLanguage::Vietnamese),
             "welsh" => Some(DictionaryLanguage::Welsh),
-            "cantonese" => Some(DictionaryLanguage::Cantonese),
+            "cantonese" | "yue" => Some(DictionaryLanguage::Cantonese),
             _ => None,
         }
     }
@@ -665,9 +734,10 @@ async fn fetch_jisho_audio_url(client: &Client, term: &str, reading: &str) -> Re
 }
 
 pub async fn audio_handler(
+    State(state): State<ServerState>,
     Query(params): Query<AudioParams>,
 ) -> Result<Json<AudioResponse>, (StatusCode, Json<Value>)> {
-    let client = Client::new();
+    let client = state.app.http.clone();
     let term = params.term.trim();
     let reading = params.reading.as_deref().unwrap_or("").trim();
 
@@ -678,15 +748,7 @@ pub async fn audio_handler(
     let language = params.language.unwrap_or(DictionaryLanguage::Japanese);
     let summary = get_audio_language_summary(language);
 
-    let result = match params.source {
-        AudioSource::Jpod101 => fetch_jpod101_audio_url(&client, term, reading).await,
-        AudioSource::LanguagePod101 => fetch_language_pod101_urls(&client, term, reading, &summary)
-            .await
-            .map(|urls| urls.into_iter().next()),
-        AudioSource::Jisho => fetch_jisho_audio_url(&client, term, reading).await,
-        AudioSource::LinguaLibre => fetch_lingua_libre_audio_url(&client, term, &summary).await,
-        AudioSource::Wiktionary => fetch_wiktionary_audio_url(&client, term, &summary).await,
-    };
+    let result = fetch_builtin_audio_url(&client, params.source, term, reading, &summary).await;
 
     match result {
         Ok(url) => Ok(Json(AudioResponse { url })),
@@ -700,6 +762,216 @@ pub async fn audio_handler(
     }
 }
 
+async fn fetch_builtin_audio_url(
+    client: &Client,
+    source: AudioSource,
+    term: &str,
+    reading: &str,
+    summary: &AudioLanguageSummary,
+) -> Result<Option<String>, anyhow::Error> {
+    match source {
+        AudioSource::Jpod101 => fetch_jpod101_audio_url(client, term, reading).await,
+        AudioSource::LanguagePod101 => fetch_language_pod101_urls(client, term, reading, summary)
+            .await
+            .map(|urls| urls.into_iter().next()),
+        AudioSource::Jisho => fetch_jisho_audio_url(client, term, reading).await,
+        AudioSource::LinguaLibre => fetch_lingua_libre_audio_url(client, term, summary).await,
+        AudioSource::Wiktionary => fetch_wiktionary_audio_url(client, term, summary).await,
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AudioUriParams {
+    pub term: String,
+    pub reading: Option<String>,
+    pub language: Option<DictionaryLanguage>,
+    /// Return the audio bytes instead of a URL.
+    #[serde(default)]
+    pub proxy: bool,
+}
+
+enum ResolvedAudio {
+    Remote(String),
+    /// A remote source fetched while probing it for `proxy=true`, streamed back as is.
+    Fetched(reqwest::Response),
+    Local(std::path::PathBuf),
+}
+
+/// Fetches `url`, returning the response only when it succeeded.
+async fn fetch_audio(client: &Client, url: &str) -> reqwest::Result<Option<reqwest::Response>> {
+    let response = client
+        .get(url)
+        .header("User-Agent", "Mozilla/5.0")
+        .send()
+        .await?;
+    Ok(response.status().is_success().then_some(response))
+}
+
+/// Checks that `url` serves something without downloading it: a `HEAD`, or for servers that
+/// refuse those, a `GET` of the first byte.
+async fn probe_audio_url(client: &Client, url: &str) -> reqwest::Result<bool> {
+    let head = client
+        .head(url)
+        .header("User-Agent", "Mozilla/5.0")
+        .send()
+        .await;
+    match head {
+        Ok(response) if response.status().is_success() => return Ok(true),
+        Ok(response)
+            if matches!(response.status(), StatusCode::NOT_FOUND | StatusCode::GONE) =>
+        {
+            return Ok(false);
+        }
+        _ => {}
+    }
+    let response = client
+        .get(url)
+        .header("User-Agent", "Mozilla/5.0")
+        .header(header::RANGE, "bytes=0-0")
+        .send()
+        .await?;
+    Ok(response.status().is_success())
+}
+
+/// Streams a fetched audio response back to the caller.
+fn proxied_audio(response: reqwest::Response) -> Response {
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("audio/mpeg")
+        .to_string();
+    (
+        [(header::CONTENT_TYPE, content_type)],
+        axum::body::Body::from_stream(response.bytes_stream()),
+    )
+        .into_response()
+}
+
+/// Walks the configured audio sources in order and returns the first playable result. Remote
+/// sources yield a URL; local files are returned inline as a `data:` URI (or as raw bytes when
+/// `proxy=true`).
+pub async fn audio_uri_handler(
+    State(state): State<ServerState>,
+    Query(params): Query<AudioUriParams>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let term = params.term.trim();
+    let reading = params.reading.as_deref().unwrap_or("").trim();
+    if term.is_empty() {
+        return Ok(Json(json!({ "url": null, "source": null })).into_response());
+    }
+
+    let language = resolve_language(&state.app, params.language);
+    let summary = get_audio_language_summary(language);
+    let client = state.app.http.clone();
+    let sources = audio_sources::load_sources(&state.app);
+
+    let mut resolved = None;
+    for (index, source) in sources.iter().enumerate() {
+        let attempt = match source {
+            AudioSourceConfig::Builtin { source } => {
+                fetch_builtin_audio_url(&client, *source, term, reading, &summary)
+                    .await
+                    .map(|url| url.map(ResolvedAudio::Remote))
+            }
+            AudioSourceConfig::UrlPattern { url } => {
+                let candidate =
+                    audio_sources::expand_url_pattern(url, term, reading, language.as_str());
+                if params.proxy {
+                    fetch_audio(&client, &candidate)
+                        .await
+                        .map(|response| response.map(ResolvedAudio::Fetched))
+                        .map_err(Into::into)
+                } else {
+                    probe_audio_url(&client, &candidate)
+                        .await
+                        .map(|found| found.then_some(ResolvedAudio::Remote(candidate)))
+                        .map_err(Into::into)
+                }
+            }
+            AudioSourceConfig::LocalDirectory { path } => Ok(audio_sources::find_local_audio(
+                &state.app,
+                path.as_deref(),
+                term,
+                reading,
+            )
+            .map(ResolvedAudio::Local)),
+        };
+
+        match attempt {
+            Ok(Some(found)) => {
+                resolved = Some((index, found));
+                break;
+            }
+            Ok(None) => {}
+            Err(e) => error!("Audio source #{} failed: {}", index, e),
+        }
+    }
+
+    let Some((source_index, found)) = resolved else {
+        return Ok(Json(json!({ "url": null, "source": null })).into_response());
+    };
+
+    let internal_error = |message: String| {
+        (
+            StatusCode::BAD_GATEWAY,
+            Json(json!({ "status": "error", "message": message })),
+        )
+    };
+
+    match found {
+        ResolvedAudio::Fetched(response) => Ok(proxied_audio(response)),
+        ResolvedAudio::Remote(url) if params.proxy => {
+            match fetch_audio(&client, &url).await {
+                Ok(Some(response)) => Ok(proxied_audio(response)),
+                Ok(None) => Err(internal_error(format!("{url} is no longer available"))),
+                Err(e) => Err(internal_error(e.to_string())),
+            }
+        }
+        ResolvedAudio::Remote(url) => {
+            Ok(Json(json!({ "url": url, "source": source_index })).into_response())
+        }
+        ResolvedAudio::Local(path) => {
+            let content_type = audio_sources::content_type_for(&path);
+            let bytes = tokio::fs::read(&path)
+                .await
+                .map_err(|e| internal_error(e.to_string()))?;
+            if params.proxy {
+                Ok(([(header::CONTENT_TYPE, content_type)], bytes).into_response())
+            } else {
+                let data_uri = format!(
+                    "data:{};base64,{}",
+                    content_type,
+                    base64::engine::general_purpose::STANDARD.encode(&bytes)
+                );
+                Ok(Json(json!({ "url": data_uri, "source": source_index })).into_response())
+            }
+        }
+    }
+}
+
+pub async fn get_audio_sources_handler(
+    State(state): State<ServerState>,
+) -> Json<Vec<AudioSourceConfig>> {
+    Json(audio_sources::load_sources(&state.app))
+}
+
+pub async fn set_audio_sources_handler(
+    State(state): State<ServerState>,
+    Json(sources): Json<Vec<AudioSourceConfig>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if let Err(message) = audio_sources::validate_sources(&state.app, &sources) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "status": "error", "message": message })),
+        ));
+    }
+    match audio_sources::store_sources(&state.app, &sources) {
+        Ok(()) => Ok(Json(json!({ "status": "ok" }))),
+        Err(e) => Ok(Json(json!({ "status": "error", "message": e.to_string() }))),
+    }
+}
+
 impl std::fmt::Display for DictionaryLanguage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.as_str())
@@ -731,7 +1003,7 @@ fn store_preferred_language(app_state: &AppState, language: DictionaryLanguage)
     }
 }
 
-fn resolve_language(
+pub(crate) fn resolve_language(
     app_state: &AppState,
     language: Option<DictionaryLanguage>,
 ) -> DictionaryLanguage {
@@ -871,9 +1143,11 @@ fn dictionary_url(language: DictionaryLanguage) -> &'static str {
     }
 }
 
-async fn download_dictionary_bytes(language: DictionaryLanguage) -> Result<Vec<u8>, String> {
+async fn download_dictionary_bytes(
+    client: &Client,
+    language: DictionaryLanguage,
+) -> Result<Vec<u8>, String> {
     let url = dictionary_url(language);
-    let client = Client::new();
     let response = client
         .get(url)
         .send()
@@ -905,20 +1179,71 @@ fn clear_dictionary_state(app_state: &AppState) {
     if let Ok(mut conn) = app_state.pool.get() {
         if let Ok(tx) = conn.transaction() {
             let _ = tx.execute("DELETE FROM terms", []);
+            let _ = tx.execute("DELETE FROM glosses_fts", []);
+            let _ = tx.execute("DELETE FROM sorting_profiles", []);
             let _ = tx.execute("DELETE FROM dictionaries", []);
             let _ = tx.execute("DELETE FROM metadata", []);
             let _ = tx.commit();
         }
+        let _ = std::fs::remove_dir_all(media::media_root(&app_state.data_dir));
+        app_state.emit(DictionaryEvent::DictionariesCleared);
         info!("🧹 [Yomitan] Vacuuming after reset...");
         let _ = conn.execute("VACUUM", []);
     }
 }
 
+/// JMdict baked into the binary at build time from the zip named by `MANATAN_PREBAKED_JMDICT`.
+#[cfg(feature = "prebaked-jmdict")]
+static PREBAKED_JMDICT: &[u8] = include_bytes!(env!("MANATAN_PREBAKED_JMDICT"));
+
+/// Where the default Japanese dictionary comes from.
+enum JmdictSource {
+    /// Skip it; the user imports their own (e.g. monolingual) dictionaries.
+    Disabled,
+    File(std::path::PathBuf),
+    /// The prebaked copy if built with `prebaked-jmdict`, otherwise a download.
+    Bundled,
+}
+
+impl JmdictSource {
+    /// Reads `MANATAN_DEFAULT_JMDICT`: `none`/`off` to skip, or a path to a JMdict zip.
+    fn from_env() -> Self {
+        match std::env::var("MANATAN_DEFAULT_JMDICT") {
+            Ok(value) => match value.trim() {
+                "" => JmdictSource::Bundled,
+                v if matches!(v.to_lowercase().as_str(), "none" | "off" | "false" | "0") => {
+                    JmdictSource::Disabled
+                }
+                path => JmdictSource::File(path.into()),
+            },
+            Err(_) => JmdictSource::Bundled,
+        }
+    }
+}
+
 pub async fn install_language_internal(
     app_state: AppState,
     language: DictionaryLanguage,
 ) -> Result<String, String> {
-    let dict_bytes = download_dictionary_bytes(language).await?;
+    let dict_bytes = match language {
+        DictionaryLanguage::Japanese => match JmdictSource::from_env() {
+            JmdictSource::Disabled => {
+                info!("⏭️ [Yomitan] Default JMdict disabled, skipping install");
+                return Ok("Default dictionary disabled; import dictionaries manually.".to_string());
+            }
+            JmdictSource::File(path) => {
+                info!("📂 [Yomitan] Installing JMdict from {}", path.display());
+                tokio::fs::read(&path)
+                    .await
+                    .map_err(|e| format!("Failed to read {}: {e}", path.display()))?
+            }
+            #[cfg(feature = "prebaked-jmdict")]
+            JmdictSource::Bundled => PREBAKED_JMDICT.to_vec(),
+            #[cfg(not(feature = "prebaked-jmdict"))]
+            JmdictSource::Bundled => download_dictionary_bytes(&app_state.http, language).await?,
+        },
+        _ => download_dictionary_bytes(&app_state.http, language).await?,
+    };
     let app_state_for_task = app_state.clone();
     let res =
         tokio::task::spawn_blocking(move || import::import_zip(&app_state_for_task, &dict_bytes))
@@ -932,10 +1257,18 @@ pub async fn manage_dictionaries_handler(
     Json(action): Json<DictionaryAction>,
 ) -> Json<Value> {
     let app_state = state.app.clone();
+    let readiness = app_state.readiness();
+    if readiness != Readiness::Ready {
+        return not_ready_response(readiness);
+    }
 
     let res = tokio::task::spawn_blocking(move || -> Result<(), String> {
         let mut conn = app_state.pool.get().map_err(|e| e.to_string())?;
         let mut should_vacuum = false;
+        let deleted = match &action {
+            DictionaryAction::Delete { id } => Some(DictionaryId(*id)),
+            _ => None,
+        };
 
         {
             let tx = conn.transaction().map_err(|e| e.to_string())?;
@@ -960,14 +1293,29 @@ pub async fn manage_dictionaries_handler(
                         rusqlite::params![id],
                     )
                     .map_err(|e| e.to_string())?;
+                    tx.execute(
+                        "DELETE FROM glosses_fts WHERE dictionary_id = ?",
+                        rusqlite::params![id],
+                    )
+                    .map_err(|e| e.to_string())?;
+                    tx.execute(
+                        "DELETE FROM sorting_profiles WHERE dictionary_id = ?",
+                        rusqlite::params![id],
+                    )
+                    .map_err(|e| e.to_string())?;
                     tx.execute(
                         "DELETE FROM dictionaries WHERE id = ?",
                         rusqlite::params![id],
                     )
                     .map_err(|e| e.to_string())?;
+                    crate::state::delete_term_count(&tx, DictionaryId(id))
+                        .map_err(|e| e.to_string())?;
+                    integrity::delete_checksum(&tx, DictionaryId(id))
+                        .map_err(|e| e.to_string())?;
 
                     let mut dicts = app_state.dictionaries.write().expect("lock");
                     dicts.remove(&DictionaryId(id));
+                    media::remove_dictionary(&app_state.data_dir, DictionaryId(id));
                     should_vacuum = true;
                 }
                 DictionaryAction::Reorder { order } => {
@@ -991,6 +1339,13 @@ pub async fn manage_dictionaries_handler(
             tx.commit().map_err(|e| e.to_string())?;
         }
 
+        app_state.emit(match deleted {
+            Some(id) => DictionaryEvent::DictionaryDeleted { id },
+            None => DictionaryEvent::DictionariesUpdated {
+                dictionaries: app_state.sorted_dictionaries(),
+            },
+        });
+
         if should_vacuum {
             info!("🧹 [Yomitan] Vacuuming database to reclaim disk space...");
             conn.execute("VACUUM", []).map_err(|e| e.to_string())?;
@@ -1008,6 +1363,15 @@ pub async fn manage_dictionaries_handler(
     }
 }
 
+/// Reply to a dictionary change attempted during startup or while an install is running.
+fn not_ready_response(readiness: Readiness) -> Json<Value> {
+    let (code, message) = match readiness {
+        Readiness::Initializing => ("initializing", "The dictionary database is starting up."),
+        _ => ("busy", "Dictionaries are importing..."),
+    };
+    Json(json!({ "status": "error", "error": code, "message": message }))
+}
+
 pub async fn unload_handler(State(state): State<ServerState>) -> Json<Value> {
     info!("♻️ [Memory] Unload requested...");
 
@@ -1049,11 +1413,13 @@ pub async fn install_defaults_handler(
     }
 
     info!("📥 [Yomitan] User requested dictionary install ({language})...");
-    app_state.set_loading(true);
+    if let Err(readiness) = app_state.begin_import() {
+        return not_ready_response(readiness);
+    }
 
     let res = install_language_internal(app_state.clone(), language).await;
 
-    app_state.set_loading(false);
+    app_state.set_readiness(Readiness::Ready);
 
     match res {
         Ok(msg) => {
@@ -1083,11 +1449,13 @@ pub async fn install_language_handler(
     }
 
     info!("📥 [Yomitan] Installing dictionary ({language})...");
-    app_state.set_loading(true);
+    if let Err(readiness) = app_state.begin_import() {
+        return not_ready_response(readiness);
+    }
 
     let res = install_language_internal(app_state.clone(), language).await;
 
-    app_state.set_loading(false);
+    app_state.set_readiness(Readiness::Ready);
 
     match res {
         Ok(msg) => {
@@ -1107,8 +1475,10 @@ pub async fn reset_db_handler(
 ) -> Json<Value> {
     let app_state = state.app.clone();
     let language = resolve_language(&app_state, payload.and_then(|val| val.0.language));
+    if let Err(readiness) = state.app.begin_import() {
+        return not_ready_response(readiness);
+    }
     info!("🧨 [Yomitan] Resetting Database ({language})...");
-    state.app.set_loading(true);
 
     let clear_state = state.app.clone();
     let clear_res = tokio::task::spawn_blocking(move || {
@@ -1117,13 +1487,13 @@ pub async fn reset_db_handler(
     .await;
 
     if let Err(e) = clear_res {
-        state.app.set_loading(false);
+        state.app.set_readiness(Readiness::Ready);
         error!("❌ [Reset] Failed to clear database: {}", e);
         return Json(json!({ "status": "error", "message": e.to_string() }));
     }
 
     let res = install_language_internal(app_state.clone(), language).await;
-    state.app.set_loading(false);
+    state.app.set_readiness(Readiness::Ready);
 
     match res {
         Ok(_) => {
@@ -1137,6 +1507,17 @@ pub async fn reset_db_handler(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/lookup",
+    tag = "yomitan",
+    params(LookupParams),
+    responses(
+        (status = 200, description = "Dictionary entries matching text at the cursor", body = Vec<ApiGroupedResult>),
+        (status = 400, description = "Unknown sorting profile or malformed dictionary filter", body = ApiError),
+        (status = 503, description = "Dictionaries are still importing", body = ApiError),
+    )
+)]
 pub async fn lookup_handler(
     State(state): State<ServerState>,
     Query(params): Query<LookupParams>,
@@ -1156,148 +1537,594 @@ pub async fn lookup_handler(
         ));
     }
 
-    let raw_results = state
-        .lookup
-        .search(&state.app, &params.text, cursor_idx, language.to_deinflect_language());
+    let profile = resolve_profile(&state.app, params.profile.as_deref())?;
+    let dict_filter = params
+        .dicts
+        .as_deref()
+        .map(parse_dictionary_filter)
+        .transpose()?;
+
+    let raw_results = state.lookup.search(
+        &state.app,
+        &params.text,
+        cursor_idx,
+        language.to_deinflect_language(),
+        profile.as_ref(),
+        dict_filter.as_ref(),
+    );
 
-    let dict_meta: std::collections::HashMap<DictionaryId, String> = {
-        let dicts = state.app.dictionaries.read().expect("lock");
-        dicts.iter().map(|(k, v)| (*k, v.name.clone())).collect()
-    };
+    let results = build_api_results(&state.app, raw_results, should_group, language);
 
-    struct Aggregator {
-        headword: String,
-        reading: String,
-        term_tags: Vec<GlossaryTag>,
-        furigana: Vec<(String, String)>,
-        glossary: Vec<ApiDefinition>,
-        frequencies: Vec<ApiFrequency>,
-        forms_set: Vec<(String, String)>,
-        match_len: usize, // Added to aggregator
+    if let Some(top) = results.first() {
+        state
+            .history
+            .record(&state.app, &top.headword, &top.reading, &params.text);
     }
 
-    let mut map: Vec<Aggregator> = Vec::new();
+    Ok(Json(results))
+}
 
-    let mut freq_map: HashMap<(String, String), Vec<ApiFrequency>> = HashMap::new();
+fn parse_dictionary_filter(
+    value: &str,
+) -> Result<std::collections::HashSet<DictionaryId>, (StatusCode, Json<Value>)> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            id.parse::<i64>().map(DictionaryId).map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({
+                        "error": "invalid_dicts",
+                        "message": format!("Invalid dictionary id '{id}'"),
+                    })),
+                )
+            })
+        })
+        .collect()
+}
 
-    let mut flat_results: Vec<ApiGroupedResult> = Vec::new();
+fn resolve_profile(
+    app_state: &AppState,
+    name: Option<&str>,
+) -> Result<Option<profiles::SortingProfile>, (StatusCode, Json<Value>)> {
+    match name.map(str::trim) {
+        Some(name) if !name.is_empty() => match profiles::get_profile(app_state, name) {
+            Ok(Some(profile)) => Ok(Some(profile)),
+            Ok(None) => Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": "unknown_profile",
+                    "message": format!("Unknown sorting profile '{name}'"),
+                })),
+            )),
+            Err(e) => Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "status": "error", "message": e.to_string() })),
+            )),
+        },
+        _ => Ok(None),
+    }
+}
 
-    for entry in raw_results {
-        let (headword, reading) = match &entry.0.term {
-            Term::Full(h, r) => (h.to_string(), r.to_string()),
-            Term::Headword(h) => (h.to_string(), "".to_string()),
-            Term::Reading(r) => (r.to_string(), "".to_string()),
-        };
+/// Lookups accepted in one `/lookup/batch` request.
+const MAX_BATCH_LOOKUPS: usize = 500;
 
-        if headword.is_empty() {
-            continue;
-        }
+#[derive(Deserialize, ToSchema)]
+pub struct BatchLookupItem {
+    pub text: String,
+    pub index: Option<usize>,
+}
 
-        let match_len = entry.0.span_chars.end as usize;
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BatchLookupParams {
+    pub group: Option<bool>,
+    #[serde(alias = "lang")]
+    pub language: Option<DictionaryLanguage>,
+    pub profile: Option<String>,
+}
 
-        let mut is_freq = false;
+/// Runs many lookups on one connection; results are returned in request order.
+#[utoipa::path(
+    post,
+    path = "/lookup/batch",
+    tag = "yomitan",
+    params(BatchLookupParams),
+    request_body = Vec<BatchLookupItem>,
+    responses(
+        (status = 200, description = "One result list per requested lookup", body = Vec<Vec<ApiGroupedResult>>),
+        (status = 400, description = "Too many lookups or unknown sorting profile", body = ApiError),
+        (status = 503, description = "Dictionaries are still importing", body = ApiError),
+    )
+)]
+pub async fn batch_lookup_handler(
+    State(state): State<ServerState>,
+    Query(params): Query<BatchLookupParams>,
+    Json(items): Json<Vec<BatchLookupItem>>,
+) -> Result<Json<Vec<Vec<ApiGroupedResult>>>, (StatusCode, Json<Value>)> {
+    if items.len() > MAX_BATCH_LOOKUPS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": "batch_too_large",
+                "message": format!("At most {MAX_BATCH_LOOKUPS} lookups per batch"),
+            })),
+        ));
+    }
+    if state.app.is_loading() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "error": "loading", "message": "Dictionaries are importing..." })),
+        ));
+    }
 
-        let (content_val, tags) = if let Record::YomitanGlossary(gloss) = &entry.0.record {
-            use wordbase_api::dict::yomitan::structured::Content;
-            if let Some(Content::String(s)) = gloss.content.first() {
-                is_freq = s.starts_with("Frequency: ");
-            }
-            // Simply extract the name field as a string
-            let t: Vec<String> = gloss.tags.iter().map(|tag| tag.name.clone()).collect();
-            (json!(gloss.content), t)
-        } else {
-            (json!(entry.0.record), vec![])
-        };
+    let language = resolve_language(&state.app, params.language);
+    let should_group = params.group.unwrap_or(true);
+    let profile = resolve_profile(&state.app, params.profile.as_deref())?;
+
+    let res = tokio::task::spawn_blocking(move || -> Result<_, String> {
+        let conn = state.app.pool.get().map_err(|e| e.to_string())?;
+        Ok(items
+            .iter()
+            .map(|item| {
+                let raw_results = state.lookup.search_with_conn(
+                    &state.app,
+                    &conn,
+                    &item.text,
+                    item.index.unwrap_or(0),
+                    language.to_deinflect_language(),
+                    profile.as_ref(),
+                    None,
+                );
+                build_api_results(&state.app, raw_results, should_group, language)
+            })
+            .collect())
+    })
+    .await;
 
-        let dict_name = dict_meta
-            .get(&entry.0.source)
-            .cloned()
-            .unwrap_or("Unknown".to_string());
+    match res {
+        Ok(Ok(results)) => Ok(Json(results)),
+        Ok(Err(e)) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "status": "error", "message": e })),
+        )),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "status": "error", "message": e.to_string() })),
+        )),
+    }
+}
 
-        if is_freq {
-            let mut val_str = "Unknown".to_string();
-            if let Some(arr) = content_val.as_array() {
-                if let Some(first) = arr.get(0) {
-                    let raw = first.as_str().unwrap_or("");
-                    val_str = raw.replace("Frequency: ", "").trim().to_string();
-                    if raw.is_empty() {
-                        if let Some(obj) = first.get("content") {
-                            if let Some(s) = obj.as_str() {
-                                val_str = s.replace("Frequency: ", "").trim().to_string();
-                            }
-                        }
-                    }
-                }
-            }
+/// Longest text accepted by `/lookup/sweep`, in characters.
+const MAX_SWEEP_CHARS: usize = 2000;
 
-            let freq_obj = ApiFrequency {
-                dictionary_name: dict_name,
-                value: val_str,
-            };
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SweepParams {
+    pub text: String,
+    #[serde(alias = "lang")]
+    pub language: Option<DictionaryLanguage>,
+}
 
-            // Store in map instead of pushing to results immediately.
-            freq_map
-                .entry((headword.clone(), reading.clone()))
-                .or_default()
-                .push(freq_obj);
-        } else {
-            // === DEFINITION LOGIC ===
-            let def_obj = ApiDefinition {
-                dictionary_name: dict_name,
-                tags,
-                content: content_val,
-            };
+/// Segments the whole text into consecutive longest matches, e.g. to underline every known word
+/// of an OCR block at once. Each result carries only the best entry for its span; unmatched
+/// characters are omitted.
+#[utoipa::path(
+    get,
+    path = "/lookup/sweep",
+    tag = "yomitan",
+    params(SweepParams),
+    responses(
+        (status = 200, description = "Matches in text order with their spans", body = Vec<ApiGroupedResult>),
+        (status = 400, description = "Text too long", body = ApiError),
+        (status = 503, description = "Dictionaries are still importing", body = ApiError),
+    )
+)]
+pub async fn sweep_lookup_handler(
+    State(state): State<ServerState>,
+    Query(params): Query<SweepParams>,
+) -> Result<Json<Vec<ApiGroupedResult>>, (StatusCode, Json<Value>)> {
+    if params.text.chars().count() > MAX_SWEEP_CHARS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": "text_too_long",
+                "message": format!("Sweep text is limited to {MAX_SWEEP_CHARS} characters"),
+            })),
+        ));
+    }
+    if state.app.is_loading() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "error": "loading", "message": "Dictionaries are importing..." })),
+        ));
+    }
 
-            if should_group {
-                if let Some(existing) = map
-                    .iter_mut()
-                    .find(|agg| agg.headword == headword && agg.reading == reading)
-                {
-                    let is_dup = existing.glossary.iter().any(|d| {
-                        d.dictionary_name == def_obj.dictionary_name
-                            && d.content.to_string() == def_obj.content.to_string()
-                    });
-                    if !is_dup {
-                        existing.glossary.push(def_obj);
-                    }
-                } else {
+    let language = resolve_language(&state.app, params.language);
+    let res = tokio::task::spawn_blocking(move || {
+        let tokens =
+            state
+                .lookup
+                .tokenize(&state.app, &params.text, language.to_deinflect_language());
+        let matches: Vec<LookupResult> = tokens.into_iter().filter_map(|t| t.entry).collect();
+        build_api_results(&state.app, matches, false, language)
+    })
+    .await;
+
+    res.map(Json).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "status": "error", "message": e.to_string() })),
+        )
+    })
+}
+
+#[derive(Deserialize)]
+pub struct HistoryParams {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+pub async fn history_handler(
+    State(state): State<ServerState>,
+    Query(params): Query<HistoryParams>,
+) -> Result<Json<Vec<history::HistoryEntry>>, (StatusCode, Json<Value>)> {
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    let offset = params.offset.unwrap_or(0).max(0);
+    history::list_history(&state.app, limit, offset)
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "status": "error", "message": e.to_string() })),
+            )
+        })
+}
+
+#[derive(Deserialize)]
+pub struct HistoryStatsParams {
+    pub top: Option<i64>,
+    pub days: Option<i64>,
+}
+
+pub async fn history_stats_handler(
+    State(state): State<ServerState>,
+    Query(params): Query<HistoryStatsParams>,
+) -> Result<Json<history::HistoryStats>, (StatusCode, Json<Value>)> {
+    let top = params.top.unwrap_or(50).clamp(1, 1000);
+    let days = params.days.unwrap_or(30).clamp(1, 3650);
+    history::history_stats(&state.app, top, days)
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "status": "error", "message": e.to_string() })),
+            )
+        })
+}
+
+#[derive(Deserialize)]
+pub struct VocabUpdate {
+    pub term: String,
+    #[serde(default)]
+    pub reading: String,
+    pub state: VocabState,
+}
+
+pub async fn update_vocab_handler(
+    State(state): State<ServerState>,
+    Json(update): Json<VocabUpdate>,
+) -> Json<Value> {
+    let term = update.term.trim();
+    if term.is_empty() {
+        return Json(json!({ "status": "error", "message": "Term must not be empty" }));
+    }
+    match vocab::set_state(&state.app, term, update.reading.trim(), update.state) {
+        Ok(()) => Json(json!({ "status": "ok" })),
+        Err(e) => Json(json!({ "status": "error", "message": e.to_string() })),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct VocabListParams {
+    pub state: Option<VocabState>,
+}
+
+pub async fn list_vocab_handler(
+    State(state): State<ServerState>,
+    Query(params): Query<VocabListParams>,
+) -> Result<Json<Vec<vocab::VocabEntry>>, (StatusCode, Json<Value>)> {
+    vocab::list_entries(&state.app, params.state)
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "status": "error", "message": e.to_string() })),
+            )
+        })
+}
+
+pub async fn list_profiles_handler(
+    State(state): State<ServerState>,
+) -> Result<Json<Vec<profiles::SortingProfile>>, (StatusCode, Json<Value>)> {
+    profiles::list_profiles(&state.app).map(Json).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "status": "error", "message": e.to_string() })),
+        )
+    })
+}
+
+pub async fn save_profile_handler(
+    State(state): State<ServerState>,
+    Json(profile): Json<profiles::SortingProfile>,
+) -> Json<Value> {
+    match profiles::save_profile(&state.app, &profile) {
+        Ok(()) => Json(json!({ "status": "ok" })),
+        Err(e) => Json(json!({ "status": "error", "message": e.to_string() })),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DeleteProfileRequest {
+    pub name: String,
+}
+
+pub async fn delete_profile_handler(
+    State(state): State<ServerState>,
+    Json(req): Json<DeleteProfileRequest>,
+) -> Json<Value> {
+    match profiles::delete_profile(&state.app, req.name.trim()) {
+        Ok(()) => Json(json!({ "status": "ok" })),
+        Err(e) => Json(json!({ "status": "error", "message": e.to_string() })),
+    }
+}
+
+pub async fn clear_history_handler(State(state): State<ServerState>) -> Json<Value> {
+    match history::clear_history(&state.app) {
+        Ok(()) => Json(json!({ "status": "ok" })),
+        Err(e) => Json(json!({ "status": "error", "message": e.to_string() })),
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReverseLookupParams {
+    pub text: String,
+    pub limit: Option<usize>,
+    pub group: Option<bool>,
+}
+
+/// English -> Japanese lookup: searches gloss text and groups hits by Japanese headword.
+#[utoipa::path(
+    get,
+    path = "/reverse-lookup",
+    tag = "yomitan",
+    params(ReverseLookupParams),
+    responses(
+        (status = 200, description = "Headwords whose glosses match the query", body = Vec<ApiGroupedResult>),
+        (status = 503, description = "Dictionaries are still importing", body = ApiError),
+    )
+)]
+pub async fn reverse_lookup_handler(
+    State(state): State<ServerState>,
+    Query(params): Query<ReverseLookupParams>,
+) -> Result<Json<Vec<ApiGroupedResult>>, (StatusCode, Json<Value>)> {
+    let limit = params.limit.unwrap_or(20).min(200);
+    let should_group = params.group.unwrap_or(true);
+
+    if state.app.is_loading() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "error": "loading", "message": "Dictionaries are importing..." })),
+        ));
+    }
+
+    let raw_results = state.lookup.reverse_search(&state.app, &params.text, limit);
+    let language = resolve_language(&state.app, None);
+
+    Ok(Json(build_api_results(
+        &state.app,
+        raw_results,
+        should_group,
+        language,
+    )))
+}
+
+pub(crate) fn build_api_results(
+    app_state: &AppState,
+    raw_results: Vec<LookupResult>,
+    should_group: bool,
+    language: DictionaryLanguage,
+) -> Vec<ApiGroupedResult> {
+    let strategy = language::strategy_for(language.to_deinflect_language());
+    let dict_meta: std::collections::HashMap<DictionaryId, String> = {
+        let dicts = app_state.dictionaries.read().expect("lock");
+        dicts.iter().map(|(k, v)| (*k, v.name.clone())).collect()
+    };
+
+    struct Aggregator {
+        headword: String,
+        reading: String,
+        term_tags: Vec<GlossaryTag>,
+        furigana: Vec<(String, String)>,
+        glossary: Vec<ApiDefinition>,
+        frequencies: Vec<ApiFrequency>,
+        pitches: Vec<ApiPitch>,
+        forms_set: Vec<(String, String)>,
+        match_len: usize, // Added to aggregator
+        span_chars: ApiSpan,
+        span_bytes: ApiSpan,
+        sequence_key: Option<(DictionaryId, i64)>,
+        match_info: lookup::MatchInfo,
+    }
+
+    let mut map: Vec<Aggregator> = Vec::new();
+
+    let mut freq_map: HashMap<(String, String), Vec<ApiFrequency>> = HashMap::new();
+    let mut pitch_map: HashMap<(String, String), Vec<ApiPitch>> = HashMap::new();
+
+    let mut flat_results: Vec<ApiGroupedResult> = Vec::new();
+
+    for entry in raw_results {
+        let (headword, reading) = match &entry.entry.term {
+            Term::Full(h, r) => (h.to_string(), r.to_string()),
+            Term::Headword(h) => (h.to_string(), "".to_string()),
+            Term::Reading(r) => (r.to_string(), "".to_string()),
+        };
+
+        if headword.is_empty() {
+            continue;
+        }
+
+        let match_len = lookup::span_len(&entry.entry) as usize;
+        let span_chars = ApiSpan::from(&entry.entry.span_chars);
+        let span_bytes = ApiSpan::from(&entry.entry.span_bytes);
+        let sequence_key = entry.sequence.map(|seq| (entry.entry.source, seq));
+
+        let mut is_freq = false;
+
+        let (content_val, tags) = if let Record::YomitanGlossary(gloss) = &entry.entry.record {
+            use wordbase_api::dict::yomitan::structured::Content;
+            if let Some(Content::String(s)) = gloss.content.first() {
+                is_freq = s.starts_with("Frequency: ");
+            }
+            // Simply extract the name field as a string
+            let t: Vec<String> = gloss.tags.iter().map(|tag| tag.name.clone()).collect();
+            (json!(gloss.content), t)
+        } else {
+            (json!(entry.entry.record), vec![])
+        };
+
+        let dict_name = dict_meta
+            .get(&entry.entry.source)
+            .cloned()
+            .unwrap_or("Unknown".to_string());
+
+        if let Some(positions) = lookup::pitch_positions(&entry.entry.record) {
+            pitch_map
+                .entry((headword.clone(), reading.clone()))
+                .or_default()
+                .extend(positions.into_iter().map(|position| ApiPitch {
+                    dictionary_name: dict_name.clone(),
+                    position,
+                }));
+        } else if is_freq {
+            let mut val_str = "Unknown".to_string();
+            if let Some(arr) = content_val.as_array() {
+                if let Some(first) = arr.get(0) {
+                    let raw = first.as_str().unwrap_or("");
+                    val_str = raw.replace("Frequency: ", "").trim().to_string();
+                    if raw.is_empty() {
+                        if let Some(obj) = first.get("content") {
+                            if let Some(s) = obj.as_str() {
+                                val_str = s.replace("Frequency: ", "").trim().to_string();
+                            }
+                        }
+                    }
+                }
+            }
+
+            let freq_obj = ApiFrequency {
+                dictionary_name: dict_name,
+                value: val_str,
+            };
+
+            // Store in map instead of pushing to results immediately.
+            freq_map
+                .entry((headword.clone(), reading.clone()))
+                .or_default()
+                .push(freq_obj);
+        } else {
+            // === DEFINITION LOGIC ===
+            let def_obj = ApiDefinition {
+                dictionary_name: dict_name,
+                tags,
+                content: content_val,
+            };
+
+            if should_group {
+                // Entries sharing a dictionary sequence number are spellings of one word
+                if let Some(existing) = map.iter_mut().find(|agg| {
+                    (agg.headword == headword && agg.reading == reading)
+                        || (sequence_key.is_some() && agg.sequence_key == sequence_key)
+                }) {
+                    let form = (headword.clone(), reading.clone());
+                    if !existing.forms_set.contains(&form) {
+                        existing.forms_set.push(form);
+                    }
+                    if existing.sequence_key.is_none() {
+                        existing.sequence_key = sequence_key;
+                    }
+                    let is_dup = existing.glossary.iter().any(|d| {
+                        d.dictionary_name == def_obj.dictionary_name
+                            && d.content.to_string() == def_obj.content.to_string()
+                    });
+                    if !is_dup {
+                        existing.glossary.push(def_obj);
+                    }
+                } else {
                     map.push(Aggregator {
                         headword: headword.clone(),
                         reading: reading.clone(),
-                        furigana: calculate_furigana(&headword, &reading),
+                        furigana: strategy.furigana(&headword, &reading),
                         glossary: vec![def_obj],
                         frequencies: vec![], // Will be filled in final pass
-                        term_tags: entry.1.unwrap_or_default(),
+                        pitches: vec![],
+                        term_tags: entry.term_tags.unwrap_or_default(),
                         forms_set: vec![(headword.clone(), reading.clone())],
                         match_len,
+                        span_chars,
+                        span_bytes,
+                        sequence_key,
+                        match_info: entry.match_info,
                     });
                 }
             } else {
                 flat_results.push(ApiGroupedResult {
                     headword: headword.clone(),
                     reading: reading.clone(),
-                    furigana: calculate_furigana(&headword, &reading),
+                    furigana: strategy.furigana(&headword, &reading),
                     glossary: vec![def_obj],
                     frequencies: vec![], // Will be filled in final pass
-                    term_tags: entry.1.unwrap_or_default(),
+                    pitches: vec![],
+                    term_tags: entry.term_tags.unwrap_or_default(),
                     forms: vec![ApiForm {
                         headword: headword.clone(),
                         reading: reading.clone(),
                     }],
                     match_len,
+                    span_chars,
+                    span_bytes,
+                    vocab_state: VocabState::Unknown,
+                    match_info: entry.match_info,
                 });
             }
         }
     }
 
-    if should_group {
-        let final_results = map
+    let mut results = if should_group {
+        let final_results: Vec<ApiGroupedResult> = map
             .into_iter()
             .map(|mut agg| {
+                // Pull in alternate spellings that were not part of the match itself
+                if let Some((dict_id, seq)) = agg.sequence_key {
+                    for form in lookup::forms_for_sequence(app_state, dict_id, seq) {
+                        if !agg.forms_set.contains(&form) {
+                            agg.forms_set.push(form);
+                        }
+                    }
+                }
+
                 // Attach frequencies if they exist for this word
                 if let Some(freqs) = freq_map.get(&(agg.headword.clone(), agg.reading.clone())) {
                     agg.frequencies.extend(freqs.clone());
                 }
+                if let Some(pitches) = pitch_map.get(&(agg.headword.clone(), agg.reading.clone())) {
+                    agg.pitches.extend(pitches.clone());
+                }
 
                 ApiGroupedResult {
                     headword: agg.headword,
@@ -1305,6 +2132,7 @@ pub async fn lookup_handler(
                     furigana: agg.furigana,
                     glossary: agg.glossary,
                     frequencies: agg.frequencies,
+                    pitches: agg.pitches,
                     term_tags: agg.term_tags,
                     forms: agg
                         .forms_set
@@ -1315,110 +2143,1141 @@ pub async fn lookup_handler(
                         })
                         .collect(),
                     match_len: agg.match_len,
+                    span_chars: agg.span_chars,
+                    span_bytes: agg.span_bytes,
+                    vocab_state: VocabState::Unknown,
+                    match_info: agg.match_info,
                 }
             })
             .collect();
 
-        Ok(Json(final_results))
+        final_results
     } else {
         // Iterate through results and attach frequencies to ALL of them.
         for res in &mut flat_results {
             if let Some(freqs) = freq_map.get(&(res.headword.clone(), res.reading.clone())) {
                 res.frequencies.extend(freqs.clone());
             }
+            if let Some(pitches) = pitch_map.get(&(res.headword.clone(), res.reading.clone())) {
+                res.pitches.extend(pitches.clone());
+            }
         }
 
-        Ok(Json(flat_results))
+        flat_results
+    };
+
+    let keys: Vec<(String, String)> = results
+        .iter()
+        .map(|r| (r.headword.clone(), r.reading.clone()))
+        .collect();
+    let states = vocab::load_states(app_state, &keys);
+    for res in &mut results {
+        if let Some(vocab_state) = states.get(&(res.headword.clone(), res.reading.clone())) {
+            res.vocab_state = *vocab_state;
+        }
     }
+
+    results
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnkiAddRequest {
+    pub term: String,
+    #[serde(default)]
+    pub reading: String,
+    /// Indices into the grouped result's `glossary`; empty selects every definition.
+    #[serde(default)]
+    pub definition_indices: Vec<usize>,
+    #[serde(default)]
+    pub sentence: String,
+    pub language: Option<DictionaryLanguage>,
+    #[serde(flatten)]
+    pub options: anki::AnkiNoteOptions,
 }
 
-fn calculate_furigana(headword: &str, reading: &str) -> Vec<(String, String)> {
-    if reading.is_empty() || headword == reading {
-        return vec![(headword.to_string(), String::new())];
+pub async fn anki_add_handler(
+    State(state): State<ServerState>,
+    Json(req): Json<AnkiAddRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let error_response = |status: StatusCode, message: String| {
+        (status, Json(json!({ "status": "error", "message": message })))
+    };
+
+    let term = req.term.trim();
+    if term.is_empty() {
+        return Err(error_response(
+            StatusCode::BAD_REQUEST,
+            "Term must not be empty".to_string(),
+        ));
     }
-    let h_chars: Vec<char> = headword.chars().collect();
-    let r_chars: Vec<char> = reading.chars().collect();
-    let mut h_start = 0;
-    let mut h_end = h_chars.len();
-    let mut r_start = 0;
-    let mut r_end = r_chars.len();
-    while h_start < h_end && r_start < r_end && h_chars[h_start] == r_chars[r_start] {
-        h_start += 1;
-        r_start += 1;
+    if state.app.is_loading() {
+        return Err(error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Dictionaries are importing...".to_string(),
+        ));
     }
-    while h_end > h_start && r_end > r_start && h_chars[h_end - 1] == r_chars[r_end - 1] {
-        h_end -= 1;
-        r_end -= 1;
+
+    let language = resolve_language(&state.app, req.language);
+    let raw_results = state.lookup.search(
+        &state.app,
+        term,
+        0,
+        language.to_deinflect_language(),
+        None,
+        None,
+    );
+    let results = build_api_results(&state.app, raw_results, true, language);
+
+    let reading = req.reading.trim();
+    let Some(entry) = results
+        .into_iter()
+        .find(|r| r.headword == term && (reading.is_empty() || r.reading == reading))
+    else {
+        return Err(error_response(
+            StatusCode::NOT_FOUND,
+            format!("No dictionary entry found for '{term}'"),
+        ));
+    };
+
+    let selected = select_definitions(&entry, &req.definition_indices)
+        .map_err(|message| error_response(StatusCode::BAD_REQUEST, message))?;
+
+    let note = anki::NoteContent {
+        expression: entry.headword.clone(),
+        reading: entry.reading.clone(),
+        glossary: selected
+            .iter()
+            .map(|def| anki::definition_to_html(&def.dictionary_name, &def.content))
+            .collect::<Vec<_>>()
+            .join(""),
+        furigana: anki::furigana_to_anki(&entry.furigana),
+        sentence: req.sentence.trim().to_string(),
+    };
+
+    let url = &state.app.anki_connect_url;
+    match anki::add_note(&state.app.http, url, &note, &req.options).await {
+        Ok(note_id) => {
+            info!("🃏 [Anki] Added note {} for {}", note_id, entry.headword);
+            Ok(Json(json!({ "status": "ok", "noteId": note_id, "note": note })))
+        }
+        Err(e) => {
+            error!("❌ [Anki] Failed to add note: {}", e);
+            Err(error_response(StatusCode::BAD_GATEWAY, e.to_string()))
+        }
     }
-    let mut parts = Vec::new();
-    if h_start > 0 {
-        parts.push((h_chars[0..h_start].iter().collect(), String::new()));
+}
+
+/// The definitions at `indices` of a grouped result; empty selects every definition.
+fn select_definitions<'a>(
+    entry: &'a ApiGroupedResult,
+    indices: &[usize],
+) -> Result<Vec<&'a ApiDefinition>, String> {
+    if indices.is_empty() {
+        return Ok(entry.glossary.iter().collect());
     }
-    if h_start < h_end {
-        parts.push((
-            h_chars[h_start..h_end].iter().collect(),
-            r_chars[r_start..r_end].iter().collect(),
+    indices
+        .iter()
+        .map(|&idx| {
+            entry.glossary.get(idx).ok_or_else(|| {
+                format!(
+                    "Definition index {idx} out of range ({} available)",
+                    entry.glossary.len()
+                )
+            })
+        })
+        .collect()
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MineRequest {
+    pub sentence: String,
+    /// Character offset of the target word within `sentence`.
+    pub start: usize,
+    /// Character offset just past the target word. Without it the longest match at `start` is
+    /// used.
+    pub end: Option<usize>,
+    pub language: Option<DictionaryLanguage>,
+    /// Sorting profile used to pick between entries, as for `/lookup`.
+    pub profile: Option<String>,
+    /// Indices into the entry's definitions; empty selects every definition.
+    #[serde(default)]
+    pub definition_indices: Vec<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MinedPitch {
+    pub dictionary_name: String,
+    pub position: usize,
+    /// Pitch graph for the reading, as inline SVG.
+    pub svg: String,
+}
+
+/// Everything a card for one word of a sentence needs, assembled server-side.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MinedCard {
+    /// Dictionary form of the word.
+    pub term: String,
+    pub reading: String,
+    /// `term` with Anki-style furigana (`食[た]べる`).
+    pub furigana: String,
+    /// The word as it appears in the sentence.
+    pub surface: String,
+    /// Position of `surface` in the sentence, in characters.
+    pub span: ApiSpan,
+    pub match_info: lookup::MatchInfo,
+    /// Selected definitions as returned by `/lookup`.
+    pub definitions: Vec<ApiDefinition>,
+    /// Selected definitions rendered as HTML.
+    pub glossary: String,
+    pub sentence: String,
+    /// The sentence with Anki-style furigana on every word with a dictionary reading.
+    pub sentence_furigana: String,
+    pub frequencies: Vec<ApiFrequency>,
+    pub pitches: Vec<MinedPitch>,
+}
+
+/// Builds a complete mining payload for the word at `start` in `sentence`: the dictionary
+/// form it deinflects to, the chosen definitions, a furigana sentence, frequencies and pitch
+/// graphs. The client pushes it to Anki or stores it itself.
+#[utoipa::path(
+    post,
+    path = "/mine",
+    tag = "yomitan",
+    request_body = MineRequest,
+    responses(
+        (status = 200, description = "Mining payload", body = MinedCard),
+        (status = 400, description = "Bad span, definition index or profile", body = ApiError),
+        (status = 404, description = "No dictionary entry at the span", body = ApiError),
+        (status = 503, description = "Dictionaries are still importing", body = ApiError),
+    )
+)]
+pub async fn mine_handler(
+    State(state): State<ServerState>,
+    Json(req): Json<MineRequest>,
+) -> Result<Json<MinedCard>, (StatusCode, Json<Value>)> {
+    let error_response = |status: StatusCode, code: &str, message: String| {
+        (
+            status,
+            Json(json!({ "status": "error", "error": code, "message": message })),
+        )
+    };
+
+    let char_count = req.sentence.chars().count();
+    let span_ok = req.start < char_count
+        && req.end.is_none_or(|end| end > req.start && end <= char_count);
+    if !span_ok {
+        return Err(error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_span",
+            format!(
+                "Span {}..{} is outside the sentence ({} characters)",
+                req.start,
+                req.end.map(|end| end.to_string()).unwrap_or_default(),
+                char_count
+            ),
         ));
     }
-    if h_end < h_chars.len() {
-        parts.push((h_chars[h_end..].iter().collect(), String::new()));
+    if state.app.is_loading() {
+        return Err(error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "loading",
+            "Dictionaries are importing...".to_string(),
+        ));
+    }
+
+    let profile = resolve_profile(&state.app, req.profile.as_deref())?;
+    let language = resolve_language(&state.app, req.language);
+    let res = tokio::task::spawn_blocking(move || -> Result<MinedCard, (StatusCode, Json<Value>)> {
+        let deinflect_language = language.to_deinflect_language();
+        let byte_start = req
+            .sentence
+            .char_indices()
+            .nth(req.start)
+            .map(|(i, _)| i)
+            .unwrap_or(req.sentence.len());
+        let raw_results = state.lookup.search(
+            &state.app,
+            &req.sentence,
+            byte_start,
+            deinflect_language,
+            profile.as_ref(),
+            None,
+        );
+        let results = build_api_results(&state.app, raw_results, true, language);
+        let Some(entry) = results
+            .into_iter()
+            .find(|r| req.end.is_none_or(|end| r.span_chars.end == end as u64))
+        else {
+            return Err(error_response(
+                StatusCode::NOT_FOUND,
+                "not_found",
+                "No dictionary entry found at the selected span".to_string(),
+            ));
+        };
+
+        let selected = select_definitions(&entry, &req.definition_indices)
+            .map_err(|message| error_response(StatusCode::BAD_REQUEST, "invalid_index", message))?;
+        let glossary = selected
+            .iter()
+            .map(|def| anki::definition_to_html(&def.dictionary_name, &def.content))
+            .collect::<Vec<_>>()
+            .join("");
+        let definitions = selected.into_iter().cloned().collect();
+
+        let strategy = language::strategy_for(deinflect_language);
+        let mut segments = Vec::new();
+        for token in state.lookup.tokenize(&state.app, &req.sentence, deinflect_language) {
+            match &token.entry {
+                Some(result) => {
+                    let (headword, reading) = lookup::term_parts(&result.entry.term);
+                    let furigana = strategy.furigana(&headword, &reading);
+                    segments.extend(anki::surface_furigana(&token.text, &furigana));
+                }
+                None => segments.push((token.text, String::new())),
+            }
+        }
+
+        let mora_count = pitch::morae(&entry.reading).len();
+        let pitches = entry
+            .pitches
+            .iter()
+            .filter(|p| mora_count > 0 && p.position <= mora_count)
+            .map(|p| MinedPitch {
+                dictionary_name: p.dictionary_name.clone(),
+                position: p.position,
+                svg: pitch::render_svg(mora_count, p.position),
+            })
+            .collect();
+
+        let surface = req
+            .sentence
+            .chars()
+            .skip(entry.span_chars.start as usize)
+            .take(entry.match_len)
+            .collect();
+        Ok(MinedCard {
+            furigana: anki::furigana_to_anki(&entry.furigana),
+            term: entry.headword,
+            reading: entry.reading,
+            surface,
+            span: entry.span_chars,
+            match_info: entry.match_info,
+            definitions,
+            glossary,
+            sentence: req.sentence.trim().to_string(),
+            sentence_furigana: anki::furigana_to_anki(&segments).trim().to_string(),
+            frequencies: entry.frequencies,
+            pitches,
+        })
+    })
+    .await;
+
+    match res {
+        Ok(card) => card.map(Json),
+        Err(e) => Err(error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "mine_failed",
+            e.to_string(),
+        )),
     }
-    parts
 }
 
+#[utoipa::path(
+    get,
+    path = "/dictionaries",
+    tag = "yomitan",
+    responses((status = 200, description = "Installed dictionaries by priority", body = DictionaryList))
+)]
 pub async fn list_dictionaries_handler(State(state): State<ServerState>) -> Json<Value> {
-    let dicts = state.app.dictionaries.read().expect("lock");
-    let mut list: Vec<_> = dicts.values().cloned().collect();
-    list.sort_by_key(|d| d.priority);
-    Json(
-        json!({ "dictionaries": list, "status": if state.app.is_loading() { "loading" } else { "ready" } }),
+    let list = state.app.sorted_dictionaries();
+    let total_terms: i64 = list.iter().map(|d| d.term_count).sum();
+    Json(json!({
+        "dictionaries": list,
+        "total_terms": total_terms,
+        "status": if state.app.is_loading() { "loading" } else { "ready" },
+        "readiness": state.app.readiness(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "yomitan",
+    responses(
+        (status = 200, description = "Ready for lookups", body = Health),
+        (status = 503, description = "Starting up or installing dictionaries", body = Health),
     )
+)]
+pub async fn health_handler(State(state): State<ServerState>) -> (StatusCode, Json<Health>) {
+    let readiness = state.app.readiness();
+    let status = match readiness {
+        Readiness::Ready => StatusCode::OK,
+        _ => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    let dictionaries = state.app.dictionaries.read().expect("lock").len();
+    (status, Json(Health { readiness, dictionaries }))
+}
+
+const DEFAULT_FREQUENCY_LIMIT: usize = 1000;
+const MAX_FREQUENCY_LIMIT: usize = 10_000;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FrequencyListParams {
+    /// Id of an imported frequency dictionary.
+    pub dict: i64,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    /// `rank` (lower is more common) or `occurrence`. Defaults to the mode of a sorting profile
+    /// using this dictionary, else `rank`.
+    #[param(value_type = Option<String>)]
+    pub mode: Option<profiles::FrequencyMode>,
+    /// Skip words already marked learning or known.
+    pub unknown_only: Option<bool>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct FrequencyList {
+    pub dictionary_id: i64,
+    /// Ranked terms in the dictionary.
+    pub total: usize,
+    pub offset: usize,
+    pub entries: Vec<frequency::FrequencyEntry>,
 }
 
+/// Terms of a frequency dictionary, most common first.
+#[utoipa::path(
+    get,
+    path = "/frequency",
+    tag = "yomitan",
+    params(FrequencyListParams),
+    responses(
+        (status = 200, description = "Ranked terms", body = FrequencyList),
+        (status = 404, description = "Unknown dictionary", body = ApiError),
+    )
+)]
+pub async fn frequency_list_handler(
+    State(state): State<ServerState>,
+    Query(params): Query<FrequencyListParams>,
+) -> Result<Json<FrequencyList>, (StatusCode, Json<Value>)> {
+    let id = DictionaryId(params.dict);
+    if !state.app.dictionaries.read().expect("lock").contains_key(&id) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "status": "error",
+                "error": "not_found",
+                "message": format!("Dictionary {} not found", id.0),
+            })),
+        ));
+    }
+
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_FREQUENCY_LIMIT)
+        .clamp(1, MAX_FREQUENCY_LIMIT);
+    let offset = params.offset.unwrap_or(0);
+    let unknown_only = params.unknown_only.unwrap_or(false);
+
+    let app_state = state.app.clone();
+    let list = tokio::task::spawn_blocking(move || -> anyhow::Result<FrequencyList> {
+        let mode = match params.mode {
+            Some(mode) => mode,
+            None => profiles::list_profiles(&app_state)?
+                .into_iter()
+                .find(|p| p.dictionary_id == id)
+                .map(|p| p.mode)
+                .unwrap_or_default(),
+        };
+        let entries = frequency::ranked_terms(&app_state, id, mode)?;
+        let total = entries.len();
+        Ok(FrequencyList {
+            dictionary_id: id.0,
+            total,
+            offset,
+            entries: frequency::page(&app_state, entries, offset, limit, unknown_only),
+        })
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|res| res)
+    .map_err(|e| {
+        error!("❌ [Frequency] Failed to list dictionary {}: {}", id.0, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "status": "error", "message": e.to_string() })),
+        )
+    })?;
+
+    Ok(Json(list))
+}
+
+/// Re-checks a dictionary's stored rows against the checksum recorded when it was imported,
+/// e.g. after a crash mid-import.
+#[utoipa::path(
+    get,
+    path = "/dictionaries/{id}/verify",
+    tag = "yomitan",
+    params(("id" = i64, Path, description = "Dictionary id")),
+    responses(
+        (status = 200, description = "Integrity report", body = integrity::IntegrityReport),
+        (status = 404, description = "Unknown dictionary", body = ApiError),
+    )
+)]
+pub async fn verify_dictionary_handler(
+    State(state): State<ServerState>,
+    Path(id): Path<i64>,
+) -> Result<Json<integrity::IntegrityReport>, (StatusCode, Json<Value>)> {
+    let id = DictionaryId(id);
+    if !state.app.dictionaries.read().expect("lock").contains_key(&id) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "status": "error",
+                "error": "not_found",
+                "message": format!("Dictionary {} not found", id.0),
+            })),
+        ));
+    }
+
+    let app_state = state.app.clone();
+    let report = tokio::task::spawn_blocking(move || {
+        let conn = app_state.pool.get().map_err(|e| e.to_string())?;
+        integrity::verify_dictionary(&conn, id).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|res| res)
+    .map_err(|message| {
+        error!("❌ [Verify] Dictionary {} check failed: {}", id.0, message);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "status": "error", "message": message })),
+        )
+    })?;
+
+    info!(
+        "🔎 [Verify] Dictionary {}: {:?} ({} missing, {} corrupt)",
+        id.0, report.status, report.missing_rows, report.corrupt_rows
+    );
+    Ok(Json(report))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PitchGraphParams {
+    /// Kana reading, split into morae for the graph.
+    pub reading: String,
+    /// Mora after which the pitch drops; `0` for heiban.
+    pub position: usize,
+}
+
+/// Draws the pitch-accent line graph for one reading, for use as an `<img>` in Anki templates
+/// or inlined into the reader.
+#[utoipa::path(
+    get,
+    path = "/pitch-graph",
+    tag = "yomitan",
+    params(PitchGraphParams),
+    responses(
+        (status = 200, description = "SVG pitch graph", content_type = "image/svg+xml", body = String),
+        (status = 400, description = "Empty reading or position past its last mora", body = ApiError),
+    )
+)]
+pub async fn pitch_graph_handler(
+    Query(params): Query<PitchGraphParams>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let mora_count = pitch::morae(&params.reading).len();
+    if mora_count == 0 || params.position > mora_count {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "status": "error",
+                "error": "invalid_pitch",
+                "message": format!(
+                    "Position {} is outside reading '{}' ({} morae)",
+                    params.position, params.reading, mora_count
+                ),
+            })),
+        ));
+    }
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+        ],
+        pitch::render_svg(mora_count, params.position),
+    )
+        .into_response())
+}
+
+/// Serves an image copied out of a dictionary archive at import.
+pub async fn media_handler(
+    State(state): State<ServerState>,
+    Path((id, path)): Path<(i64, String)>,
+) -> Result<Response, StatusCode> {
+    let relative = media::sanitize_path(&path).ok_or(StatusCode::BAD_REQUEST)?;
+    let file = media::dictionary_dir(&state.app.data_dir, DictionaryId(id)).join(relative);
+    let bytes = tokio::fs::read(&file).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, media::content_type_for(&file)),
+            (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+        ],
+        bytes,
+    )
+        .into_response())
+}
+
+pub async fn events_ws_handler(
+    State(state): State<ServerState>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| stream_events(socket, state))
+}
+
+async fn stream_events(mut socket: WebSocket, state: ServerState) {
+    let mut rx = state.app.events.subscribe();
+    let mut pending = Some(state.app.snapshot());
+
+    loop {
+        if let Some(event) = pending.take() {
+            let Ok(text) = serde_json::to_string(&event) else {
+                continue;
+            };
+            if socket.send(Message::Text(text.into())).await.is_err() {
+                break;
+            }
+        }
+
+        tokio::select! {
+            event = rx.recv() => match event {
+                Ok(event) => pending = Some(event),
+                // Missed events can't be replayed; resync the client with current state.
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("⚠️ [WS] Subscriber lagged, skipped {} events", skipped);
+                    pending = Some(state.app.snapshot());
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Content types browsers and CLI tools send for ZIP uploads.
+const ZIP_CONTENT_TYPES: &[&str] = &[
+    "application/zip",
+    "application/x-zip",
+    "application/x-zip-compressed",
+    "application/octet-stream",
+    "multipart/x-zip",
+];
+
+#[utoipa::path(
+    post,
+    path = "/import",
+    tag = "yomitan",
+    request_body(content = ImportUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 202, description = "Import queued; poll the job for its outcome", body = ImportAccepted),
+        (status = 400, description = "Malformed upload", body = ApiError),
+        (status = 413, description = "Upload exceeds the import limit", body = ApiError),
+        (status = 415, description = "Upload is not a ZIP archive", body = ApiError),
+    )
+)]
 pub async fn import_handler(
     State(state): State<ServerState>,
+    headers: header::HeaderMap,
     mut multipart: Multipart,
-) -> Json<Value> {
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let error_response = |status: StatusCode, code: &str, message: String| {
+        (
+            status,
+            Json(json!({ "status": "error", "error": code, "message": message })),
+        )
+    };
+
+    let declared_len = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if declared_len.is_some_and(|len| len > import::MAX_IMPORT_BYTES) {
+        return Err(error_response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "too_large",
+            format!(
+                "Upload exceeds the {} MB import limit",
+                import::MAX_IMPORT_BYTES / (1024 * 1024)
+            ),
+        ));
+    }
+
     loop {
-        match multipart.next_field().await {
-            Ok(Some(field)) => {
-                if field.name() == Some("file") {
-                    match field.bytes().await {
-                        Ok(data) => {
-                            info!("📥 [Import API] Received upload ({} bytes)", data.len());
-                            let app_state = state.app.clone();
-                            let res = tokio::task::spawn_blocking(move || {
-                                import::import_zip(&app_state, &data)
-                            })
-                            .await
-                            .unwrap();
-                            return match res {
-                                Ok(msg) => {
-                                    info!("✅ {}", msg);
-                                    Json(json!({ "status": "ok", "message": msg }))
-                                }
-                                Err(e) => {
-                                    error!("❌ {}", e);
-                                    Json(json!({ "status": "error", "message": e.to_string() }))
-                                }
-                            };
-                        }
-                        Err(e) => {
-                            return Json(
-                                json!({ "status": "error", "message": format!("Upload Failed: {}", e) }),
-                            );
-                        }
-                    }
-                }
-            }
+        let mut field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
             Ok(None) => break,
             Err(e) => {
                 error!("❌ [Import API] Multipart error: {}", e);
-                return Json(
-                    json!({ "status": "error", "message": format!("Multipart Error: {}", e) }),
-                );
+                return Err(error_response(
+                    StatusCode::BAD_REQUEST,
+                    "multipart",
+                    format!("Multipart Error: {}", e),
+                ));
             }
+        };
+        if field.name() != Some("file") {
+            continue;
         }
+
+        if let Some(content_type) = field.content_type() {
+            let mime = content_type.split(';').next().unwrap_or("").trim();
+            if !mime.is_empty() && !ZIP_CONTENT_TYPES.contains(&mime.to_ascii_lowercase().as_str())
+            {
+                return Err(error_response(
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    "unsupported_media_type",
+                    format!("Expected a ZIP archive, got '{}'", mime),
+                ));
+            }
+        }
+
+        let mut data = Vec::with_capacity(declared_len.unwrap_or(0).min(64 * 1024 * 1024));
+        loop {
+            match field.chunk().await {
+                Ok(Some(chunk)) => {
+                    if data.len() + chunk.len() > import::MAX_IMPORT_BYTES {
+                        return Err(error_response(
+                            StatusCode::PAYLOAD_TOO_LARGE,
+                            "too_large",
+                            format!(
+                                "Upload exceeds the {} MB import limit",
+                                import::MAX_IMPORT_BYTES / (1024 * 1024)
+                            ),
+                        ));
+                    }
+                    data.extend_from_slice(&chunk);
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    error!("❌ [Import API] Upload failed: {}", e);
+                    return Err(error_response(
+                        StatusCode::BAD_REQUEST,
+                        "upload_failed",
+                        format!("Upload Failed: {}", e),
+                    ));
+                }
+            }
+        }
+
+        if data.is_empty() {
+            return Err(error_response(
+                StatusCode::BAD_REQUEST,
+                "empty_upload",
+                "Uploaded file is empty".to_string(),
+            ));
+        }
+        if !import::looks_like_zip(&data) {
+            return Err(error_response(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "not_zip",
+                import::ImportError::NotZip.to_string(),
+            ));
+        }
+
+        let job = state.imports.create(data.len());
+        info!(
+            "📥 [Import API] Received upload ({} bytes) as job {}",
+            data.len(),
+            job.id
+        );
+        tokio::spawn(run_import_job(state.clone(), job.id, data));
+
+        return Ok((StatusCode::ACCEPTED, Json(json!({ "status": "ok", "job_id": job.id }))));
+    }
+
+    Err(error_response(
+        StatusCode::BAD_REQUEST,
+        "missing_file",
+        "No file field found".to_string(),
+    ))
+}
+
+/// Error code reported for a failed import.
+fn import_error_code(error: &anyhow::Error) -> &'static str {
+    match error.downcast_ref::<import::ImportError>() {
+        Some(import::ImportError::NotZip) => "not_zip",
+        Some(import::ImportError::CorruptZip(_)) => "corrupt_zip",
+        Some(import::ImportError::MissingIndex) => "missing_index",
+        Some(import::ImportError::UnsupportedFormat(_)) => "unsupported_format",
+        Some(import::ImportError::AlreadyImported(_)) => "already_imported",
+        None => "import_failed",
+    }
+}
+
+async fn run_import_job(state: ServerState, id: u64, data: Vec<u8>) {
+    let run_lock = state.imports.run_lock();
+    let _turn = run_lock.lock().await;
+    // Resets and dictionary deletes are refused while this is held.
+    let importing = state.app.begin_import_when_ready().await;
+    state.imports.set_running(id);
+
+    let app_state = state.app.clone();
+    let res = tokio::task::spawn_blocking(move || import::import_zip(&app_state, &data)).await;
+    let outcome = match res {
+        Ok(Ok(msg)) => {
+            info!("✅ [Import Job {}] {}", id, msg);
+            Ok(msg)
+        }
+        Ok(Err(e)) => {
+            error!("❌ [Import Job {}] {}", id, e);
+            Err((import_error_code(&e), e.to_string()))
+        }
+        Err(e) => {
+            error!("❌ [Import Job {}] Import task failed: {}", id, e);
+            Err(("import_failed", "Import task failed unexpectedly".to_string()))
+        }
+    };
+    // Ready before the job reports done, so lookups work as soon as a client sees it finish.
+    drop(importing);
+    state.imports.finish(id, outcome);
+}
+
+#[utoipa::path(
+    get,
+    path = "/import/jobs/{id}",
+    tag = "yomitan",
+    params(("id" = u64, Path, description = "Job id returned by `POST /import`")),
+    responses(
+        (status = 200, description = "Job status", body = ImportJob),
+        (status = 404, description = "Unknown or expired job", body = ApiError),
+    )
+)]
+pub async fn import_job_handler(
+    State(state): State<ServerState>,
+    Path(id): Path<u64>,
+) -> Result<Json<ImportJob>, (StatusCode, Json<Value>)> {
+    state.imports.get(id).map(Json).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "status": "error",
+                "error": "not_found",
+                "message": format!("Import job {} not found", id),
+            })),
+        )
+    })
+}
+
+fn user_dictionary_info(state: &ServerState) -> Value {
+    let segmenter = state.lookup.segmenter();
+    let path = segmenter.user_dictionary();
+    let entries = path
+        .as_ref()
+        .and_then(|p| std::fs::read_to_string(p).ok())
+        .and_then(|csv| crate::segmenter::validate_user_dictionary(&csv).ok());
+    json!({
+        "status": "ok",
+        "segmentation_enabled": segmenter.is_enabled(),
+        "path": path.map(|p| p.display().to_string()),
+        "entries": entries,
+    })
+}
+
+pub async fn get_user_dictionary_handler(State(state): State<ServerState>) -> Json<Value> {
+    Json(user_dictionary_info(&state))
+}
+
+/// Replaces the Lindera user dictionary with the CSV request body.
+pub async fn upload_user_dictionary_handler(
+    State(state): State<ServerState>,
+    body: String,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let entries = crate::segmenter::validate_user_dictionary(&body).map_err(|message| {
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({
+                "status": "error",
+                "error": "invalid_user_dictionary",
+                "message": message,
+            })),
+        )
+    })?;
+
+    let path = state.app.data_dir.join(crate::segmenter::USER_DICTIONARY_FILE);
+    if let Err(e) = std::fs::write(&path, body) {
+        error!("❌ [Segmenter] Failed to write user dictionary: {}", e);
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "status": "error", "message": e.to_string() })),
+        ));
+    }
+    state.lookup.segmenter().set_user_dictionary(Some(path));
+    info!("✂️ [Segmenter] User dictionary updated ({} entries)", entries);
+
+    let mut response = user_dictionary_info(&state);
+    if !state.lookup.segmenter().is_enabled() {
+        response["warning"] =
+            json!("Saved, but Japanese segmentation is disabled so it will not be used");
+    }
+    Ok(Json(response))
+}
+
+pub async fn delete_user_dictionary_handler(State(state): State<ServerState>) -> Json<Value> {
+    let uploaded = state.app.data_dir.join(crate::segmenter::USER_DICTIONARY_FILE);
+    if uploaded.exists()
+        && let Err(e) = std::fs::remove_file(&uploaded)
+    {
+        return Json(json!({ "status": "error", "message": e.to_string() }));
+    }
+    state.lookup.segmenter().set_user_dictionary(None);
+    info!("✂️ [Segmenter] User dictionary removed");
+    Json(user_dictionary_info(&state))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, sync::Arc};
+
+    use axum::{
+        Json,
+        extract::{Query, State},
+    };
+    use serde_json::json;
+
+    use super::{
+        DictionaryAction, DictionaryLanguage, LookupParams, ReverseLookupParams,
+        clear_dictionary_state, lookup_handler, manage_dictionaries_handler,
+        reverse_lookup_handler, run_import_job,
+    };
+    use crate::{
+        ServerState, audio_sources,
+        events::DictionaryEvent,
+        history::LookupRecorder,
+        import,
+        jobs::{ImportJobs, JobStatus},
+        lookup::LookupService,
+        state::{AppState, Readiness},
+    };
+
+    /// A format 3 dictionary zip holding one bank.
+    fn dictionary_zip(title: &str, bank_name: &str, bank: serde_json::Value) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        let index = json!({ "title": title, "revision": "1", "format": 3 });
+        zip.start_file("index.json", options).expect("start index");
+        zip.write_all(index.to_string().as_bytes()).expect("write index");
+        zip.start_file(bank_name, options).expect("start bank");
+        zip.write_all(bank.to_string().as_bytes()).expect("write bank");
+        zip.finish().expect("finish zip").into_inner()
+    }
+
+    fn term_zip(title: &str, term: &str, reading: &str, gloss: &str) -> Vec<u8> {
+        let bank = json!([[term, reading, "", "", 0, [gloss], 0, ""]]);
+        dictionary_zip(title, "term_bank_1.json", bank)
+    }
+
+    #[tokio::test]
+    async fn dictionary_filter_applies_before_the_longest_match_wins() {
+        let data_dir =
+            std::env::temp_dir().join(format!("manatan-lookup-filter-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let app = AppState::new(data_dir.clone());
+        app.initialize();
+        import::import_zip(&app, &term_zip("Short", "日本", "にほん", "Japan"))
+            .expect("import Short");
+        import::import_zip(&app, &term_zip("Long", "日本語", "にほんご", "Japanese language"))
+            .expect("import Long");
+        let frequencies = json!([["日本", "freq", { "reading": "にほん", "frequency": 100 }]]);
+        import::import_zip(
+            &app,
+            &dictionary_zip("Counts", "term_meta_bank_1.json", frequencies),
+        )
+        .expect("import Counts");
+        let short_id = app
+            .sorted_dictionaries()
+            .into_iter()
+            .find(|dict| dict.name == "Short")
+            .expect("Short installed")
+            .id;
+        let state = ServerState {
+            app,
+            lookup: Arc::new(LookupService::new()),
+            imports: ImportJobs::default(),
+            history: LookupRecorder::default(),
+        };
+
+        let lookup = |dicts: Option<String>| {
+            let state = state.clone();
+            async move {
+                let params = LookupParams {
+                    text: "日本語を話す".to_string(),
+                    index: Some(0),
+                    group: Some(true),
+                    language: Some(DictionaryLanguage::Japanese),
+                    profile: None,
+                    dicts,
+                };
+                lookup_handler(State(state), Query(params))
+                    .await
+                    .expect("lookup")
+                    .0
+            }
+        };
+
+        let all = lookup(None).await;
+        assert_eq!(all.first().map(|r| r.headword.as_str()), Some("日本語"));
+
+        // Only the shorter match is in the selected dictionary; the longer one from the
+        // excluded dictionary must not hide it, and the unselected frequency dictionary still
+        // annotates it.
+        let filtered = lookup(Some(short_id.0.to_string())).await;
+        let headwords: Vec<_> = filtered.iter().map(|r| r.headword.as_str()).collect();
+        assert_eq!(headwords, ["日本"]);
+        assert!(
+            filtered[0]
+                .glossary
+                .iter()
+                .all(|def| def.dictionary_name == "Short")
+        );
+        let sources: Vec<_> = filtered[0]
+            .frequencies
+            .iter()
+            .map(|freq| freq.dictionary_name.as_str())
+            .collect();
+        assert_eq!(sources, ["Counts"]);
+
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+
+    #[test]
+    fn reset_keeps_audio_sources() {
+        let data_dir =
+            std::env::temp_dir().join(format!("manatan-reset-audio-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let app = AppState::new(data_dir.clone());
+        // Stored where older versions kept it; startup moves it to its own table.
+        let stored = json!([{ "type": "url-pattern", "url": "https://example.com/{term}.mp3" }]);
+        app.pool
+            .get()
+            .expect("connection")
+            .execute(
+                "INSERT INTO metadata (key, value) VALUES ('audio_sources', ?)",
+                [stored.to_string()],
+            )
+            .expect("store legacy sources");
+        app.initialize();
+        import::import_zip(&app, &term_zip("Words", "日本", "にほん", "Japan"))
+            .expect("import Words");
+
+        clear_dictionary_state(&app);
+
+        assert!(app.sorted_dictionaries().is_empty());
+        let sources = serde_json::to_value(audio_sources::load_sources(&app)).expect("serialize");
+        assert_eq!(sources, stored);
+
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+
+    #[tokio::test]
+    async fn import_jobs_wait_for_a_reset_and_hold_importing() {
+        let data_dir =
+            std::env::temp_dir().join(format!("manatan-import-job-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let app = AppState::new(data_dir.clone());
+        app.initialize();
+        let state = ServerState {
+            app: app.clone(),
+            lookup: Arc::new(LookupService::new()),
+            imports: ImportJobs::default(),
+            history: LookupRecorder::default(),
+        };
+
+        // A reset or install is running.
+        app.begin_import().expect("ready");
+        let data = term_zip("Words", "日本", "にほん", "Japan");
+        let job = state.imports.create(data.len());
+        let task = tokio::spawn(run_import_job(state.clone(), job.id, data));
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let queued = state.imports.get(job.id).expect("job");
+        assert_eq!(queued.status, JobStatus::Queued);
+
+        let mut events = app.events.subscribe();
+        app.set_readiness(Readiness::Ready);
+        task.await.expect("import job");
+
+        assert_eq!(
+            state.imports.get(job.id).expect("job").status,
+            JobStatus::Done
+        );
+        assert_eq!(app.readiness(), Readiness::Ready);
+        let mut readiness = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let DictionaryEvent::LoadingChanged { readiness: r, .. } = event {
+                readiness.push(r);
+            }
+        }
+        assert_eq!(
+            readiness,
+            [Readiness::Ready, Readiness::Importing, Readiness::Ready]
+        );
+
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+
+    #[tokio::test]
+    async fn reverse_lookups_survive_deleting_a_dictionary() {
+        let data_dir =
+            std::env::temp_dir().join(format!("manatan-reverse-delete-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let app = AppState::new(data_dir.clone());
+        app.initialize();
+        import::import_zip(&app, &term_zip("Dogs", "犬", "いぬ", "dog"))
+            .expect("import Dogs");
+        import::import_zip(&app, &term_zip("Cats", "猫", "ねこ", "cat"))
+            .expect("import Cats");
+        let dogs = app
+            .sorted_dictionaries()
+            .into_iter()
+            .find(|dict| dict.name == "Dogs")
+            .expect("Dogs installed")
+            .id;
+        let state = ServerState {
+            app,
+            lookup: Arc::new(LookupService::new()),
+            imports: ImportJobs::default(),
+            history: LookupRecorder::default(),
+        };
+
+        // Deleting vacuums the database.
+        let deleted = manage_dictionaries_handler(
+            State(state.clone()),
+            Json(DictionaryAction::Delete { id: dogs.0 }),
+        )
+        .await;
+        assert_eq!(deleted.0["status"], "ok");
+
+        let reverse = |text: &str| {
+            let params = ReverseLookupParams {
+                text: text.to_string(),
+                limit: None,
+                group: Some(true),
+            };
+            reverse_lookup_handler(State(state.clone()), Query(params))
+        };
+        let cats = reverse("cat").await.expect("reverse lookup").0;
+        let headwords: Vec<_> = cats.iter().map(|r| r.headword.as_str()).collect();
+        assert_eq!(headwords, ["猫"]);
+        assert!(reverse("dog").await.expect("reverse lookup").0.is_empty());
+
+        let _ = std::fs::remove_dir_all(&data_dir);
     }
-    Json(json!({ "status": "error", "message": "No file field found" }))
 }
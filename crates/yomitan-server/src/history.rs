@@ -0,0 +1,220 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+use tracing::error;
+
+use crate::state::{AppState, now_secs};
+
+/// Longest source-text context kept per history row (in characters).
+const MAX_CONTEXT_CHARS: usize = 200;
+/// A lookup repeating one recorded this recently isn't recorded again; hovering back and
+/// forth over a word would otherwise add a row on every pass.
+const DEBOUNCE: Duration = Duration::from_secs(30);
+
+/// Term, reading and context of a recorded lookup.
+type LookupKey = (String, String, String);
+
+/// Records `/lookup` results off the request path, skipping repeats of recent lookups.
+#[derive(Clone, Default)]
+pub struct LookupRecorder {
+    recent: Arc<Mutex<HashMap<LookupKey, Instant>>>,
+}
+
+impl LookupRecorder {
+    pub fn record(&self, state: &AppState, term: &str, reading: &str, context: &str) {
+        let context: String = context.chars().take(MAX_CONTEXT_CHARS).collect();
+        let key = (term.to_string(), reading.to_string(), context);
+        if !self.should_record(&key, Instant::now()) {
+            return;
+        }
+        let state = state.clone();
+        tokio::task::spawn_blocking(move || {
+            let (term, reading, context) = key;
+            if let Err(e) = record_lookup(&state, &term, &reading, &context) {
+                error!("❌ [History] Failed to record lookup: {}", e);
+            }
+        });
+    }
+
+    fn should_record(&self, key: &LookupKey, now: Instant) -> bool {
+        let mut recent = self.recent.lock().expect("lock poisoned");
+        recent.retain(|_, at| now.duration_since(*at) < DEBOUNCE);
+        if recent.contains_key(key) {
+            return false;
+        }
+        recent.insert(key.clone(), now);
+        true
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub term: String,
+    pub reading: String,
+    pub context: String,
+    pub created_at: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TermCount {
+    pub term: String,
+    pub reading: String,
+    pub count: i64,
+    pub last_looked_up: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DayCount {
+    pub day: String,
+    pub count: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryStats {
+    pub total_lookups: i64,
+    pub unique_terms: i64,
+    pub top_terms: Vec<TermCount>,
+    pub lookups_per_day: Vec<DayCount>,
+}
+
+pub fn record_lookup(state: &AppState, term: &str, reading: &str, context: &str) -> Result<()> {
+    let context: String = context.chars().take(MAX_CONTEXT_CHARS).collect();
+    let conn = state.pool.get()?;
+    conn.execute(
+        "INSERT INTO lookup_history (term, reading, context, created_at) VALUES (?, ?, ?, ?)",
+        rusqlite::params![term, reading, context, now_secs()],
+    )?;
+    Ok(())
+}
+
+pub fn list_history(state: &AppState, limit: i64, offset: i64) -> Result<Vec<HistoryEntry>> {
+    let conn = state.pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT id, term, reading, context, created_at FROM lookup_history
+         ORDER BY created_at DESC, id DESC LIMIT ? OFFSET ?",
+    )?;
+    let rows = stmt.query_map(rusqlite::params![limit, offset], |row| {
+        Ok(HistoryEntry {
+            id: row.get(0)?,
+            term: row.get(1)?,
+            reading: row.get(2)?,
+            context: row.get(3)?,
+            created_at: row.get(4)?,
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+}
+
+pub fn history_stats(state: &AppState, top: i64, days: i64) -> Result<HistoryStats> {
+    let conn = state.pool.get()?;
+
+    let (total_lookups, unique_terms): (i64, i64) = conn.query_row(
+        "SELECT COUNT(*), COUNT(DISTINCT term || char(31) || reading) FROM lookup_history",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let mut stmt = conn.prepare(
+        "SELECT term, reading, COUNT(*) AS hits, MAX(created_at) FROM lookup_history
+         GROUP BY term, reading ORDER BY hits DESC, MAX(created_at) DESC LIMIT ?",
+    )?;
+    let top_terms = stmt
+        .query_map([top], |row| {
+            Ok(TermCount {
+                term: row.get(0)?,
+                reading: row.get(1)?,
+                count: row.get(2)?,
+                last_looked_up: row.get(3)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let since = now_secs() - days.max(0) * 86_400;
+    let mut stmt = conn.prepare(
+        "SELECT strftime('%Y-%m-%d', created_at, 'unixepoch') AS day, COUNT(*)
+         FROM lookup_history WHERE created_at >= ? GROUP BY day ORDER BY day",
+    )?;
+    let lookups_per_day = stmt
+        .query_map([since], |row| {
+            Ok(DayCount {
+                day: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(HistoryStats {
+        total_lookups,
+        unique_terms,
+        top_terms,
+        lookups_per_day,
+    })
+}
+
+pub fn clear_history(state: &AppState) -> Result<()> {
+    let conn = state.pool.get()?;
+    conn.execute("DELETE FROM lookup_history", [])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{
+        DEBOUNCE, LookupRecorder, clear_history, history_stats, list_history, record_lookup,
+    };
+    use crate::state::AppState;
+
+    fn key(term: &str) -> super::LookupKey {
+        (term.to_string(), String::new(), "context".to_string())
+    }
+
+    #[test]
+    fn repeated_lookups_are_debounced() {
+        let recorder = LookupRecorder::default();
+        let start = Instant::now();
+        assert!(recorder.should_record(&key("食べる"), start));
+        assert!(!recorder.should_record(&key("食べる"), start + Duration::from_secs(1)));
+        assert!(recorder.should_record(&key("飲む"), start + Duration::from_secs(1)));
+        assert!(recorder.should_record(&key("食べる"), start + DEBOUNCE));
+    }
+
+    #[test]
+    fn records_lists_and_clears_history() {
+        let data_dir =
+            std::env::temp_dir().join(format!("manatan-history-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let state = AppState::new(data_dir.clone());
+
+        record_lookup(&state, "食べる", "たべる", "ご飯を食べる").expect("record");
+        record_lookup(&state, "飲む", "のむ", "水を飲む").expect("record");
+        record_lookup(&state, "食べる", "たべる", &"あ".repeat(300)).expect("record");
+
+        let entries = list_history(&state, 10, 0).expect("list");
+        let terms: Vec<_> = entries.iter().map(|e| e.term.as_str()).collect();
+        assert_eq!(terms, ["食べる", "飲む", "食べる"]);
+        assert_eq!(entries[0].context.chars().count(), 200);
+        assert_eq!(list_history(&state, 1, 1).expect("page")[0].term, "飲む");
+
+        let stats = history_stats(&state, 5, 7).expect("stats");
+        assert_eq!((stats.total_lookups, stats.unique_terms), (3, 2));
+        assert_eq!(stats.top_terms[0].term, "食べる");
+        assert_eq!(stats.top_terms[0].count, 2);
+
+        clear_history(&state).expect("clear");
+        assert!(list_history(&state, 10, 0).expect("list").is_empty());
+
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+}
@@ -1,23 +1,71 @@
 use anyhow::Result;
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 use std::collections::{HashMap, HashSet};
-use std::io::Read;
-use tracing::info;
+use std::io::{Read, Seek};
+use std::path::PathBuf;
+use tracing::{error, info, warn};
 use wordbase_api::{
     dict::yomitan::{structured, Glossary, GlossaryTag},
     DictionaryId, DictionaryKind, DictionaryMeta, Record,
 };
 use zip::ZipArchive;
 
-use crate::state::{AppState, DictionaryData, StoredRecord};
+use crate::{
+    codec,
+    events::DictionaryEvent,
+    integrity::{Checksum, store_checksum},
+    lookup, media, pitch,
+    state::{AppState, DbPool, DictionaryData, StoredRecord, store_term_count},
+};
+
+/// Import failures the API reports with a specific status instead of a generic 500.
+#[derive(Debug, thiserror::Error)]
+pub enum ImportError {
+    #[error("Upload is not a ZIP archive")]
+    NotZip,
+    #[error("Invalid ZIP archive: {0}")]
+    CorruptZip(String),
+    #[error("No index.json found in zip")]
+    MissingIndex,
+    #[error("{0}")]
+    UnsupportedFormat(String),
+    #[error("Dictionary '{0}' is already imported.")]
+    AlreadyImported(String),
+}
+
+/// Local file header signature every non-empty ZIP archive starts with.
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Largest dictionary archive accepted by the import API.
+pub const MAX_IMPORT_BYTES: usize = 1024 * 1024 * 1024;
+
+pub fn looks_like_zip(data: &[u8]) -> bool {
+    data.starts_with(ZIP_MAGIC)
+}
 
 pub fn import_zip(state: &AppState, data: &[u8]) -> Result<String> {
+    let result = import_zip_inner(state, data);
+    if let Err(e) = &result {
+        state.emit(DictionaryEvent::ImportFailed {
+            message: e.to_string(),
+        });
+    }
+    result
+}
+
+fn import_zip_inner(state: &AppState, data: &[u8]) -> Result<String> {
     info!(
         "📦 [Import] Starting ZIP import (size: {} bytes)...",
         data.len()
     );
 
-    let mut zip = ZipArchive::new(std::io::Cursor::new(data))?;
+    if !looks_like_zip(data) {
+        return Err(ImportError::NotZip.into());
+    }
+
+    let mut zip = ZipArchive::new(std::io::Cursor::new(data))
+        .map_err(|e| ImportError::CorruptZip(e.to_string()))?;
 
     // 1. Find index.json
     let mut index_file_name = None;
@@ -30,10 +78,9 @@ pub fn import_zip(state: &AppState, data: &[u8]) -> Result<String> {
         }
     }
 
-    let index_file_name =
-        index_file_name.ok_or_else(|| anyhow::anyhow!("No index.json found in zip"))?;
+    let index_file_name = index_file_name.ok_or(ImportError::MissingIndex)?;
 
-    let meta = {
+    let (meta, format_version) = {
         let mut file = zip.by_name(&index_file_name)?;
         let mut s = String::new();
         file.read_to_string(&mut s)?;
@@ -45,21 +92,28 @@ pub fn import_zip(state: &AppState, data: &[u8]) -> Result<String> {
                 .as_i64()
                 .or_else(|| value.as_str().and_then(|text| text.parse::<i64>().ok()))
         });
-        if format_version != Some(3) {
-            return Err(anyhow::anyhow!(match format_version {
-                Some(found) => format!(
-                    "Unsupported dictionary format version {} (expected 3).",
+        let format_version = match format_version {
+            Some(version @ 1..=3) => version,
+            Some(found) => {
+                return Err(ImportError::UnsupportedFormat(format!(
+                    "Unsupported dictionary format version {} (expected 1 to 3).",
                     found
-                ),
-                None => "Unsupported dictionary format: missing version (expected 3).".to_string(),
-            }));
-        }
+                ))
+                .into());
+            }
+            None => {
+                return Err(ImportError::UnsupportedFormat(
+                    "Unsupported dictionary format: missing version (expected 1 to 3).".to_string(),
+                )
+                .into());
+            }
+        };
 
         let name = json["title"].as_str().unwrap_or("Unknown").to_string();
         let mut dm = DictionaryMeta::new(DictionaryKind::Yomitan, name);
         dm.version = json["revision"].as_str().map(|s| s.to_string());
         dm.description = json["description"].as_str().map(|s| s.to_string());
-        dm
+        (dm, format_version)
     };
 
     let dict_name = meta.name.clone();
@@ -70,12 +124,12 @@ pub fn import_zip(state: &AppState, data: &[u8]) -> Result<String> {
             .values()
             .any(|dict| dict.name.trim().to_lowercase() == normalized_name)
         {
-            return Err(anyhow::anyhow!(format!(
-                "Dictionary '{}' is already imported.",
-                dict_name
-            )));
+            return Err(ImportError::AlreadyImported(dict_name).into());
         }
     }
+    state.emit(DictionaryEvent::ImportStarted {
+        name: dict_name.clone(),
+    });
 
     // 2. Database Transaction Setup
     let mut conn = state.pool.get()?;
@@ -103,6 +157,7 @@ pub fn import_zip(state: &AppState, data: &[u8]) -> Result<String> {
                 name: dict_name.clone(),
                 priority: 0,
                 enabled: true,
+                term_count: 0,
             },
         );
     }
@@ -112,7 +167,9 @@ pub fn import_zip(state: &AppState, data: &[u8]) -> Result<String> {
         .filter_map(|i| zip.by_index(i).ok().map(|f| f.name().to_string()))
         .collect();
 
-    let mut terms_found = 0;
+    let mut terms_found: i64 = 0;
+    let mut checksum = Checksum::default();
+    let mut images = ImageExtractor::new(state, dict_id, &index_file_name);
 
     // Create reusable encoder
     let mut encoder = snap::raw::Encoder::new();
@@ -121,15 +178,22 @@ pub fn import_zip(state: &AppState, data: &[u8]) -> Result<String> {
         // === BRANCH 1: Standard Definitions (term_bank) ===
         if name.contains("term_bank") && !name.contains("term_meta") && name.ends_with(".json") {
             info!("   -> Processing Definitions: {}", name);
-            let mut file = zip.by_name(&name)?;
             let mut s = String::new();
-            file.read_to_string(&mut s)?;
+            zip.by_name(&name)?.read_to_string(&mut s)?;
 
-            let bank: Vec<Value> = serde_json::from_str(&s).unwrap_or_default();
+            let mut bank: Vec<Value> = serde_json::from_str(&s).unwrap_or_default();
+            if format_version == 1 {
+                bank = bank.into_iter().filter_map(upgrade_v1_term).collect();
+            }
 
             // Note: Added dictionary_id column to INSERT
-            let mut stmt =
-                tx.prepare("INSERT INTO terms (term, dictionary_id, json) VALUES (?, ?, ?)")?;
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO terms (term, dictionary_id, json, sequence, hash)
+                 VALUES (?, ?, ?, ?, ?)",
+            )?;
+            let mut fts_stmt = tx.prepare(
+                "INSERT INTO glosses_fts (rowid, gloss, dictionary_id) VALUES (?, ?, ?)",
+            )?;
 
             for entry in bank {
                 if let Some(arr) = entry.as_array() {
@@ -172,14 +236,18 @@ pub fn import_zip(state: &AppState, data: &[u8]) -> Result<String> {
 
                     // --- Content (Index 5) ---
                     let mut content_list = Vec::new();
+                    let mut gloss_texts = Vec::new();
                     if let Some(defs) = arr.get(5).and_then(|v| v.as_array()) {
                         for d in defs {
                             if let Some(str_def) = d.as_str() {
                                 content_list.push(structured::Content::String(str_def.to_string()));
                             } else if d.is_object() || d.is_array() {
+                                let mut d = d.clone();
+                                images.rewrite(&mut d, &mut zip);
                                 let json_str = serde_json::to_string(&d).unwrap_or_default();
                                 content_list.push(structured::Content::String(json_str));
                             }
+                            collect_gloss_text(d, &mut gloss_texts);
                         }
                     }
 
@@ -201,25 +269,50 @@ pub fn import_zip(state: &AppState, data: &[u8]) -> Result<String> {
                         Some(term_tags)
                     };
 
+                    // --- Sequence (Index 6): links alternate spellings of one entry ---
+                    let sequence = arr.get(6).and_then(|v| v.as_i64()).filter(|seq| *seq > 0);
+
                     let stored = StoredRecord {
                         dictionary_id: dict_id,
                         record,
                         term_tags,
                         reading: stored_reading.clone(),
                         headword: Some(headword.to_string()),
+                        sequence,
                     };
 
                     // CHANGED: Serialize to bytes -> Compress -> Insert
-                    let json_bytes = serde_json::to_vec(&stored)?;
-                    let compressed = encoder.compress_vec(&json_bytes)?;
-
-                    // Insert Headword mapping
-                    stmt.execute(rusqlite::params![headword, dict_id.0, compressed])?;
+                    let encoded = codec::encode(&stored)?;
+                    let compressed = encoder.compress_vec(&encoded)?;
+                    let hash = record_hash(&encoded);
+
+                    // Insert Headword mapping; identical entries already in the bank are ignored
+                    let inserted = stmt.execute(rusqlite::params![
+                        headword, dict_id.0, compressed, sequence, hash
+                    ])?;
+                    if inserted == 0 {
+                        continue;
+                    }
                     terms_found += 1;
+                    checksum.add(hash);
+
+                    // Index gloss text against the headword row for reverse lookups
+                    if !gloss_texts.is_empty() {
+                        let row_id = tx.last_insert_rowid();
+                        fts_stmt.execute(rusqlite::params![
+                            row_id,
+                            gloss_texts.join("; "),
+                            dict_id.0
+                        ])?;
+                    }
 
                     // Insert Reading mapping
                     if let Some(r) = stored_reading {
-                        stmt.execute(rusqlite::params![r, dict_id.0, compressed])?;
+                        let inserted = stmt
+                            .execute(rusqlite::params![r, dict_id.0, compressed, sequence, hash])?;
+                        if inserted > 0 {
+                            checksum.add(hash);
+                        }
                     }
                 }
             }
@@ -234,14 +327,19 @@ pub fn import_zip(state: &AppState, data: &[u8]) -> Result<String> {
             let bank: Vec<Value> = serde_json::from_str(&s).unwrap_or_default();
 
             // PREPARE STATEMENT LOCALLY FOR THIS BATCH
-            let mut stmt =
-                tx.prepare("INSERT INTO terms (term, dictionary_id, json) VALUES (?, ?, ?)")?;
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO terms (term, dictionary_id, json, hash) VALUES (?, ?, ?, ?)",
+            )?;
 
             struct MetaEntry {
                 reading: Option<String>,
                 value: String,
+                /// The numeric `value` when the dictionary gives one; the display value may be
+                /// formatted (`12,345`, `1.2万`).
+                numeric: Option<i64>,
             }
             let mut file_freq_map: HashMap<String, Vec<MetaEntry>> = HashMap::new();
+            let mut pitch_rows: Vec<(String, String, Vec<usize>)> = Vec::new();
 
             for entry in bank {
                 if let Some(arr) = entry.as_array() {
@@ -259,6 +357,7 @@ pub fn import_zip(state: &AppState, data: &[u8]) -> Result<String> {
 
                     if mode == "freq" {
                         let mut display_val = String::new();
+                        let mut numeric_val = None;
                         let mut specific_reading = None;
 
                         // Case 1: Object (may contain reading + value)
@@ -271,6 +370,7 @@ pub fn import_zip(state: &AppState, data: &[u8]) -> Result<String> {
                             let freq_data = obj.get("frequency").unwrap_or(data_blob);
 
                             if let Some(freq_obj) = freq_data.as_object() {
+                                numeric_val = freq_obj.get("value").and_then(|v| v.as_i64());
                                 if let Some(dv) =
                                     freq_obj.get("displayValue").and_then(|v| v.as_str())
                                 {
@@ -279,6 +379,7 @@ pub fn import_zip(state: &AppState, data: &[u8]) -> Result<String> {
                                     display_val = v.to_string();
                                 }
                             } else if let Some(v) = freq_data.as_i64() {
+                                numeric_val = Some(v);
                                 display_val = v.to_string();
                             } else if let Some(s) = freq_data.as_str() {
                                 display_val = s.to_string();
@@ -288,6 +389,7 @@ pub fn import_zip(state: &AppState, data: &[u8]) -> Result<String> {
                         else if let Some(s) = data_blob.as_str() {
                             display_val = s.to_string();
                         } else if let Some(n) = data_blob.as_i64() {
+                            numeric_val = Some(n);
                             display_val = n.to_string();
                         }
 
@@ -301,7 +403,26 @@ pub fn import_zip(state: &AppState, data: &[u8]) -> Result<String> {
                             .push(MetaEntry {
                                 reading: specific_reading,
                                 value: display_val,
+                                numeric: numeric_val,
                             });
+                    } else if mode == "pitch" {
+                        let Some(reading) = data_blob.get("reading").and_then(|v| v.as_str())
+                        else {
+                            continue;
+                        };
+                        let positions: Vec<usize> = data_blob
+                            .get("pitches")
+                            .and_then(|v| v.as_array())
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|p| match p.get("position")? {
+                                Value::String(pattern) => pitch::downstep_from_pattern(pattern),
+                                position => position.as_u64().map(|n| n as usize),
+                            })
+                            .collect();
+                        if !positions.is_empty() {
+                            pitch_rows.push((term.to_string(), reading.to_string(), positions));
+                        }
                     }
                 }
             }
@@ -333,8 +454,10 @@ pub fn import_zip(state: &AppState, data: &[u8]) -> Result<String> {
                         format!("Frequency: {}", entry.value)
                     };
 
+                    // Frequency rows keep the numeric value in `popularity`, which they have
+                    // no other use for, so ranking doesn't depend on parsing the display value.
                     let record = Record::YomitanGlossary(Glossary {
-                        popularity: 0,
+                        popularity: entry.numeric.unwrap_or(0),
                         tags: vec![],
                         content: vec![structured::Content::String(content_str)],
                     });
@@ -345,29 +468,405 @@ pub fn import_zip(state: &AppState, data: &[u8]) -> Result<String> {
                         term_tags: None,
                         reading: entry.reading.clone(),
                         headword: Some(term.clone()),
+                        sequence: None,
                     };
 
-                    let json_bytes = serde_json::to_vec(&stored)?;
-                    let compressed = encoder.compress_vec(&json_bytes)?;
+                    let encoded = codec::encode(&stored)?;
+                    let compressed = encoder.compress_vec(&encoded)?;
+                    let hash = record_hash(&encoded);
 
-                    stmt.execute(rusqlite::params![term, dict_id.0, compressed])?;
+                    if stmt.execute(rusqlite::params![term, dict_id.0, compressed, hash])? == 0 {
+                        continue;
+                    }
                     terms_found += 1;
+                    checksum.add(hash);
 
                     if let Some(r) = &entry.reading {
-                        if r != &term {
-                            stmt.execute(rusqlite::params![r, dict_id.0, compressed])?;
+                        if r != &term
+                            && stmt.execute(rusqlite::params![r, dict_id.0, compressed, hash])? > 0
+                        {
+                            checksum.add(hash);
                         }
                     }
                 }
             }
+
+            // Insert Pitch Accents
+            for (term, reading, positions) in pitch_rows {
+                let record = Record::YomitanGlossary(Glossary {
+                    popularity: 0,
+                    tags: vec![],
+                    content: vec![structured::Content::String(pitch::format_positions(
+                        &positions,
+                    ))],
+                });
+                let stored = StoredRecord {
+                    dictionary_id: dict_id,
+                    record,
+                    term_tags: None,
+                    reading: Some(reading.clone()),
+                    headword: Some(term.clone()),
+                    sequence: None,
+                };
+
+                let encoded = codec::encode(&stored)?;
+                let compressed = encoder.compress_vec(&encoded)?;
+                let hash = record_hash(&encoded);
+
+                if stmt.execute(rusqlite::params![term, dict_id.0, compressed, hash])? == 0 {
+                    continue;
+                }
+                terms_found += 1;
+                checksum.add(hash);
+
+                if reading != term
+                    && stmt.execute(rusqlite::params![reading, dict_id.0, compressed, hash])? > 0
+                {
+                    checksum.add(hash);
+                }
+            }
         }
     }
 
+    if images.copied > 0 {
+        info!("   -> Copied {} images", images.copied);
+    }
+
+    store_term_count(&tx, dict_id, terms_found)?;
+    store_checksum(&tx, dict_id, checksum)?;
     tx.commit()?;
     info!(
         "💾 [Import] Database transaction committed. Total Terms: {}",
         terms_found
     );
 
+    if let Some(dict) = state.dictionaries.write().expect("lock").get_mut(&dict_id) {
+        dict.term_count = terms_found;
+    }
+    state.emit(DictionaryEvent::ImportFinished {
+        id: dict_id,
+        name: dict_name.clone(),
+        term_count: terms_found,
+    });
+
     Ok(format!("Imported '{}'", dict_name))
 }
+
+/// Rewrites a format 1 term bank row, `[expression, reading, definitionTags, rules, score,
+/// ...glossary]`, into the format 3 layout with an empty sequence and term tags.
+fn upgrade_v1_term(entry: Value) -> Option<Value> {
+    let Value::Array(mut arr) = entry else {
+        return None;
+    };
+    if arr.len() < 5 {
+        return None;
+    }
+    let glossary = arr.split_off(5);
+    arr.extend([Value::Array(glossary), Value::from(0), Value::from("")]);
+    Some(Value::Array(arr))
+}
+
+/// Copies images referenced by structured content out of the archive into the dictionary's
+/// media directory and points the nodes at the served copy.
+struct ImageExtractor {
+    /// Directory holding `index.json` inside the archive; image paths are relative to it.
+    prefix: String,
+    dir: PathBuf,
+    dict_id: DictionaryId,
+    /// Archive path -> served path, or `None` when the file is missing or unreadable.
+    resolved: HashMap<String, Option<String>>,
+    copied: usize,
+}
+
+impl ImageExtractor {
+    fn new(state: &AppState, dict_id: DictionaryId, index_file_name: &str) -> Self {
+        let prefix = index_file_name
+            .strip_suffix("index.json")
+            .unwrap_or_default()
+            .to_string();
+        Self {
+            prefix,
+            dir: media::dictionary_dir(&state.data_dir, dict_id),
+            dict_id,
+            resolved: HashMap::new(),
+            copied: 0,
+        }
+    }
+
+    /// Rewrites the `path` of every `img` / legacy `image` node under `node`.
+    fn rewrite<R: Read + Seek>(&mut self, node: &mut Value, zip: &mut ZipArchive<R>) {
+        match node {
+            Value::Array(items) => {
+                for item in items {
+                    self.rewrite(item, zip);
+                }
+            }
+            Value::Object(obj) => {
+                let is_image = obj.get("tag").and_then(|v| v.as_str()) == Some("img")
+                    || obj.get("type").and_then(|v| v.as_str()) == Some("image");
+                if is_image {
+                    if let Some(path) = obj.get("path").and_then(|v| v.as_str()) {
+                        if let Some(url) = self.resolve(path, zip) {
+                            obj.insert("path".to_string(), Value::String(url));
+                        }
+                    }
+                }
+                if let Some(content) = obj.get_mut("content") {
+                    self.rewrite(content, zip);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn resolve<R: Read + Seek>(&mut self, path: &str, zip: &mut ZipArchive<R>) -> Option<String> {
+        if let Some(url) = self.resolved.get(path) {
+            return url.clone();
+        }
+        let url = self.copy(path, zip);
+        if url.is_none() {
+            warn!("⚠️ [Import] Image '{}' could not be copied from the archive", path);
+        }
+        self.resolved.insert(path.to_string(), url.clone());
+        url
+    }
+
+    fn copy<R: Read + Seek>(&mut self, path: &str, zip: &mut ZipArchive<R>) -> Option<String> {
+        let relative = media::sanitize_path(path)?;
+        let mut bytes = Vec::new();
+        zip.by_name(&format!("{}{}", self.prefix, path))
+            .ok()?
+            .read_to_end(&mut bytes)
+            .ok()?;
+        let target = self.dir.join(&relative);
+        std::fs::create_dir_all(target.parent()?).ok()?;
+        std::fs::write(&target, bytes).ok()?;
+        self.copied += 1;
+        Some(media::url_path(self.dict_id, path))
+    }
+}
+
+/// Content hash of a serialized record. Together with the term and dictionary it forms the
+/// uniqueness key that keeps duplicate bank entries from multiplying definitions.
+pub(crate) fn record_hash(encoded: &[u8]) -> i64 {
+    let digest = Sha256::digest(encoded);
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&digest[..8]);
+    i64::from_le_bytes(prefix)
+}
+
+/// Metadata key set once every headword row has its gloss text in `glosses_fts`.
+const GLOSS_INDEX_KEY: &str = "glosses_fts_backfilled";
+const GLOSS_BACKFILL_BATCH: i64 = 2000;
+
+/// Indexes the gloss text of rows imported before reverse lookups existed, on a background
+/// thread. Runs once per database; reverse lookups just miss those rows until it finishes.
+pub fn spawn_gloss_backfill(pool: DbPool) {
+    let Ok(conn) = pool.get() else {
+        return;
+    };
+    let done = conn
+        .query_row(
+            "SELECT 1 FROM metadata WHERE key = ?",
+            [GLOSS_INDEX_KEY],
+            |_| Ok(()),
+        )
+        .is_ok();
+    if done {
+        return;
+    }
+    drop(conn);
+
+    std::thread::spawn(move || match backfill_gloss_index(&pool) {
+        Ok(0) => {}
+        Ok(rows) => info!("🔎 [Import] Indexed glosses of {} existing entries", rows),
+        Err(e) => error!("❌ [Import] Gloss index backfill failed: {}", e),
+    });
+}
+
+/// Empties `glosses_fts` and forgets that it was backfilled, so the next backfill indexes every
+/// entry again.
+pub(crate) fn clear_gloss_index(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM glosses_fts", [])?;
+    conn.execute("DELETE FROM metadata WHERE key = ?", [GLOSS_INDEX_KEY])?;
+    Ok(())
+}
+
+fn backfill_gloss_index(pool: &DbPool) -> Result<usize> {
+    let mut conn = pool.get()?;
+    let mut decoder = snap::raw::Decoder::new();
+    let mut last_id = 0;
+    let mut indexed = 0;
+
+    loop {
+        let rows: Vec<(i64, String, i64, Vec<u8>)> = conn
+            .prepare(
+                "SELECT id, term, dictionary_id, json FROM terms
+                 WHERE id > ? ORDER BY id LIMIT ?",
+            )?
+            .query_map([last_id, GLOSS_BACKFILL_BATCH], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        let Some((last, ..)) = rows.last() else {
+            break;
+        };
+        last_id = *last;
+
+        let tx = conn.transaction()?;
+        {
+            let mut exists = tx.prepare("SELECT 1 FROM glosses_fts WHERE rowid = ?")?;
+            let mut insert = tx.prepare(
+                "INSERT INTO glosses_fts (rowid, gloss, dictionary_id) VALUES (?, ?, ?)",
+            )?;
+            for (id, term, dictionary_id, compressed) in rows {
+                let Some(stored) = codec::decode_row(&mut decoder, &compressed) else {
+                    continue;
+                };
+                // Import indexes the headword row only; reading rows share its record.
+                if stored.headword.as_deref() != Some(term.as_str())
+                    || lookup::is_frequency_record(&stored.record)
+                    || lookup::is_pitch_record(&stored.record)
+                    || exists.exists([id])?
+                {
+                    continue;
+                }
+                let gloss_texts = stored_gloss_text(&stored.record);
+                if gloss_texts.is_empty() {
+                    continue;
+                }
+                insert.execute(rusqlite::params![id, gloss_texts.join("; "), dictionary_id])?;
+                indexed += 1;
+            }
+        }
+        tx.commit()?;
+    }
+
+    conn.execute(
+        "INSERT OR REPLACE INTO metadata (key, value) VALUES (?, '1')",
+        [GLOSS_INDEX_KEY],
+    )?;
+    Ok(indexed)
+}
+
+/// Gloss text of a stored record, as import collects it from the dictionary's JSON. Structured
+/// definitions are stored as JSON strings.
+fn stored_gloss_text(record: &Record) -> Vec<String> {
+    let Record::YomitanGlossary(gloss) = record else {
+        return Vec::new();
+    };
+    let mut out = Vec::new();
+    for content in &gloss.content {
+        let structured::Content::String(text) = content else {
+            continue;
+        };
+        let parsed = text
+            .starts_with(['{', '['])
+            .then(|| serde_json::from_str::<Value>(text).ok())
+            .flatten();
+        match parsed {
+            Some(value) => collect_gloss_text(&value, &mut out),
+            None => collect_gloss_text(&Value::String(text.clone()), &mut out),
+        }
+    }
+    out
+}
+
+/// Walks a term bank definition (plain string or structured content) and collects its visible
+/// text leaves. Images and data attributes are skipped.
+fn collect_gloss_text(value: &Value, out: &mut Vec<String>) {
+    match value {
+        Value::String(s) => {
+            let trimmed = s.trim();
+            if !trimmed.is_empty() {
+                out.push(trimmed.to_string());
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect_gloss_text(item, out);
+            }
+        }
+        Value::Object(obj) => {
+            let kind = obj.get("type").and_then(|v| v.as_str());
+            let tag = obj.get("tag").and_then(|v| v.as_str());
+            if kind == Some("image") || tag == Some("img") {
+                return;
+            }
+            if kind == Some("text") {
+                if let Some(text) = obj.get("text") {
+                    collect_gloss_text(text, out);
+                }
+                return;
+            }
+            if let Some(content) = obj.get("content") {
+                collect_gloss_text(content, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::{backfill_gloss_index, import_zip, upgrade_v1_term};
+    use crate::state::AppState;
+    use serde_json::json;
+
+    #[test]
+    fn upgrades_v1_term_rows() {
+        let row = json!(["食べる", "たべる", "v1", "v1", 10, "to eat", "to live on"]);
+        assert_eq!(
+            upgrade_v1_term(row),
+            Some(json!(["食べる", "たべる", "v1", "v1", 10, ["to eat", "to live on"], 0, ""]))
+        );
+        assert_eq!(upgrade_v1_term(json!(["食べる", "たべる"])), None);
+    }
+
+    #[test]
+    fn backfills_the_gloss_index_of_existing_rows() {
+        let data_dir =
+            std::env::temp_dir().join(format!("manatan-gloss-backfill-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let state = AppState::new(data_dir.clone());
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("index.json", options).expect("start index");
+        let index = json!({ "title": "Glosses", "revision": "1", "format": 3 });
+        zip.write_all(index.to_string().as_bytes()).expect("write index");
+        zip.start_file("term_bank_1.json", options).expect("start bank");
+        let structured = json!({ "type": "structured-content", "content": ["to drink"] });
+        let bank = json!([
+            ["食べる", "たべる", "", "", 0, ["to eat"], 1, ""],
+            ["飲む", "のむ", "", "", 0, [structured], 2, ""],
+        ]);
+        zip.write_all(bank.to_string().as_bytes()).expect("write bank");
+        let data = zip.finish().expect("finish zip").into_inner();
+        import_zip(&state, &data).expect("import");
+
+        // As in a database imported before the index existed.
+        let conn = state.pool.get().expect("connection");
+        conn.execute("DELETE FROM glosses_fts", []).expect("clear index");
+        drop(conn);
+
+        assert_eq!(backfill_gloss_index(&state.pool).expect("backfill"), 2);
+        let conn = state.pool.get().expect("connection");
+        let mut glosses: Vec<String> = conn
+            .prepare("SELECT gloss FROM glosses_fts ORDER BY rowid")
+            .expect("prepare")
+            .query_map([], |row| row.get(0))
+            .expect("query")
+            .collect::<rusqlite::Result<_>>()
+            .expect("rows");
+        glosses.sort();
+        assert_eq!(glosses, ["to drink", "to eat"]);
+        drop(conn);
+
+        // Rows that already have their gloss indexed are left alone.
+        assert_eq!(backfill_gloss_index(&state.pool).expect("backfill"), 0);
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+}
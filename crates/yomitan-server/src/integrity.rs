@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use rusqlite::Connection;
+use serde::Serialize;
+use utoipa::ToSchema;
+use wordbase_api::DictionaryId;
+
+use crate::{codec, import::record_hash};
+
+/// Corrupt rows listed individually in a report; the rest are only counted.
+const MAX_REPORTED_ROWS: usize = 50;
+
+/// Order-independent checksum over a dictionary's term rows: the row count plus the wrapping
+/// sum of each row's record hash. Recorded when an import commits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Checksum {
+    pub rows: i64,
+    pub sum: i64,
+}
+
+impl Checksum {
+    pub fn add(&mut self, hash: i64) {
+        self.rows += 1;
+        self.sum = self.sum.wrapping_add(hash);
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        let (rows, sum) = value.split_once(':')?;
+        Some(Self {
+            rows: rows.parse().ok()?,
+            sum: sum.parse().ok()?,
+        })
+    }
+}
+
+fn checksum_key(id: DictionaryId) -> String {
+    format!("checksum:{}", id.0)
+}
+
+pub fn load_checksum(conn: &Connection, id: DictionaryId) -> Option<Checksum> {
+    conn.query_row(
+        "SELECT value FROM metadata WHERE key = ?",
+        [checksum_key(id)],
+        |row| row.get::<_, String>(0),
+    )
+    .ok()
+    .and_then(|value| Checksum::parse(&value))
+}
+
+pub fn store_checksum(
+    conn: &Connection,
+    id: DictionaryId,
+    checksum: Checksum,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO metadata (key, value) VALUES (?, ?)",
+        rusqlite::params![
+            checksum_key(id),
+            format!("{}:{}", checksum.rows, checksum.sum)
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn delete_checksum(conn: &Connection, id: DictionaryId) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM metadata WHERE key = ?", [checksum_key(id)])?;
+    Ok(())
+}
+
+/// Recomputes every dictionary's checksum from the stored hashes, after rows were rewritten.
+pub fn rebuild_checksums(conn: &Connection) -> rusqlite::Result<()> {
+    let mut checksums: HashMap<i64, Checksum> = HashMap::new();
+    let mut stmt = conn.prepare("SELECT dictionary_id, hash FROM terms WHERE hash IS NOT NULL")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        checksums.entry(row.get(0)?).or_default().add(row.get(1)?);
+    }
+    for (id, checksum) in checksums {
+        store_checksum(conn, DictionaryId(id), checksum)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityStatus {
+    /// Every row decodes and the checksum matches the one recorded at import.
+    Ok,
+    /// Rows are missing, corrupt or the checksum differs.
+    Damaged,
+    /// Imported before checksums were recorded; only row decoding was checked.
+    Unverified,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CorruptRow {
+    pub row_id: i64,
+    pub term: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct IntegrityReport {
+    pub status: IntegrityStatus,
+    pub expected: Option<Checksum>,
+    pub actual: Checksum,
+    /// Rows recorded at import that are no longer in the database.
+    pub missing_rows: i64,
+    pub corrupt_rows: i64,
+    /// The first few corrupt rows.
+    pub corrupt: Vec<CorruptRow>,
+}
+
+/// Re-reads every term row of `id`, checking that it decompresses, parses and still matches
+/// the hash stored next to it, and compares the totals with the checksum taken at import.
+pub fn verify_dictionary(conn: &Connection, id: DictionaryId) -> rusqlite::Result<IntegrityReport> {
+    let expected = load_checksum(conn, id);
+    let mut actual = Checksum::default();
+    let mut corrupt_rows = 0;
+    let mut corrupt = Vec::new();
+    let mut decoder = snap::raw::Decoder::new();
+
+    let mut stmt =
+        conn.prepare("SELECT rowid, term, json, hash FROM terms WHERE dictionary_id = ?")?;
+    let mut rows = stmt.query([id.0])?;
+    while let Some(row) = rows.next()? {
+        let row_id: i64 = row.get(0)?;
+        let term: String = row.get(1)?;
+        let compressed: Vec<u8> = row.get(2)?;
+        let stored_hash: Option<i64> = row.get(3)?;
+
+        let problem = match decoder.decompress_vec(&compressed) {
+            Err(e) => Some(format!("decompression failed: {e}")),
+            Ok(raw) => {
+                let hash = record_hash(&raw);
+                if codec::decode(&raw).is_none() {
+                    Some("record does not parse".to_string())
+                } else if stored_hash.is_some_and(|stored| stored != hash) {
+                    Some("hash mismatch".to_string())
+                } else {
+                    actual.add(hash);
+                    None
+                }
+            }
+        };
+
+        if let Some(reason) = problem {
+            corrupt_rows += 1;
+            if corrupt.len() < MAX_REPORTED_ROWS {
+                corrupt.push(CorruptRow {
+                    row_id,
+                    term,
+                    reason,
+                });
+            }
+        }
+    }
+
+    let missing_rows = expected
+        .map(|e| (e.rows - actual.rows - corrupt_rows).max(0))
+        .unwrap_or(0);
+    let status = match expected {
+        _ if corrupt_rows > 0 => IntegrityStatus::Damaged,
+        Some(expected) if expected != actual => IntegrityStatus::Damaged,
+        Some(_) => IntegrityStatus::Ok,
+        None => IntegrityStatus::Unverified,
+    };
+
+    Ok(IntegrityReport {
+        status,
+        expected,
+        actual,
+        missing_rows,
+        corrupt_rows,
+        corrupt,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Checksum;
+
+    #[test]
+    fn checksum_ignores_row_order() {
+        let mut a = Checksum::default();
+        let mut b = Checksum::default();
+        for hash in [i64::MAX, 7, -3] {
+            a.add(hash);
+        }
+        for hash in [-3, i64::MAX, 7] {
+            b.add(hash);
+        }
+        assert_eq!(a, b);
+        assert_eq!(Checksum::parse("3:-42"), Some(Checksum { rows: 3, sum: -42 }));
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::state::now_secs;
+
+/// Finished jobs kept for polling before the oldest are dropped.
+const MAX_FINISHED_JOBS: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Waiting for an earlier import to finish.
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+/// A dictionary upload being imported in the background.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct ImportJob {
+    pub id: u64,
+    pub status: JobStatus,
+    /// Size of the uploaded archive in bytes.
+    pub size: usize,
+    /// Result message once the job is done or failed.
+    pub message: Option<String>,
+    /// Machine-readable error code of a failed job, e.g. `already_imported`.
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+#[derive(Default)]
+struct JobTable {
+    next_id: u64,
+    jobs: HashMap<u64, ImportJob>,
+}
+
+/// Registry of import jobs. Imports run one at a time, in upload order.
+#[derive(Clone, Default)]
+pub struct ImportJobs {
+    table: Arc<Mutex<JobTable>>,
+    run_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl ImportJobs {
+    /// Registers a queued job for an upload of `size` bytes.
+    pub fn create(&self, size: usize) -> ImportJob {
+        let mut table = self.table.lock().expect("lock");
+        table.next_id += 1;
+        let job = ImportJob {
+            id: table.next_id,
+            status: JobStatus::Queued,
+            size,
+            message: None,
+            error: None,
+            created_at: now_secs(),
+            finished_at: None,
+        };
+        table.jobs.insert(job.id, job.clone());
+        job
+    }
+
+    pub fn get(&self, id: u64) -> Option<ImportJob> {
+        self.table.lock().expect("lock").jobs.get(&id).cloned()
+    }
+
+    /// Held while a job runs so that queued jobs wait their turn.
+    pub fn run_lock(&self) -> Arc<tokio::sync::Mutex<()>> {
+        self.run_lock.clone()
+    }
+
+    pub fn set_running(&self, id: u64) {
+        if let Some(job) = self.table.lock().expect("lock").jobs.get_mut(&id) {
+            job.status = JobStatus::Running;
+        }
+    }
+
+    pub fn finish(&self, id: u64, result: Result<String, (&str, String)>) {
+        let mut table = self.table.lock().expect("lock");
+        if let Some(job) = table.jobs.get_mut(&id) {
+            job.finished_at = Some(now_secs());
+            match result {
+                Ok(message) => {
+                    job.status = JobStatus::Done;
+                    job.message = Some(message);
+                }
+                Err((code, message)) => {
+                    job.status = JobStatus::Failed;
+                    job.error = Some(code.to_string());
+                    job.message = Some(message);
+                }
+            }
+        }
+        prune(&mut table);
+    }
+}
+
+fn prune(table: &mut JobTable) {
+    let mut finished: Vec<u64> = table
+        .jobs
+        .values()
+        .filter(|job| job.finished_at.is_some())
+        .map(|job| job.id)
+        .collect();
+    if finished.len() <= MAX_FINISHED_JOBS {
+        return;
+    }
+    finished.sort_unstable();
+    let excess = finished.len() - MAX_FINISHED_JOBS;
+    for id in &finished[..excess] {
+        table.jobs.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_recent_finished_jobs() {
+        let jobs = ImportJobs::default();
+        let pending = jobs.create(1);
+        let ids: Vec<u64> = (0..MAX_FINISHED_JOBS + 5)
+            .map(|_| {
+                let job = jobs.create(1);
+                jobs.finish(job.id, Err(("import_failed", "boom".to_string())));
+                job.id
+            })
+            .collect();
+
+        assert_eq!(jobs.get(pending.id).unwrap().status, JobStatus::Queued);
+        assert!(jobs.get(ids[0]).is_none());
+        let last = jobs.get(*ids.last().unwrap()).unwrap();
+        assert_eq!(last.status, JobStatus::Failed);
+        assert_eq!(last.error.as_deref(), Some("import_failed"));
+    }
+}
@@ -0,0 +1,281 @@
+//! Per-language behaviour of the lookup pipeline: which spellings of the scanned text get
+//! deinflected, which deinflected candidates are plausible, and how readings are displayed.
+
+use crate::{deinflector::Language, segmenter};
+
+pub trait LanguageStrategy: Send + Sync {
+    /// Normalized spellings of `text` to run through the deinflector. Must include `text`.
+    fn variants(&self, text: &str) -> Vec<String> {
+        vec![text.to_string()]
+    }
+
+    /// Whether a deinflected `candidate` is a plausible reading of `source`.
+    fn is_valid_candidate(&self, _source: &str, _candidate: &str) -> bool {
+        true
+    }
+
+    /// Whether a one-character substring should be looked up at all.
+    fn allows_single_character(&self, _text: &str) -> bool {
+        true
+    }
+
+    /// Ruby segments `(base, reading)` for displaying a headword with its reading.
+    fn furigana(&self, headword: &str, reading: &str) -> Vec<(String, String)> {
+        if reading.is_empty() || headword == reading {
+            vec![(headword.to_string(), String::new())]
+        } else {
+            vec![(headword.to_string(), reading.to_string())]
+        }
+    }
+}
+
+pub fn strategy_for(language: Language) -> &'static dyn LanguageStrategy {
+    match language {
+        Language::Japanese => &Japanese,
+        Language::Chinese | Language::Cantonese => &Chinese,
+        Language::Arabic => &Arabic,
+        language if is_cased_alphabetic(language) => &CasedAlphabetic,
+        _ => &Plain,
+    }
+}
+
+struct Japanese;
+struct Chinese;
+struct Arabic;
+struct CasedAlphabetic;
+struct Plain;
+
+impl LanguageStrategy for Japanese {
+    fn variants(&self, text: &str) -> Vec<String> {
+        let mut variants = vec![text.to_string()];
+        let normalized = katakana_to_hiragana(text);
+        let prolonged = replace_prolonged_sound_mark(&normalized);
+        for variant in [normalized, prolonged] {
+            if !variants.contains(&variant) {
+                variants.push(variant);
+            }
+        }
+        variants
+    }
+
+    /// A candidate containing kanji must share at least one kanji with the source, which stops
+    /// kana deinflections from matching unrelated kanji headwords.
+    fn is_valid_candidate(&self, source: &str, candidate: &str) -> bool {
+        if source == candidate {
+            return true;
+        }
+        let source_kanji: Vec<char> = source.chars().filter(|c| is_ideograph(*c)).collect();
+        let mut cand_kanji = candidate.chars().filter(|c| is_ideograph(*c)).peekable();
+        if cand_kanji.peek().is_none() {
+            return true;
+        }
+        cand_kanji.any(|k| source_kanji.contains(&k))
+    }
+
+    fn furigana(&self, headword: &str, reading: &str) -> Vec<(String, String)> {
+        calculate_furigana(headword, reading)
+    }
+}
+
+impl LanguageStrategy for Chinese {
+    /// Dictionaries key entries by either script, so try both renderings.
+    fn variants(&self, text: &str) -> Vec<String> {
+        let mut variants = vec![text.to_string()];
+        variants.extend(segmenter::chinese_script_variants(text));
+        variants
+    }
+}
+
+impl LanguageStrategy for Arabic {
+    fn variants(&self, text: &str) -> Vec<String> {
+        let mut variants = vec![text.to_string()];
+        let normalized = crate::deinflector::arabic::strip_diacritics(text);
+        if normalized != text {
+            variants.push(normalized);
+        }
+        variants
+    }
+}
+
+impl LanguageStrategy for CasedAlphabetic {
+    fn variants(&self, text: &str) -> Vec<String> {
+        let lower = text.to_lowercase();
+        if lower == text {
+            vec![text.to_string()]
+        } else {
+            vec![text.to_string(), lower]
+        }
+    }
+
+    /// Single letters are noise except for the English words "a" and "I".
+    fn allows_single_character(&self, text: &str) -> bool {
+        text.eq_ignore_ascii_case("a") || text.eq_ignore_ascii_case("i")
+    }
+}
+
+impl LanguageStrategy for Plain {}
+
+fn is_cased_alphabetic(language: Language) -> bool {
+    matches!(
+        language,
+        Language::English
+            | Language::Spanish
+            | Language::French
+            | Language::German
+            | Language::Portuguese
+            | Language::Italian
+            | Language::Dutch
+            | Language::Norwegian
+            | Language::Swedish
+            | Language::Danish
+            | Language::Finnish
+            | Language::Estonian
+            | Language::Latvian
+            | Language::Romanian
+            | Language::Polish
+            | Language::Czech
+            | Language::Hungarian
+            | Language::Turkish
+            | Language::Indonesian
+            | Language::Vietnamese
+            | Language::Tagalog
+            | Language::Maltese
+            | Language::Welsh
+            | Language::Bulgarian
+            | Language::Russian
+            | Language::Ukrainian
+            | Language::Greek
+            | Language::Latin
+            | Language::Mongolian
+    )
+}
+
+fn is_ideograph(c: char) -> bool {
+    ('\u{4E00}'..='\u{9FFF}').contains(&c)
+}
+
+fn katakana_to_hiragana(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            let code = c as u32;
+            if (0x30A1..=0x30F6).contains(&code) {
+                std::char::from_u32(code - 0x60).unwrap_or(c)
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+fn replace_prolonged_sound_mark(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut previous = None;
+
+    for c in text.chars() {
+        if c == 'ー' {
+            if let Some(prev) = previous {
+                if let Some(vowel) = prolonged_vowel(prev) {
+                    result.push(vowel);
+                    previous = Some(vowel);
+                    continue;
+                }
+            }
+        }
+
+        result.push(c);
+        previous = Some(c);
+    }
+
+    result
+}
+
+fn prolonged_vowel(kana: char) -> Option<char> {
+    const A_ROW: &str = "ぁあかがさざただなはばぱまやゃらわゎ";
+    const I_ROW: &str = "ぃいきぎしじちぢにひびぴみりゐ";
+    const U_ROW: &str = "ぅうくぐすずつづぬふぶぷむゆゅる";
+    const E_ROW: &str = "ぇえけげせぜてでねへべぺめれゑ";
+    const O_ROW: &str = "ぉおこごそぞとどのほぼぽもよょろを";
+
+    if A_ROW.contains(kana) {
+        Some('あ')
+    } else if I_ROW.contains(kana) {
+        Some('い')
+    } else if U_ROW.contains(kana) {
+        Some('う')
+    } else if E_ROW.contains(kana) {
+        Some('え')
+    } else if O_ROW.contains(kana) {
+        Some('う')
+    } else {
+        None
+    }
+}
+
+fn calculate_furigana(headword: &str, reading: &str) -> Vec<(String, String)> {
+    if reading.is_empty() || headword == reading {
+        return vec![(headword.to_string(), String::new())];
+    }
+    let h_chars: Vec<char> = headword.chars().collect();
+    let r_chars: Vec<char> = reading.chars().collect();
+    let mut h_start = 0;
+    let mut h_end = h_chars.len();
+    let mut r_start = 0;
+    let mut r_end = r_chars.len();
+    while h_start < h_end && r_start < r_end && h_chars[h_start] == r_chars[r_start] {
+        h_start += 1;
+        r_start += 1;
+    }
+    while h_end > h_start && r_end > r_start && h_chars[h_end - 1] == r_chars[r_end - 1] {
+        h_end -= 1;
+        r_end -= 1;
+    }
+    let mut parts = Vec::new();
+    if h_start > 0 {
+        parts.push((h_chars[0..h_start].iter().collect(), String::new()));
+    }
+    if h_start < h_end {
+        parts.push((
+            h_chars[h_start..h_end].iter().collect(),
+            r_chars[r_start..r_end].iter().collect(),
+        ));
+    }
+    if h_end < h_chars.len() {
+        parts.push((h_chars[h_end..].iter().collect(), String::new()));
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::strategy_for;
+    use crate::deinflector::Language;
+
+    #[test]
+    fn kanji_overlap_filter_only_applies_to_japanese() {
+        let japanese = strategy_for(Language::Japanese);
+        assert!(!japanese.is_valid_candidate("たべる", "食べる"));
+        assert!(japanese.is_valid_candidate("食べた", "食べる"));
+
+        let spanish = strategy_for(Language::Spanish);
+        assert!(spanish.is_valid_candidate("comiendo", "comer"));
+        assert_eq!(spanish.variants("Comer"), vec!["Comer", "comer"]);
+    }
+
+    #[test]
+    fn furigana_is_only_split_for_japanese() {
+        let japanese = strategy_for(Language::Japanese);
+        assert_eq!(
+            japanese.furigana("食べる", "たべる"),
+            vec![
+                ("食".to_string(), "た".to_string()),
+                ("べる".to_string(), String::new())
+            ]
+        );
+
+        let chinese = strategy_for(Language::Chinese);
+        assert_eq!(
+            chinese.furigana("中文", "zhōng wén"),
+            vec![("中文".to_string(), "zhōng wén".to_string())]
+        );
+    }
+}
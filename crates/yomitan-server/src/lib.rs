@@ -3,49 +3,168 @@ use std::{path::PathBuf, sync::Arc};
 use axum::{
     Router,
     extract::DefaultBodyLimit,
+    middleware,
     routing::{get, post},
 };
 use tower_http::{cors::CorsLayer, limit::RequestBodyLimitLayer};
 
+pub mod anki;
+pub mod audio_sources;
+pub mod codec;
 pub mod handlers;
 pub mod deinflector;
+pub mod events;
+pub mod frequency;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod history;
 pub mod import;
+pub mod integrity;
+pub mod jobs;
+pub mod language;
 pub mod lookup;
+pub mod media;
+pub mod numeric;
+pub mod openapi;
+pub mod pitch;
+pub mod profiles;
+pub mod ratelimit;
+pub mod segmenter;
 pub mod state;
+pub mod vocab;
 
 use handlers::{
-    audio_handler, import_handler, install_defaults_handler, install_language_handler, list_dictionaries_handler,
-    lookup_handler, manage_dictionaries_handler, reset_db_handler, unload_handler,
+    anki_add_handler, audio_handler, audio_uri_handler, batch_lookup_handler, clear_history_handler,
+    delete_profile_handler, delete_user_dictionary_handler, events_ws_handler,
+    frequency_list_handler, get_audio_sources_handler, get_user_dictionary_handler, health_handler,
+    history_handler, history_stats_handler, import_handler, import_job_handler,
+    install_defaults_handler, install_language_handler, list_dictionaries_handler,
+    list_profiles_handler, list_vocab_handler, lookup_handler, manage_dictionaries_handler,
+    media_handler, mine_handler, pitch_graph_handler, reset_db_handler, reverse_lookup_handler,
+    save_profile_handler, set_audio_sources_handler, sweep_lookup_handler, unload_handler,
+    update_vocab_handler, upload_user_dictionary_handler, verify_dictionary_handler,
 };
+use history::LookupRecorder;
+use jobs::ImportJobs;
 use lookup::LookupService;
+use ratelimit::RateLimiter;
 use state::AppState;
 
 #[derive(Clone)]
 pub struct ServerState {
     pub app: AppState,
     pub lookup: Arc<LookupService>,
+    pub imports: ImportJobs,
+    pub history: LookupRecorder,
 }
 
 pub fn create_router(data_dir: PathBuf) -> Router {
+    let lookup = LookupService::new();
+    lookup
+        .segmenter()
+        .set_user_dictionary(user_dictionary_path(&data_dir));
     let state = ServerState {
         app: AppState::new(data_dir),
-        lookup: Arc::new(LookupService::new()),
+        lookup: Arc::new(lookup),
+        imports: ImportJobs::default(),
+        history: LookupRecorder::default(),
     };
 
-    let limit = 1024 * 1024 * 1024;
+    // Requests are accepted straight away; lookups and dictionary changes report the
+    // initialization state until `AppState::initialize` has upgraded the database.
+    let init_state = state.app.clone();
+    tokio::task::spawn_blocking(move || init_state.initialize());
+
+    #[cfg(feature = "grpc")]
+    grpc::spawn_from_env(state.clone());
+
+    let limit = import::MAX_IMPORT_BYTES;
+    let lookup_limiter = RateLimiter::from_env("MANATAN_LOOKUP_RATE_LIMIT");
+    let import_limiter = RateLimiter::from_env("MANATAN_IMPORT_RATE_LIMIT");
 
     Router::new()
-        .route("/lookup", get(lookup_handler))
+        .route(
+            "/lookup",
+            get(lookup_handler).route_layer(middleware::from_fn_with_state(
+                lookup_limiter.clone(),
+                ratelimit::enforce,
+            )),
+        )
+        .route(
+            "/lookup/batch",
+            post(batch_lookup_handler).route_layer(middleware::from_fn_with_state(
+                lookup_limiter.clone(),
+                ratelimit::enforce,
+            )),
+        )
+        .route(
+            "/lookup/sweep",
+            get(sweep_lookup_handler).route_layer(middleware::from_fn_with_state(
+                lookup_limiter,
+                ratelimit::enforce,
+            )),
+        )
+        .route("/reverse-lookup", get(reverse_lookup_handler))
         .route("/audio", get(audio_handler))
+        .route("/audio-uri", get(audio_uri_handler))
+        .route(
+            "/audio-sources",
+            get(get_audio_sources_handler).post(set_audio_sources_handler),
+        )
+        .route("/anki/add", post(anki_add_handler))
+        .route("/mine", post(mine_handler))
         .route("/dictionaries", get(list_dictionaries_handler))
-        .route("/import", post(import_handler))
+        .route("/health", get(health_handler))
+        .route("/dictionaries/{id}/verify", get(verify_dictionary_handler))
+        .route("/frequency", get(frequency_list_handler))
+        .route("/media/{id}/{*path}", get(media_handler))
+        .route("/pitch-graph", get(pitch_graph_handler))
+        .route(
+            "/profiles",
+            get(list_profiles_handler)
+                .post(save_profile_handler)
+                .delete(delete_profile_handler),
+        )
+        .route("/history", get(history_handler).delete(clear_history_handler))
+        .route("/history/stats", get(history_stats_handler))
+        .route("/vocab", get(list_vocab_handler).post(update_vocab_handler))
+        .route("/ws", get(events_ws_handler))
+        .route(
+            "/import",
+            post(import_handler).route_layer(middleware::from_fn_with_state(
+                import_limiter,
+                ratelimit::enforce,
+            )),
+        )
+        .route("/import/jobs/{id}", get(import_job_handler))
         .route("/reset", post(reset_db_handler))
         .route("/manage", post(manage_dictionaries_handler))
         .route("/install-defaults", post(install_defaults_handler))
         .route("/install-language", post(install_language_handler))
         .route("/unload", post(unload_handler))
+        .route(
+            "/segmenter/user-dictionary",
+            get(get_user_dictionary_handler)
+                .post(upload_user_dictionary_handler)
+                .delete(delete_user_dictionary_handler),
+        )
         .layer(CorsLayer::permissive())
         .layer(DefaultBodyLimit::max(limit))
         .layer(RequestBodyLimitLayer::new(limit))
         .with_state(state)
 }
+
+/// `MANATAN_LINDERA_USER_DICTIONARY`, else a previously uploaded CSV in the data directory.
+fn user_dictionary_path(data_dir: &std::path::Path) -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("MANATAN_LINDERA_USER_DICTIONARY") {
+        let path = PathBuf::from(path.trim());
+        if path.is_file() {
+            return Some(path);
+        }
+        tracing::warn!(
+            "⚠️ [Segmenter] MANATAN_LINDERA_USER_DICTIONARY {} not found",
+            path.display()
+        );
+    }
+    Some(data_dir.join(segmenter::USER_DICTIONARY_FILE)).filter(|path| path.is_file())
+}
@@ -1,33 +1,126 @@
 use std::collections::{HashMap, HashSet};
 
+use serde::Serialize;
 use tracing::error;
+use utoipa::ToSchema;
 use wordbase_api::{
-    dict::yomitan::GlossaryTag, DictionaryId, FrequencyValue, Record, RecordEntry, RecordId, Span,
-    Term,
+    dict::yomitan::{structured::Content, GlossaryTag},
+    DictionaryId, FrequencyValue, Record, RecordEntry, RecordId, Span, Term,
 };
 
-use crate::deinflector::{Deinflector, Language as DeinflectLanguage};
-use crate::state::{AppState, StoredRecord};
+use crate::codec;
+use crate::deinflector::{self, Deinflector, Language as DeinflectLanguage};
+use crate::language;
+use crate::numeric::{self, NumericPrefix};
+use crate::pitch;
+use crate::profiles::{FrequencyMode, SortingProfile};
+use crate::segmenter::{self, ChineseSegmenter, Segmenter, SegmenterKind};
+use crate::state::AppState;
 
 pub struct LookupService {
     deinflector: Deinflector,
+    segmenter: Segmenter,
+    chinese: ChineseSegmenter,
+}
+
+/// A single dictionary row matched by a lookup.
+pub struct LookupResult {
+    pub entry: RecordEntry,
+    pub term_tags: Option<Vec<GlossaryTag>>,
+    /// Dictionary sequence number shared by alternate spellings of the same word.
+    pub sequence: Option<i64>,
+    pub match_info: MatchInfo,
+}
+
+/// How a lookup result was reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchKind {
+    /// The text matched a headword as written.
+    Exact,
+    /// The text matched only the entry's reading (kana for a kanji headword).
+    Reading,
+    /// The text matched after undoing inflections.
+    Deinflected,
+    /// The dictionary form came from the morphological segmenter.
+    Segmenter,
+    /// A numeral followed by a counter looked up on its own.
+    Counter,
+    /// Reverse lookup on definition text.
+    Gloss,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema)]
+pub struct MatchInfo {
+    pub kind: MatchKind,
+    /// The part of the input that matched.
+    pub surface: String,
+    /// The form that was looked up, e.g. the dictionary form of a deinflected verb.
+    pub candidate: String,
+    /// Id of the deinflection rule that produced `candidate`.
+    pub rule: Option<String>,
+    /// Every transform undone to reach `candidate`, from the dictionary form outwards
+    /// (食べる: passive, negative, past for 食べられなかった).
+    pub chain: Vec<InflectionStep>,
+    /// The text continues in the same script after the match, so only the start of a longer
+    /// word was found.
+    pub prefix: bool,
+    /// `candidate` was found in the entry's reading rather than its headword (はなしあい for
+    /// 話し合い). Such results rank below headword matches of the same length.
+    pub via_reading: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema)]
+pub struct InflectionStep {
+    /// Transform id from the language's deinflection rules, e.g. `-た`.
+    pub id: String,
+    /// Readable name, e.g. `past`.
+    pub label: String,
+}
+
+/// A segment of text produced by [`LookupService::tokenize`]. `start`/`end` are character
+/// offsets, `byte_start`/`byte_end` UTF-8 byte offsets into the input.
+pub struct Token {
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+    pub byte_start: usize,
+    pub byte_end: usize,
+    /// Best dictionary match for the segment; `None` for text no dictionary covers.
+    pub entry: Option<LookupResult>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct Candidate {
     pub word: String,
     pub source_len: usize,
-    pub _reason: String,
+    pub kind: MatchKind,
+    /// Deinflection transforms from the dictionary form outwards; empty for other kinds.
+    pub chain: Vec<String>,
+    /// Set for counter candidates: the numeral in front of `word` (三 in 三匹).
+    pub numeral: Option<NumericPrefix>,
 }
 
 impl LookupService {
     pub fn new() -> Self {
+        Self::with_segmenter(SegmenterKind::from_env())
+    }
+
+    pub fn with_segmenter(kind: SegmenterKind) -> Self {
         Self {
             deinflector: Deinflector::new(),
+            segmenter: Segmenter::new(kind),
+            chinese: ChineseSegmenter::new(),
         }
     }
 
-    pub fn unload_tokenizer(&self) {}
+    pub fn unload_tokenizer(&self) {
+        self.segmenter.unload();
+    }
+
+    pub fn segmenter(&self) -> &Segmenter {
+        &self.segmenter
+    }
 
     pub fn search(
         &self,
@@ -35,10 +128,9 @@ impl LookupService {
         text: &str,
         cursor_offset: usize,
         language: DeinflectLanguage,
-    ) -> Vec<(RecordEntry, Option<Vec<GlossaryTag>>)> {
-        let mut results = Vec::new();
-        let mut processed_candidates = HashSet::new();
-
+        profile: Option<&SortingProfile>,
+        dicts: Option<&HashSet<DictionaryId>>,
+    ) -> Vec<LookupResult> {
         let conn = match state.pool.get() {
             Ok(c) => c,
             Err(e) => {
@@ -46,6 +138,28 @@ impl LookupService {
                 return vec![];
             }
         };
+        self.search_with_conn(state, &conn, text, cursor_offset, language, profile, dicts)
+    }
+
+    /// Same as [`search`](Self::search) on a caller-provided connection, so a batch of lookups
+    /// shares one connection and its cached prepared statement.
+    ///
+    /// `dicts` restricts the entries to those dictionaries before the match length is settled,
+    /// so a longer match from an excluded dictionary can't hide a shorter one. Frequency and
+    /// pitch rows only annotate entries and are kept whatever the filter says.
+    #[allow(clippy::too_many_arguments)]
+    pub fn search_with_conn(
+        &self,
+        state: &AppState,
+        conn: &rusqlite::Connection,
+        text: &str,
+        cursor_offset: usize,
+        language: DeinflectLanguage,
+        profile: Option<&SortingProfile>,
+        dicts: Option<&HashSet<DictionaryId>>,
+    ) -> Vec<LookupResult> {
+        let mut results = Vec::new();
+        let mut processed_candidates = HashSet::new();
 
         let dict_configs: HashMap<DictionaryId, (bool, i64)> = {
             let dicts = state.dictionaries.read().expect("lock");
@@ -55,14 +169,6 @@ impl LookupService {
                 .collect()
         };
 
-        let mut stmt = match conn.prepare("SELECT dictionary_id, json FROM terms WHERE term = ?") {
-            Ok(s) => s,
-            Err(e) => {
-                error!("❌ DB Prepare Error: {}", e);
-                return vec![];
-            }
-        };
-
         let start_index = self.snap_to_char_boundary(text, cursor_offset);
         if start_index >= text.len() {
             return vec![];
@@ -70,92 +176,215 @@ impl LookupService {
 
         let search_text = &text[start_index..];
         let chars: Vec<char> = search_text.chars().take(24).collect();
+        // Spans are reported relative to the full input so clients can highlight the match.
+        let char_start = text[..start_index].chars().count();
+
+        // The window is tokenized once. The segmenter's dictionary form for the leading token
+        // catches inflections the rule-based deinflector misses.
+        let tokens = match language {
+            DeinflectLanguage::Japanese => {
+                let window: String = chars.iter().collect();
+                self.segmenter.tokens(&window)
+            }
+            _ => None,
+        };
+        let leading_token = tokens.as_ref().and_then(|tokens| tokens.first());
+        // Chinese has no spaces; prefer the match that ends on a segmenter word boundary over
+        // a longer match that swallows the start of the next word.
+        let preferred_len = match language {
+            DeinflectLanguage::Chinese | DeinflectLanguage::Cantonese => {
+                let window: String = chars.iter().collect();
+                self.chinese.leading_word_len(&window)
+            }
+            _ => None,
+        };
+        let numeral = match language {
+            DeinflectLanguage::Japanese => numeric::parse_numeric_prefix(&chars),
+            _ => None,
+        };
         let mut decoder = snap::raw::Decoder::new();
+        let strategy = language::strategy_for(language);
 
-        for len in (1..=chars.len()).rev() {
-            let substring: String = chars[0..len].iter().collect();
+        let token_ends = tokens.as_ref().map(|tokens| segmenter::token_ends(tokens));
+        let rounds = lookup_rounds(chars.len(), token_ends.as_deref());
 
-            // Skip single character Latin/Symbol lookups unless explicitly desired
-            if should_skip_single_character(language)
-                && len < 2
-                && !substring.eq_ignore_ascii_case("a")
-                && !substring.eq_ignore_ascii_case("i")
-            {
+        // Gather every candidate for every substring length of a round first, then resolve them
+        // all with one query instead of one per candidate.
+        for lengths in rounds {
+            let longest_found = results
+                .iter()
+                .filter(|result: &&LookupResult| !is_annotation_record(&result.entry.record))
+                .map(|result| span_len(&result.entry) as usize)
+                .max();
+            let lengths = lengths_to_try(lengths, longest_found);
+            if lengths.is_empty() {
                 continue;
             }
+            let mut passes = Vec::new();
+            let mut words = HashSet::new();
+            for len in lengths {
+                let substring: String = chars[0..len].iter().collect();
 
-            let candidates = self.generate_candidates(&substring, language);
-
-            for candidate in candidates {
-                if !self.is_valid_candidate(&substring, &candidate.word, language) {
+                // Skip single character Latin/Symbol lookups unless explicitly desired
+                if len < 2 && !strategy.allows_single_character(&substring) {
                     continue;
                 }
 
-                if processed_candidates.contains(&candidate.word) {
-                    continue;
+                let prefix = search_text
+                    .chars()
+                    .nth(len)
+                    .is_some_and(|next| same_script(chars[len - 1], next));
+
+                let mut candidates = self.generate_candidates(&substring, language);
+                if let Some(token) = leading_token {
+                    if token.char_len == len && token.base_form != substring {
+                        candidates.push(Candidate {
+                            word: token.base_form.clone(),
+                            source_len: len,
+                            kind: MatchKind::Segmenter,
+                            chain: Vec::new(),
+                            numeral: None,
+                        });
+                    }
+                }
+                // 三匹, 2千円: dictionaries rarely list the combination, so try the counter alone.
+                if let Some(numeral) = numeral.filter(|n| n.char_len < len) {
+                    candidates.push(Candidate {
+                        word: chars[numeral.char_len..len].iter().collect(),
+                        source_len: len,
+                        kind: MatchKind::Counter,
+                        chain: Vec::new(),
+                        numeral: Some(numeral),
+                    });
                 }
-                processed_candidates.insert(candidate.word.clone());
 
-                let rows = stmt.query_map(rusqlite::params![candidate.word], |row| {
-                    let dict_id: i64 = row.get(0)?;
-                    let compressed: Vec<u8> = row.get(1)?;
-                    Ok((dict_id, compressed))
-                });
+                candidates.retain(|c| strategy.is_valid_candidate(&substring, &c.word));
+                words.extend(candidates.iter().map(|c| c.word.clone()));
+                passes.push((substring, prefix, candidates));
+            }
 
-                if let Ok(mapped_rows) = rows {
-                    for row_result in mapped_rows {
-                        if let Ok((dict_id_raw, compressed_data)) = row_result {
-                            let dict_id = DictionaryId(dict_id_raw);
+            let rows_by_term = match fetch_terms(conn, &words) {
+                Ok(rows) => rows,
+                Err(e) => {
+                    error!("❌ DB Query Error: {}", e);
+                    return vec![];
+                }
+            };
 
-                            if let Some((enabled, _)) = dict_configs.get(&dict_id) {
-                                if !*enabled {
-                                    continue;
-                                }
+            for (substring, prefix, candidates) in passes {
+                let found_before = results.len();
+                for candidate in candidates {
+                    if candidate.numeral.is_some() {
+                        // The whole expression is a dictionary term (一人, 三日); use that instead.
+                        if results.len() > found_before {
+                            continue;
+                        }
+                    } else {
+                        if processed_candidates.contains(&candidate.word) {
+                            continue;
+                        }
+                        processed_candidates.insert(candidate.word.clone());
+                    }
+
+                    let Some(rows) = rows_by_term.get(&candidate.word) else {
+                        continue;
+                    };
+
+                    for (dict_id_raw, compressed_data) in rows {
+                        let dict_id = DictionaryId(*dict_id_raw);
+
+                        if let Some((enabled, _)) = dict_configs.get(&dict_id) {
+                            if !*enabled {
+                                continue;
                             }
+                        }
 
-                            if let Ok(decompressed) = decoder.decompress_vec(&compressed_data) {
-                                if let Ok(stored) =
-                                    serde_json::from_slice::<StoredRecord>(&decompressed)
+                        if let Ok(decompressed) = decoder.decompress_vec(compressed_data) {
+                            if let Some(stored) = codec::decode(&decompressed) {
+                                if dicts.is_some_and(|allowed| !allowed.contains(&dict_id))
+                                    && !is_annotation_record(&stored.record)
                                 {
-                                    let match_len = candidate.source_len;
+                                    continue;
+                                }
+                                let match_len = candidate.source_len;
+                                let match_bytes: usize =
+                                    chars[..match_len].iter().map(|c| c.len_utf8()).sum();
 
-                                    let headword = stored
-                                        .headword
-                                        .as_deref()
-                                        .unwrap_or(candidate.word.as_str());
-                                    let term_obj =
+                                let headword = stored
+                                    .headword
+                                    .as_deref()
+                                    .unwrap_or(candidate.word.as_str());
+                                let term_obj = match candidate.numeral {
+                                    Some(numeral) => {
+                                        let (headword, reading) = numeric::counter_term(
+                                            &chars[..numeral.char_len],
+                                            numeral.value,
+                                            headword,
+                                            stored.reading.as_deref(),
+                                        );
+                                        Term::from_parts(Some(&headword), Some(&reading))
+                                            .or_else(|| Term::from_headword(headword))
+                                    }
+                                    None => {
                                         Term::from_parts(Some(headword), stored.reading.as_deref())
-                                            .unwrap_or_else(|| {
-                                                Term::from_headword(headword.to_string()).unwrap()
-                                            });
-
-                                    let mut freq = 0;
-                                    if let Record::YomitanGlossary(g) = &stored.record {
-                                        freq = g.popularity;
+                                            .or_else(|| Term::from_headword(headword.to_string()))
                                     }
+                                };
+                                // An empty headword and reading make no term.
+                                let Some(term_obj) = term_obj else {
+                                    continue;
+                                };
 
-                                    results.push((
-                                        RecordEntry {
-                                            span_bytes: Span {
-                                                start: 0,
-                                                end: candidate.word.len() as u64,
-                                            },
-                                            span_chars: Span {
-                                                start: 0,
-                                                end: match_len as u64,
-                                            },
-                                            source: stored.dictionary_id,
-                                            term: term_obj,
-                                            record_id: RecordId(0),
-                                            record: stored.record.clone(),
-                                            profile_sorting_frequency: None,
-                                            source_sorting_frequency: Some(FrequencyValue::Rank(
-                                                freq,
-                                            )),
-                                        },
-                                        stored.term_tags,
-                                    ));
+                                let mut freq = 0;
+                                if let Record::YomitanGlossary(g) = &stored.record {
+                                    freq = g.popularity;
                                 }
+
+                                let word = Some(candidate.word.as_str());
+                                let via_reading = stored.headword.as_deref() != word
+                                    && stored.reading.as_deref() == word;
+                                let match_info = MatchInfo {
+                                    kind: if via_reading && candidate.kind == MatchKind::Exact {
+                                        MatchKind::Reading
+                                    } else {
+                                        candidate.kind
+                                    },
+                                    surface: substring.clone(),
+                                    candidate: candidate.word.clone(),
+                                    rule: candidate.chain.first().cloned(),
+                                    chain: candidate
+                                        .chain
+                                        .iter()
+                                        .map(|id| InflectionStep {
+                                            id: id.clone(),
+                                            label: deinflector::transform_label(language, id),
+                                        })
+                                        .collect(),
+                                    prefix,
+                                    via_reading,
+                                };
+
+                                results.push(LookupResult {
+                                    entry: RecordEntry {
+                                        span_bytes: Span {
+                                            start: start_index as u64,
+                                            end: (start_index + match_bytes) as u64,
+                                        },
+                                        span_chars: Span {
+                                            start: char_start as u64,
+                                            end: (char_start + match_len) as u64,
+                                        },
+                                        source: stored.dictionary_id,
+                                        term: term_obj,
+                                        record_id: RecordId(0),
+                                        record: stored.record.clone(),
+                                        profile_sorting_frequency: None,
+                                        source_sorting_frequency: Some(FrequencyValue::Rank(freq)),
+                                    },
+                                    term_tags: stored.term_tags,
+                                    sequence: stored.sequence,
+                                    match_info,
+                                });
                             }
                         }
                     }
@@ -163,18 +392,45 @@ impl LookupService {
             }
         }
 
+        if let Some(profile) = profile {
+            apply_profile_frequencies(&mut results, profile);
+        }
+
         results.sort_by(|a, b| {
-            let len_cmp = b.0.span_chars.end.cmp(&a.0.span_chars.end);
+            if let Some(preferred) = preferred_len.map(|len| len as u64) {
+                let pref_cmp = (span_len(&b.entry) == preferred)
+                    .cmp(&(span_len(&a.entry) == preferred));
+                if pref_cmp != std::cmp::Ordering::Equal {
+                    return pref_cmp;
+                }
+            }
+
+            let len_cmp = span_len(&b.entry).cmp(&span_len(&a.entry));
             if len_cmp != std::cmp::Ordering::Equal {
                 return len_cmp;
             }
 
+            // Kana-only input (often from OCR) reaches kanji entries through their readings;
+            // keep those behind entries whose headword matched.
+            let tier_cmp = a.match_info.via_reading.cmp(&b.match_info.via_reading);
+            if tier_cmp != std::cmp::Ordering::Equal {
+                return tier_cmp;
+            }
+
+            let profile_cmp = compare_profile_frequency(
+                a.entry.profile_sorting_frequency.as_ref(),
+                b.entry.profile_sorting_frequency.as_ref(),
+            );
+            if profile_cmp != std::cmp::Ordering::Equal {
+                return profile_cmp;
+            }
+
             let prio_a = dict_configs
-                .get(&a.0.source)
+                .get(&a.entry.source)
                 .map(|(_, p)| *p)
                 .unwrap_or(999);
             let prio_b = dict_configs
-                .get(&b.0.source)
+                .get(&b.entry.source)
                 .map(|(_, p)| *p)
                 .unwrap_or(999);
 
@@ -190,114 +446,218 @@ impl LookupService {
                     None => 0,
                 }
             };
-            get_val(b.0.source_sorting_frequency.as_ref())
-                .cmp(&get_val(a.0.source_sorting_frequency.as_ref()))
+            get_val(b.entry.source_sorting_frequency.as_ref())
+                .cmp(&get_val(a.entry.source_sorting_frequency.as_ref()))
         });
 
         results
     }
 
-    fn snap_to_char_boundary(&self, text: &str, index: usize) -> usize {
-        if index >= text.len() {
-            return text.len();
-        }
-        let mut i = index;
-        while i > 0 && !text.is_char_boundary(i) {
-            i -= 1;
-        }
-        i
-    }
-
-    fn is_valid_candidate(
+    /// Searches gloss text for `query` and returns the matching Japanese entries, ranked so
+    /// that exact gloss matches come before prefix matches and plain full-text hits.
+    pub fn reverse_search(
         &self,
-        source: &str,
-        candidate: &str,
-        language: DeinflectLanguage,
-    ) -> bool {
-        if source == candidate {
-            return true;
+        state: &AppState,
+        query: &str,
+        limit: usize,
+    ) -> Vec<LookupResult> {
+        let normalized_query = normalize_gloss(query);
+        if normalized_query.is_empty() || limit == 0 {
+            return vec![];
         }
-        match language {
-            DeinflectLanguage::Japanese | DeinflectLanguage::Chinese => {
-                let source_kanji: Vec<char> =
-                    source.chars().filter(|c| self.is_ideograph(*c)).collect();
-                let cand_kanji: Vec<char> = candidate
-                    .chars()
-                    .filter(|c| self.is_ideograph(*c))
-                    .collect();
-                if !cand_kanji.is_empty() {
-                    for k in cand_kanji {
-                        if source_kanji.contains(&k) {
-                            return true;
-                        }
-                    }
-                    return false;
-                }
-                true
+
+        let conn = match state.pool.get() {
+            Ok(c) => c,
+            Err(e) => {
+                error!("❌ Failed to get DB connection: {}", e);
+                return vec![];
+            }
+        };
+
+        let dict_configs: HashMap<DictionaryId, (bool, i64)> = {
+            let dicts = state.dictionaries.read().expect("lock");
+            dicts
+                .iter()
+                .map(|(id, d)| (*id, (d.enabled, d.priority)))
+                .collect()
+        };
+
+        let mut stmt = match conn.prepare(
+            "SELECT terms.dictionary_id, terms.json, glosses_fts.gloss, glosses_fts.rank
+             FROM glosses_fts JOIN terms ON terms.id = glosses_fts.rowid
+             WHERE glosses_fts MATCH ? ORDER BY glosses_fts.rank LIMIT ?",
+        ) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("❌ DB Prepare Error: {}", e);
+                return vec![];
+            }
+        };
+
+        // Quote as a phrase so user input is never parsed as FTS syntax.
+        let fts_query = format!("\"{}\"", query.trim().replace('"', "\"\""));
+        // Over-fetch so re-ranking by match tier has enough candidates to work with.
+        let fetch_limit = (limit * 8).min(1000) as i64;
+
+        let rows = match stmt.query_map(rusqlite::params![fts_query, fetch_limit], |row| {
+            let dict_id: i64 = row.get(0)?;
+            let compressed: Vec<u8> = row.get(1)?;
+            let gloss: String = row.get(2)?;
+            let rank: f64 = row.get(3)?;
+            Ok((dict_id, compressed, gloss, rank))
+        }) {
+            Ok(rows) => rows,
+            Err(e) => {
+                error!("❌ Reverse lookup query failed: {}", e);
+                return vec![];
+            }
+        };
+
+        let mut decoder = snap::raw::Decoder::new();
+        // The whole (trimmed) query is the matched span.
+        let lead_bytes = query.len() - query.trim_start().len();
+        let lead_chars = query[..lead_bytes].chars().count();
+        let match_len = query.trim().chars().count();
+        let mut ranked = Vec::new();
+
+        for (dict_id_raw, compressed_data, gloss, rank) in rows.flatten() {
+            let dict_id = DictionaryId(dict_id_raw);
+            let priority = match dict_configs.get(&dict_id) {
+                Some((false, _)) => continue,
+                Some((true, p)) => *p,
+                None => 999,
+            };
+
+            let Some(stored) = codec::decode_row(&mut decoder, &compressed_data) else {
+                continue;
+            };
+            let Some(headword) = stored.headword.as_deref() else {
+                continue;
+            };
+            let Some(term_obj) = Term::from_parts(Some(headword), stored.reading.as_deref())
+            else {
+                continue;
+            };
+
+            let mut freq = 0;
+            if let Record::YomitanGlossary(g) = &stored.record {
+                freq = g.popularity;
             }
-            _ => true,
+
+            let tier = gloss_match_tier(&gloss, &normalized_query);
+            let term_key = (headword.to_string(), stored.reading.clone());
+            ranked.push((
+                (tier, priority, rank),
+                term_key,
+                LookupResult {
+                    entry: RecordEntry {
+                        span_bytes: Span {
+                            start: lead_bytes as u64,
+                            end: (lead_bytes + query.trim().len()) as u64,
+                        },
+                        span_chars: Span {
+                            start: lead_chars as u64,
+                            end: (lead_chars + match_len) as u64,
+                        },
+                        source: stored.dictionary_id,
+                        term: term_obj,
+                        record_id: RecordId(0),
+                        record: stored.record,
+                        profile_sorting_frequency: None,
+                        source_sorting_frequency: Some(FrequencyValue::Rank(freq)),
+                    },
+                    term_tags: stored.term_tags,
+                    sequence: stored.sequence,
+                    match_info: MatchInfo {
+                        kind: MatchKind::Gloss,
+                        surface: query.trim().to_string(),
+                        candidate: query.trim().to_string(),
+                        rule: None,
+                        chain: Vec::new(),
+                        prefix: false,
+                        via_reading: false,
+                    },
+                },
+            ));
         }
-    }
 
-    fn is_ideograph(&self, c: char) -> bool {
-        c >= '\u{4E00}' && c <= '\u{9FFF}'
-    }
+        ranked.sort_by(|(a, _, _), (b, _, _)| {
+            a.0.cmp(&b.0)
+                .then(a.1.cmp(&b.1))
+                .then(a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal))
+        });
 
-    fn katakana_to_hiragana(&self, text: &str) -> String {
-        text.chars()
-            .map(|c| {
-                let code = c as u32;
-                if (0x30A1..=0x30F6).contains(&code) {
-                    std::char::from_u32(code - 0x60).unwrap_or(c)
-                } else {
-                    c
+        // Limit applies to distinct headwords, not individual definition rows.
+        let mut seen_terms = HashSet::new();
+        let mut results = Vec::new();
+        for (_, term_key, entry) in ranked {
+            if !seen_terms.contains(&term_key) {
+                if seen_terms.len() >= limit {
+                    continue;
                 }
-            })
-            .collect()
+                seen_terms.insert(term_key);
+            }
+            results.push(entry);
+        }
+
+        results
     }
 
-    fn replace_prolonged_sound_mark(&self, text: &str) -> String {
-        let mut result = String::with_capacity(text.len());
-        let mut previous = None;
+    /// Splits `text` into consecutive longest matches. Characters no dictionary covers become
+    /// single-character tokens without an entry.
+    pub fn tokenize(
+        &self,
+        state: &AppState,
+        text: &str,
+        language: DeinflectLanguage,
+    ) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let mut byte_pos = 0;
+        let mut char_pos = 0;
 
-        for c in text.chars() {
-            if c == 'ー' {
-                if let Some(prev) = previous {
-                    if let Some(vowel) = self.prolonged_vowel(prev) {
-                        result.push(vowel);
-                        previous = Some(vowel);
-                        continue;
-                    }
-                }
-            }
+        while byte_pos < text.len() {
+            let rest = &text[byte_pos..];
+            // Frequency and pitch rows only annotate other entries, so they can't represent a
+            // token.
+            let entry = self
+                .search(state, text, byte_pos, language, None, None)
+                .into_iter()
+                .find(|r| !is_annotation_record(&r.entry.record));
+            let match_chars = entry
+                .as_ref()
+                .map(|r| span_len(&r.entry) as usize)
+                .unwrap_or(1)
+                .max(1);
+            let match_bytes = rest
+                .char_indices()
+                .nth(match_chars)
+                .map(|(i, _)| i)
+                .unwrap_or(rest.len());
 
-            result.push(c);
-            previous = Some(c);
+            tokens.push(Token {
+                text: rest[..match_bytes].to_string(),
+                start: char_pos,
+                end: char_pos + match_chars,
+                byte_start: byte_pos,
+                byte_end: byte_pos + match_bytes,
+                entry,
+            });
+            byte_pos += match_bytes;
+            char_pos += match_chars;
         }
 
-        result
-    }
-
-    fn prolonged_vowel(&self, kana: char) -> Option<char> {
-        const A_ROW: &str = "ぁあかがさざただなはばぱまやゃらわゎ";
-        const I_ROW: &str = "ぃいきぎしじちぢにひびぴみりゐ";
-        const U_ROW: &str = "ぅうくぐすずつづぬふぶぷむゆゅる";
-        const E_ROW: &str = "ぇえけげせぜてでねへべぺめれゑ";
-        const O_ROW: &str = "ぉおこごそぞとどのほぼぽもよょろを";
-
-        if A_ROW.contains(kana) {
-            Some('あ')
-        } else if I_ROW.contains(kana) {
-            Some('い')
-        } else if U_ROW.contains(kana) {
-            Some('う')
-        } else if E_ROW.contains(kana) {
-            Some('え')
-        } else if O_ROW.contains(kana) {
-            Some('う')
-        } else {
-            None
+        tokens
+    }
+
+    fn snap_to_char_boundary(&self, text: &str, index: usize) -> usize {
+        if index >= text.len() {
+            return text.len();
+        }
+        let mut i = index;
+        while i > 0 && !text.is_char_boundary(i) {
+            i -= 1;
         }
+        i
     }
 
     fn generate_candidates(&self, text: &str, language: DeinflectLanguage) -> Vec<Candidate> {
@@ -307,74 +667,13 @@ impl LookupService {
         candidates.push(Candidate {
             word: text.to_string(),
             source_len,
-            _reason: "Original".to_string(),
+            kind: MatchKind::Exact,
+            chain: Vec::new(),
+            numeral: None,
         });
 
-        match language {
-            DeinflectLanguage::Japanese => {
-                let mut variants = HashSet::new();
-                variants.insert(text.to_string());
-
-                let normalized = self.katakana_to_hiragana(text);
-                variants.insert(normalized.clone());
-
-                let prolonged = self.replace_prolonged_sound_mark(&normalized);
-                variants.insert(prolonged);
-
-                for variant in variants {
-                    self.add_deinflections(
-                        DeinflectLanguage::Japanese,
-                        &variant,
-                        source_len,
-                        &mut candidates,
-                    );
-                }
-            }
-            DeinflectLanguage::Korean => {
-                self.add_deinflections(
-                    DeinflectLanguage::Korean,
-                    text,
-                    source_len,
-                    &mut candidates,
-                );
-            }
-            language if should_lowercase(language) => {
-                let lower = text.to_lowercase();
-                let sources = if lower == text {
-                    vec![text.to_string()]
-                } else {
-                    vec![text.to_string(), lower]
-                };
-
-                for source in sources {
-                    self.add_deinflections(language, &source, source_len, &mut candidates);
-                }
-            }
-            DeinflectLanguage::Chinese => {
-                self.add_deinflections(
-                    DeinflectLanguage::Chinese,
-                    text,
-                    source_len,
-                    &mut candidates,
-                );
-            }
-            DeinflectLanguage::Arabic => {
-                let mut variants = HashSet::new();
-                variants.insert(text.to_string());
-                let normalized = crate::deinflector::arabic::strip_diacritics(text);
-                variants.insert(normalized);
-                for variant in variants {
-                    self.add_deinflections(
-                        DeinflectLanguage::Arabic,
-                        &variant,
-                        source_len,
-                        &mut candidates,
-                    );
-                }
-            }
-            _ => {
-                self.add_deinflections(language, text, source_len, &mut candidates);
-            }
+        for variant in language::strategy_for(language).variants(text) {
+            self.add_deinflections(language, &variant, source_len, &mut candidates);
         }
 
         candidates
@@ -387,54 +686,390 @@ impl LookupService {
         source_len: usize,
         candidates: &mut Vec<Candidate>,
     ) {
-        for word in self.deinflector.deinflect(language, text) {
+        for (word, chain) in self.deinflector.deinflect_with_chains(language, text) {
             if word.is_empty() {
                 continue;
             }
+            let kind = if chain.is_empty() {
+                MatchKind::Exact
+            } else {
+                MatchKind::Deinflected
+            };
             candidates.push(Candidate {
                 word,
                 source_len,
-                _reason: "Deinflect".to_string(),
+                kind,
+                chain,
+                numeral: None,
             });
         }
     }
 }
 
-fn should_skip_single_character(language: DeinflectLanguage) -> bool {
-    should_lowercase(language)
+/// Returns every `(headword, reading)` pair stored under the given dictionary sequence
+/// number, i.e. the alternate spellings of one dictionary entry.
+pub fn forms_for_sequence(
+    state: &AppState,
+    dictionary_id: DictionaryId,
+    sequence: i64,
+) -> Vec<(String, String)> {
+    let conn = match state.pool.get() {
+        Ok(c) => c,
+        Err(e) => {
+            error!("❌ Failed to get DB connection: {}", e);
+            return vec![];
+        }
+    };
+    let mut stmt = match conn
+        .prepare_cached("SELECT json FROM terms WHERE dictionary_id = ? AND sequence = ?")
+    {
+        Ok(s) => s,
+        Err(e) => {
+            error!("❌ DB Prepare Error: {}", e);
+            return vec![];
+        }
+    };
+    let Ok(rows) = stmt.query_map(rusqlite::params![dictionary_id.0, sequence], |row| {
+        row.get::<_, Vec<u8>>(0)
+    }) else {
+        return vec![];
+    };
+
+    let mut decoder = snap::raw::Decoder::new();
+    let mut forms = Vec::new();
+    for compressed in rows.flatten() {
+        let Some(stored) = codec::decode_row(&mut decoder, &compressed) else {
+            continue;
+        };
+        let Some(headword) = stored.headword else {
+            continue;
+        };
+        let form = (headword, stored.reading.unwrap_or_default());
+        if !forms.contains(&form) {
+            forms.push(form);
+        }
+    }
+    forms
+}
+
+/// Substring lengths to look up, longest first, in rounds. Substrings that end on a token
+/// boundary are tried first; brute-forcing every length is the second round, or the only one
+/// when there is no segmenter.
+fn lookup_rounds(char_len: usize, token_ends: Option<&[usize]>) -> Vec<Vec<usize>> {
+    let all_lengths: Vec<usize> = (1..=char_len).rev().collect();
+    match token_ends {
+        Some(ends) => {
+            let ends: HashSet<usize> = ends.iter().copied().collect();
+            let (lattice, rest): (Vec<usize>, Vec<usize>) =
+                all_lengths.into_iter().partition(|len| ends.contains(len));
+            vec![lattice, rest]
+        }
+        None => vec![all_lengths],
+    }
+}
+
+/// The lengths of a round still worth trying once earlier rounds matched up to
+/// `longest_found` characters: only longer ones, such as a word ending inside a token the
+/// segmenter split differently.
+fn lengths_to_try(lengths: Vec<usize>, longest_found: Option<usize>) -> Vec<usize> {
+    match longest_found {
+        Some(longest) => lengths.into_iter().filter(|len| *len > longest).collect(),
+        None => lengths,
+    }
+}
+
+/// Number of source characters a lookup result matched.
+pub fn span_len(entry: &RecordEntry) -> u64 {
+    entry.span_chars.end - entry.span_chars.start
+}
+
+pub(crate) fn term_parts(term: &Term) -> (String, String) {
+    match term {
+        Term::Full(h, r) => (h.to_string(), r.to_string()),
+        Term::Headword(h) => (h.to_string(), String::new()),
+        Term::Reading(r) => (r.to_string(), String::new()),
+    }
 }
 
-fn should_lowercase(language: DeinflectLanguage) -> bool {
-    matches!(
-        language,
-        DeinflectLanguage::English
-            | DeinflectLanguage::Spanish
-            | DeinflectLanguage::French
-            | DeinflectLanguage::German
-            | DeinflectLanguage::Portuguese
-            | DeinflectLanguage::Italian
-            | DeinflectLanguage::Dutch
-            | DeinflectLanguage::Norwegian
-            | DeinflectLanguage::Swedish
-            | DeinflectLanguage::Danish
-            | DeinflectLanguage::Finnish
-            | DeinflectLanguage::Estonian
-            | DeinflectLanguage::Latvian
-            | DeinflectLanguage::Romanian
-            | DeinflectLanguage::Polish
-            | DeinflectLanguage::Czech
-            | DeinflectLanguage::Hungarian
-            | DeinflectLanguage::Turkish
-            | DeinflectLanguage::Indonesian
-            | DeinflectLanguage::Vietnamese
-            | DeinflectLanguage::Tagalog
-            | DeinflectLanguage::Maltese
-            | DeinflectLanguage::Welsh
-            | DeinflectLanguage::Bulgarian
-            | DeinflectLanguage::Russian
-            | DeinflectLanguage::Ukrainian
-            | DeinflectLanguage::Greek
-            | DeinflectLanguage::Latin
-            | DeinflectLanguage::Mongolian
-    )
+/// Whether two neighbouring characters belong to one run of kanji, katakana or letters.
+/// Hiragana is left out since particles and okurigana follow words directly.
+fn same_script(a: char, b: char) -> bool {
+    fn script(c: char) -> Option<u8> {
+        match c {
+            '\u{4E00}'..='\u{9FFF}' | '々' => Some(0),
+            '\u{30A0}'..='\u{30FF}' => Some(1),
+            '\u{3041}'..='\u{309F}' => None,
+            c if c.is_alphabetic() => Some(2),
+            _ => None,
+        }
+    }
+    script(a).is_some_and(|s| script(b) == Some(s))
+}
+
+/// Rows for all `words` in one statement, grouped by term. The word list is passed as a JSON
+/// array so the prepared statement is the same whatever the number of candidates.
+fn fetch_terms(
+    conn: &rusqlite::Connection,
+    words: &HashSet<String>,
+) -> rusqlite::Result<HashMap<String, Vec<(i64, Vec<u8>)>>> {
+    let mut rows_by_term: HashMap<String, Vec<(i64, Vec<u8>)>> = HashMap::new();
+    if words.is_empty() {
+        return Ok(rows_by_term);
+    }
+
+    let words_json = serde_json::to_string(words).unwrap_or_default();
+    let mut stmt = conn.prepare_cached(
+        "SELECT term, dictionary_id, json FROM terms
+         WHERE term IN (SELECT value FROM json_each(?))",
+    )?;
+    let rows = stmt.query_map([words_json], |row| {
+        Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?))
+    })?;
+    for row in rows {
+        let (term, dict_id, compressed) = row?;
+        rows_by_term.entry(term).or_default().push((dict_id, compressed));
+    }
+    Ok(rows_by_term)
+}
+
+pub(crate) fn is_frequency_record(record: &Record) -> bool {
+    let Record::YomitanGlossary(gloss) = record else {
+        return false;
+    };
+    matches!(gloss.content.first(), Some(Content::String(text)) if text.starts_with("Frequency: "))
+}
+
+/// Downstep positions of a pitch-accent row (stored as `Pitch: 0,2`).
+pub(crate) fn pitch_positions(record: &Record) -> Option<Vec<usize>> {
+    let Record::YomitanGlossary(gloss) = record else {
+        return None;
+    };
+    let Some(Content::String(text)) = gloss.content.first() else {
+        return None;
+    };
+    pitch::parse_positions(text)
+}
+
+pub(crate) fn is_pitch_record(record: &Record) -> bool {
+    pitch_positions(record).is_some()
+}
+
+/// Frequency and pitch rows, which annotate other entries rather than being entries.
+fn is_annotation_record(record: &Record) -> bool {
+    is_frequency_record(record) || is_pitch_record(record)
+}
+
+/// Extracts the numeric value of a frequency row (stored as `Frequency: <display value>`). The
+/// dictionary's numeric value is kept in `popularity` since imports started storing it; older
+/// rows only have the display value to go by.
+pub(crate) fn frequency_value(record: &Record) -> Option<i64> {
+    let Record::YomitanGlossary(gloss) = record else {
+        return None;
+    };
+    let Some(Content::String(text)) = gloss.content.first() else {
+        return None;
+    };
+    let value = text.strip_prefix("Frequency: ")?;
+    if gloss.popularity != 0 {
+        return Some(gloss.popularity);
+    }
+    parse_display_frequency(value)
+}
+
+/// Leading number of a display value such as `12,345`, `1.2万` or `8500㋕`, with grouping
+/// separators dropped and a trailing 千/万/億 multiplier applied.
+fn parse_display_frequency(value: &str) -> Option<i64> {
+    let value = value.trim_start();
+    let number_end = value
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, ',' | '，' | '_' | '\'' | '.')))
+        .unwrap_or(value.len());
+    let number: String = value[..number_end]
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    if !number.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let multiplier = match value[number_end..].chars().next() {
+        Some('千') => 1_000,
+        Some('万') => 10_000,
+        Some('億') => 100_000_000,
+        _ => 1,
+    };
+    match number.split_once('.') {
+        None => number.parse::<i64>().ok()?.checked_mul(multiplier),
+        Some((whole, fraction)) => {
+            let value = format!("{whole}.{}", fraction.replace('.', "")).parse::<f64>().ok()?;
+            Some((value * multiplier as f64).round() as i64)
+        }
+    }
+}
+
+/// Fills `profile_sorting_frequency` from the profile's frequency dictionary rows that were
+/// matched alongside the definitions.
+fn apply_profile_frequencies(results: &mut [LookupResult], profile: &SortingProfile) {
+    let mut values: HashMap<String, Vec<(Option<String>, i64)>> = HashMap::new();
+    for result in results.iter() {
+        if result.entry.source != profile.dictionary_id {
+            continue;
+        }
+        let Some(value) = frequency_value(&result.entry.record) else {
+            continue;
+        };
+        let (headword, reading) = term_parts(&result.entry.term);
+        let reading = (!reading.is_empty()).then_some(reading);
+        values.entry(headword).or_default().push((reading, value));
+    }
+    if values.is_empty() {
+        return;
+    }
+
+    for result in results.iter_mut() {
+        let (headword, reading) = term_parts(&result.entry.term);
+        let Some(candidates) = values.get(&headword) else {
+            continue;
+        };
+        // Prefer a reading-specific value, fall back to the reading-less one.
+        let value = candidates
+            .iter()
+            .find(|(r, _)| r.as_deref() == Some(reading.as_str()))
+            .or_else(|| candidates.iter().find(|(r, _)| r.is_none()))
+            .or_else(|| candidates.first())
+            .map(|(_, v)| *v);
+        result.entry.profile_sorting_frequency = value.map(|v| match profile.mode {
+            FrequencyMode::Rank => FrequencyValue::Rank(v),
+            FrequencyMode::Occurrence => FrequencyValue::Occurrence(v),
+        });
+    }
+}
+
+/// Orders entries with a profile frequency before those without; ranks ascend, occurrence
+/// counts descend.
+fn compare_profile_frequency(
+    a: Option<&FrequencyValue>,
+    b: Option<&FrequencyValue>,
+) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+    match (a, b) {
+        (Some(FrequencyValue::Rank(x)), Some(FrequencyValue::Rank(y))) => x.cmp(y),
+        (Some(FrequencyValue::Occurrence(x)), Some(FrequencyValue::Occurrence(y))) => y.cmp(x),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        _ => Ordering::Equal,
+    }
+}
+
+/// Lowercases a gloss (or query), drops parenthesised notes and a leading infinitive "to ".
+fn normalize_gloss(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut depth = 0usize;
+    for c in text.chars() {
+        match c {
+            '(' | '（' => depth += 1,
+            ')' | '）' => depth = depth.saturating_sub(1),
+            _ if depth == 0 => stripped.push(c),
+            _ => {}
+        }
+    }
+    let lowered = stripped.trim().to_lowercase();
+    let collapsed = lowered.split_whitespace().collect::<Vec<_>>().join(" ");
+    match collapsed.strip_prefix("to ") {
+        Some(rest) => rest.to_string(),
+        None => collapsed,
+    }
+}
+
+/// 0 = a gloss equals the query, 1 = a gloss starts with the query as a whole word,
+/// 2 = the query only appears somewhere inside the gloss text.
+fn gloss_match_tier(gloss: &str, normalized_query: &str) -> u8 {
+    let mut best = 2;
+    for part in gloss.split([';', ',', '\n']) {
+        let part = normalize_gloss(part);
+        if part == normalized_query {
+            return 0;
+        }
+        if let Some(rest) = part.strip_prefix(normalized_query) {
+            if rest.starts_with(' ') {
+                best = 1;
+            }
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use wordbase_api::{
+        FrequencyValue, Record,
+        dict::yomitan::{Glossary, structured::Content},
+    };
+
+    use super::{
+        compare_profile_frequency, frequency_value, gloss_match_tier, lengths_to_try,
+        lookup_rounds, normalize_gloss,
+    };
+
+    #[test]
+    fn reverse_lookup_ranks_exact_gloss_first() {
+        let query = normalize_gloss("Eat");
+        assert_eq!(gloss_match_tier("to eat; to live on (e.g. a salary)", &query), 0);
+        assert_eq!(gloss_match_tier("eat up; to consume", &query), 1);
+        assert_eq!(gloss_match_tier("to dine; to have a meal (eat)", &query), 2);
+    }
+
+    #[test]
+    fn profile_frequency_orders_known_values_first() {
+        let (common, rare) = (FrequencyValue::Rank(10), FrequencyValue::Rank(500));
+        assert_eq!(compare_profile_frequency(Some(&common), Some(&rare)), Ordering::Less);
+        assert_eq!(compare_profile_frequency(None, Some(&rare)), Ordering::Greater);
+
+        let (few, many) = (FrequencyValue::Occurrence(10), FrequencyValue::Occurrence(500));
+        assert_eq!(compare_profile_frequency(Some(&few), Some(&many)), Ordering::Greater);
+    }
+
+    #[test]
+    fn lattice_round_comes_before_the_other_lengths() {
+        // 食べ|させ|られる: tokens end after 2, 4 and 7 characters.
+        assert_eq!(
+            lookup_rounds(7, Some(&[2, 4, 7])),
+            vec![vec![7, 4, 2], vec![6, 5, 3, 1]]
+        );
+        assert_eq!(lookup_rounds(3, None), vec![vec![3, 2, 1]]);
+    }
+
+    #[test]
+    fn later_rounds_only_try_longer_matches() {
+        let rest = vec![6, 5, 3, 1];
+        assert_eq!(lengths_to_try(rest.clone(), None), rest);
+        // A lattice match of 4 characters still lets a 5 or 6 character word inside the next
+        // token win; shorter ones are skipped.
+        assert_eq!(lengths_to_try(rest.clone(), Some(4)), vec![6, 5]);
+        assert_eq!(lengths_to_try(rest, Some(7)), Vec::<usize>::new());
+    }
+
+    fn frequency_row(display: &str, popularity: i64) -> Record {
+        Record::YomitanGlossary(Glossary {
+            popularity,
+            tags: vec![],
+            content: vec![Content::String(format!("Frequency: {display}"))],
+        })
+    }
+
+    #[test]
+    fn frequency_value_prefers_the_numeric_value() {
+        assert_eq!(frequency_value(&frequency_row("12,345 (たべる)", 12345)), Some(12345));
+        assert_eq!(frequency_value(&frequency_row("★★★", 3)), Some(3));
+    }
+
+    #[test]
+    fn frequency_value_parses_formatted_display_values() {
+        assert_eq!(frequency_value(&frequency_row("12,345", 0)), Some(12345));
+        assert_eq!(frequency_value(&frequency_row("1，234 (たべる)", 0)), Some(1234));
+        assert_eq!(frequency_value(&frequency_row("1.2万", 0)), Some(12000));
+        assert_eq!(frequency_value(&frequency_row("3億", 0)), Some(300_000_000));
+        assert_eq!(frequency_value(&frequency_row("8500㋕", 0)), Some(8500));
+        assert_eq!(frequency_value(&frequency_row("★★★", 0)), None);
+    }
 }
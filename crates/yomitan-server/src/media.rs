@@ -0,0 +1,72 @@
+use std::path::{Path, PathBuf};
+
+use wordbase_api::DictionaryId;
+
+/// Images referenced by structured content, copied out of dictionary archives on import.
+pub fn media_root(data_dir: &Path) -> PathBuf {
+    data_dir.join("media")
+}
+
+pub fn dictionary_dir(data_dir: &Path, id: DictionaryId) -> PathBuf {
+    media_root(data_dir).join(id.0.to_string())
+}
+
+/// Path (relative to the API root) that `GET /media/{id}/{*path}` serves the file under.
+pub fn url_path(id: DictionaryId, path: &str) -> String {
+    format!("media/{}/{}", id.0, path)
+}
+
+/// Validates an archive-relative path, rejecting anything that could escape the media
+/// directory.
+pub fn sanitize_path(path: &str) -> Option<PathBuf> {
+    if path.is_empty() || path.starts_with('/') || path.contains('\\') {
+        return None;
+    }
+    let mut clean = PathBuf::new();
+    for part in path.split('/') {
+        if part.is_empty() || part == "." || part == ".." || part.contains(':') {
+            return None;
+        }
+        clean.push(part);
+    }
+    Some(clean)
+}
+
+pub fn remove_dictionary(data_dir: &Path, id: DictionaryId) {
+    let _ = std::fs::remove_dir_all(dictionary_dir(data_dir, id));
+}
+
+pub fn content_type_for(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("bmp") => "image/bmp",
+        Some("avif") => "image/avif",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sanitize_path;
+    use std::path::PathBuf;
+
+    #[test]
+    fn rejects_escaping_paths() {
+        assert_eq!(
+            sanitize_path("img/kanji/日.png"),
+            Some(PathBuf::from("img/kanji/日.png"))
+        );
+        for bad in ["", "/etc/passwd", "../x.png", "img/../../x.png", "img//x.png", "C:x.png"] {
+            assert_eq!(sanitize_path(bad), None, "{bad}");
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! Japanese numerals and counter words (三匹, 2千円, ５本 ...).
+//!
+//! Dictionaries don't list every number + counter combination, so lookups parse the numeral
+//! off the front, look up the counter on its own and rebuild the reading with the usual
+//! sound changes (さんびき, にせんえん).
+
+/// A numeral at the start of a lookup window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NumericPrefix {
+    pub value: u64,
+    pub char_len: usize,
+}
+
+/// Longest numeral run we try to parse; anything longer isn't a counter expression.
+const MAX_NUMERAL_CHARS: usize = 16;
+
+fn digit_value(c: char) -> Option<u64> {
+    match c {
+        '0'..='9' => Some(c as u64 - '0' as u64),
+        '０'..='９' => Some(c as u64 - '０' as u64),
+        '〇' | '零' => Some(0),
+        '一' => Some(1),
+        '二' => Some(2),
+        '三' => Some(3),
+        '四' => Some(4),
+        '五' => Some(5),
+        '六' => Some(6),
+        '七' => Some(7),
+        '八' => Some(8),
+        '九' => Some(9),
+        _ => None,
+    }
+}
+
+/// 十, 百 and 千 multiply the digits before them within a four-digit group.
+fn small_unit(c: char) -> Option<u64> {
+    match c {
+        '十' => Some(10),
+        '百' => Some(100),
+        '千' => Some(1_000),
+        _ => None,
+    }
+}
+
+/// 万, 億 and 兆 close a four-digit group.
+fn large_unit(c: char) -> Option<u64> {
+    match c {
+        '万' => Some(10_000),
+        '億' => Some(100_000_000),
+        '兆' => Some(1_000_000_000_000),
+        _ => None,
+    }
+}
+
+fn is_numeral(c: char) -> bool {
+    digit_value(c).is_some() || small_unit(c).is_some() || large_unit(c).is_some()
+}
+
+/// Parses a mixed Arabic/kanji numeral (`3`, `２０`, `二十五`, `2千`, `1万5千`) from the start of
+/// `chars`.
+pub fn parse_numeric_prefix(chars: &[char]) -> Option<NumericPrefix> {
+    let char_len = chars.iter().take_while(|c| is_numeral(**c)).count();
+    if char_len == 0 || char_len > MAX_NUMERAL_CHARS {
+        return None;
+    }
+    // A leading unit without digits is fine (十, 百円), a leading 万/億/兆 is not a number.
+    if large_unit(chars[0]).is_some() {
+        return None;
+    }
+
+    let mut total: u64 = 0;
+    let mut group: u64 = 0;
+    let mut digits: Option<u64> = None;
+    for &c in &chars[..char_len] {
+        if let Some(d) = digit_value(c) {
+            digits = Some(digits.unwrap_or(0).checked_mul(10)?.checked_add(d)?);
+        } else if let Some(unit) = small_unit(c) {
+            group = group.checked_add(digits.unwrap_or(1).checked_mul(unit)?)?;
+            digits = None;
+        } else if let Some(unit) = large_unit(c) {
+            let count = group.checked_add(digits.unwrap_or(0))?;
+            total = total.checked_add(count.max(1).checked_mul(unit)?)?;
+            group = 0;
+            digits = None;
+        }
+    }
+    let value = total.checked_add(group)?.checked_add(digits.unwrap_or(0))?;
+    Some(NumericPrefix { value, char_len })
+}
+
+const DIGIT_READINGS: [&str; 10] = [
+    "ぜろ", "いち", "に", "さん", "よん", "ご", "ろく", "なな", "はち", "きゅう",
+];
+
+fn read_hundreds(digit: u64) -> &'static str {
+    match digit {
+        1 => "ひゃく",
+        3 => "さんびゃく",
+        6 => "ろっぴゃく",
+        8 => "はっぴゃく",
+        _ => "",
+    }
+}
+
+fn read_thousands(digit: u64) -> &'static str {
+    match digit {
+        1 => "せん",
+        3 => "さんぜん",
+        8 => "はっせん",
+        _ => "",
+    }
+}
+
+/// Reads 1..=9999 in hiragana.
+fn read_group(n: u64) -> String {
+    let mut out = String::new();
+    let (thousands, hundreds, tens, ones) = (n / 1000, n / 100 % 10, n / 10 % 10, n % 10);
+    if thousands > 0 {
+        match read_thousands(thousands) {
+            "" => {
+                out.push_str(DIGIT_READINGS[thousands as usize]);
+                out.push_str("せん");
+            }
+            special => out.push_str(special),
+        }
+    }
+    if hundreds > 0 {
+        match read_hundreds(hundreds) {
+            "" => {
+                out.push_str(DIGIT_READINGS[hundreds as usize]);
+                out.push_str("ひゃく");
+            }
+            special => out.push_str(special),
+        }
+    }
+    if tens > 0 {
+        if tens > 1 {
+            out.push_str(DIGIT_READINGS[tens as usize]);
+        }
+        out.push_str("じゅう");
+    }
+    if ones > 0 {
+        out.push_str(DIGIT_READINGS[ones as usize]);
+    }
+    out
+}
+
+/// Hiragana reading of a number, e.g. `2000` → `にせん`, `1_0000_0000_0000` → `いっちょう`.
+pub fn number_reading(value: u64) -> String {
+    if value == 0 {
+        return DIGIT_READINGS[0].to_string();
+    }
+    const GROUPS: [(u64, &str); 3] = [
+        (1_000_000_000_000, "ちょう"),
+        (100_000_000, "おく"),
+        (10_000, "まん"),
+    ];
+
+    let mut out = String::new();
+    let mut rest = value;
+    for (unit, name) in GROUPS {
+        let count = rest / unit;
+        rest %= unit;
+        if count > 0 {
+            out.push_str(&with_counter(count, name));
+        }
+    }
+    if rest > 0 {
+        out.push_str(&read_group(rest));
+    }
+    out
+}
+
+/// Reading of `value` followed by a counter read as `counter_reading` (hiragana), applying
+/// gemination (いっこ, はっぽん) and voicing (さんびき, さんぼん).
+pub fn with_counter(value: u64, counter_reading: &str) -> String {
+    let number = number_reading(value);
+    let Some(first) = counter_reading.chars().next() else {
+        return number;
+    };
+    let rest = &counter_reading[first.len_utf8()..];
+
+    let geminated = geminate(&number);
+    let row = consonant_row(first);
+    // さん, せん, まん, なん voice a following h; よん does not (よんほん).
+    let ends_in_n = number.ends_with('ん') && !number.ends_with("よん");
+
+    match (row, geminated) {
+        (Row::H, Some(stem)) => format!("{stem}っ{}{rest}", shift(first, Shift::Semivoiced)),
+        (Row::H, None) if ends_in_n => format!("{number}{}{rest}", shift(first, Shift::Voiced)),
+        (Row::K, Some(stem)) => format!("{stem}っ{counter_reading}"),
+        // 六/百 only geminate before k and h; before s and t they keep their full reading.
+        (Row::S | Row::T, Some(stem)) if !number.ends_with('く') => {
+            format!("{stem}っ{counter_reading}")
+        }
+        _ => format!("{number}{counter_reading}"),
+    }
+}
+
+/// The stem of a number reading ending in a mora that becomes っ before a counter.
+fn geminate(number: &str) -> Option<&str> {
+    ["いち", "ろく", "はち", "じゅう", "ひゃく"]
+        .iter()
+        .any(|suffix| number.ends_with(suffix))
+        .then(|| {
+            let last = number.chars().last().map(char::len_utf8).unwrap_or(0);
+            &number[..number.len() - last]
+        })
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Row {
+    K,
+    S,
+    T,
+    H,
+    Other,
+}
+
+fn consonant_row(c: char) -> Row {
+    match c {
+        'か' | 'き' | 'く' | 'け' | 'こ' => Row::K,
+        'さ' | 'し' | 'す' | 'せ' | 'そ' => Row::S,
+        'た' | 'ち' | 'つ' | 'て' | 'と' => Row::T,
+        'は' | 'ひ' | 'ふ' | 'へ' | 'ほ' => Row::H,
+        _ => Row::Other,
+    }
+}
+
+enum Shift {
+    Voiced,
+    Semivoiced,
+}
+
+/// は-row kana with a dakuten (ば) or handakuten (ぱ). Each sits one or two code points later.
+fn shift(c: char, shift: Shift) -> char {
+    let offset = match shift {
+        Shift::Voiced => 1,
+        Shift::Semivoiced => 2,
+    };
+    char::from_u32(c as u32 + offset).unwrap_or(c)
+}
+
+/// Headword and reading for a numeral followed by a counter term, e.g. `三` + 匹 (ひき) →
+/// `三匹` (さんびき).
+pub fn counter_term(
+    numeral: &[char],
+    value: u64,
+    headword: &str,
+    reading: Option<&str>,
+) -> (String, String) {
+    let counter_reading = reading.filter(|r| !r.is_empty()).unwrap_or(headword);
+    let mut combined: String = numeral.iter().collect();
+    combined.push_str(headword);
+    (combined, with_counter(value, counter_reading))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Option<u64> {
+        let chars: Vec<char> = text.chars().collect();
+        parse_numeric_prefix(&chars).map(|n| n.value)
+    }
+
+    #[test]
+    fn parses_mixed_numerals() {
+        assert_eq!(parse("三匹"), Some(3));
+        assert_eq!(parse("2千円"), Some(2000));
+        assert_eq!(parse("２０本"), Some(20));
+        assert_eq!(parse("二十五歳"), Some(25));
+        assert_eq!(parse("1万5千"), Some(15000));
+        assert_eq!(parse("百円"), Some(100));
+        assert_eq!(parse("万年筆"), None);
+        assert_eq!(parse("円"), None);
+    }
+
+    #[test]
+    fn applies_counter_sound_changes() {
+        assert_eq!(with_counter(3, "ひき"), "さんびき");
+        assert_eq!(with_counter(1, "ひき"), "いっぴき");
+        assert_eq!(with_counter(6, "こ"), "ろっこ");
+        assert_eq!(with_counter(10, "ほん"), "じゅっぽん");
+        assert_eq!(with_counter(8, "さつ"), "はっさつ");
+        assert_eq!(with_counter(6, "さつ"), "ろくさつ");
+        assert_eq!(with_counter(2000, "えん"), "にせんえん");
+        assert_eq!(number_reading(1_000_000_000_000), "いっちょう");
+        assert_eq!(number_reading(3800), "さんぜんはっぴゃく");
+    }
+}
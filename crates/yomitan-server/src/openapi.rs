@@ -0,0 +1,73 @@
+use serde::Serialize;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{
+    handlers,
+    state::{DictionaryData, Readiness},
+};
+
+/// OpenAPI description of the public lookup and import endpoints. Paths are relative to
+/// wherever the router is nested (`/api/yomitan` in the desktop app).
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        handlers::lookup_handler,
+        handlers::batch_lookup_handler,
+        handlers::sweep_lookup_handler,
+        handlers::reverse_lookup_handler,
+        handlers::list_dictionaries_handler,
+        handlers::health_handler,
+        handlers::verify_dictionary_handler,
+        handlers::frequency_list_handler,
+        handlers::pitch_graph_handler,
+        handlers::mine_handler,
+        handlers::import_handler,
+        handlers::import_job_handler,
+    ),
+    components(schemas(ApiError, DictionaryList, Health, ImportAccepted, ImportUpload, Readiness)),
+    tags((name = "yomitan", description = "Dictionary lookups and management"))
+)]
+pub struct ApiDoc;
+
+/// Error body returned with non-2xx statuses.
+#[derive(Serialize, ToSchema)]
+pub struct ApiError {
+    /// Always `"error"`.
+    pub status: String,
+    /// Machine-readable error code, e.g. `already_imported`.
+    pub error: Option<String>,
+    pub message: String,
+}
+
+/// Response to `POST /import`; the import itself runs in the background.
+#[derive(Serialize, ToSchema)]
+pub struct ImportAccepted {
+    pub status: String,
+    /// Id to poll at `GET /import/jobs/{id}`.
+    pub job_id: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DictionaryList {
+    pub dictionaries: Vec<DictionaryData>,
+    pub total_terms: i64,
+    /// `"loading"` during startup or while dictionaries install, otherwise `"ready"`.
+    pub status: String,
+    pub readiness: Readiness,
+}
+
+/// Response of `GET /health`, sent with 503 until the server is ready for lookups.
+#[derive(Serialize, ToSchema)]
+pub struct Health {
+    pub readiness: Readiness,
+    /// Number of installed dictionaries.
+    pub dictionaries: usize,
+}
+
+/// Multipart form accepted by `POST /import`.
+#[derive(ToSchema)]
+pub struct ImportUpload {
+    /// Yomitan dictionary ZIP archive.
+    #[schema(value_type = String, format = Binary)]
+    pub file: Vec<u8>,
+}
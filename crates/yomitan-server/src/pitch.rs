@@ -0,0 +1,150 @@
+//! Pitch-accent line graphs, drawn the way Japanese dictionaries show them: one dot per mora
+//! plus a hollow marker for a following particle, joined by a line that steps between the
+//! high and low rows.
+
+use std::fmt::Write;
+
+/// Horizontal distance between morae in SVG user units.
+const STEP: usize = 50;
+const HIGH_Y: usize = 25;
+const LOW_Y: usize = 75;
+const PARTICLE_RADIUS: f32 = 12.0;
+const STROKE: &str = r#"fill="none" stroke="currentColor" stroke-width="5""#;
+
+/// Content prefix of the pitch rows imported from term meta banks, like `Frequency: ` for
+/// frequency rows.
+pub const PITCH_PREFIX: &str = "Pitch: ";
+
+/// Stored content of a pitch row: `Pitch: 0,2`.
+pub fn format_positions(positions: &[usize]) -> String {
+    let positions: Vec<String> = positions.iter().map(|p| p.to_string()).collect();
+    format!("{PITCH_PREFIX}{}", positions.join(","))
+}
+
+pub fn parse_positions(text: &str) -> Option<Vec<usize>> {
+    text.strip_prefix(PITCH_PREFIX)?
+        .split(',')
+        .map(|p| p.trim().parse().ok())
+        .collect()
+}
+
+/// Converts an `H`/`L` pattern (`LHHL`) to the mora the pitch drops after, `0` if it never
+/// drops.
+pub fn downstep_from_pattern(pattern: &str) -> Option<usize> {
+    let levels: Vec<char> = pattern.chars().map(|c| c.to_ascii_uppercase()).collect();
+    if levels.is_empty() || levels.iter().any(|c| *c != 'H' && *c != 'L') {
+        return None;
+    }
+    Some(
+        levels
+            .windows(2)
+            .position(|w| w == ['H', 'L'])
+            .map_or(0, |i| i + 1),
+    )
+}
+
+/// Small kana that merge with the preceding kana into a single mora.
+fn is_small_kana(c: char) -> bool {
+    "ゃゅょぁぃぅぇぉゎャュョァィゥェォヮ".contains(c)
+}
+
+/// Splits a kana reading into morae. `っ` and `ー` count as morae of their own.
+pub fn morae(reading: &str) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for c in reading.chars().filter(|c| !c.is_whitespace()) {
+        match out.last_mut() {
+            Some(last) if is_small_kana(c) => last.push(c),
+            _ => out.push(c.to_string()),
+        }
+    }
+    out
+}
+
+/// Whether mora `index` is high for a downstep after mora `downstep` (0 = heiban). Index
+/// `mora_count` is the following particle.
+pub fn is_high(index: usize, downstep: usize) -> bool {
+    match downstep {
+        0 => index > 0,
+        1 => index == 0,
+        _ => index > 0 && index < downstep,
+    }
+}
+
+/// Renders the graph for `mora_count` morae with a downstep after mora `downstep`.
+pub fn render_svg(mora_count: usize, downstep: usize) -> String {
+    let points: Vec<(f32, f32)> = (0..=mora_count)
+        .map(|i| {
+            let y = if is_high(i, downstep) { HIGH_Y } else { LOW_Y };
+            ((STEP / 2 + i * STEP) as f32, y as f32)
+        })
+        .collect();
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {} 100" height="1.5em">"#,
+        (mora_count + 1) * STEP
+    );
+
+    // The line stops at the particle's outline so the marker stays hollow on any background.
+    let mut line: Vec<String> = points[..mora_count]
+        .iter()
+        .map(|(x, y)| format!("{x},{y}"))
+        .collect();
+    let (px, py) = points[mora_count];
+    if let Some(&(lx, ly)) = mora_count.checked_sub(1).map(|i| &points[i]) {
+        let (dx, dy) = (px - lx, py - ly);
+        let scale = 1.0 - PARTICLE_RADIUS / (dx * dx + dy * dy).sqrt();
+        let round = |v: f32| (v * 10.0).round() / 10.0;
+        line.push(format!("{},{}", round(lx + dx * scale), round(ly + dy * scale)));
+    }
+    let _ = write!(svg, r#"<polyline points="{}" {STROKE}/>"#, line.join(" "));
+
+    for (x, y) in &points[..mora_count] {
+        let _ = write!(svg, r#"<circle cx="{x}" cy="{y}" r="15" fill="currentColor"/>"#);
+    }
+    let _ = write!(svg, r#"<circle cx="{px}" cy="{py}" r="{PARTICLE_RADIUS}" {STROKE}/>"#);
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_morae() {
+        assert_eq!(morae("きょうと"), ["きょ", "う", "と"]);
+        assert_eq!(morae("がっこう"), ["が", "っ", "こ", "う"]);
+        assert_eq!(morae("コーヒー"), ["コ", "ー", "ヒ", "ー"]);
+    }
+
+    #[test]
+    fn pitch_patterns() {
+        let pattern = |count: usize, downstep: usize| -> String {
+            (0..=count)
+                .map(|i| if is_high(i, downstep) { 'H' } else { 'L' })
+                .collect()
+        };
+        assert_eq!(pattern(3, 0), "LHHH");
+        assert_eq!(pattern(3, 1), "HLLL");
+        assert_eq!(pattern(3, 2), "LHLL");
+        assert_eq!(pattern(3, 3), "LHHL");
+    }
+
+    #[test]
+    fn reads_positions_and_patterns() {
+        assert_eq!(parse_positions(&format_positions(&[0, 2])), Some(vec![0, 2]));
+        assert_eq!(parse_positions("Frequency: 3"), None);
+        assert_eq!(downstep_from_pattern("HLL"), Some(1));
+        assert_eq!(downstep_from_pattern("LHHL"), Some(3));
+        assert_eq!(downstep_from_pattern("LHH"), Some(0));
+        assert_eq!(downstep_from_pattern("LXH"), None);
+    }
+
+    #[test]
+    fn draws_one_dot_per_mora() {
+        let svg = render_svg(3, 2);
+        assert_eq!(svg.matches("fill=\"currentColor\"").count(), 3);
+        assert!(svg.contains(r#"<polyline points="25,75 75,25 125,75 163,75""#));
+        assert!(render_svg(1, 0).contains(r#"points="25,75 66.5,33.5""#));
+    }
+}
@@ -0,0 +1,90 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use wordbase_api::DictionaryId;
+
+use crate::state::AppState;
+
+/// How the values in a frequency dictionary should be ordered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrequencyMode {
+    /// Lower values are more common (e.g. corpus rank).
+    #[default]
+    Rank,
+    /// Higher values are more common (e.g. occurrence counts).
+    Occurrence,
+}
+
+impl FrequencyMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            FrequencyMode::Rank => "rank",
+            FrequencyMode::Occurrence => "occurrence",
+        }
+    }
+
+    fn from_str(value: &str) -> Self {
+        match value {
+            "occurrence" => FrequencyMode::Occurrence,
+            _ => FrequencyMode::Rank,
+        }
+    }
+}
+
+/// A named choice of frequency dictionary used to drive `profile_sorting_frequency`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SortingProfile {
+    pub name: String,
+    pub dictionary_id: DictionaryId,
+    #[serde(default)]
+    pub mode: FrequencyMode,
+}
+
+pub fn list_profiles(state: &AppState) -> Result<Vec<SortingProfile>> {
+    let conn = state.pool.get()?;
+    let mut stmt =
+        conn.prepare("SELECT name, dictionary_id, mode FROM sorting_profiles ORDER BY name")?;
+    let rows = stmt.query_map([], |row| {
+        Ok(SortingProfile {
+            name: row.get(0)?,
+            dictionary_id: DictionaryId(row.get(1)?),
+            mode: FrequencyMode::from_str(&row.get::<_, String>(2)?),
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+}
+
+pub fn get_profile(state: &AppState, name: &str) -> Result<Option<SortingProfile>> {
+    Ok(list_profiles(state)?
+        .into_iter()
+        .find(|profile| profile.name == name))
+}
+
+pub fn save_profile(state: &AppState, profile: &SortingProfile) -> Result<()> {
+    let name = profile.name.trim();
+    if name.is_empty() {
+        return Err(anyhow!("Profile name must not be empty"));
+    }
+    if !state
+        .dictionaries
+        .read()
+        .expect("lock")
+        .contains_key(&profile.dictionary_id)
+    {
+        return Err(anyhow!("Unknown dictionary id {}", profile.dictionary_id.0));
+    }
+
+    let conn = state.pool.get()?;
+    conn.execute(
+        "INSERT OR REPLACE INTO sorting_profiles (name, dictionary_id, mode) VALUES (?, ?, ?)",
+        rusqlite::params![name, profile.dictionary_id.0, profile.mode.as_str()],
+    )?;
+    Ok(())
+}
+
+pub fn delete_profile(state: &AppState, name: &str) -> Result<()> {
+    let conn = state.pool.get()?;
+    conn.execute("DELETE FROM sorting_profiles WHERE name = ?", [name])?;
+    Ok(())
+}
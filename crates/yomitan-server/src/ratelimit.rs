@@ -0,0 +1,173 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+use tracing::warn;
+
+/// Buckets kept before idle (full) ones are pruned.
+const MAX_TRACKED_CLIENTS: usize = 4096;
+
+/// Per-client token bucket. Each client starts with `burst` tokens which refill at
+/// `rate_per_sec`; a request spends one token.
+pub struct RateLimiter {
+    rate_per_sec: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub type SharedRateLimiter = Option<Arc<RateLimiter>>;
+
+impl RateLimiter {
+    pub fn new(rate_per_sec: f64, burst: f64) -> Self {
+        Self {
+            rate_per_sec,
+            burst: burst.max(1.0),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reads a limit such as `20/s`, `120/m` or `10/m:3` (rate, then optional burst) from `var`.
+    /// Unset or invalid values disable limiting.
+    pub fn from_env(var: &str) -> SharedRateLimiter {
+        let spec = std::env::var(var).ok()?;
+        match parse_spec(&spec) {
+            Some((rate, burst)) => Some(Arc::new(Self::new(rate, burst))),
+            None => {
+                warn!("⚠️ [RateLimit] Ignoring invalid {}={:?}", var, spec);
+                None
+            }
+        }
+    }
+
+    /// Spends a token for `client`, or returns how long until one is available.
+    pub fn check(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().expect("lock");
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            let (rate, burst) = (self.rate_per_sec, self.burst);
+            buckets.retain(|_, b| b.refilled(now, rate, burst) < burst);
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = bucket.refilled(now, self.rate_per_sec, self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let missing = 1.0 - bucket.tokens;
+            Err(Duration::from_secs_f64(missing / self.rate_per_sec))
+        }
+    }
+}
+
+impl Bucket {
+    fn refilled(&self, now: Instant, rate: f64, burst: f64) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * rate).min(burst)
+    }
+}
+
+fn parse_spec(spec: &str) -> Option<(f64, f64)> {
+    let (rate_part, burst_part) = match spec.trim().split_once(':') {
+        Some((rate, burst)) => (rate, Some(burst)),
+        None => (spec.trim(), None),
+    };
+    let (count, unit) = rate_part.split_once('/')?;
+    let count: f64 = count.trim().parse().ok().filter(|c: &f64| *c > 0.0)?;
+    let per_secs = match unit.trim() {
+        "s" | "sec" | "second" => 1.0,
+        "m" | "min" | "minute" => 60.0,
+        "h" | "hour" => 3600.0,
+        _ => return None,
+    };
+    let burst = match burst_part {
+        Some(b) => b.trim().parse().ok().filter(|b: &f64| *b >= 1.0)?,
+        None => count,
+    };
+    Some((count / per_secs, burst))
+}
+
+pub async fn enforce(
+    State(limiter): State<SharedRateLimiter>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(limiter) = limiter else {
+        return next.run(req).await;
+    };
+
+    // Without ConnectInfo (embedded servers) every caller shares one bucket.
+    let client = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+
+    match limiter.check(client, Instant::now()) {
+        Ok(()) => next.run(req).await,
+        Err(retry_after) => {
+            let secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            let mut response = (
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(json!({
+                    "status": "error",
+                    "error": "rate_limited",
+                    "message": format!("Too many requests, retry in {secs}s"),
+                })),
+            )
+                .into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_allows_burst_then_refills() {
+        let limiter = RateLimiter::new(2.0, 2.0);
+        let client = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let start = Instant::now();
+
+        assert!(limiter.check(client, start).is_ok());
+        assert!(limiter.check(client, start).is_ok());
+        let wait = limiter.check(client, start).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+
+        assert!(limiter.check(client, start + Duration::from_millis(500)).is_ok());
+        assert!(limiter.check(IpAddr::V4(Ipv4Addr::BROADCAST), start).is_ok());
+    }
+
+    #[test]
+    fn parses_rate_specs() {
+        assert_eq!(parse_spec("20/s"), Some((20.0, 20.0)));
+        assert_eq!(parse_spec("6/m:2"), Some((0.1, 2.0)));
+        assert_eq!(parse_spec("0/s"), None);
+        assert_eq!(parse_spec("fast"), None);
+    }
+}
@@ -0,0 +1,388 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+#[cfg(feature = "lindera")]
+use std::{
+    collections::HashMap,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+#[cfg(feature = "lindera")]
+use tracing::info;
+use tracing::warn;
+
+/// Which Lindera dictionary backs Japanese segmentation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SegmenterKind {
+    /// Candidate generation relies on deinflection only.
+    None,
+    Ipadic,
+    Unidic,
+}
+
+impl SegmenterKind {
+    /// Reads `MANATAN_LINDERA_DICTIONARY` (`ipadic`, `unidic` or `none`). Defaults to `none`,
+    /// since UniDic in particular is large and slow to load on mobile.
+    pub fn from_env() -> Self {
+        match std::env::var("MANATAN_LINDERA_DICTIONARY") {
+            Ok(value) => Self::from_str(&value).unwrap_or_else(|| {
+                warn!("⚠️ [Segmenter] Unknown dictionary kind '{value}', segmentation disabled");
+                SegmenterKind::None
+            }),
+            Err(_) => SegmenterKind::None,
+        }
+    }
+
+    fn from_str(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "none" | "off" => Some(SegmenterKind::None),
+            "ipadic" => Some(SegmenterKind::Ipadic),
+            "unidic" => Some(SegmenterKind::Unidic),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "lindera")]
+    fn uri(&self) -> Option<&'static str> {
+        match self {
+            SegmenterKind::None => None,
+            SegmenterKind::Ipadic => Some("embedded://ipadic"),
+            SegmenterKind::Unidic => Some("embedded://unidic"),
+        }
+    }
+
+    /// Index of the dictionary-form column in the token details.
+    #[cfg(feature = "lindera")]
+    fn base_form_columns(&self) -> &'static [usize] {
+        match self {
+            SegmenterKind::None => &[],
+            SegmenterKind::Ipadic => &[6],
+            // orthBase, then lemma
+            SegmenterKind::Unidic => &[10, 7],
+        }
+    }
+}
+
+/// Windows whose tokens are remembered. Hovering re-sends the same text over and over, so a
+/// small table is enough; it is simply cleared when full.
+#[cfg(feature = "lindera")]
+const TOKEN_CACHE_SIZE: usize = 256;
+
+/// One token of a piece of text: its surface length and dictionary form.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub char_len: usize,
+    pub base_form: String,
+}
+
+/// Char offsets at which the tokens end, i.e. the prefix lengths that stop on a word boundary.
+pub fn token_ends(tokens: &[Token]) -> Vec<usize> {
+    tokens
+        .iter()
+        .scan(0, |end, token| {
+            *end += token.char_len;
+            Some(*end)
+        })
+        .collect()
+}
+
+/// Lazily loaded Lindera tokenizer. If loading fails the segmenter disables itself and lookups
+/// fall back to plain deinflection-based candidate generation.
+pub struct Segmenter {
+    kind: SegmenterKind,
+    /// Optional Lindera user dictionary CSV (names, slang, ...) layered over the system one.
+    user_dictionary: RwLock<Option<PathBuf>>,
+    #[cfg(feature = "lindera")]
+    tokenizer: RwLock<Option<lindera::tokenizer::Tokenizer>>,
+    #[cfg(feature = "lindera")]
+    failed: AtomicBool,
+    #[cfg(feature = "lindera")]
+    cache: Mutex<HashMap<String, Arc<Vec<Token>>>>,
+}
+
+impl Segmenter {
+    pub fn new(kind: SegmenterKind) -> Self {
+        if kind != SegmenterKind::None && !cfg!(feature = "lindera") {
+            warn!(
+                "⚠️ [Segmenter] {:?} requested but built without Lindera support, segmentation disabled",
+                kind
+            );
+        }
+        Self {
+            kind,
+            user_dictionary: RwLock::new(None),
+            #[cfg(feature = "lindera")]
+            tokenizer: RwLock::new(None),
+            #[cfg(feature = "lindera")]
+            failed: AtomicBool::new(false),
+            #[cfg(feature = "lindera")]
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn kind(&self) -> SegmenterKind {
+        self.kind
+    }
+
+    /// Whether Japanese segmentation can actually run in this build and configuration.
+    pub fn is_enabled(&self) -> bool {
+        self.kind != SegmenterKind::None && cfg!(feature = "lindera")
+    }
+
+    pub fn user_dictionary(&self) -> Option<PathBuf> {
+        self.user_dictionary.read().ok()?.clone()
+    }
+
+    /// Switches the user dictionary. The tokenizer is rebuilt on the next lookup, and a
+    /// previous load failure is forgotten so a fixed dictionary gets another chance.
+    pub fn set_user_dictionary(&self, path: Option<PathBuf>) {
+        if let Ok(mut guard) = self.user_dictionary.write() {
+            *guard = path;
+        }
+        #[cfg(feature = "lindera")]
+        self.failed.store(false, Ordering::Relaxed);
+        self.unload();
+    }
+
+    /// Drops the loaded dictionary; it is reloaded on the next lookup that needs it.
+    pub fn unload(&self) {
+        #[cfg(feature = "lindera")]
+        if let Ok(mut guard) = self.tokenizer.write() {
+            *guard = None;
+        }
+        #[cfg(feature = "lindera")]
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.clear();
+        }
+    }
+
+    /// Tokenizes `text` once, reusing the result for a window seen recently.
+    #[cfg(feature = "lindera")]
+    pub fn tokens(&self, text: &str) -> Option<Arc<Vec<Token>>> {
+        if self.kind == SegmenterKind::None || self.failed.load(Ordering::Relaxed) || text.is_empty()
+        {
+            return None;
+        }
+        if let Some(tokens) = self.cache.lock().ok()?.get(text) {
+            return Some(tokens.clone());
+        }
+
+        let tokens = Arc::new(self.tokenize(text)?);
+        if let Ok(mut cache) = self.cache.lock() {
+            if cache.len() >= TOKEN_CACHE_SIZE {
+                cache.clear();
+            }
+            cache.insert(text.to_string(), tokens.clone());
+        }
+        Some(tokens)
+    }
+
+    #[cfg(not(feature = "lindera"))]
+    pub fn tokens(&self, _text: &str) -> Option<Arc<Vec<Token>>> {
+        None
+    }
+
+    #[cfg(feature = "lindera")]
+    fn tokenize(&self, text: &str) -> Option<Vec<Token>> {
+        {
+            let guard = self.tokenizer.read().ok()?;
+            if let Some(tokenizer) = guard.as_ref() {
+                return self.split(tokenizer, text);
+            }
+        }
+
+        let mut guard = self.tokenizer.write().ok()?;
+        if guard.is_none() {
+            match self.load() {
+                Ok(tokenizer) => {
+                    info!("✂️ [Segmenter] Loaded {:?} dictionary", self.kind);
+                    *guard = Some(tokenizer);
+                }
+                Err(e) => {
+                    warn!(
+                        "⚠️ [Segmenter] Failed to load {:?} dictionary, falling back to deinflection only: {}",
+                        self.kind, e
+                    );
+                    self.failed.store(true, Ordering::Relaxed);
+                    return None;
+                }
+            }
+        }
+        guard
+            .as_ref()
+            .and_then(|tokenizer| self.split(tokenizer, text))
+    }
+
+    #[cfg(feature = "lindera")]
+    fn load(&self) -> anyhow::Result<lindera::tokenizer::Tokenizer> {
+        use lindera::{dictionary::load_dictionary, mode::Mode, segmenter::Segmenter};
+
+        let uri = self
+            .kind
+            .uri()
+            .ok_or_else(|| anyhow::anyhow!("no dictionary configured"))?;
+        let dictionary = load_dictionary(uri)?;
+        // A broken user dictionary shouldn't take the system dictionary down with it.
+        let user_dictionary = self.user_dictionary().and_then(|path| {
+            match lindera::dictionary::load_user_dictionary(
+                &path.to_string_lossy(),
+                &dictionary.metadata,
+            ) {
+                Ok(user_dictionary) => {
+                    info!("✂️ [Segmenter] Loaded user dictionary {}", path.display());
+                    Some(user_dictionary)
+                }
+                Err(e) => {
+                    warn!(
+                        "⚠️ [Segmenter] Ignoring user dictionary {}: {}",
+                        path.display(),
+                        e
+                    );
+                    None
+                }
+            }
+        });
+        let segmenter = Segmenter::new(Mode::Normal, dictionary, user_dictionary);
+        Ok(lindera::tokenizer::Tokenizer::new(segmenter))
+    }
+
+    #[cfg(feature = "lindera")]
+    fn split(&self, tokenizer: &lindera::tokenizer::Tokenizer, text: &str) -> Option<Vec<Token>> {
+        let mut tokens = tokenizer.tokenize(text).ok()?;
+        let tokens = tokens
+            .iter_mut()
+            .map(|token| {
+                let surface = token.surface.to_string();
+                let details = token.details();
+                let base_form = self
+                    .kind
+                    .base_form_columns()
+                    .iter()
+                    .filter_map(|idx| details.get(*idx))
+                    .find(|value| !value.is_empty() && **value != "*")
+                    .map(|value| value.to_string())
+                    .unwrap_or_else(|| surface.clone());
+                Token {
+                    char_len: surface.chars().count(),
+                    base_form,
+                }
+            })
+            .collect();
+        Some(tokens)
+    }
+}
+
+/// File name of an uploaded user dictionary inside the data directory.
+pub const USER_DICTIONARY_FILE: &str = "lindera_user_dictionary.csv";
+
+/// Checks a Lindera "simple" user dictionary (`surface,part_of_speech,reading` per line) or a
+/// detailed one with the system dictionary's full column set. Returns the number of entries.
+pub fn validate_user_dictionary(csv: &str) -> Result<usize, String> {
+    let mut entries = 0;
+    for (line_no, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let columns: Vec<&str> = line.split(',').collect();
+        if columns.len() < 3 {
+            return Err(format!(
+                "Line {}: expected at least 3 columns (surface,part_of_speech,reading)",
+                line_no + 1
+            ));
+        }
+        if columns[0].trim().is_empty() {
+            return Err(format!("Line {}: surface form is empty", line_no + 1));
+        }
+        entries += 1;
+    }
+    if entries == 0 {
+        return Err("User dictionary has no entries".to_string());
+    }
+    Ok(entries)
+}
+
+/// Chinese word segmentation (jieba) and script variant conversion.
+pub struct ChineseSegmenter {
+    #[cfg(feature = "chinese")]
+    jieba: std::sync::OnceLock<jieba_rs::Jieba>,
+}
+
+impl ChineseSegmenter {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "chinese")]
+            jieba: std::sync::OnceLock::new(),
+        }
+    }
+
+    /// Character length of the first word jieba finds in `text`.
+    #[cfg(feature = "chinese")]
+    pub fn leading_word_len(&self, text: &str) -> Option<usize> {
+        let jieba = self.jieba.get_or_init(jieba_rs::Jieba::new);
+        jieba
+            .cut(text, true)
+            .first()
+            .map(|word| word.chars().count())
+            .filter(|len| *len > 0)
+    }
+
+    #[cfg(not(feature = "chinese"))]
+    pub fn leading_word_len(&self, _text: &str) -> Option<usize> {
+        None
+    }
+}
+
+impl Default for ChineseSegmenter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Simplified and traditional renderings of `text` that differ from the input.
+#[cfg(feature = "chinese")]
+pub fn chinese_script_variants(text: &str) -> Vec<String> {
+    let mut variants = Vec::new();
+    for variant in [zhconv::Variant::ZhHans, zhconv::Variant::ZhHant] {
+        let converted = zhconv::zhconv(text, variant);
+        if converted != text && !variants.contains(&converted) {
+            variants.push(converted);
+        }
+    }
+    variants
+}
+
+#[cfg(not(feature = "chinese"))]
+pub fn chinese_script_variants(_text: &str) -> Vec<String> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Token, token_ends, validate_user_dictionary};
+
+    #[test]
+    fn user_dictionary_needs_surface_pos_and_reading() {
+        assert_eq!(
+            validate_user_dictionary("東京スカイツリー,カスタム名詞,トウキョウスカイツリー\n\n"),
+            Ok(1)
+        );
+        assert!(validate_user_dictionary("ナツキ,名詞").is_err());
+        assert!(validate_user_dictionary(",名詞,ナツキ").is_err());
+        assert!(validate_user_dictionary("\n").is_err());
+    }
+
+    #[test]
+    fn token_ends_are_cumulative() {
+        let token = |char_len: usize| Token {
+            char_len,
+            base_form: String::new(),
+        };
+        assert_eq!(token_ends(&[token(2), token(2), token(1)]), [2, 4, 5]);
+        assert!(token_ends(&[]).is_empty());
+    }
+}
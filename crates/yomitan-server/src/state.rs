@@ -1,26 +1,42 @@
 use std::{
     collections::HashMap,
     path::PathBuf,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc, RwLock,
-    },
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
 use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, watch};
 use tracing::info;
+use utoipa::ToSchema;
 use wordbase_api::{dict::yomitan::GlossaryTag, DictionaryId, Record};
 
+use crate::events::{DictionaryEvent, EVENT_CHANNEL_CAPACITY};
+
 pub type DbPool = Pool<SqliteConnectionManager>;
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, ToSchema)]
 pub struct DictionaryData {
+    #[schema(value_type = i64)]
     pub id: DictionaryId,
     pub name: String,
     pub priority: i64,
     pub enabled: bool,
+    #[serde(default)]
+    pub term_count: i64,
+}
+
+/// Where the server is between opening the database and serving lookups.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Readiness {
+    /// Schema upgrades and term counts of an existing database are still running.
+    Initializing,
+    /// A dictionary import, install or reset is in progress.
+    Importing,
+    Ready,
 }
 
 #[derive(Clone)]
@@ -29,7 +45,11 @@ pub struct AppState {
     pub next_dict_id: Arc<RwLock<i64>>,
     pub pool: DbPool,
     pub data_dir: PathBuf,
-    pub loading: Arc<AtomicBool>,
+    pub readiness: Arc<watch::Sender<Readiness>>,
+    pub anki_connect_url: String,
+    /// Shared by every outgoing request so connections are pooled.
+    pub http: reqwest::Client,
+    pub events: broadcast::Sender<DictionaryEvent>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -40,6 +60,8 @@ pub struct StoredRecord {
     pub reading: Option<String>,
     #[serde(default)]
     pub headword: Option<String>,
+    #[serde(default)]
+    pub sequence: Option<i64>,
 }
 
 impl AppState {
@@ -68,17 +90,58 @@ impl AppState {
              );
 
              CREATE TABLE IF NOT EXISTS terms (
+                id INTEGER PRIMARY KEY,
                 term TEXT NOT NULL,
                 dictionary_id INTEGER NOT NULL,
-                json BLOB NOT NULL
+                json BLOB NOT NULL,
+                sequence INTEGER,
+                hash INTEGER
              );
              
              CREATE INDEX IF NOT EXISTS idx_term ON terms(term);
              CREATE INDEX IF NOT EXISTS idx_dict_term ON terms(dictionary_id);
-             
+
+             -- Gloss text index for reverse (English -> Japanese) lookups.
+             -- rowid is the id of the headword row in `terms`.
+             CREATE VIRTUAL TABLE IF NOT EXISTS glosses_fts USING fts5(
+                gloss,
+                dictionary_id UNINDEXED
+             );
+
+             CREATE TABLE IF NOT EXISTS lookup_history (
+                id INTEGER PRIMARY KEY,
+                term TEXT NOT NULL,
+                reading TEXT NOT NULL DEFAULT '',
+                context TEXT NOT NULL DEFAULT '',
+                created_at INTEGER NOT NULL
+             );
+
+             CREATE INDEX IF NOT EXISTS idx_history_created ON lookup_history(created_at);
+             CREATE INDEX IF NOT EXISTS idx_history_term ON lookup_history(term, reading);
+
+             CREATE TABLE IF NOT EXISTS vocabulary (
+                term TEXT NOT NULL,
+                reading TEXT NOT NULL DEFAULT '',
+                state TEXT NOT NULL,
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (term, reading)
+             );
+
+             CREATE TABLE IF NOT EXISTS sorting_profiles (
+                name TEXT PRIMARY KEY,
+                dictionary_id INTEGER NOT NULL,
+                mode TEXT NOT NULL DEFAULT 'rank'
+             );
+
              CREATE TABLE IF NOT EXISTS metadata (
                 key TEXT PRIMARY KEY,
                 value TEXT
+             );
+
+             -- User settings, kept apart from `metadata` so a dictionary reset leaves them.
+             CREATE TABLE IF NOT EXISTS audio_sources (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                config TEXT NOT NULL
              );",
         )
         .expect("Failed to initialize database tables");
@@ -98,6 +161,7 @@ impl AppState {
                         name: row.get(1)?,
                         priority: row.get(2)?,
                         enabled: row.get(3)?,
+                        term_count: 0,
                     })
                 })
                 .unwrap();
@@ -117,20 +181,322 @@ impl AppState {
             dicts.len()
         );
 
+        let anki_connect_url = std::env::var("MANATAN_ANKICONNECT_URL")
+            .unwrap_or_else(|_| crate::anki::DEFAULT_ANKI_CONNECT_URL.to_string());
+
         Self {
             dictionaries: Arc::new(RwLock::new(dicts)),
             next_dict_id: Arc::new(RwLock::new(max_id + 1)),
             pool,
             data_dir,
-            loading: Arc::new(AtomicBool::new(false)),
+            readiness: Arc::new(watch::Sender::new(Readiness::Initializing)),
+            anki_connect_url,
+            http: reqwest::Client::new(),
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+        }
+    }
+
+    /// Upgrades databases created by older versions and fills in missing term counts, then
+    /// marks the state ready. Lookups and dictionary changes are refused until this finishes,
+    /// so it can run in the background while the server already accepts connections.
+    pub fn initialize(&self) {
+        let mut conn = self.pool.get().expect("Failed to get DB connection");
+
+        // Databases created before sequence numbers were stored lack the column.
+        let has_sequence = conn
+            .prepare("SELECT 1 FROM pragma_table_info('terms') WHERE name = 'sequence'")
+            .and_then(|mut stmt| stmt.exists([]))
+            .unwrap_or(false);
+        if !has_sequence {
+            conn.execute("ALTER TABLE terms ADD COLUMN sequence INTEGER", [])
+                .expect("Failed to add sequence column");
+        }
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_dict_sequence ON terms(dictionary_id, sequence)",
+            [],
+        )
+        .expect("Failed to create sequence index");
+
+        // Rows imported before deduplication have a NULL hash, which the unique index ignores.
+        let has_hash = conn
+            .prepare("SELECT 1 FROM pragma_table_info('terms') WHERE name = 'hash'")
+            .and_then(|mut stmt| stmt.exists([]))
+            .unwrap_or(false);
+        if !has_hash {
+            conn.execute("ALTER TABLE terms ADD COLUMN hash INTEGER", [])
+                .expect("Failed to add hash column");
+        }
+        conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_term_hash ON terms(dictionary_id, term, hash)",
+            [],
+        )
+        .expect("Failed to create term hash index");
+
+        migrate_term_ids(&mut conn).expect("Failed to add term ids");
+
+        // Audio sources used to be stored in `metadata`, which a dictionary reset clears.
+        conn.execute_batch(
+            "INSERT OR IGNORE INTO audio_sources (id, config)
+                SELECT 1, value FROM metadata WHERE key = 'audio_sources';
+             DELETE FROM metadata WHERE key = 'audio_sources';",
+        )
+        .expect("Failed to move audio sources out of metadata");
+
+        // Term counts are cached in metadata so listing dictionaries never scans `terms`.
+        // Databases imported before the cache existed get a one-off count here.
+        let ids: Vec<DictionaryId> =
+            self.dictionaries.read().expect("lock").keys().copied().collect();
+        for id in ids {
+            let count = match load_term_count(&conn, id) {
+                Some(count) => count,
+                None => {
+                    let count = conn
+                        .query_row(
+                            "SELECT COUNT(DISTINCT json) FROM terms WHERE dictionary_id = ?",
+                            [id.0],
+                            |row| row.get(0),
+                        )
+                        .unwrap_or(0);
+                    let _ = store_term_count(&conn, id, count);
+                    count
+                }
+            };
+            if let Some(dict) = self.dictionaries.write().expect("lock").get_mut(&id) {
+                dict.term_count = count;
+            }
         }
+
+        info!("✅ [Yomitan] Database ready");
+        crate::codec::spawn_migration(self.pool.clone());
+        crate::import::spawn_gloss_backfill(self.pool.clone());
+        self.set_readiness(Readiness::Ready);
     }
 
-    pub fn set_loading(&self, val: bool) {
-        self.loading.store(val, Ordering::SeqCst);
+    pub fn readiness(&self) -> Readiness {
+        *self.readiness.borrow()
     }
 
+    pub fn set_readiness(&self, readiness: Readiness) {
+        let changed = self.readiness.send_if_modified(|current| {
+            let changed = *current != readiness;
+            *current = readiness;
+            changed
+        });
+        if changed {
+            self.emit(DictionaryEvent::LoadingChanged {
+                loading: readiness != Readiness::Ready,
+                readiness,
+            });
+        }
+    }
+
+    /// Whether lookups should be refused, either during startup or while importing.
     pub fn is_loading(&self) -> bool {
-        self.loading.load(Ordering::Relaxed)
+        self.readiness() != Readiness::Ready
+    }
+
+    /// Moves from `Ready` to `Importing` for an install or reset, or returns the state that
+    /// prevents it. Checking and switching happen atomically so two installs can't overlap.
+    pub fn begin_import(&self) -> Result<(), Readiness> {
+        let mut blocked = None;
+        self.readiness.send_if_modified(|current| {
+            if *current == Readiness::Ready {
+                *current = Readiness::Importing;
+                true
+            } else {
+                blocked = Some(*current);
+                false
+            }
+        });
+        match blocked {
+            Some(readiness) => Err(readiness),
+            None => {
+                self.emit(DictionaryEvent::LoadingChanged {
+                    loading: true,
+                    readiness: Readiness::Importing,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// Waits out startup and any install, reset or earlier import, then moves to `Importing`
+    /// until the returned guard is dropped. Uploaded imports queue behind those instead of being
+    /// refused.
+    pub async fn begin_import_when_ready(&self) -> ImportGuard {
+        let mut rx = self.readiness.subscribe();
+        while self.begin_import().is_err() {
+            let _ = rx.wait_for(|r| *r == Readiness::Ready).await;
+        }
+        ImportGuard(self.clone())
+    }
+
+    /// Broadcasts a dictionary event. Having no subscribers is not an error.
+    pub fn emit(&self, event: DictionaryEvent) {
+        let _ = self.events.send(event);
+    }
+
+    pub fn snapshot(&self) -> DictionaryEvent {
+        DictionaryEvent::Snapshot {
+            dictionaries: self.sorted_dictionaries(),
+            loading: self.is_loading(),
+            readiness: self.readiness(),
+        }
+    }
+
+    pub fn sorted_dictionaries(&self) -> Vec<DictionaryData> {
+        let dicts = self.dictionaries.read().expect("lock");
+        let mut list: Vec<_> = dicts.values().cloned().collect();
+        list.sort_by_key(|d| d.priority);
+        list
+    }
+}
+
+pub(crate) fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Gives the `terms` table of databases created before it had one an explicit
+/// `id INTEGER PRIMARY KEY`, which `glosses_fts` refers to. Implicit rowids may be renumbered by
+/// `VACUUM`, so the gloss index of those databases is dropped and rebuilt by the backfill.
+fn migrate_term_ids(conn: &mut rusqlite::Connection) -> rusqlite::Result<()> {
+    let has_id = conn
+        .prepare("SELECT 1 FROM pragma_table_info('terms') WHERE name = 'id'")?
+        .exists([])?;
+    if has_id {
+        return Ok(());
+    }
+
+    info!("🔧 [Yomitan] Adding ids to dictionary entries...");
+    let tx = conn.transaction()?;
+    tx.execute_batch(
+        "CREATE TABLE terms_with_id (
+            id INTEGER PRIMARY KEY,
+            term TEXT NOT NULL,
+            dictionary_id INTEGER NOT NULL,
+            json BLOB NOT NULL,
+            sequence INTEGER,
+            hash INTEGER
+         );
+         INSERT INTO terms_with_id (id, term, dictionary_id, json, sequence, hash)
+            SELECT rowid, term, dictionary_id, json, sequence, hash FROM terms;
+         DROP TABLE terms;
+         ALTER TABLE terms_with_id RENAME TO terms;
+
+         CREATE INDEX idx_term ON terms(term);
+         CREATE INDEX idx_dict_term ON terms(dictionary_id);
+         CREATE INDEX idx_dict_sequence ON terms(dictionary_id, sequence);
+         CREATE UNIQUE INDEX idx_term_hash ON terms(dictionary_id, term, hash);",
+    )?;
+    crate::import::clear_gloss_index(&tx)?;
+    tx.commit()
+}
+
+/// Holds the state in `Importing`, and marks it ready again when dropped, however the import
+/// ended.
+pub struct ImportGuard(AppState);
+
+impl Drop for ImportGuard {
+    fn drop(&mut self) {
+        self.0.set_readiness(Readiness::Ready);
+    }
+}
+
+fn term_count_key(id: DictionaryId) -> String {
+    format!("term_count:{}", id.0)
+}
+
+pub fn load_term_count(conn: &rusqlite::Connection, id: DictionaryId) -> Option<i64> {
+    conn.query_row(
+        "SELECT value FROM metadata WHERE key = ?",
+        [term_count_key(id)],
+        |row| row.get::<_, String>(0),
+    )
+    .ok()
+    .and_then(|value| value.parse().ok())
+}
+
+pub fn store_term_count(
+    conn: &rusqlite::Connection,
+    id: DictionaryId,
+    count: i64,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO metadata (key, value) VALUES (?, ?)",
+        rusqlite::params![term_count_key(id), count.to_string()],
+    )?;
+    Ok(())
+}
+
+pub fn delete_term_count(conn: &rusqlite::Connection, id: DictionaryId) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM metadata WHERE key = ?", [term_count_key(id)])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AppState;
+
+    #[test]
+    fn older_databases_get_term_ids_and_a_fresh_gloss_index() {
+        let data_dir =
+            std::env::temp_dir().join(format!("manatan-term-ids-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        std::fs::create_dir_all(&data_dir).expect("create data dir");
+        {
+            let conn = rusqlite::Connection::open(data_dir.join("yomitan.db")).expect("open");
+            conn.execute_batch(
+                "CREATE TABLE terms (
+                    term TEXT NOT NULL,
+                    dictionary_id INTEGER NOT NULL,
+                    json BLOB NOT NULL,
+                    sequence INTEGER,
+                    hash INTEGER
+                 );
+                 INSERT INTO terms (rowid, term, dictionary_id, json, hash)
+                    VALUES (5, '犬', 1, x'00', 1), (9, '猫', 1, x'00', 2);",
+            )
+            .expect("create old schema");
+        }
+
+        let app = AppState::new(data_dir.clone());
+        let conn = app.pool.get().expect("connection");
+        conn.execute(
+            "INSERT INTO glosses_fts (rowid, gloss, dictionary_id) VALUES (9, 'dog', 1)",
+            [],
+        )
+        .expect("index stale gloss");
+        app.initialize();
+
+        let rows: Vec<(i64, String)> = conn
+            .prepare("SELECT id, term FROM terms ORDER BY id")
+            .expect("prepare")
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .expect("query")
+            .collect::<rusqlite::Result<_>>()
+            .expect("rows");
+        assert_eq!(rows, [(5, "犬".to_string()), (9, "猫".to_string())]);
+        let indexed: i64 = conn
+            .query_row("SELECT COUNT(*) FROM glosses_fts", [], |row| row.get(0))
+            .expect("count");
+        assert_eq!(indexed, 0);
+        let duplicate = conn.execute(
+            "INSERT INTO terms (term, dictionary_id, json, hash) VALUES ('猫', 1, x'00', 2)",
+            [],
+        );
+        assert!(
+            matches!(
+                duplicate,
+                Err(rusqlite::Error::SqliteFailure(ref err, _))
+                    if err.code == rusqlite::ErrorCode::ConstraintViolation
+            ),
+            "the unique hash index was lost: {duplicate:?}"
+        );
+
+        drop(conn);
+        let _ = std::fs::remove_dir_all(&data_dir);
     }
 }
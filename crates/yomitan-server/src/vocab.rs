@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use utoipa::ToSchema;
+
+use crate::state::{AppState, now_secs};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum VocabState {
+    #[default]
+    Unknown,
+    Learning,
+    Known,
+}
+
+impl VocabState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VocabState::Unknown => "unknown",
+            VocabState::Learning => "learning",
+            VocabState::Known => "known",
+        }
+    }
+
+    fn from_str(value: &str) -> Option<Self> {
+        match value {
+            "unknown" => Some(VocabState::Unknown),
+            "learning" => Some(VocabState::Learning),
+            "known" => Some(VocabState::Known),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VocabEntry {
+    pub term: String,
+    pub reading: String,
+    pub state: VocabState,
+    pub updated_at: i64,
+}
+
+/// Stores the state for a word. Setting a word back to `Unknown` removes its row, so the table
+/// only ever holds words the user has actually marked.
+pub fn set_state(state: &AppState, term: &str, reading: &str, vocab_state: VocabState) -> Result<()> {
+    let conn = state.pool.get()?;
+    if vocab_state == VocabState::Unknown {
+        conn.execute(
+            "DELETE FROM vocabulary WHERE term = ? AND reading = ?",
+            rusqlite::params![term, reading],
+        )?;
+        return Ok(());
+    }
+
+    let now = now_secs();
+    conn.execute(
+        "INSERT OR REPLACE INTO vocabulary (term, reading, state, updated_at) VALUES (?, ?, ?, ?)",
+        rusqlite::params![term, reading, vocab_state.as_str(), now],
+    )?;
+    Ok(())
+}
+
+/// Resolves the state of each `(headword, reading)` pair. A row stored with an empty reading
+/// applies to every reading of that headword unless a more specific row exists.
+pub fn load_states(
+    state: &AppState,
+    keys: &[(String, String)],
+) -> HashMap<(String, String), VocabState> {
+    let mut states = HashMap::new();
+    if keys.is_empty() {
+        return states;
+    }
+
+    let conn = match state.pool.get() {
+        Ok(c) => c,
+        Err(e) => {
+            error!("❌ Failed to get DB connection: {}", e);
+            return states;
+        }
+    };
+    let mut stmt = match conn.prepare("SELECT reading, state FROM vocabulary WHERE term = ?") {
+        Ok(s) => s,
+        Err(e) => {
+            error!("❌ DB Prepare Error: {}", e);
+            return states;
+        }
+    };
+
+    for (headword, reading) in keys {
+        if states.contains_key(&(headword.clone(), reading.clone())) {
+            continue;
+        }
+        let Ok(rows) = stmt.query_map([headword], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        }) else {
+            continue;
+        };
+
+        let mut fallback = None;
+        let mut exact = None;
+        for (row_reading, row_state) in rows.flatten() {
+            let Some(parsed) = VocabState::from_str(&row_state) else {
+                continue;
+            };
+            if &row_reading == reading {
+                exact = Some(parsed);
+            } else if row_reading.is_empty() {
+                fallback = Some(parsed);
+            }
+        }
+
+        if let Some(found) = exact.or(fallback) {
+            states.insert((headword.clone(), reading.clone()), found);
+        }
+    }
+
+    states
+}
+
+pub fn list_entries(state: &AppState, filter: Option<VocabState>) -> Result<Vec<VocabEntry>> {
+    let conn = state.pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT term, reading, state, updated_at FROM vocabulary
+         WHERE ?1 IS NULL OR state = ?1 ORDER BY updated_at DESC",
+    )?;
+    let rows = stmt.query_map([filter.map(|f| f.as_str())], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, i64>(3)?,
+        ))
+    })?;
+
+    let mut entries = Vec::new();
+    for row in rows {
+        let (term, reading, raw_state, updated_at) = row?;
+        if let Some(vocab_state) = VocabState::from_str(&raw_state) {
+            entries.push(VocabEntry {
+                term,
+                reading,
+                state: vocab_state,
+                updated_at,
+            });
+        }
+    }
+    Ok(entries)
+}